@@ -0,0 +1,80 @@
+use async_trait::async_trait;
+use rust_bert::pipelines::text_generation::{TextGenerationConfig, TextGenerationModel};
+use tokio::sync::Mutex;
+use tracing::{debug, error, info};
+
+use super::{Generation, GenerationParams, LlmBackend};
+use crate::errors::ChatbotError;
+use crate::models::ModelInfo;
+use crate::services::HuggingFaceConfig;
+use crate::sessions::ConversationContext;
+
+/// Runs a rust_bert text generation model in process.
+///
+/// The pipeline does not expose token scores, so responses carry no confidence.
+pub struct RustBertBackend {
+    model: Mutex<TextGenerationModel>,
+    config: HuggingFaceConfig,
+}
+
+impl RustBertBackend {
+    pub async fn new(config: Option<HuggingFaceConfig>) -> Result<Self, ChatbotError> {
+        let config = config.unwrap_or_default();
+        info!("Initializing HuggingFace model: {}", config.model_name);
+
+        let model = TextGenerationModel::new(Default::default()).map_err(|e| {
+            error!("Failed to initialize model: {:?}", e);
+            ChatbotError::ModelInitializationError(e.to_string())
+        })?;
+
+        Ok(Self { model: Mutex::new(model), config })
+    }
+
+    /// Sends one-off messages as they are and session history as a transcript.
+    fn prompt(context: &ConversationContext) -> String {
+        match (&context.session_id, context.messages.as_slice()) {
+            (None, [message]) => message.content.clone(),
+            _ => context.to_prompt(),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmBackend for RustBertBackend {
+    async fn generate(
+        &self,
+        context: &ConversationContext,
+        params: &GenerationParams,
+    ) -> Result<Generation, ChatbotError> {
+        let input_text = Self::prompt(context);
+        debug!("Generating response for input: {}", input_text);
+
+        let generation_config = TextGenerationConfig {
+            max_length: params.max_tokens,
+            num_beams: self.config.num_beams,
+            temperature: params.temperature,
+            top_k: self.config.top_k,
+            top_p: self.config.top_p,
+            do_sample: self.config.do_sample,
+            repetition_penalty: self.config.repetition_penalty,
+            ..Default::default()
+        };
+
+        let model = self.model.lock().await;
+        let output = model.generate(&[input_text], Some(&generation_config)).map_err(|e| {
+            error!("Model generation error: {:?}", e);
+            ChatbotError::ModelError(e.to_string())
+        })?;
+        let text = output.into_iter().next().ok_or(ChatbotError::EmptyResponse)?;
+        Ok(Generation::text(text))
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo {
+            backend: "huggingface".to_string(),
+            model: self.config.model_name.clone(),
+            max_tokens: Some(self.config.max_length),
+            supports_confidence: false,
+        }
+    }
+}
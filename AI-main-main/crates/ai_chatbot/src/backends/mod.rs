@@ -0,0 +1,135 @@
+//! Module: Backends
+//! Language model backends behind `ChatbotService`.
+//!
+//! A [`LlmBackend`] turns a conversation into generated text. The backend is chosen with
+//! [`BackendKind`] in `ModelConfig`: the local rust_bert model, an OpenAI-compatible
+//! chat-completions API, or a scripted backend for tests. Session handling, streaming and
+//! response formatting are shared by all of them in [`crate::services::LlmChatbot`].
+//!
+//! Backends that generate incrementally also implement [`LlmBackend::generate_stream`];
+//! for the others, streamed responses are generated in full and replayed.
+
+mod local;
+mod openai;
+mod scripted;
+
+use async_trait::async_trait;
+use std::sync::Arc;
+
+pub use self::local::RustBertBackend;
+pub use self::openai::OpenAiBackend;
+pub use self::scripted::ScriptedBackend;
+use crate::config::{BackendKind, ServiceConfig};
+use crate::errors::ChatbotError;
+use crate::models::{ModelInfo, ToolCall, Usage};
+use crate::sessions::ConversationContext;
+use crate::streaming::DeltaStream;
+
+/// Sampling parameters that can be changed at runtime through `update_config`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GenerationParams {
+    pub max_tokens: usize,
+    pub temperature: f32,
+}
+
+impl Default for GenerationParams {
+    fn default() -> Self {
+        Self { max_tokens: 100, temperature: 0.7 }
+    }
+}
+
+/// Text generated by a backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generation {
+    pub text: String,
+    /// Model that produced the text, if the backend reports it
+    pub model: Option<String>,
+    /// Log-probability of each generated token, if the backend reports them
+    pub token_logprobs: Option<Vec<f32>>,
+    /// Token counts, if the backend reports them
+    pub usage: Option<Usage>,
+    /// Tools the model wants called before it answers; `text` may then be empty
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl Generation {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            model: None,
+            token_logprobs: None,
+            usage: None,
+            tool_calls: Vec::new(),
+        }
+    }
+
+    /// A generation that only requests tool calls.
+    pub fn tool_calls(tool_calls: Vec<ToolCall>) -> Self {
+        Self { tool_calls, ..Self::text("") }
+    }
+
+    /// Geometric mean of the token probabilities, or `None` without log-probabilities.
+    pub fn confidence(&self) -> Option<f32> {
+        let logprobs = self.token_logprobs.as_deref().filter(|logprobs| !logprobs.is_empty())?;
+        let mean = logprobs.iter().sum::<f32>() / logprobs.len() as f32;
+        Some(mean.exp().clamp(0.0, 1.0))
+    }
+}
+
+/// A language model that answers conversations.
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+    /// Generates the assistant's reply to `context`, whose last message is the new one.
+    ///
+    /// Backends that support tool calling may answer with calls of `context.tools`
+    /// instead; the results are then in `context.tool_rounds` on the next call.
+    async fn generate(
+        &self,
+        context: &ConversationContext,
+        params: &GenerationParams,
+    ) -> Result<Generation, ChatbotError>;
+
+    /// Streams the assistant's reply to `context` as it is generated. Tools are not
+    /// offered, so the reply is always text.
+    ///
+    /// Returns `None` if the backend cannot stream; callers then use [`Self::generate`].
+    async fn generate_stream(
+        &self,
+        _context: &ConversationContext,
+        _params: &GenerationParams,
+    ) -> Result<Option<DeltaStream>, ChatbotError> {
+        Ok(None)
+    }
+
+    /// Describes the model, for the model info endpoint.
+    fn model_info(&self) -> ModelInfo;
+}
+
+/// Creates the backend selected by `config.model_config.backend`.
+pub async fn from_config(config: &ServiceConfig) -> Result<Arc<dyn LlmBackend>, ChatbotError> {
+    Ok(match config.model_config.backend {
+        BackendKind::Local => {
+            Arc::new(RustBertBackend::new(Some(config.model_config.huggingface.clone())).await?)
+        }
+        BackendKind::OpenAi => Arc::new(OpenAiBackend::from_config(config)?),
+        BackendKind::Scripted => Arc::new(ScriptedBackend::new()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confidence_is_the_geometric_mean_token_probability() {
+        let mut generation = Generation::text("Hi there");
+        assert_eq!(generation.confidence(), None);
+
+        generation.token_logprobs = Some(vec![0.5f32.ln(), 0.8f32.ln()]);
+        let expected = (0.5f32 * 0.8).sqrt();
+        assert!((generation.confidence().unwrap() - expected).abs() < 1e-6);
+
+        generation.token_logprobs = Some(Vec::new());
+        assert_eq!(generation.confidence(), None);
+    }
+}
@@ -0,0 +1,585 @@
+use async_trait::async_trait;
+use futures::stream;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tracing::{debug, error};
+
+use super::{Generation, GenerationParams, LlmBackend};
+use crate::config::ServiceConfig;
+use crate::errors::ChatbotError;
+use crate::models::{ModelInfo, ToolCall, ToolDefinition, Usage};
+use crate::sessions::ConversationContext;
+use crate::streaming::DeltaStream;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Bounds a whole completion; streamed ones can run longer, so they are bounded per chunk.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a stream may go without a chunk, including before its first one.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Client for any server implementing the OpenAI chat-completions API.
+///
+/// Token log-probabilities are requested with every completion, so responses carry a
+/// confidence whenever the server supports them. Streamed completions use the API's
+/// Server-Sent Events mode and carry no log-probabilities.
+pub struct OpenAiBackend {
+    client: reqwest::Client,
+    endpoint: String,
+    api_key: String,
+    org_id: Option<String>,
+    model: String,
+}
+
+impl OpenAiBackend {
+    /// `base_url` is the API root, e.g. `https://api.openai.com/v1`.
+    pub fn new(
+        base_url: &str,
+        api_key: impl Into<String>,
+        model: impl Into<String>,
+    ) -> Result<Self, ChatbotError> {
+        let client = reqwest::Client::builder().connect_timeout(CONNECT_TIMEOUT).build()?;
+        Ok(Self {
+            client,
+            endpoint: format!("{}/chat/completions", base_url.trim_end_matches('/')),
+            api_key: api_key.into(),
+            org_id: None,
+            model: model.into(),
+        })
+    }
+
+    pub fn with_org_id(mut self, org_id: Option<String>) -> Self {
+        self.org_id = org_id;
+        self
+    }
+
+    pub fn from_config(config: &ServiceConfig) -> Result<Self, ChatbotError> {
+        Ok(Self::new(&config.api_endpoint, &config.api_key, &config.model_config.openai_model)?
+            .with_org_id(config.org_id.clone()))
+    }
+
+    fn request<'a>(
+        &'a self,
+        context: &'a ConversationContext,
+        params: &GenerationParams,
+        stream: bool,
+    ) -> CompletionRequest<'a> {
+        CompletionRequest {
+            model: &self.model,
+            messages: context
+                .messages
+                .iter()
+                .map(|message| RequestMessage::new(&message.role, message.content.clone()))
+                .chain(RequestMessage::tool_rounds(context))
+                .collect(),
+            max_tokens: params.max_tokens,
+            temperature: params.temperature,
+            logprobs: !stream,
+            stream,
+            tools: if stream {
+                Vec::new()
+            } else {
+                context
+                    .tools
+                    .iter()
+                    .map(|function| RequestTool { kind: "function", function })
+                    .collect()
+            },
+        }
+    }
+
+    /// Sends `request`, turning unsuccessful statuses into errors.
+    async fn send(
+        &self,
+        request: &CompletionRequest<'_>,
+    ) -> Result<reqwest::Response, ChatbotError> {
+        debug!(
+            "Requesting completion from {} with {} messages",
+            self.endpoint,
+            request.messages.len()
+        );
+
+        let mut builder = self.client.post(&self.endpoint).bearer_auth(&self.api_key).json(request);
+        if let Some(org_id) = &self.org_id {
+            builder = builder.header("OpenAI-Organization", org_id);
+        }
+        let response = if request.stream {
+            idle_timeout(builder.send()).await??
+        } else {
+            builder.timeout(REQUEST_TIMEOUT).send().await?
+        };
+
+        let status = response.status();
+        if !status.is_success() {
+            // The body may echo the request or name the account, so it is only logged.
+            let body = response.text().await.unwrap_or_default();
+            error!("Completion request failed with {}: {}", status, body);
+            return Err(match status.as_u16() {
+                // The service's own credentials were refused; that is not the client's fault.
+                401 | 403 => ChatbotError::ConnectionError(
+                    "Backend rejected the service credentials".to_string(),
+                ),
+                // The service's quota ran out, not the caller's
+                429 => ChatbotError::ConnectionError("Backend is rate limiting".to_string()),
+                code if code >= 500 => {
+                    ChatbotError::ConnectionError(format!("Backend returned {}", status))
+                }
+                _ => ChatbotError::ModelError(format!("Backend returned {}", status)),
+            });
+        }
+        Ok(response)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CompletionRequest<'a> {
+    model: &'a str,
+    messages: Vec<RequestMessage<'a>>,
+    max_tokens: usize,
+    temperature: f32,
+    logprobs: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<RequestTool<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct RequestMessage<'a> {
+    role: &'a str,
+    /// `null` on assistant messages that only call tools
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<FunctionCall>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<&'a str>,
+}
+
+impl<'a> RequestMessage<'a> {
+    fn new(role: &'a str, content: String) -> Self {
+        Self { role, content: Some(content), tool_calls: Vec::new(), tool_call_id: None }
+    }
+
+    /// The messages that replay the tool calls of `context`, each call followed by its result.
+    fn tool_rounds(context: &'a ConversationContext) -> Vec<Self> {
+        let mut messages = Vec::new();
+        for round in &context.tool_rounds {
+            messages.push(Self {
+                role: "assistant",
+                content: None,
+                tool_calls: round
+                    .iter()
+                    .map(|invocation| FunctionCall::from(&invocation.call))
+                    .collect(),
+                tool_call_id: None,
+            });
+            for invocation in round {
+                messages.push(Self {
+                    tool_call_id: Some(&invocation.call.id),
+                    ..Self::new("tool", invocation.result_json().to_string())
+                });
+            }
+        }
+        messages
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RequestTool<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: &'a ToolDefinition,
+}
+
+/// A tool call as the API encodes it, with the arguments as a JSON string.
+#[derive(Debug, Serialize, Deserialize)]
+struct FunctionCall {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    function: FunctionCallBody,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FunctionCallBody {
+    name: String,
+    arguments: String,
+}
+
+impl From<&ToolCall> for FunctionCall {
+    fn from(call: &ToolCall) -> Self {
+        Self {
+            id: call.id.clone(),
+            kind: "function".to_string(),
+            function: FunctionCallBody {
+                name: call.name.clone(),
+                arguments: call.arguments.to_string(),
+            },
+        }
+    }
+}
+
+impl From<FunctionCall> for ToolCall {
+    /// Arguments that are not valid JSON are kept as a string, which fails validation.
+    fn from(call: FunctionCall) -> Self {
+        let arguments = serde_json::from_str(&call.function.arguments)
+            .unwrap_or(serde_json::Value::String(call.function.arguments));
+        Self { id: call.id, name: call.function.name, arguments }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionResponse {
+    model: Option<String>,
+    choices: Vec<Choice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: ResponseMessage,
+    logprobs: Option<ChoiceLogprobs>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseMessage {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<FunctionCall>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChoiceLogprobs {
+    content: Option<Vec<TokenLogprob>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenLogprob {
+    logprob: f32,
+}
+
+impl CompletionResponse {
+    fn into_generation(self) -> Result<Generation, ChatbotError> {
+        let choice = self.choices.into_iter().next().ok_or(ChatbotError::EmptyResponse)?;
+        let text = choice.message.content.unwrap_or_default();
+        let tool_calls: Vec<ToolCall> =
+            choice.message.tool_calls.into_iter().map(ToolCall::from).collect();
+        if text.is_empty() && tool_calls.is_empty() {
+            return Err(ChatbotError::EmptyResponse);
+        }
+        let token_logprobs = choice
+            .logprobs
+            .and_then(|logprobs| logprobs.content)
+            .map(|tokens| tokens.into_iter().map(|token| token.logprob).collect());
+        Ok(Generation { text, model: self.model, token_logprobs, usage: self.usage, tool_calls })
+    }
+}
+
+/// One event of a streamed completion.
+#[derive(Debug, Deserialize)]
+struct CompletionChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkChoice {
+    delta: ChunkDelta,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkDelta {
+    content: Option<String>,
+}
+
+/// Splits a `text/event-stream` body into the data of its events.
+#[derive(Debug, Default)]
+struct SseDecoder {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    /// Appends `chunk` and returns the data of every event it completes.
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        // Lines are only decoded once complete, so characters split across chunks survive.
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(self.data.join("\n"));
+                    self.data.clear();
+                }
+            } else if let Some(data) = line.strip_prefix("data:") {
+                self.data.push(data.strip_prefix(' ').unwrap_or(data).to_string());
+            }
+        }
+        events
+    }
+}
+
+/// Reads the text deltas of a streamed completion until the server sends `[DONE]`.
+struct ChunkReader {
+    response: reqwest::Response,
+    decoder: SseDecoder,
+    events: VecDeque<String>,
+}
+
+impl ChunkReader {
+    async fn next_delta(&mut self) -> Option<Result<String, ChatbotError>> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                if event == "[DONE]" {
+                    return None;
+                }
+                let chunk: CompletionChunk = match serde_json::from_str(&event) {
+                    Ok(chunk) => chunk,
+                    Err(err) => return Some(Err(err.into())),
+                };
+                let text: String =
+                    chunk.choices.into_iter().filter_map(|choice| choice.delta.content).collect();
+                if !text.is_empty() {
+                    return Some(Ok(text));
+                }
+                continue;
+            }
+            match idle_timeout(self.response.chunk()).await {
+                Ok(Ok(Some(bytes))) => self.events.extend(self.decoder.push(&bytes)),
+                Ok(Ok(None)) => return None,
+                Ok(Err(err)) => return Some(Err(err.into())),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// Waits for `future` at most [`IDLE_TIMEOUT`].
+async fn idle_timeout<T>(future: impl std::future::Future<Output = T>) -> Result<T, ChatbotError> {
+    tokio::time::timeout(IDLE_TIMEOUT, future).await.map_err(|_| {
+        ChatbotError::ConnectionError(format!(
+            "Backend sent nothing for {}s",
+            IDLE_TIMEOUT.as_secs()
+        ))
+    })
+}
+
+#[async_trait]
+impl LlmBackend for OpenAiBackend {
+    async fn generate(
+        &self,
+        context: &ConversationContext,
+        params: &GenerationParams,
+    ) -> Result<Generation, ChatbotError> {
+        let response = self.send(&self.request(context, params, false)).await?;
+        let completion: CompletionResponse = response.json().await?;
+        completion.into_generation()
+    }
+
+    async fn generate_stream(
+        &self,
+        context: &ConversationContext,
+        params: &GenerationParams,
+    ) -> Result<Option<DeltaStream>, ChatbotError> {
+        let response = self.send(&self.request(context, params, true)).await?;
+        let reader =
+            ChunkReader { response, decoder: SseDecoder::default(), events: VecDeque::new() };
+        Ok(Some(Box::pin(stream::unfold(reader, |mut reader| async move {
+            let delta = reader.next_delta().await?;
+            Some((delta, reader))
+        }))))
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo {
+            backend: "openai".to_string(),
+            model: self.model.clone(),
+            max_tokens: None,
+            supports_confidence: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completion_response_yields_text_logprobs_and_usage() {
+        let body = r#"{
+            "id": "chatcmpl-1",
+            "model": "gpt-4o-mini-2024-07-18",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Hi!"},
+                "logprobs": {"content": [
+                    {"token": "Hi", "logprob": -0.1, "top_logprobs": []},
+                    {"token": "!", "logprob": -0.3, "top_logprobs": []}
+                ]},
+                "finish_reason": "stop"
+            }],
+            "usage": {"prompt_tokens": 9, "completion_tokens": 2, "total_tokens": 11}
+        }"#;
+
+        let completion: CompletionResponse = serde_json::from_str(body).unwrap();
+        let generation = completion.into_generation().unwrap();
+        assert_eq!(generation.text, "Hi!");
+        assert_eq!(generation.model.as_deref(), Some("gpt-4o-mini-2024-07-18"));
+        assert_eq!(generation.token_logprobs, Some(vec![-0.1, -0.3]));
+        assert_eq!(generation.usage.unwrap().total_tokens, 11);
+        assert!((generation.confidence().unwrap() - (-0.2f32).exp()).abs() < 1e-6);
+
+        let empty: CompletionResponse =
+            serde_json::from_str(r#"{"choices": [], "usage": null}"#).unwrap();
+        assert!(matches!(empty.into_generation(), Err(ChatbotError::EmptyResponse)));
+    }
+
+    #[test]
+    fn test_tool_calls_are_parsed_and_replayed_with_their_results() {
+        use crate::models::ToolInvocation;
+        use serde_json::json;
+
+        let body = r#"{
+            "choices": [{
+                "message": {"role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "add", "arguments": "{\"a\": 2, \"b\": 3}"}
+                }]}
+            }]
+        }"#;
+        let completion: CompletionResponse = serde_json::from_str(body).unwrap();
+        let generation = completion.into_generation().unwrap();
+        assert_eq!(generation.text, "");
+        let call = generation.tool_calls[0].clone();
+        assert_eq!((call.id.as_str(), call.name.as_str()), ("call_1", "add"));
+        assert_eq!(call.arguments, json!({"a": 2, "b": 3}));
+
+        let mut context = ConversationContext::new(None, Vec::new(), 0);
+        context.tool_rounds.push(vec![ToolInvocation {
+            call,
+            output: Some(json!(5)),
+            error: None,
+            duration_ms: 0,
+        }]);
+        let replayed = serde_json::to_value(RequestMessage::tool_rounds(&context)).unwrap();
+        assert_eq!(
+            replayed,
+            json!([
+                {"role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "add", "arguments": "{\"a\":2,\"b\":3}"}
+                }]},
+                {"role": "tool", "content": "5", "tool_call_id": "call_1"}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_sse_events_are_decoded_across_chunk_boundaries() {
+        let mut decoder = SseDecoder::default();
+        let body = "data: {\"a\": \"h\u{e9}\"}\r\n\r\n: keep-alive\n\ndata: [DONE]\n\n".as_bytes();
+        let split = body.iter().position(|&byte| byte == 0xc3).unwrap() + 1;
+
+        assert!(decoder.push(&body[..split]).is_empty());
+        assert_eq!(decoder.push(&body[split..]), vec!["{\"a\": \"h\u{e9}\"}", "[DONE]"]);
+    }
+
+    /// Answers one request on a local port with `response`, returning the base URL and
+    /// the request it received.
+    async fn serve_once(response: String) -> (String, tokio::task::JoinHandle<String>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            loop {
+                let read = socket.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|line| {
+                            line.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|n| n.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+                assert!(read > 0, "connection closed mid-request");
+            }
+            socket.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (base_url, server)
+    }
+
+    fn hello() -> ConversationContext {
+        let message = crate::models::ChatMessage {
+            role: "user".to_string(),
+            content: "Hi".to_string(),
+            metadata: None,
+        };
+        ConversationContext::new(None, vec![message], 1)
+    }
+
+    #[tokio::test]
+    async fn test_streamed_completion_yields_deltas_as_they_arrive() {
+        use futures::StreamExt;
+
+        let events = [
+            r#"{"choices":[{"delta":{"role":"assistant"}}]}"#,
+            r#"{"choices":[{"delta":{"content":"Hel"}}]}"#,
+            r#"{"choices":[{"delta":{"content":"lo!"}}]}"#,
+            "[DONE]",
+        ];
+        let body: String = events.iter().map(|event| format!("data: {}\n\n", event)).collect();
+        let (base_url, server) = serve_once(format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\r\n{}",
+            body
+        ))
+        .await;
+
+        let backend = OpenAiBackend::new(&base_url, "key", "gpt-test").unwrap();
+        let deltas = backend
+            .generate_stream(&hello(), &GenerationParams::default())
+            .await
+            .unwrap()
+            .expect("the OpenAI backend streams");
+        let deltas: Vec<String> = deltas.map(Result::unwrap).collect().await;
+        assert_eq!(deltas, ["Hel", "lo!"]);
+        assert!(server.await.unwrap().contains("\"stream\":true"));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_service_credentials_are_not_reported_to_the_client() {
+        use actix_web::ResponseError;
+
+        let body = r#"{"error": {"message": "Incorrect API key provided: sk-abc123"}}"#;
+        let (base_url, _server) = serve_once(format!(
+            "HTTP/1.1 401 Unauthorized\r\ncontent-length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ))
+        .await;
+
+        let backend = OpenAiBackend::new(&base_url, "sk-abc123", "gpt-test").unwrap();
+        let err = backend.generate(&hello(), &GenerationParams::default()).await.unwrap_err();
+        assert!(matches!(err, ChatbotError::ConnectionError(_)));
+        assert!(!err.to_string().contains("sk-abc123"));
+        assert_eq!(err.status_code(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,84 @@
+use async_trait::async_trait;
+use futures::stream;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use super::{Generation, GenerationParams, LlmBackend};
+use crate::errors::ChatbotError;
+use crate::models::ModelInfo;
+use crate::sessions::ConversationContext;
+use crate::streaming::{DeltaStream, split_tokens};
+
+/// Deterministic backend that needs no model weights, for tests and local development.
+///
+/// Queued replies are returned in order; once they run out it answers `Echo: <message>`.
+/// Streamed replies are sent a word at a time. The contexts it was called with are kept
+/// for inspection.
+pub struct ScriptedBackend {
+    model: String,
+    replies: Mutex<VecDeque<Result<Generation, ChatbotError>>>,
+    calls: Mutex<Vec<ConversationContext>>,
+}
+
+impl ScriptedBackend {
+    pub fn new() -> Self {
+        Self {
+            model: "mock".to_string(),
+            replies: Mutex::new(VecDeque::new()),
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Queues a reply, e.g. one with log-probabilities or a backend error.
+    pub fn with_reply(self, reply: Result<Generation, ChatbotError>) -> Self {
+        self.replies.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push_back(reply);
+        self
+    }
+
+    /// Contexts passed to `generate` so far, oldest first.
+    pub fn calls(&self) -> Vec<ConversationContext> {
+        self.calls.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+}
+
+impl Default for ScriptedBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl LlmBackend for ScriptedBackend {
+    async fn generate(
+        &self,
+        context: &ConversationContext,
+        _params: &GenerationParams,
+    ) -> Result<Generation, ChatbotError> {
+        self.calls.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(context.clone());
+        let reply =
+            self.replies.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).pop_front();
+        reply.unwrap_or_else(|| {
+            let message = context.messages.last().map(|m| m.content.as_str()).unwrap_or_default();
+            Ok(Generation::text(format!("Echo: {}", message)))
+        })
+    }
+
+    async fn generate_stream(
+        &self,
+        context: &ConversationContext,
+        params: &GenerationParams,
+    ) -> Result<Option<DeltaStream>, ChatbotError> {
+        let generation = self.generate(context, params).await?;
+        let deltas = split_tokens(&generation.text).into_iter().map(Ok);
+        Ok(Some(Box::pin(stream::iter(deltas))))
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo {
+            backend: "scripted".to_string(),
+            model: self.model.clone(),
+            max_tokens: None,
+            supports_confidence: false,
+        }
+    }
+}
@@ -1,7 +1,11 @@
 use crate::errors::ChatError;
-use crate::services::HuggingFaceConfig;
+use crate::i18n::{DEFAULT_LOCALE, DEFAULT_LOCALES_DIR};
+use crate::models::{ModerationAction, ModerationStage};
+use crate::services::{DEFAULT_MAX_TOOL_ITERATIONS, HuggingFaceConfig};
+use common::config::{AuthConfig, JwtAlgorithm};
 use serde::Deserialize;
 use std::env;
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ServiceConfig {
@@ -15,18 +19,73 @@ pub struct ServiceConfig {
     pub model_config: ModelConfig,
     /// Rate limiting configuration
     pub rate_limit: RateLimitConfig,
+    /// Conversation session configuration
+    pub sessions: SessionConfig,
+    /// Message catalogs and prompt templates
+    #[serde(default)]
+    pub locales: LocaleConfig,
+    /// Content moderation of requests and responses
+    #[serde(default)]
+    pub moderation: ModerationConfig,
+    /// Bearer tokens required on the API; callers name themselves with `user_id` when unset
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ModelConfig {
+    /// Which backend generates responses
+    #[serde(default)]
+    pub backend: BackendKind,
     /// HuggingFace model configuration
     pub huggingface: HuggingFaceConfig,
+    /// Model requested from the OpenAI-compatible API at `api_endpoint`
+    #[serde(default = "default_openai_model")]
+    pub openai_model: String,
+    /// Rounds of tool calls allowed before the model has to answer
+    #[serde(default = "default_max_tool_iterations")]
+    pub max_tool_iterations: usize,
     /// Whether to use streaming responses
     pub stream: bool,
     /// Cache configuration
     pub cache_config: CacheConfig,
 }
 
+fn default_openai_model() -> String {
+    "gpt-4o-mini".to_string()
+}
+
+fn default_max_tool_iterations() -> usize {
+    DEFAULT_MAX_TOOL_ITERATIONS
+}
+
+/// Language model backend of the chat service.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    /// rust_bert model running in process
+    #[default]
+    Local,
+    /// OpenAI-compatible chat-completions API
+    #[serde(rename = "openai")]
+    OpenAi,
+    /// Echoes messages back, for tests and local development
+    Scripted,
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "local" | "huggingface" => Ok(BackendKind::Local),
+            "openai" | "open_ai" => Ok(BackendKind::OpenAi),
+            "scripted" | "mock" => Ok(BackendKind::Scripted),
+            other => Err(format!("unknown model backend '{}'", other)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct CacheConfig {
     /// Whether to enable response caching
@@ -35,6 +94,10 @@ pub struct CacheConfig {
     pub ttl_seconds: u64,
     /// Maximum cache size in MB
     pub max_size_mb: u64,
+    /// Minimum cosine similarity for answering a question from a cached near-duplicate;
+    /// only exact repeats are answered when unset
+    #[serde(default)]
+    pub semantic_threshold: Option<f32>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -43,11 +106,222 @@ pub struct RateLimitConfig {
     pub requests_per_minute: u32,
     /// Maximum concurrent requests
     pub max_concurrent: u32,
+    /// Requests a client may make in a burst; defaults to `requests_per_minute`
+    #[serde(default)]
+    pub burst: Option<u32>,
+    /// What identifies a client
+    #[serde(default)]
+    pub key: RateLimitKey,
+    /// Keys accepted in the `X-API-Key` header when limits are keyed by API key
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    /// How requests are counted
+    #[serde(default)]
+    pub algorithm: RateLimitAlgorithm,
+    /// Redis URL to share limits across instances; limits are per instance when unset
+    #[serde(default)]
+    pub redis_url: Option<String>,
+}
+
+/// What a rate limit is keyed by. Requests without the chosen identity are rejected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    /// The subject of the authenticated JWT; needs `auth` to be configured
+    UserId,
+    /// The `X-API-Key` header, if it is one of `api_keys`
+    ApiKey,
+    /// The peer IP address
+    #[default]
+    Ip,
+}
+
+impl FromStr for RateLimitKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "user_id" | "user" => Ok(RateLimitKey::UserId),
+            "api_key" | "key" => Ok(RateLimitKey::ApiKey),
+            "ip" => Ok(RateLimitKey::Ip),
+            other => Err(format!("unknown rate limit key '{}'", other)),
+        }
+    }
+}
+
+/// How requests are counted against the limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitAlgorithm {
+    /// Allows bursts of up to `burst` requests, refilled at the per-minute rate
+    #[default]
+    TokenBucket,
+    /// Counts requests over a sliding one-minute window
+    SlidingWindow,
+}
+
+impl FromStr for RateLimitAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "token_bucket" => Ok(RateLimitAlgorithm::TokenBucket),
+            "sliding_window" => Ok(RateLimitAlgorithm::SlidingWindow),
+            other => Err(format!("unknown rate limit algorithm '{}'", other)),
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: 60,
+            max_concurrent: 10,
+            burst: None,
+            key: RateLimitKey::default(),
+            api_keys: Vec::new(),
+            algorithm: RateLimitAlgorithm::default(),
+            redis_url: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionConfig {
+    /// Maximum number of messages kept per session; older ones are dropped or summarised
+    pub max_messages: usize,
+    /// Sessions idle for longer than this are discarded
+    pub idle_ttl_seconds: u64,
+    /// Total tokens the model accepts, prompt and response together
+    pub context_window_tokens: usize,
+    /// Tokens kept free for the generated response
+    pub reserved_response_tokens: usize,
+    /// Tokens set aside for the summary of older turns when summarising
+    pub summary_tokens: usize,
+    /// What to do with older turns that no longer fit
+    pub overflow: ContextOverflow,
+}
+
+/// How older turns are handled once they no longer fit the context window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextOverflow {
+    /// Leave them out of the prompt
+    Truncate,
+    /// Fold them into a running summary that is sent instead
+    Summarize,
+}
+
+impl FromStr for ContextOverflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "truncate" => Ok(ContextOverflow::Truncate),
+            "summarize" | "summarise" => Ok(ContextOverflow::Summarize),
+            other => Err(format!("unknown context overflow strategy '{}'", other)),
+        }
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            max_messages: 50,
+            idle_ttl_seconds: 1800,
+            context_window_tokens: 1024,
+            reserved_response_tokens: 256,
+            summary_tokens: 128,
+            overflow: ContextOverflow::Truncate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LocaleConfig {
+    /// Directory holding one `<locale>.toml` catalog per locale
+    pub directory: String,
+    /// Locale used when a request asks for none of the loaded ones
+    pub default_locale: String,
+}
+
+impl Default for LocaleConfig {
+    fn default() -> Self {
+        Self {
+            directory: DEFAULT_LOCALES_DIR.to_string(),
+            default_locale: DEFAULT_LOCALE.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModerationConfig {
+    /// Whether messages and responses are moderated at all
+    pub enabled: bool,
+    /// Rules applied in order; later rules see the redactions of earlier ones
+    pub rules: Vec<ModerationRuleConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModerationRuleConfig {
+    /// Reported in moderation verdicts and logs
+    pub name: String,
+    /// What the rule matches
+    #[serde(flatten)]
+    pub detector: DetectorConfig,
+    /// What happens to matched content
+    pub action: ModerationAction,
+    /// Stage the rule is limited to; applied to both when omitted
+    #[serde(default)]
+    pub stage: Option<ModerationStage>,
+    /// Text substituted for redacted matches
+    #[serde(default)]
+    pub replacement: Option<String>,
+}
+
+/// Kinds of content a moderation rule can match.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "detector", rename_all = "snake_case")]
+pub enum DetectorConfig {
+    /// Whole words or phrases, ignoring case
+    Blocklist { terms: Vec<String> },
+    /// A regular expression
+    Regex { pattern: String },
+    /// Email addresses
+    Email,
+    /// Phone numbers of 7 to 15 digits
+    Phone,
+    /// Payment card numbers passing the Luhn check
+    CardNumber,
+}
+
+impl ModerationRuleConfig {
+    pub fn new(name: &str, detector: DetectorConfig, action: ModerationAction) -> Self {
+        Self { name: name.to_string(), detector, action, stage: None, replacement: None }
+    }
+}
+
+impl Default for ModerationConfig {
+    /// Redacts personal data in both directions.
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rules: vec![
+                ModerationRuleConfig::new(
+                    "card_number",
+                    DetectorConfig::CardNumber,
+                    ModerationAction::Redact,
+                ),
+                ModerationRuleConfig::new("email", DetectorConfig::Email, ModerationAction::Redact),
+                ModerationRuleConfig::new("phone", DetectorConfig::Phone, ModerationAction::Redact),
+            ],
+        }
+    }
 }
 
 impl Default for CacheConfig {
     fn default() -> Self {
-        Self { enabled: true, ttl_seconds: 3600, max_size_mb: 1024 }
+        Self { enabled: true, ttl_seconds: 3600, max_size_mb: 1024, semantic_threshold: None }
     }
 }
 
@@ -94,12 +368,51 @@ impl ServiceConfig {
                 .map_err(|_| ChatError::ConfigError("AI_SERVICE_API_KEY not set".to_string()))?,
             org_id: env::var("AI_SERVICE_ORG_ID").ok(),
             model_config: ModelConfig {
+                backend: env::var("AI_BACKEND")
+                    .unwrap_or_else(|_| "local".to_string())
+                    .parse()
+                    .map_err(ChatError::ConfigError)?,
                 huggingface: huggingface_config,
+                openai_model: env::var("AI_OPENAI_MODEL")
+                    .unwrap_or_else(|_| default_openai_model()),
+                max_tool_iterations: env::var("AI_MAX_TOOL_ITERATIONS")
+                    .map_or(Ok(DEFAULT_MAX_TOOL_ITERATIONS), |value| value.parse())
+                    .map_err(|_| {
+                        ChatError::ConfigError("Invalid AI_MAX_TOOL_ITERATIONS".to_string())
+                    })?,
                 stream: env::var("AI_STREAM_ENABLED")
                     .unwrap_or_else(|_| "false".to_string())
                     .parse()
                     .map_err(|_| ChatError::ConfigError("Invalid AI_STREAM_ENABLED".to_string()))?,
-                cache_config: CacheConfig::default(),
+                cache_config: CacheConfig {
+                    enabled: env::var("AI_CACHE_ENABLED")
+                        .unwrap_or_else(|_| "true".to_string())
+                        .parse()
+                        .map_err(|_| {
+                            ChatError::ConfigError("Invalid AI_CACHE_ENABLED".to_string())
+                        })?,
+                    ttl_seconds: env::var("AI_CACHE_TTL_SECONDS")
+                        .unwrap_or_else(|_| "3600".to_string())
+                        .parse()
+                        .map_err(|_| {
+                            ChatError::ConfigError("Invalid AI_CACHE_TTL_SECONDS".to_string())
+                        })?,
+                    max_size_mb: env::var("AI_CACHE_MAX_SIZE_MB")
+                        .unwrap_or_else(|_| "1024".to_string())
+                        .parse()
+                        .map_err(|_| {
+                            ChatError::ConfigError("Invalid AI_CACHE_MAX_SIZE_MB".to_string())
+                        })?,
+                    semantic_threshold: env::var("AI_CACHE_SEMANTIC_THRESHOLD")
+                        .ok()
+                        .map(|threshold| threshold.parse())
+                        .transpose()
+                        .map_err(|_| {
+                            ChatError::ConfigError(
+                                "Invalid AI_CACHE_SEMANTIC_THRESHOLD".to_string(),
+                            )
+                        })?,
+                },
             },
             rate_limit: RateLimitConfig {
                 requests_per_minute: env::var("AI_SERVICE_RATE_LIMIT")
@@ -114,7 +427,136 @@ impl ServiceConfig {
                     .map_err(|_| {
                         ChatError::ConfigError("Invalid AI_SERVICE_MAX_CONCURRENT".to_string())
                     })?,
+                burst: env::var("AI_RATE_LIMIT_BURST")
+                    .ok()
+                    .map(|burst| burst.parse())
+                    .transpose()
+                    .map_err(|_| {
+                        ChatError::ConfigError("Invalid AI_RATE_LIMIT_BURST".to_string())
+                    })?,
+                key: env::var("AI_RATE_LIMIT_KEY")
+                    .unwrap_or_else(|_| "ip".to_string())
+                    .parse()
+                    .map_err(ChatError::ConfigError)?,
+                api_keys: env::var("AI_RATE_LIMIT_API_KEYS")
+                    .map(|keys| {
+                        keys.split(',')
+                            .map(str::trim)
+                            .filter(|key| !key.is_empty())
+                            .map(str::to_string)
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                algorithm: env::var("AI_RATE_LIMIT_ALGORITHM")
+                    .unwrap_or_else(|_| "token_bucket".to_string())
+                    .parse()
+                    .map_err(ChatError::ConfigError)?,
+                redis_url: env::var("AI_RATE_LIMIT_REDIS_URL").ok(),
             },
+            sessions: SessionConfig {
+                max_messages: env::var("AI_SESSION_MAX_MESSAGES")
+                    .unwrap_or_else(|_| "50".to_string())
+                    .parse()
+                    .map_err(|_| {
+                        ChatError::ConfigError("Invalid AI_SESSION_MAX_MESSAGES".to_string())
+                    })?,
+                idle_ttl_seconds: env::var("AI_SESSION_IDLE_TTL_SECONDS")
+                    .unwrap_or_else(|_| "1800".to_string())
+                    .parse()
+                    .map_err(|_| {
+                        ChatError::ConfigError("Invalid AI_SESSION_IDLE_TTL_SECONDS".to_string())
+                    })?,
+                context_window_tokens: env::var("AI_CONTEXT_WINDOW_TOKENS")
+                    .unwrap_or_else(|_| "1024".to_string())
+                    .parse()
+                    .map_err(|_| {
+                        ChatError::ConfigError("Invalid AI_CONTEXT_WINDOW_TOKENS".to_string())
+                    })?,
+                reserved_response_tokens: env::var("AI_RESERVED_RESPONSE_TOKENS")
+                    .unwrap_or_else(|_| "256".to_string())
+                    .parse()
+                    .map_err(|_| {
+                        ChatError::ConfigError("Invalid AI_RESERVED_RESPONSE_TOKENS".to_string())
+                    })?,
+                summary_tokens: env::var("AI_SUMMARY_TOKENS")
+                    .unwrap_or_else(|_| "128".to_string())
+                    .parse()
+                    .map_err(|_| ChatError::ConfigError("Invalid AI_SUMMARY_TOKENS".to_string()))?,
+                overflow: env::var("AI_CONTEXT_OVERFLOW")
+                    .unwrap_or_else(|_| "truncate".to_string())
+                    .parse()
+                    .map_err(ChatError::ConfigError)?,
+            },
+            locales: LocaleConfig {
+                directory: env::var("AI_LOCALES_DIR")
+                    .unwrap_or_else(|_| DEFAULT_LOCALES_DIR.to_string()),
+                default_locale: env::var("AI_DEFAULT_LOCALE")
+                    .unwrap_or_else(|_| DEFAULT_LOCALE.to_string()),
+            },
+            moderation: ModerationConfig::from_env()?,
+            auth: auth_from_env()?,
         })
     }
 }
+
+/// Token validation settings, if `AI_JWT_SECRET` or `AI_JWKS_PATH` is set.
+fn auth_from_env() -> Result<Option<AuthConfig>, ChatError> {
+    let jwt_secret = env::var("AI_JWT_SECRET").ok();
+    let jwks_path = env::var("AI_JWKS_PATH").ok();
+    if jwt_secret.is_none() && jwks_path.is_none() {
+        return Ok(None);
+    }
+    let algorithm = match env::var("AI_JWT_ALGORITHM").as_deref() {
+        Err(_) | Ok("HS256") => JwtAlgorithm::HS256,
+        Ok("RS256") => JwtAlgorithm::RS256,
+        Ok("ES256") => JwtAlgorithm::ES256,
+        Ok(other) => {
+            return Err(ChatError::ConfigError(format!("unknown JWT algorithm '{}'", other)));
+        }
+    };
+    Ok(Some(AuthConfig {
+        jwt_secret: jwt_secret.unwrap_or_default(),
+        token_expiration_hours: 24,
+        algorithm,
+        issuer: env::var("AI_JWT_ISSUER").ok(),
+        audience: env::var("AI_JWT_AUDIENCE").ok(),
+        leeway_seconds: env::var("AI_JWT_LEEWAY_SECONDS")
+            .unwrap_or_else(|_| "60".to_string())
+            .parse()
+            .map_err(|_| ChatError::ConfigError("Invalid AI_JWT_LEEWAY_SECONDS".to_string()))?,
+        jwks_path,
+    }))
+}
+
+impl ModerationConfig {
+    /// The default rules, plus a blocking rule for the comma-separated terms of
+    /// `AI_MODERATION_BLOCKLIST`.
+    fn from_env() -> Result<Self, ChatError> {
+        let mut config = ModerationConfig {
+            enabled: env::var("AI_MODERATION_ENABLED")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .map_err(|_| ChatError::ConfigError("Invalid AI_MODERATION_ENABLED".to_string()))?,
+            ..ModerationConfig::default()
+        };
+        if let Ok(blocklist) = env::var("AI_MODERATION_BLOCKLIST") {
+            let terms: Vec<String> = blocklist
+                .split(',')
+                .map(str::trim)
+                .filter(|term| !term.is_empty())
+                .map(str::to_string)
+                .collect();
+            if !terms.is_empty() {
+                config.rules.insert(
+                    0,
+                    ModerationRuleConfig::new(
+                        "blocklist",
+                        DetectorConfig::Blocklist { terms },
+                        ModerationAction::Block,
+                    ),
+                );
+            }
+        }
+        Ok(config)
+    }
+}
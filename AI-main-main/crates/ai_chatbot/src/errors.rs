@@ -1,9 +1,12 @@
 use actix_web::{HttpResponse, error::ResponseError, http::StatusCode};
-use serde_json::json;
+use common::errors::{ApiError, ErrorCode};
 use shared::SharedError;
 use std::io;
 use thiserror::Error;
 
+use crate::i18n::Catalog;
+use crate::models::ModerationStage;
+
 #[derive(Error, Debug)]
 pub enum ChatbotError {
     // Model-related errors
@@ -16,8 +19,17 @@ pub enum ChatbotError {
     #[error("Model not loaded")]
     ModelNotLoaded,
 
-    #[error("Context window exceeded")]
-    ContextWindowExceeded,
+    #[error("Context window exceeded: {tokens} tokens needed, {limit} available")]
+    ContextWindowExceeded { tokens: usize, limit: usize },
+
+    #[error("Session not found: {0}")]
+    SessionNotFound(String),
+
+    #[error("Session already exists: {0}")]
+    SessionExists(String),
+
+    #[error("Content blocked by moderation rule {rule} ({stage})")]
+    ContentBlocked { stage: ModerationStage, rule: String },
 
     // Request/Response errors
     #[error("Invalid request: {0}")]
@@ -72,56 +84,104 @@ pub enum ChatbotError {
     Shared(#[from] SharedError),
 }
 
-impl ResponseError for ChatbotError {
-    fn error_response(&self) -> HttpResponse {
-        let (status, error_type) = match self {
-            // 400 Bad Request
-            Self::InvalidRequest(_) | Self::InvalidInput(_) => {
-                (StatusCode::BAD_REQUEST, "BAD_REQUEST")
+impl From<&ChatbotError> for ApiError {
+    fn from(err: &ChatbotError) -> Self {
+        use ChatbotError::*;
+
+        match err {
+            InvalidRequest(msg) | InvalidInput(msg) => ApiError::bad_request(msg.clone()),
+            AuthError(msg) => ApiError::unauthorized(msg.clone()),
+            RateLimitExceeded => ApiError::new(ErrorCode::RateLimited, err.to_string()),
+            SessionNotFound(_) => ApiError::not_found(err.to_string()),
+            SessionExists(_) => ApiError::new(ErrorCode::Conflict, err.to_string()),
+            ModelError(_) | ContextWindowExceeded { .. } | ContentBlocked { .. } => {
+                ApiError::new(ErrorCode::UnprocessableEntity, err.to_string())
+            }
+            ModelNotLoaded | ModelInitializationError(_) | ConnectionError(_) => {
+                ApiError::new(ErrorCode::ServiceUnavailable, "The model is not available")
+                    .with_cause(format!("Chatbot unavailable: {}", err))
             }
+            Shared(shared) => shared.into(),
+            ConfigError(_)
+            | EmptyResponse
+            | NetworkError(_)
+            | CacheError(_)
+            | IoError(_)
+            | SerializationError(_)
+            | InternalError(_)
+            | Unknown(_)
+            | Other(_) => ApiError::internal(err),
+        }
+    }
+}
 
-            // 401 Unauthorized
-            Self::AuthError(_) => (StatusCode::UNAUTHORIZED, "UNAUTHORIZED"),
+impl From<ChatbotError> for ApiError {
+    fn from(err: ChatbotError) -> Self {
+        ApiError::from(&err)
+    }
+}
 
-            // 429 Too Many Requests
-            Self::RateLimitExceeded => (StatusCode::TOO_MANY_REQUESTS, "RATE_LIMIT_EXCEEDED"),
+impl ChatbotError {
+    /// Catalog key of the client-facing message, with its placeholder values.
+    ///
+    /// Errors whose message would leak internals share the generic `error_generic` text.
+    pub fn message_key(&self) -> Option<(&'static str, Vec<(&'static str, String)>)> {
+        use ChatbotError::*;
 
-            // 422 Unprocessable Entity
-            Self::ModelError(_) | Self::ConfigError(_) | Self::ContextWindowExceeded => {
-                (StatusCode::UNPROCESSABLE_ENTITY, "UNPROCESSABLE_ENTITY")
+        Some(match self {
+            InvalidRequest(detail) | InvalidInput(detail) => {
+                ("errors.invalid_request", vec![("detail", detail.clone())])
             }
-
-            // 503 Service Unavailable
-            Self::ConnectionError(_) | Self::ModelNotLoaded | Self::ModelInitializationError(_) => {
-                (StatusCode::SERVICE_UNAVAILABLE, "SERVICE_UNAVAILABLE")
+            AuthError(_) => ("errors.unauthorized", Vec::new()),
+            RateLimitExceeded => ("errors.rate_limit_exceeded", Vec::new()),
+            SessionNotFound(id) => ("errors.session_not_found", vec![("session_id", id.clone())]),
+            SessionExists(id) => ("errors.session_exists", vec![("session_id", id.clone())]),
+            ContextWindowExceeded { tokens, limit } => (
+                "errors.context_window_exceeded",
+                vec![("tokens", tokens.to_string()), ("limit", limit.to_string())],
+            ),
+            ModelError(detail) => ("errors.model_error", vec![("detail", detail.clone())]),
+            ContentBlocked { stage: ModerationStage::Input, .. } => {
+                ("errors.input_blocked", Vec::new())
             }
-
-            // 500 Internal Server Error
-            _ => (StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_SERVER_ERROR"),
-        };
-
-        HttpResponse::build(status).json(json!({
-            "error": {
-                "message": self.to_string(),
-                "type": error_type,
-                "details": format!("{:?}", self)
+            ContentBlocked { stage: ModerationStage::Output, .. } => {
+                ("errors.output_blocked", Vec::new())
             }
-        }))
+            ModelNotLoaded | ModelInitializationError(_) | ConnectionError(_) => {
+                ("errors.model_unavailable", Vec::new())
+            }
+            Shared(_) => return None,
+            ConfigError(_)
+            | EmptyResponse
+            | NetworkError(_)
+            | CacheError(_)
+            | IoError(_)
+            | SerializationError(_)
+            | InternalError(_)
+            | Unknown(_)
+            | Other(_) => ("error_generic", Vec::new()),
+        })
     }
 
-    fn status_code(&self) -> StatusCode {
-        match self {
-            Self::InvalidRequest(_) | Self::InvalidInput(_) => StatusCode::BAD_REQUEST,
-            Self::AuthError(_) => StatusCode::UNAUTHORIZED,
-            Self::RateLimitExceeded => StatusCode::TOO_MANY_REQUESTS,
-            Self::ModelError(_) | Self::ConfigError(_) | Self::ContextWindowExceeded => {
-                StatusCode::UNPROCESSABLE_ENTITY
+    /// The API error with its message translated into `locale` where the catalog has one.
+    pub fn localized(&self, catalog: &Catalog, locale: &str) -> ApiError {
+        let mut api = ApiError::from(self);
+        if let Some((key, args)) = self.message_key() {
+            if let Some(message) = catalog.format(locale, key, &args) {
+                api.message = message;
             }
-            Self::ConnectionError(_) | Self::ModelNotLoaded | Self::ModelInitializationError(_) => {
-                StatusCode::SERVICE_UNAVAILABLE
-            }
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
+        api
+    }
+}
+
+impl ResponseError for ChatbotError {
+    fn error_response(&self) -> HttpResponse {
+        ApiError::from(self).error_response()
+    }
+
+    fn status_code(&self) -> StatusCode {
+        ApiError::from(self).status_code()
     }
 }
 
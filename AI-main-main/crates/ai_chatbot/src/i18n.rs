@@ -0,0 +1,260 @@
+//! Module: Localisation
+//! Loads per-locale message catalogs and negotiates the locale of a request.
+//!
+//! Each `<locale>.toml` file in the locales directory is one catalog. Nested tables are
+//! flattened into dotted keys, so `[errors] rate_limit_exceeded = "..."` is looked up as
+//! `errors.rate_limit_exceeded`. Lookups walk a fallback chain from the most specific tag
+//! to the default locale: `pt-BR`, then `pt`, then `en`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tracing::debug;
+
+use crate::errors::ChatbotError;
+
+pub const DEFAULT_LOCALE: &str = "en";
+pub const DEFAULT_LOCALES_DIR: &str = "locales";
+
+/// Messages of all loaded locales.
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    bundles: HashMap<String, HashMap<String, String>>,
+    default_locale: String,
+}
+
+impl Catalog {
+    /// An empty catalog; every lookup misses.
+    pub fn new(default_locale: &str) -> Self {
+        Self { bundles: HashMap::new(), default_locale: normalize_tag(default_locale) }
+    }
+
+    /// Loads every `*.toml` file in `dir`, named after its locale.
+    pub fn load_dir(dir: impl AsRef<Path>, default_locale: &str) -> Result<Self, ChatbotError> {
+        let dir = dir.as_ref();
+        let mut catalog = Self::new(default_locale);
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+                continue;
+            }
+            let Some(locale) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            catalog.add_toml(locale, &fs::read_to_string(&path)?).map_err(|e| {
+                ChatbotError::ConfigError(format!("Invalid locale file {}: {}", path.display(), e))
+            })?;
+        }
+        debug!("Loaded locales {:?} from {}", catalog.locales(), dir.display());
+        Ok(catalog)
+    }
+
+    /// Adds the messages of a TOML document to `locale`, replacing existing keys.
+    pub fn add_toml(&mut self, locale: &str, source: &str) -> Result<(), ChatbotError> {
+        let table: toml::Table = source
+            .parse()
+            .map_err(|e: toml::de::Error| ChatbotError::ConfigError(e.to_string()))?;
+        let bundle = self.bundles.entry(normalize_tag(locale)).or_default();
+        flatten("", &table, bundle);
+        Ok(())
+    }
+
+    pub fn default_locale(&self) -> &str {
+        &self.default_locale
+    }
+
+    /// Loaded locales, sorted.
+    pub fn locales(&self) -> Vec<&str> {
+        let mut locales: Vec<_> = self.bundles.keys().map(String::as_str).collect();
+        locales.sort_unstable();
+        locales
+    }
+
+    /// Locales consulted for `locale`, most specific first and ending with the default.
+    pub fn fallback_chain(&self, locale: &str) -> Vec<String> {
+        let mut chain = subtag_chain(locale);
+        if !chain.contains(&self.default_locale) {
+            chain.push(self.default_locale.clone());
+        }
+        chain
+    }
+
+    /// Looks `key` up along the fallback chain of `locale`.
+    pub fn get(&self, locale: &str, key: &str) -> Option<&str> {
+        self.fallback_chain(locale)
+            .iter()
+            .find_map(|tag| self.bundles.get(tag).and_then(|bundle| bundle.get(key)))
+            .map(String::as_str)
+    }
+
+    /// Looks `key` up and fills in its `{name}` placeholders from `args`.
+    ///
+    /// Placeholders without an argument are left as they are.
+    pub fn format(&self, locale: &str, key: &str, args: &[(&str, String)]) -> Option<String> {
+        let text = self.get(locale, key)?;
+        Some(interpolate(text, |name| {
+            args.iter().find(|(arg, _)| *arg == name).map(|(_, value)| value.clone())
+        }))
+    }
+
+    /// Picks the locale of a request: the explicitly requested one if it is available,
+    /// then the `Accept-Language` preferences in order, then the default locale.
+    ///
+    /// A tag matches if it or one of its parents was loaded, so `de-AT` selects `de`.
+    pub fn negotiate(&self, requested: Option<&str>, accept_language: Option<&str>) -> String {
+        requested
+            .map(str::to_string)
+            .into_iter()
+            .chain(accept_language.map(parse_accept_language).unwrap_or_default())
+            .find_map(|candidate| {
+                subtag_chain(&candidate).into_iter().find(|tag| self.bundles.contains_key(tag))
+            })
+            .unwrap_or_else(|| self.default_locale.clone())
+    }
+}
+
+/// Lowercases a language tag and uses `-` as the separator.
+fn normalize_tag(tag: &str) -> String {
+    tag.trim().replace('_', "-").to_ascii_lowercase()
+}
+
+/// `pt-br` → `[pt-br, pt]`.
+fn subtag_chain(locale: &str) -> Vec<String> {
+    let tag = normalize_tag(locale);
+    let mut chain = Vec::new();
+    let mut current = tag.as_str();
+    while !current.is_empty() {
+        chain.push(current.to_string());
+        current = current.rfind('-').map_or("", |idx| &current[..idx]);
+    }
+    chain
+}
+
+/// Language tags of an `Accept-Language` header, most preferred first.
+///
+/// Wildcards and tags with `q=0` are dropped.
+pub fn parse_accept_language(header: &str) -> Vec<String> {
+    let mut tags: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (!tag.is_empty() && tag != "*" && quality > 0.0).then(|| (normalize_tag(tag), quality))
+        })
+        .collect();
+    // Stable, so tags of equal quality keep their order.
+    tags.sort_by(|a, b| b.1.total_cmp(&a.1));
+    tags.into_iter().map(|(tag, _)| tag).collect()
+}
+
+fn flatten(prefix: &str, table: &toml::Table, bundle: &mut HashMap<String, String>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match value {
+            toml::Value::Table(table) => flatten(&key, table, bundle),
+            toml::Value::String(text) => {
+                bundle.insert(key, text.clone());
+            }
+            other => {
+                bundle.insert(key, other.to_string());
+            }
+        }
+    }
+}
+
+/// Replaces `{name}` placeholders with `lookup(name)`, leaving unknown ones untouched.
+pub(crate) fn interpolate(text: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let name = after.find('}').map(|end| &after[..end]).filter(|name| is_placeholder(name));
+        match name.and_then(|name| lookup(name).map(|value| (name, value))) {
+            Some((name, value)) => {
+                output.push_str(&value);
+                rest = &after[name.len() + 1..];
+            }
+            None => {
+                output.push('{');
+                rest = after;
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Placeholder names in `text`, in order of appearance.
+pub(crate) fn placeholders(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        if let Some(name) =
+            rest.find('}').map(|end| &rest[..end]).filter(|name| is_placeholder(name))
+        {
+            names.push(name);
+        }
+    }
+    names
+}
+
+fn is_placeholder(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> Catalog {
+        let mut catalog = Catalog::new("en");
+        catalog
+            .add_toml(
+                "en",
+                "greeting = \"Hello\"\n[errors]\nsession_not_found = \"No session {id}\"",
+            )
+            .unwrap();
+        catalog.add_toml("pt", "greeting = \"Olá\"").unwrap();
+        catalog.add_toml("pt-BR", "greeting = \"Oi\"").unwrap();
+        catalog
+    }
+
+    #[test]
+    fn test_lookups_fall_back_to_parent_and_default_locales() {
+        let catalog = catalog();
+        assert_eq!(catalog.fallback_chain("pt_BR"), ["pt-br", "pt", "en"]);
+        assert_eq!(catalog.get("pt-BR", "greeting"), Some("Oi"));
+        assert_eq!(catalog.get("pt-PT", "greeting"), Some("Olá"));
+        assert_eq!(catalog.get("fr", "greeting"), Some("Hello"));
+        assert_eq!(
+            catalog.format("pt", "errors.session_not_found", &[("id", "s1".to_string())]),
+            Some("No session s1".to_string())
+        );
+        assert_eq!(catalog.get("en", "missing"), None);
+    }
+
+    #[test]
+    fn test_negotiation_prefers_the_request_then_accept_language() {
+        let catalog = catalog();
+        let header = "fr-CH, pt-PT;q=0.8, en;q=0.9, *;q=0.5";
+        assert_eq!(parse_accept_language(header), ["fr-ch", "en", "pt-pt"]);
+        assert_eq!(catalog.negotiate(None, Some(header)), "en");
+        assert_eq!(catalog.negotiate(None, Some("de, pt-PT;q=0.5")), "pt");
+        assert_eq!(catalog.negotiate(Some("pt-BR"), Some("en")), "pt-br");
+        assert_eq!(catalog.negotiate(Some("xx"), None), "en");
+    }
+
+    #[test]
+    fn test_interpolation_leaves_unknown_placeholders() {
+        let text = "{greeting}, {name}! {not a placeholder} {}";
+        let filled = interpolate(text, |name| (name == "greeting").then(|| "Hi".to_string()));
+        assert_eq!(filled, "Hi, {name}! {not a placeholder} {}");
+        assert_eq!(placeholders(text), ["greeting", "name"]);
+    }
+}
@@ -1,11 +1,21 @@
 pub mod api;
+pub mod backends;
 pub mod config;
 pub mod errors;
+pub mod i18n;
 pub mod models;
+pub mod moderation;
+pub mod prompts;
+pub mod rate_limit;
+pub mod response_cache;
 pub mod services;
+pub mod sessions;
+pub mod streaming;
+pub mod tools;
+pub mod transcripts;
 
 // Re-export commonly used items
 pub use api::ChatEndpoint;
 pub use errors::ChatError;
-pub use models::{ChatMessage, ChatResponse, ResponseMetadata};
+pub use models::{ChatMessage, ChatResponse, ModelInfo, ResponseMetadata, StreamEvent};
 pub use services::ChatService;
@@ -1,15 +1,29 @@
 use actix_web::{App, HttpServer, web};
-use tracing::{Level, info};
+use common::auth::JwtAuthenticator;
+use common::middleware::auth::JwtAuth;
+use metrics::{MetricsRegistry, metrics_handler};
+use std::sync::Arc;
+use tracing::{Level, error, info, warn};
 use tracing_subscriber::FmtSubscriber;
 
 mod api;
+mod backends;
 mod config;
 mod data_processing;
 mod errors;
 mod handlers;
+mod i18n;
 mod middleware;
 mod models;
+mod moderation;
+mod prompts;
+mod rate_limit;
+mod response_cache;
 mod services;
+mod sessions;
+mod streaming;
+mod tools;
+mod transcripts;
 mod utils;
 mod validators;
 
@@ -27,23 +41,70 @@ async fn main() -> std::io::Result<()> {
 
     info!("Starting AI Chatbot service...");
 
+    let service_config = config::ServiceConfig::from_env();
+    if let Err(e) = &service_config {
+        warn!("Using the default configuration: {}", e);
+    }
+
+    let registry =
+        Arc::new(MetricsRegistry::new("chatbot").expect("Failed to create metrics registry"));
+
     // Initialize application state
-    let app_state =
-        handlers::AppState::new().await.expect("Failed to initialize application state");
+    let app_state = match &service_config {
+        Ok(config) => handlers::AppState::from_config(config, &registry).await,
+        Err(_) => handlers::AppState::new().await,
+    }
+    .expect("Failed to initialize application state");
     let app_data = web::Data::new(app_state);
+    let registry_data = web::Data::from(registry);
+
+    // Discard idle conversation sessions in the background
+    let sessions = app_data.sessions().clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            let purged = sessions.purge_expired().await;
+            if purged > 0 {
+                info!("Purged {} idle sessions", purged);
+            }
+        }
+    });
+
+    // Without auth configured, callers name themselves with the `user_id` parameter
+    let auth = service_config.as_ref().ok().and_then(|c| c.auth.as_ref()).map(|config| {
+        let authenticator =
+            JwtAuthenticator::from_config(config).expect("Failed to initialize authentication");
+        JwtAuth::new(Arc::new(authenticator))
+    });
+    if auth.is_none() {
+        warn!("Authentication is disabled; sessions are keyed by the caller's user_id");
+    }
+
+    // Rate limits are shared by all workers
+    let rate_limit_config = service_config.map(|c| c.rate_limit).unwrap_or_default();
+    if rate_limit_config.key == config::RateLimitKey::UserId && auth.is_none() {
+        error!("Rate limits are keyed by user ID without authentication; requests will fail");
+    }
+    let rate_limiter = rate_limit::RateLimiter::from_config(&rate_limit_config)
+        .expect("Failed to initialize rate limiter");
 
     // Start HTTP server
     HttpServer::new(move || {
-        App::new()
+        let app = App::new()
             .app_data(app_data.clone())
+            .app_data(registry_data.clone())
             .wrap(middleware::Logger::default())
             .wrap(middleware::Cors::default())
-            .service(
-                web::scope("/api/v1")
-                    .route("/chat", web::post().to(handlers::chat))
-                    .route("/health", web::get().to(handlers::health_check))
-                    .route("/model-info", web::get().to(handlers::model_info)),
-            )
+            // Probes reach the health check without a token
+            .route("/api/v1/health", web::get().to(handlers::health_check))
+            .route("/metrics", web::get().to(metrics_handler));
+        let api = web::scope("/api/v1").configure(api_routes).wrap(rate_limiter.clone());
+        // Outermost, so the rate limiter sees the verified claims
+        match &auth {
+            Some(auth) => app.service(api.wrap(auth.clone())),
+            None => app.service(api),
+        }
     })
     .bind("0.0.0.0:8080")?
     .workers(num_cpus::get())
@@ -51,6 +112,18 @@ async fn main() -> std::io::Result<()> {
     .await
 }
 
+fn api_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/chat", web::post().to(handlers::chat))
+        .route("/chat/stream", web::post().to(handlers::chat_stream))
+        .route("/chat/ws", web::get().to(handlers::chat_ws))
+        .route("/model-info", web::get().to(handlers::model_info))
+        .route("/sessions", web::get().to(handlers::list_sessions))
+        .route("/sessions/{session_id}", web::get().to(handlers::get_session))
+        .route("/sessions/{session_id}", web::delete().to(handlers::delete_session))
+        .route("/sessions/{session_id}/export", web::get().to(handlers::export_session))
+        .route("/sessions/{session_id}/import", web::post().to(handlers::import_session));
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2,6 +2,7 @@ use actix_web::{
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
     Error,
 };
+use common::errors::{CorrelationId as RequestCorrelationId, CORRELATION_ID_HEADER};
 use futures::future::{ok, Ready};
 use futures::Future;
 use std::{
@@ -50,14 +51,14 @@ where
 
     fn call(&self, mut req: ServiceRequest) -> Self::Future {
         // Check for existing Correlation ID
-        let correlation_id = if let Some(id) = req.headers().get("X-Correlation-ID") {
+        let correlation_id = if let Some(id) = req.headers().get(CORRELATION_ID_HEADER) {
             id.to_str().unwrap_or_default().to_string()
         } else {
             Uuid::new_v4().to_string()
         };
 
         // Insert Correlation ID into request extensions for access in handlers
-        req.extensions_mut().insert::<String>(correlation_id.clone());
+        req.extensions_mut().insert(RequestCorrelationId(correlation_id.clone()));
 
         // Clone Correlation ID for response header
         let correlation_id_clone = correlation_id.clone();
@@ -68,7 +69,7 @@ where
             let res = fut.await?;
             // Insert Correlation ID into response headers
             res.headers_mut().insert(
-                "X-Correlation-ID",
+                CORRELATION_ID_HEADER.parse().unwrap(),
                 correlation_id_clone.parse().unwrap(),
             );
             Ok(res)
@@ -2,7 +2,7 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 /// Represents a chat message in the conversation
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ChatMessage {
     /// The role of the message sender (e.g., "user", "assistant")
     pub role: String,
@@ -14,7 +14,7 @@ pub struct ChatMessage {
 }
 
 /// Metadata associated with a chat message
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MessageMetadata {
     /// When the message was created
     pub timestamp: DateTime<Utc>,
@@ -24,6 +24,9 @@ pub struct MessageMetadata {
     /// Optional session ID for tracking conversations
     #[serde(skip_serializing_if = "Option::is_none")]
     pub session_id: Option<String>,
+    /// Negotiated locale, selecting the system message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
 }
 
 /// Response from the chat service
@@ -47,6 +50,132 @@ pub struct ResponseMetadata {
     /// Optional confidence score (0.0 to 1.0)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub confidence: Option<f32>,
+    /// What content moderation found and did, if it ran
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moderation: Option<ModerationVerdict>,
+    /// Tools called while generating the response, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_invocations: Vec<ToolInvocation>,
+}
+
+/// A tool the model may call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    /// Tells the model what the tool does and when to use it
+    pub description: String,
+    /// JSON schema of the arguments object
+    pub parameters: serde_json::Value,
+}
+
+/// A call of a tool requested by the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// Identifier assigned by the model, echoed back with the result
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value,
+}
+
+/// A tool call and its outcome
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolInvocation {
+    pub call: ToolCall,
+    /// The tool's result; absent if the call failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<serde_json::Value>,
+    /// Why the call failed, as reported to the model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Time spent in the tool in milliseconds
+    pub duration_ms: u64,
+}
+
+impl ToolInvocation {
+    /// What the model is told about the outcome.
+    pub fn result_json(&self) -> serde_json::Value {
+        match (&self.output, &self.error) {
+            (_, Some(error)) => serde_json::json!({ "error": error }),
+            (Some(output), None) => output.clone(),
+            (None, None) => serde_json::Value::Null,
+        }
+    }
+}
+
+/// Where in the exchange a moderation rule is applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModerationStage {
+    /// The user's message, before generation
+    Input,
+    /// The generated response
+    Output,
+}
+
+impl ModerationStage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ModerationStage::Input => "input",
+            ModerationStage::Output => "output",
+        }
+    }
+}
+
+impl std::fmt::Display for ModerationStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What happens to content matched by a moderation rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModerationAction {
+    /// Keep the content and record the match
+    Flag,
+    /// Replace the matched text
+    Redact,
+    /// Reject the whole message
+    Block,
+}
+
+/// A rule that matched during moderation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModerationFinding {
+    /// Name of the rule
+    pub rule: String,
+    pub stage: ModerationStage,
+    pub action: ModerationAction,
+    /// Number of matches
+    pub matches: usize,
+}
+
+/// Outcome of moderating a request and its response
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModerationVerdict {
+    /// Rules that matched, in the order they were applied; empty if the exchange was clean
+    pub findings: Vec<ModerationFinding>,
+}
+
+impl ModerationVerdict {
+    /// The strictest action taken, if any rule matched
+    pub fn action(&self) -> Option<ModerationAction> {
+        self.findings.iter().map(|finding| finding.action).max()
+    }
+}
+
+/// Describes the model behind the chat service
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    /// Backend serving the model (e.g., "huggingface", "openai")
+    pub backend: String,
+    /// Name of the model
+    pub model: String,
+    /// Maximum generation length, if the backend has a fixed one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<usize>,
+    /// Whether responses carry a confidence derived from token log-probabilities
+    pub supports_confidence: bool,
 }
 
 /// Configuration for the chat service
@@ -61,3 +190,37 @@ pub struct ChatConfig {
     /// Whether to use streaming responses
     pub stream: bool,
 }
+
+/// One frame of a streamed chat response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    /// A piece of generated text, to be appended to what came before
+    Token { text: String },
+    /// Sent once after the last token
+    Done(StreamMetadata),
+}
+
+/// Metadata sent in the final frame of a stream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamMetadata {
+    /// Version of the model used
+    pub model_version: String,
+    /// Token counts of the prompt and the generated text
+    pub usage: Usage,
+    /// Time from the request to the first token in milliseconds
+    pub time_to_first_token_ms: u64,
+    /// Time from the request to the last token in milliseconds
+    pub latency_ms: u64,
+    /// What content moderation found and did, if it ran
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moderation: Option<ModerationVerdict>,
+}
+
+/// Token usage of a single generation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub total_tokens: usize,
+}
@@ -0,0 +1,222 @@
+use regex::Regex;
+use std::ops::Range;
+
+use crate::errors::ChatbotError;
+
+/// Finds content a moderation rule applies to.
+pub trait Detector: Send + Sync {
+    /// Byte ranges of the matches in `text`, in order and not overlapping.
+    fn find(&self, text: &str) -> Vec<Range<usize>>;
+}
+
+/// Matches a regular expression.
+pub struct RegexDetector {
+    regex: Regex,
+}
+
+impl RegexDetector {
+    pub fn new(pattern: &str) -> Result<Self, ChatbotError> {
+        let regex = Regex::new(pattern).map_err(|e| {
+            ChatbotError::ConfigError(format!("Invalid moderation pattern {}: {}", pattern, e))
+        })?;
+        Ok(Self { regex })
+    }
+
+    /// Matches any of `terms` as a whole word or phrase, ignoring case.
+    pub fn blocklist<S: AsRef<str>>(terms: &[S]) -> Result<Self, ChatbotError> {
+        let alternatives: Vec<_> = terms.iter().map(|term| regex::escape(term.as_ref())).collect();
+        if alternatives.is_empty() {
+            return Err(ChatbotError::ConfigError("Empty moderation blocklist".to_string()));
+        }
+        Self::new(&format!(r"(?i)\b(?:{})\b", alternatives.join("|")))
+    }
+}
+
+impl Detector for RegexDetector {
+    fn find(&self, text: &str) -> Vec<Range<usize>> {
+        self.regex.find_iter(text).map(|m| m.range()).collect()
+    }
+}
+
+/// Matches email addresses.
+pub struct EmailDetector(RegexDetector);
+
+impl EmailDetector {
+    pub fn new() -> Self {
+        let pattern = r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}\b";
+        Self(RegexDetector::new(pattern).expect("email pattern is valid"))
+    }
+}
+
+impl Default for EmailDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Detector for EmailDetector {
+    fn find(&self, text: &str) -> Vec<Range<usize>> {
+        self.0.find(text)
+    }
+}
+
+/// Matches digit sequences with the usual separators, filtered by their number of digits.
+struct DigitRunDetector {
+    candidates: Regex,
+    digits: Range<usize>,
+    /// Decides on a candidate given its text and its digits
+    accept: fn(&str, &[u32]) -> bool,
+}
+
+impl DigitRunDetector {
+    fn find(&self, text: &str) -> Vec<Range<usize>> {
+        self.candidates
+            .find_iter(text)
+            .filter(|m| {
+                let digits: Vec<u32> = m.as_str().chars().filter_map(|c| c.to_digit(10)).collect();
+                self.digits.contains(&digits.len()) && (self.accept)(m.as_str(), &digits)
+            })
+            .map(|m| m.range())
+            .collect()
+    }
+}
+
+/// Matches phone numbers of 7 to 15 digits.
+///
+/// Only numbers written like phone numbers match: with a `+` country code, a parenthesised
+/// area code, or in dash- or space-separated groups. Plain numbers, dates and numbers with
+/// thousands separators do not.
+pub struct PhoneDetector(DigitRunDetector);
+
+impl PhoneDetector {
+    pub fn new() -> Self {
+        let candidates = Regex::new(r"(?:\+|\()?\b\d(?:[\s.()-]{0,2}\d){6,14}\b")
+            .expect("phone pattern is valid");
+        Self(DigitRunDetector { candidates, digits: 7..16, accept: |text, _| phone_like(text) })
+    }
+}
+
+fn phone_like(candidate: &str) -> bool {
+    let groups = |text: &str| -> Vec<usize> {
+        text.split(|c: char| !c.is_ascii_digit())
+            .filter(|group| !group.is_empty())
+            .map(str::len)
+            .collect()
+    };
+    if candidate.contains(['(', ')']) {
+        // A closed area code, first or straight after the country code: (555) 123-4567
+        return match (candidate.find('('), candidate.find(')')) {
+            (Some(open), Some(close)) if open < close => {
+                let before = &candidate[..open];
+                groups(before).len() <= usize::from(before.starts_with('+'))
+                    && candidate.matches(['(', ')']).count() == 2
+            }
+            _ => false,
+        };
+    }
+    if candidate.starts_with('+') {
+        return true;
+    }
+    let groups = groups(candidate);
+    let dotted = candidate.contains('.');
+    match groups.as_slice() {
+        // A bare number, or a decimal such as 3.14159265
+        [_] => false,
+        [_, _] if dotted => false,
+        // Dates: 2024-01-15, 15.01.2024
+        [4, 2, 2] | [2, 2, 4] => false,
+        // Thousands separators: 1.234.567, 12 345 678
+        [first, rest @ ..] if *first <= 3 && rest.iter().all(|&len| len == 3) => false,
+        _ => true,
+    }
+}
+
+impl Default for PhoneDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Detector for PhoneDetector {
+    fn find(&self, text: &str) -> Vec<Range<usize>> {
+        self.0.find(text)
+    }
+}
+
+/// Matches payment card numbers of 13 to 19 digits that pass the Luhn check.
+pub struct CardNumberDetector(DigitRunDetector);
+
+impl CardNumberDetector {
+    pub fn new() -> Self {
+        let candidates =
+            Regex::new(r"\b\d(?:[ -]?\d){12,18}\b").expect("card number pattern is valid");
+        Self(DigitRunDetector {
+            candidates,
+            digits: 13..20,
+            accept: |_, digits| luhn_valid(digits),
+        })
+    }
+}
+
+impl Default for CardNumberDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Detector for CardNumberDetector {
+    fn find(&self, text: &str) -> Vec<Range<usize>> {
+        self.0.find(text)
+    }
+}
+
+/// Luhn checksum, which every payment card number satisfies.
+fn luhn_valid(digits: &[u32]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| match i % 2 {
+            0 => digit,
+            _ if digit > 4 => digit * 2 - 9,
+            _ => digit * 2,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches<'a>(detector: &dyn Detector, text: &'a str) -> Vec<&'a str> {
+        detector.find(text).into_iter().map(|range| &text[range]).collect()
+    }
+
+    #[test]
+    fn test_pii_detectors_find_emails_phones_and_valid_cards() {
+        let text = "Mail ada.l@example.co.uk or call +44 20 7946 0958, card 4111 1111 1111 1111, \
+                    not 4111 1111 1111 1112, order 12345.";
+        assert_eq!(matches(&EmailDetector::new(), text), ["ada.l@example.co.uk"]);
+        assert_eq!(matches(&CardNumberDetector::new(), text), ["4111 1111 1111 1111"]);
+        assert_eq!(
+            matches(&PhoneDetector::new(), "Call +44 20 7946 0958 or (555) 123-4567, order 12345."),
+            ["+44 20 7946 0958", "(555) 123-4567"]
+        );
+        assert_eq!(
+            matches(&PhoneDetector::new(), "Ring 555-123-4567 or 020 7946 0958 or 555-1234."),
+            ["555-123-4567", "020 7946 0958", "555-1234"]
+        );
+        assert_eq!(
+            matches(&PhoneDetector::new(), "Dial +44 (0) 20 7946 0958."),
+            ["+44 (0) 20 7946 0958"]
+        );
+        let numbers = "On 2024-01-15 (15.01.2024) 10! = 3628800, i.e. 3.628.800 or 3 628 800; \
+                       pi is 3.14159265 and the total 1.234.567.";
+        assert!(matches(&PhoneDetector::new(), numbers).is_empty());
+
+        let blocklist = RegexDetector::blocklist(&["darn", "heck it"]).unwrap();
+        assert_eq!(matches(&blocklist, "Darn, HECK IT. Darnation!"), ["Darn", "HECK IT"]);
+        assert!(RegexDetector::new("(unclosed").is_err());
+    }
+}
@@ -0,0 +1,386 @@
+//! Module: Moderation
+//! Screens user messages before generation and responses after it.
+//!
+//! A [`Moderator`] applies its rules in order. Each rule pairs a [`Detector`] with an
+//! action: `block` rejects the message, `redact` replaces the matches and `flag` only
+//! records them. What matched is reported as a [`ModerationVerdict`] on the response, so
+//! altered messages can be audited. Imported transcripts are screened as input as well.
+
+mod detectors;
+
+pub use detectors::{CardNumberDetector, Detector, EmailDetector, PhoneDetector, RegexDetector};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::config::{DetectorConfig, ModerationConfig, ModerationRuleConfig};
+use crate::errors::ChatbotError;
+use crate::models::{
+    ChatConfig, ChatMessage, ChatResponse, ModelInfo, ModerationAction, ModerationFinding,
+    ModerationStage, ModerationVerdict, StreamEvent,
+};
+use crate::services::{ChatbotService, ReplyFilter};
+use crate::sessions::{ApproximateTokenCounter, TokenCounter};
+use crate::streaming::{TokenStream, replay_tokens, split_tokens};
+
+pub const DEFAULT_REPLACEMENT: &str = "[REDACTED]";
+
+/// A detector with the action taken on its matches.
+pub struct Rule {
+    name: String,
+    detector: Box<dyn Detector>,
+    action: ModerationAction,
+    stage: Option<ModerationStage>,
+    replacement: String,
+}
+
+impl Rule {
+    /// A rule applied to both input and output.
+    pub fn new(
+        name: impl Into<String>,
+        detector: Box<dyn Detector>,
+        action: ModerationAction,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            detector,
+            action,
+            stage: None,
+            replacement: DEFAULT_REPLACEMENT.to_string(),
+        }
+    }
+
+    pub fn from_config(config: &ModerationRuleConfig) -> Result<Self, ChatbotError> {
+        let detector: Box<dyn Detector> = match &config.detector {
+            DetectorConfig::Blocklist { terms } => Box::new(RegexDetector::blocklist(terms)?),
+            DetectorConfig::Regex { pattern } => Box::new(RegexDetector::new(pattern)?),
+            DetectorConfig::Email => Box::new(EmailDetector::new()),
+            DetectorConfig::Phone => Box::new(PhoneDetector::new()),
+            DetectorConfig::CardNumber => Box::new(CardNumberDetector::new()),
+        };
+        let mut rule = Self::new(&config.name, detector, config.action);
+        rule.stage = config.stage;
+        if let Some(replacement) = &config.replacement {
+            rule.replacement = replacement.clone();
+        }
+        Ok(rule)
+    }
+
+    /// Limit the rule to `stage`
+    pub fn only(mut self, stage: ModerationStage) -> Self {
+        self.stage = Some(stage);
+        self
+    }
+
+    pub fn with_replacement(mut self, replacement: impl Into<String>) -> Self {
+        self.replacement = replacement.into();
+        self
+    }
+
+    fn applies_to(&self, stage: ModerationStage) -> bool {
+        self.stage.is_none_or(|only| only == stage)
+    }
+}
+
+/// Result of moderating one piece of text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Moderation {
+    /// The text with redactions applied
+    pub text: String,
+    /// Rules that matched
+    pub findings: Vec<ModerationFinding>,
+    /// The rule that blocked the text, if one did; later rules are then not applied
+    pub blocked_by: Option<String>,
+}
+
+/// An ordered set of moderation rules.
+#[derive(Default)]
+pub struct Moderator {
+    rules: Vec<Rule>,
+}
+
+impl Moderator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_config(config: &ModerationConfig) -> Result<Self, ChatbotError> {
+        let rules = config.rules.iter().map(Rule::from_config).collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn with_rule(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Whether any rule applies to `stage`
+    pub fn screens(&self, stage: ModerationStage) -> bool {
+        self.rules.iter().any(|rule| rule.applies_to(stage))
+    }
+
+    /// Applies the rules of `stage` to `text` in order.
+    pub fn moderate(&self, stage: ModerationStage, text: &str) -> Moderation {
+        let mut moderation =
+            Moderation { text: text.to_string(), findings: Vec::new(), blocked_by: None };
+        for rule in self.rules.iter().filter(|rule| rule.applies_to(stage)) {
+            let matches = rule.detector.find(&moderation.text);
+            if matches.is_empty() {
+                continue;
+            }
+            moderation.findings.push(ModerationFinding {
+                rule: rule.name.clone(),
+                stage,
+                action: rule.action,
+                matches: matches.len(),
+            });
+            match rule.action {
+                ModerationAction::Block => {
+                    moderation.blocked_by = Some(rule.name.clone());
+                    break;
+                }
+                ModerationAction::Redact => {
+                    for range in matches.into_iter().rev() {
+                        moderation.text.replace_range(range, &rule.replacement);
+                    }
+                }
+                ModerationAction::Flag => {}
+            }
+        }
+        moderation
+    }
+
+    /// Applies the rules of `stage` to the content of `message`, logging what matched.
+    ///
+    /// Returns the message to pass on with the findings, or an error if a rule blocks it.
+    pub fn moderate_message(
+        &self,
+        stage: ModerationStage,
+        message: &ChatMessage,
+    ) -> Result<(ChatMessage, Vec<ModerationFinding>), ChatbotError> {
+        let moderation = self.moderate(stage, &message.content);
+        audit(message, &moderation);
+        if let Some(rule) = moderation.blocked_by {
+            return Err(ChatbotError::ContentBlocked { stage, rule });
+        }
+        let moderated = ChatMessage { content: moderation.text, ..message.clone() };
+        Ok((moderated, moderation.findings))
+    }
+}
+
+/// Stores replies as they are sent: redacted, or not at all if an output rule blocks them.
+impl ReplyFilter for Moderator {
+    fn filter(&self, reply: &str) -> Option<String> {
+        let moderation = self.moderate(ModerationStage::Output, reply);
+        moderation.blocked_by.is_none().then_some(moderation.text)
+    }
+}
+
+/// A [`ChatbotService`] whose messages and responses pass through a [`Moderator`].
+///
+/// Redacted messages are what the inner service sees, so personal data stays out of
+/// session history and the response cache. Responses are moderated on their way out; to
+/// keep them out of the history as well, give the same moderator to
+/// [`LlmChatbot::with_reply_filter`](crate::services::LlmChatbot::with_reply_filter).
+/// Output rules need the whole response, so when there are any, streams are generated in
+/// full and then replayed.
+pub struct ModeratedChatbot {
+    inner: Arc<dyn ChatbotService>,
+    moderator: Arc<Moderator>,
+}
+
+impl ModeratedChatbot {
+    pub fn new(inner: Arc<dyn ChatbotService>, moderator: impl Into<Arc<Moderator>>) -> Self {
+        Self { inner, moderator: moderator.into() }
+    }
+
+    /// The message to pass on, with the input findings.
+    fn moderate_input(
+        &self,
+        message: &ChatMessage,
+    ) -> Result<(ChatMessage, Vec<ModerationFinding>), ChatbotError> {
+        self.moderator.moderate_message(ModerationStage::Input, message)
+    }
+}
+
+/// Logs which rules matched, for auditing altered and rejected messages.
+fn audit(message: &ChatMessage, moderation: &Moderation) {
+    if moderation.findings.is_empty() {
+        return;
+    }
+    let user_id = message.metadata.as_ref().and_then(|metadata| metadata.user_id.as_deref());
+    let rules: Vec<_> = moderation.findings.iter().map(|finding| finding.rule.as_str()).collect();
+    match &moderation.blocked_by {
+        Some(rule) => warn!("Moderation rule {} blocked a message for user {:?}", rule, user_id),
+        None => info!("Moderation rules {:?} matched a message for user {:?}", rules, user_id),
+    }
+}
+
+#[async_trait]
+impl ChatbotService for ModeratedChatbot {
+    async fn generate_response(&self, message: &ChatMessage) -> Result<ChatResponse, ChatbotError> {
+        let (moderated, mut findings) = self.moderate_input(message)?;
+        let mut response = self.inner.generate_response(&moderated).await?;
+
+        let output = self.moderator.moderate(ModerationStage::Output, &response.message.content);
+        audit(message, &output);
+        if let Some(rule) = output.blocked_by {
+            return Err(ChatbotError::ContentBlocked { stage: ModerationStage::Output, rule });
+        }
+        findings.extend(output.findings);
+        response.message.content = output.text;
+        response.metadata.moderation = Some(ModerationVerdict { findings });
+        Ok(response)
+    }
+
+    async fn generate_stream(&self, message: &ChatMessage) -> Result<TokenStream, ChatbotError> {
+        if self.moderator.screens(ModerationStage::Output) {
+            let started = Instant::now();
+            let response = self.generate_response(message).await?;
+            let tokens = replay_tokens(
+                split_tokens(&response.message.content),
+                Duration::ZERO,
+                response.metadata.model_version,
+                ApproximateTokenCounter.count(&message.content),
+                started,
+            );
+            return Ok(with_verdict(tokens, response.metadata.moderation.unwrap_or_default()));
+        }
+
+        let (moderated, findings) = self.moderate_input(message)?;
+        let tokens = self.inner.generate_stream(&moderated).await?;
+        Ok(with_verdict(tokens, ModerationVerdict { findings }))
+    }
+
+    async fn get_model_info(&self) -> Result<ModelInfo, ChatbotError> {
+        self.inner.get_model_info().await
+    }
+
+    async fn update_config(&self, config: ChatConfig) -> Result<(), ChatbotError> {
+        self.inner.update_config(config).await
+    }
+}
+
+/// Attaches `verdict` to the final frame of `tokens`.
+fn with_verdict(tokens: TokenStream, verdict: ModerationVerdict) -> TokenStream {
+    Box::pin(tokens.map(move |event| match event {
+        Ok(StreamEvent::Done(mut metadata)) => {
+            metadata.moderation = Some(verdict.clone());
+            Ok(StreamEvent::Done(metadata))
+        }
+        other => other,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::{Generation, ScriptedBackend};
+    use crate::services::LlmChatbot;
+
+    fn message(content: &str) -> ChatMessage {
+        ChatMessage { role: "user".to_string(), content: content.to_string(), metadata: None }
+    }
+
+    #[tokio::test]
+    async fn test_rules_block_redact_and_flag_in_both_directions() {
+        let backend = Arc::new(
+            ScriptedBackend::new()
+                .with_reply(Ok(Generation::text("Sure, write to help@example.com")))
+                .with_reply(Ok(Generation::text("That is classified"))),
+        );
+        let moderator = Moderator::from_config(&ModerationConfig::default())
+            .unwrap()
+            .with_rule(Rule::new(
+                "secrets",
+                Box::new(RegexDetector::new(r"(?i)\bclassified\b").unwrap()),
+                ModerationAction::Block,
+            ))
+            .with_rule(
+                Rule::new(
+                    "refunds",
+                    Box::new(RegexDetector::blocklist(&["refund"]).unwrap()),
+                    ModerationAction::Flag,
+                )
+                .only(ModerationStage::Input),
+            );
+        let chatbot = ModeratedChatbot::new(Arc::new(LlmChatbot::new(backend.clone())), moderator);
+
+        let response = chatbot
+            .generate_response(&message(
+                "Refund card 4111-1111-1111-1111 please, I'm ada@example.org",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            backend.calls()[0].messages[0].content,
+            "Refund card [REDACTED] please, I'm [REDACTED]"
+        );
+        assert_eq!(response.message.content, "Sure, write to [REDACTED]");
+        let verdict = response.metadata.moderation.unwrap();
+        let rules: Vec<_> =
+            verdict.findings.iter().map(|finding| (finding.rule.as_str(), finding.stage)).collect();
+        assert_eq!(
+            rules,
+            [
+                ("card_number", ModerationStage::Input),
+                ("email", ModerationStage::Input),
+                ("refunds", ModerationStage::Input),
+                ("email", ModerationStage::Output),
+            ]
+        );
+        assert_eq!(verdict.action(), Some(ModerationAction::Redact));
+
+        let blocked = chatbot.generate_response(&message("Tell me")).await;
+        assert!(matches!(
+            blocked,
+            Err(ChatbotError::ContentBlocked { stage: ModerationStage::Output, ref rule }) if rule == "secrets"
+        ));
+        let blocked = chatbot.generate_stream(&message("Is it classified?")).await;
+        assert!(matches!(
+            blocked,
+            Err(ChatbotError::ContentBlocked { stage: ModerationStage::Input, .. })
+        ));
+        assert_eq!(backend.calls().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_session_history_holds_moderated_replies() {
+        use crate::models::MessageMetadata;
+
+        let backend = Arc::new(
+            ScriptedBackend::new()
+                .with_reply(Ok(Generation::text("Write to help@example.com")))
+                .with_reply(Ok(Generation::text("That is classified"))),
+        );
+        let moderator = Arc::new(
+            Moderator::from_config(&ModerationConfig::default()).unwrap().with_rule(Rule::new(
+                "secrets",
+                Box::new(RegexDetector::new(r"(?i)\bclassified\b").unwrap()),
+                ModerationAction::Block,
+            )),
+        );
+        let inner = LlmChatbot::new(backend).with_reply_filter(moderator.clone());
+        let sessions = inner.sessions().clone();
+        let chatbot = ModeratedChatbot::new(Arc::new(inner), moderator);
+        let message = |content: &str| ChatMessage {
+            metadata: Some(MessageMetadata {
+                timestamp: chrono::Utc::now(),
+                user_id: Some("u1".to_string()),
+                session_id: Some("s1".to_string()),
+                locale: None,
+            }),
+            ..message(content)
+        };
+
+        chatbot.generate_response(&message("How do I reach you?")).await.unwrap();
+        assert!(chatbot.generate_response(&message("Tell me")).await.is_err());
+
+        let session = sessions.get("s1", Some("u1")).await.unwrap();
+        let contents: Vec<_> = session.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["How do I reach you?", "Write to [REDACTED]"]);
+    }
+}
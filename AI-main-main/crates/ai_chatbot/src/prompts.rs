@@ -0,0 +1,128 @@
+//! Module: Prompts
+//! Named prompt templates and per-locale system messages.
+//!
+//! Templates live in the locale catalogs under `[templates]`, with `{name}` placeholders
+//! filled from request variables; `{message}` is always the user's message. The system
+//! message of a locale is its `system_prompt` key and is sent ahead of the conversation.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::config::LocaleConfig;
+use crate::errors::ChatbotError;
+use crate::i18n::{self, Catalog, DEFAULT_LOCALE};
+
+pub const SYSTEM_PROMPT_KEY: &str = "system_prompt";
+const TEMPLATES_PREFIX: &str = "templates.";
+
+/// Templates and system messages of all locales.
+pub struct PromptLibrary {
+    catalog: Arc<Catalog>,
+}
+
+impl PromptLibrary {
+    pub fn new(catalog: Arc<Catalog>) -> Self {
+        Self { catalog }
+    }
+
+    /// Loads the configured locales, or starts without any if the directory is missing.
+    ///
+    /// Malformed catalogs are still an error.
+    pub fn from_config(config: &LocaleConfig) -> Result<Self, ChatbotError> {
+        let catalog = match Catalog::load_dir(&config.directory, &config.default_locale) {
+            Err(ChatbotError::IoError(e)) => {
+                warn!("No prompt templates loaded from {}: {}", config.directory, e);
+                Catalog::new(&config.default_locale)
+            }
+            result => result?,
+        };
+        Ok(Self::new(Arc::new(catalog)))
+    }
+
+    /// Loads the locales shipped with the service, or starts without any if they are unusable.
+    pub fn load_default() -> Self {
+        Self::from_config(&LocaleConfig::default()).unwrap_or_else(|e| {
+            warn!("No prompt templates loaded: {}", e);
+            Self::new(Arc::new(Catalog::new(DEFAULT_LOCALE)))
+        })
+    }
+
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+
+    /// System message sent ahead of conversations in `locale`, if one is configured.
+    pub fn system_message(&self, locale: &str) -> Option<&str> {
+        self.catalog.get(locale, SYSTEM_PROMPT_KEY).filter(|message| !message.trim().is_empty())
+    }
+
+    /// Renders the template `name` of `locale` with `message` and `variables`.
+    pub fn render(
+        &self,
+        name: &str,
+        locale: &str,
+        message: &str,
+        variables: &HashMap<String, String>,
+    ) -> Result<String, ChatbotError> {
+        let key = format!("{}{}", TEMPLATES_PREFIX, name);
+        let template = self.catalog.get(locale, &key).ok_or_else(|| {
+            ChatbotError::InvalidRequest(format!("Unknown prompt template: {}", name))
+        })?;
+
+        let value = |placeholder: &str| match placeholder {
+            "message" => Some(message.to_string()),
+            other => variables.get(other).cloned(),
+        };
+        let missing: Vec<_> =
+            i18n::placeholders(template).into_iter().filter(|name| value(name).is_none()).collect();
+        if !missing.is_empty() {
+            return Err(ChatbotError::InvalidInput(format!(
+                "Template {} needs variables: {}",
+                name,
+                missing.join(", ")
+            )));
+        }
+        Ok(i18n::interpolate(template, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library() -> PromptLibrary {
+        let mut catalog = Catalog::new("en");
+        catalog
+            .add_toml(
+                "en",
+                r#"
+system_prompt = "You are a helpful assistant."
+[templates]
+translate = "Translate into {language}: {message}"
+"#,
+            )
+            .unwrap();
+        catalog.add_toml("es", "system_prompt = \"Eres un asistente útil.\"").unwrap();
+        PromptLibrary::new(Arc::new(catalog))
+    }
+
+    #[test]
+    fn test_templates_render_variables_and_fall_back_across_locales() {
+        let library = library();
+        let variables = HashMap::from([("language".to_string(), "French".to_string())]);
+        assert_eq!(
+            library.render("translate", "es", "Good morning", &variables).unwrap(),
+            "Translate into French: Good morning"
+        );
+        assert!(matches!(
+            library.render("translate", "en", "Hi", &HashMap::new()),
+            Err(ChatbotError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            library.render("poem", "en", "Hi", &HashMap::new()),
+            Err(ChatbotError::InvalidRequest(_))
+        ));
+        assert_eq!(library.system_message("es-MX"), Some("Eres un asistente útil."));
+    }
+}
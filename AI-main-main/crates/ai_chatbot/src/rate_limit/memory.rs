@@ -0,0 +1,138 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use super::{Bucket, Decision, Quota, RateLimitStore, Window};
+use crate::config::RateLimitAlgorithm;
+use crate::errors::ChatbotError;
+
+/// Number of checks between sweeps of idle clients.
+const PRUNE_INTERVAL: u64 = 1024;
+
+#[derive(Debug, Clone, Copy)]
+enum Entry {
+    Bucket(Bucket),
+    Window(Window),
+}
+
+#[derive(Default)]
+struct State {
+    entries: HashMap<String, Entry>,
+    checks: u64,
+}
+
+/// Per-process rate limit store. Limits are not shared between instances.
+pub struct InMemoryRateLimitStore {
+    state: Mutex<State>,
+    epoch: Instant,
+}
+
+impl InMemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self { state: Mutex::new(State::default()), epoch: Instant::now() }
+    }
+
+    /// Number of clients currently tracked.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        // The state stays consistent even if a holder panicked.
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Counts a request at `now_ms` milliseconds since the store was created.
+    pub(crate) fn check_at(&self, key: &str, quota: &Quota, now_ms: u64) -> Decision {
+        let mut state = self.lock();
+        state.checks += 1;
+        if state.checks.is_multiple_of(PRUNE_INTERVAL) {
+            // Clients are dropped once they are back to a full allowance: a bucket once it has
+            // refilled from empty, a window once it no longer overlaps the one counted.
+            state.entries.retain(|_, entry| {
+                let (last, idle_ms) = match entry {
+                    Entry::Bucket(bucket) => (bucket.updated_ms, quota.refill_ms()),
+                    Entry::Window(window) => {
+                        (window.start_ms + quota.window_ms(), 2 * quota.window_ms())
+                    }
+                };
+                now_ms.saturating_sub(last) < idle_ms
+            });
+        }
+
+        let entry = state.entries.get(key).copied();
+        let (entry, decision) = match quota.algorithm {
+            RateLimitAlgorithm::TokenBucket => {
+                let previous = match entry {
+                    Some(Entry::Bucket(bucket)) => Some(bucket),
+                    _ => None,
+                };
+                let (bucket, allowed) = Bucket::take(previous, quota, now_ms);
+                (Entry::Bucket(bucket), bucket.decision(quota, allowed))
+            }
+            RateLimitAlgorithm::SlidingWindow => {
+                let previous = match entry {
+                    Some(Entry::Window(window)) => Some(window),
+                    _ => None,
+                };
+                let (window, allowed) = Window::hit(previous, quota, now_ms);
+                (Entry::Window(window), window.decision(quota, allowed, now_ms))
+            }
+        };
+        state.entries.insert(key.to_string(), entry);
+        decision
+    }
+}
+
+impl Default for InMemoryRateLimitStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for InMemoryRateLimitStore {
+    async fn check(&self, key: &str, quota: &Quota) -> Result<Decision, ChatbotError> {
+        let now_ms = self.epoch.elapsed().as_millis() as u64;
+        Ok(self.check_at(key, quota, now_ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_keys_are_limited_independently_and_idle_keys_pruned() {
+        let store = InMemoryRateLimitStore::new();
+        let quota = Quota {
+            limit: 1,
+            window: Duration::from_secs(1),
+            burst: 1,
+            algorithm: RateLimitAlgorithm::TokenBucket,
+        };
+
+        assert!(store.check_at("a", &quota, 0).allowed);
+        assert!(!store.check_at("a", &quota, 0).allowed);
+        assert!(store.check_at("b", &quota, 0).allowed);
+        assert!(store.check_at("a", &quota, 1_000).allowed);
+        assert_eq!(store.len(), 2);
+
+        for i in 0..PRUNE_INTERVAL {
+            store.check_at(&format!("c{}", i), &quota, 1_000);
+        }
+        store.check_at("late", &quota, 10_000);
+        // The sweep ran on a check at 1s, so all keys seen at 0s or 1s are still there.
+        assert!(store.len() > 2);
+        for _ in 0..PRUNE_INTERVAL {
+            store.check_at("late", &quota, 10_000);
+        }
+        assert_eq!(store.len(), 1);
+    }
+}
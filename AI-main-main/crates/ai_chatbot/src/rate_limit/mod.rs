@@ -0,0 +1,541 @@
+//! Module: Rate Limiting
+//! Enforces `RateLimitConfig` with per-client quotas and a concurrency limit.
+//!
+//! Clients are identified by verified user ID, known API key or IP address (see
+//! [`RateLimitKey`]) and counted in a [`RateLimitStore`]: in memory for a single instance,
+//! or in Redis when several instances must share limits. Every response carries
+//! `X-RateLimit-Limit`, `X-RateLimit-Remaining` and `X-RateLimit-Reset`; rejected requests
+//! get a 429 with `Retry-After`.
+
+mod memory;
+mod redis_store;
+
+use actix_web::{
+    Error, HttpMessage, ResponseError,
+    body::{BodySize, BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    error::InternalError,
+    http::header::{self, HeaderMap, HeaderName, HeaderValue},
+    web::Bytes,
+};
+use async_trait::async_trait;
+use common::auth::Claims;
+use futures::Future;
+use futures::future::{Ready, ok};
+use std::{
+    pin::Pin,
+    rc::Rc,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, error, warn};
+
+pub use self::memory::InMemoryRateLimitStore;
+pub use self::redis_store::RedisRateLimitStore;
+use crate::config::{RateLimitAlgorithm, RateLimitConfig, RateLimitKey};
+use crate::errors::ChatbotError;
+
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+const LIMIT_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const REMAINING_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const RESET_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+/// The allowance of a single client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    /// Requests allowed per `window`
+    pub limit: u32,
+    pub window: Duration,
+    /// Bucket capacity for the token bucket algorithm
+    pub burst: u32,
+    pub algorithm: RateLimitAlgorithm,
+}
+
+impl Quota {
+    pub fn from_config(config: &RateLimitConfig) -> Self {
+        let limit = config.requests_per_minute.max(1);
+        Self {
+            limit,
+            window: Duration::from_secs(60),
+            burst: config.burst.unwrap_or(limit).max(1),
+            algorithm: config.algorithm,
+        }
+    }
+
+    fn window_ms(&self) -> u64 {
+        (self.window.as_millis() as u64).max(1)
+    }
+
+    fn refill_per_ms(&self) -> f64 {
+        self.limit as f64 / self.window_ms() as f64
+    }
+
+    /// Time an empty token bucket takes to fill up again; longer than the window when
+    /// `burst` exceeds `limit`.
+    fn refill_ms(&self) -> u64 {
+        (self.burst as f64 / self.refill_per_ms()).ceil() as u64
+    }
+}
+
+/// The outcome of counting one request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Time until the client's allowance is fully restored
+    pub reset_after: Duration,
+    /// Set when the request was rejected: how long to wait before retrying
+    pub retry_after: Option<Duration>,
+}
+
+/// Counts requests per client key.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Counts one request against `key` and reports whether it fits `quota`.
+    async fn check(&self, key: &str, quota: &Quota) -> Result<Decision, ChatbotError>;
+}
+
+/// Token bucket state: `tokens` were available at `updated_ms`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Bucket {
+    pub tokens: f64,
+    pub updated_ms: u64,
+}
+
+impl Bucket {
+    /// Refills the bucket up to `now_ms` and takes a token if one is available.
+    pub(crate) fn take(state: Option<Bucket>, quota: &Quota, now_ms: u64) -> (Bucket, bool) {
+        let capacity = quota.burst as f64;
+        let mut bucket = state.unwrap_or(Bucket { tokens: capacity, updated_ms: now_ms });
+        let elapsed = now_ms.saturating_sub(bucket.updated_ms) as f64;
+        bucket.tokens = (bucket.tokens + elapsed * quota.refill_per_ms()).min(capacity);
+        bucket.updated_ms = bucket.updated_ms.max(now_ms);
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        (bucket, allowed)
+    }
+
+    pub(crate) fn decision(&self, quota: &Quota, allowed: bool) -> Decision {
+        let rate = quota.refill_per_ms();
+        Decision {
+            allowed,
+            limit: quota.burst,
+            remaining: self.tokens.floor().max(0.0) as u32,
+            reset_after: millis((quota.burst as f64 - self.tokens) / rate),
+            retry_after: (!allowed).then(|| millis((1.0 - self.tokens) / rate)),
+        }
+    }
+}
+
+/// Sliding window state: counts of the fixed window starting at `start_ms` and the one
+/// before it. The previous count is weighted by how much of it still overlaps the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Window {
+    pub start_ms: u64,
+    pub current: u32,
+    pub previous: u32,
+}
+
+impl Window {
+    /// Advances the window to `now_ms` and counts the request if it fits.
+    pub(crate) fn hit(state: Option<Window>, quota: &Quota, now_ms: u64) -> (Window, bool) {
+        let len = quota.window_ms();
+        let mut window = state.unwrap_or(Window { start_ms: now_ms, current: 0, previous: 0 });
+        let elapsed = now_ms.saturating_sub(window.start_ms);
+        if elapsed >= 2 * len {
+            window = Window { start_ms: now_ms - elapsed % len, current: 0, previous: 0 };
+        } else if elapsed >= len {
+            window =
+                Window { start_ms: window.start_ms + len, current: 0, previous: window.current };
+        }
+        let allowed = window.estimate(quota, now_ms) + 1.0 <= quota.limit as f64;
+        if allowed {
+            window.current += 1;
+        }
+        (window, allowed)
+    }
+
+    fn estimate(&self, quota: &Quota, now_ms: u64) -> f64 {
+        let len = quota.window_ms() as f64;
+        let elapsed = now_ms.saturating_sub(self.start_ms) as f64;
+        self.previous as f64 * ((len - elapsed) / len).max(0.0) + self.current as f64
+    }
+
+    pub(crate) fn decision(&self, quota: &Quota, allowed: bool, now_ms: u64) -> Decision {
+        let len = quota.window_ms() as f64;
+        let limit = quota.limit as f64;
+        let elapsed = now_ms.saturating_sub(self.start_ms) as f64;
+        let retry_after = (!allowed).then(|| {
+            if self.current as f64 + 1.0 > limit {
+                // Wait for the next window, where this one's count starts decaying.
+                millis(len - elapsed + len / limit)
+            } else {
+                // Wait until enough of the previous window has slid out.
+                let spare = limit - self.current as f64 - 1.0;
+                millis(len - elapsed - spare * len / self.previous.max(1) as f64)
+            }
+        });
+        Decision {
+            allowed,
+            limit: quota.limit,
+            remaining: (limit - self.estimate(quota, now_ms)).floor().max(0.0) as u32,
+            reset_after: millis(2.0 * len - elapsed),
+            retry_after,
+        }
+    }
+}
+
+fn millis(ms: f64) -> Duration {
+    Duration::from_millis(ms.max(0.0).ceil() as u64)
+}
+
+/// Middleware enforcing a [`RateLimitConfig`].
+///
+/// Wrap it inside [`common::middleware::auth::JwtAuth`] when keying by user ID, so the
+/// claims are available. Requests without the configured identity are rejected instead of
+/// sharing an allowance. If the store fails, requests are let through rather than turning
+/// a Redis outage into a full outage.
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    quota: Quota,
+    key: RateLimitKey,
+    api_keys: Arc<[String]>,
+    concurrency: Arc<Semaphore>,
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitStore>, config: &RateLimitConfig) -> Self {
+        Self {
+            store,
+            quota: Quota::from_config(config),
+            key: config.key,
+            api_keys: config.api_keys.clone().into(),
+            concurrency: Arc::new(Semaphore::new(config.max_concurrent.max(1) as usize)),
+        }
+    }
+
+    /// Uses Redis when `redis_url` is configured and an in-memory store otherwise.
+    pub fn from_config(config: &RateLimitConfig) -> Result<Self, ChatbotError> {
+        if config.key == RateLimitKey::ApiKey && config.api_keys.is_empty() {
+            return Err(ChatbotError::ConfigError(
+                "Rate limits keyed by API key need api_keys".to_string(),
+            ));
+        }
+        let store: Arc<dyn RateLimitStore> = match &config.redis_url {
+            Some(url) => Arc::new(RedisRateLimitStore::new(url)?),
+            None => Arc::new(InMemoryRateLimitStore::new()),
+        };
+        Ok(Self::new(store, config))
+    }
+
+    /// The verified identity requests are counted against.
+    fn client_key(&self, req: &ServiceRequest) -> Result<String, ChatbotError> {
+        match self.key {
+            RateLimitKey::UserId => match req.extensions().get::<Claims>() {
+                Some(claims) => Ok(format!("user:{}", claims.sub)),
+                None => {
+                    error!(
+                        "Rate limits are keyed by user ID but {} {} has no verified claims; \
+                         is the limiter wrapped in JwtAuth?",
+                        req.method(),
+                        req.path()
+                    );
+                    Err(ChatbotError::AuthError("A bearer token is required".to_string()))
+                }
+            },
+            // Keys are counted by position, so the secrets themselves are never stored.
+            RateLimitKey::ApiKey => req
+                .headers()
+                .get(API_KEY_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|key| self.api_keys.iter().position(|known| known == key))
+                .map(|index| format!("key:{}", index))
+                .ok_or_else(|| ChatbotError::AuthError("Unknown API key".to_string())),
+            RateLimitKey::Ip => Ok(match req.peer_addr() {
+                Some(addr) => format!("ip:{}", addr.ip()),
+                None => "ip:unknown".to_string(),
+            }),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<PermitBody>;
+    type Error = Error;
+    type Transform = RateLimiterMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimiterMiddleware { service: Rc::new(service), limiter: self.clone() })
+    }
+}
+
+pub struct RateLimiterMiddleware<S> {
+    service: Rc<S>,
+    limiter: RateLimiter,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimiterMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<PermitBody>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let limiter = self.limiter.clone();
+
+        Box::pin(async move {
+            let key = match limiter.client_key(&req) {
+                Ok(key) => key,
+                Err(e) => {
+                    debug!("Rejected {} {}: {}", req.method(), req.path(), e);
+                    return Err(e.into());
+                }
+            };
+            let decision = match limiter.store.check(&key, &limiter.quota).await {
+                Ok(decision) => Some(decision),
+                Err(e) => {
+                    warn!("Rate limit store unavailable, allowing request: {}", e);
+                    None
+                }
+            };
+            if let Some(decision) = decision.filter(|decision| !decision.allowed) {
+                debug!("Rate limited {} on {} {}", key, req.method(), req.path());
+                return Err(rejection(&decision));
+            }
+
+            let Ok(permit) = limiter.concurrency.clone().try_acquire_owned() else {
+                debug!("Concurrency limit reached on {} {}", req.method(), req.path());
+                let busy = Decision {
+                    allowed: false,
+                    limit: limiter.quota.limit,
+                    remaining: decision.map_or(0, |decision| decision.remaining),
+                    reset_after: Duration::from_secs(1),
+                    retry_after: Some(Duration::from_secs(1)),
+                };
+                return Err(rejection(&busy));
+            };
+
+            let mut res = service.call(req).await?;
+            if let Some(decision) = decision {
+                insert_headers(res.headers_mut(), &decision);
+            }
+            Ok(res.map_body(|_, body| PermitBody { body: body.boxed(), _permit: permit }))
+        })
+    }
+}
+
+/// A response body that holds a concurrency permit until it is sent or dropped, so SSE
+/// and WebSocket responses count against `max_concurrent` for as long as they stream.
+pub struct PermitBody {
+    body: BoxBody,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl MessageBody for PermitBody {
+    type Error = <BoxBody as MessageBody>::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        Pin::new(&mut self.body).poll_next(cx)
+    }
+}
+
+fn rejection(decision: &Decision) -> Error {
+    let mut response = ChatbotError::RateLimitExceeded.error_response();
+    insert_headers(response.headers_mut(), decision);
+    InternalError::from_response(ChatbotError::RateLimitExceeded, response).into()
+}
+
+/// Adds the `X-RateLimit-*` headers, and `Retry-After` for rejections. Durations are
+/// rounded up to whole seconds.
+fn insert_headers(headers: &mut HeaderMap, decision: &Decision) {
+    let seconds = |duration: Duration| {
+        let secs = duration.as_secs() + u64::from(duration.subsec_nanos() > 0);
+        HeaderValue::from(secs)
+    };
+    headers.insert(LIMIT_HEADER, HeaderValue::from(decision.limit));
+    headers.insert(REMAINING_HEADER, HeaderValue::from(decision.remaining));
+    headers.insert(RESET_HEADER, seconds(decision.reset_after));
+    if let Some(retry_after) = decision.retry_after {
+        headers.insert(header::RETRY_AFTER, seconds(retry_after));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{TestRequest, call_service, init_service, try_call_service};
+    use actix_web::{App, HttpResponse, http::StatusCode, web};
+
+    fn config(requests_per_minute: u32, max_concurrent: u32) -> RateLimitConfig {
+        RateLimitConfig {
+            requests_per_minute,
+            max_concurrent,
+            key: RateLimitKey::ApiKey,
+            api_keys: vec!["alpha".to_string(), "beta".to_string()],
+            ..RateLimitConfig::default()
+        }
+    }
+
+    fn quota(algorithm: RateLimitAlgorithm) -> Quota {
+        Quota { limit: 6, window: Duration::from_secs(60), burst: 3, algorithm }
+    }
+
+    #[test]
+    fn test_token_bucket_allows_bursts_then_refills() {
+        let quota = quota(RateLimitAlgorithm::TokenBucket);
+        let mut state = None;
+        for _ in 0..3 {
+            let (bucket, allowed) = Bucket::take(state, &quota, 0);
+            assert!(allowed);
+            state = Some(bucket);
+        }
+        let (bucket, allowed) = Bucket::take(state, &quota, 0);
+        assert!(!allowed);
+        let decision = bucket.decision(&quota, allowed);
+        // Six per minute refills one token every ten seconds.
+        assert_eq!(decision.retry_after, Some(Duration::from_secs(10)));
+        assert_eq!(decision.reset_after, Duration::from_secs(30));
+
+        let (_, allowed) = Bucket::take(Some(bucket), &quota, 10_000);
+        assert!(allowed);
+
+        // Buckets larger than the per-window limit take longer than a window to refill.
+        assert_eq!(quota.refill_ms(), 30_000);
+        assert_eq!(Quota { burst: 12, ..quota }.refill_ms(), 120_000);
+    }
+
+    #[test]
+    fn test_sliding_window_weights_the_previous_window() {
+        let quota = quota(RateLimitAlgorithm::SlidingWindow);
+        let mut state = None;
+        for _ in 0..6 {
+            let (window, allowed) = Window::hit(state, &quota, 1_000);
+            assert!(allowed);
+            state = Some(window);
+        }
+        let (window, allowed) = Window::hit(state, &quota, 30_000);
+        assert!(!allowed);
+        // The next window opens at 61s, and one slot frees up 10s into it.
+        assert_eq!(
+            window.decision(&quota, allowed, 30_000).retry_after,
+            Some(Duration::from_secs(41))
+        );
+
+        // Half-way into the next window half of the previous count still applies.
+        let (window, allowed) = Window::hit(Some(window), &quota, 91_000);
+        assert!(allowed);
+        assert_eq!(window.decision(&quota, allowed, 91_000).remaining, 2);
+    }
+
+    #[actix_rt::test]
+    async fn test_requests_over_the_limit_are_rejected_with_headers() {
+        let limiter = RateLimiter::new(Arc::new(InMemoryRateLimitStore::new()), &config(2, 10));
+        let app =
+            init_service(App::new().wrap(limiter).route("/chat", web::post().to(HttpResponse::Ok)))
+                .await;
+        let request = |key: &str| {
+            TestRequest::post().uri("/chat").insert_header((API_KEY_HEADER, key)).to_request()
+        };
+
+        let resp = call_service(&app, request("alpha")).await;
+        assert_eq!(resp.headers().get("x-ratelimit-limit").unwrap(), "2");
+        assert_eq!(resp.headers().get("x-ratelimit-remaining").unwrap(), "1");
+        call_service(&app, request("alpha")).await;
+
+        let err = try_call_service(&app, request("alpha")).await.expect_err("over the limit");
+        let resp = err.error_response();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "30");
+        assert_eq!(resp.headers().get("x-ratelimit-remaining").unwrap(), "0");
+
+        // Other keys have their own allowance, and unknown keys none at all.
+        assert!(call_service(&app, request("beta")).await.status().is_success());
+        let err = try_call_service(&app, request("gamma")).await.expect_err("unknown key");
+        assert_eq!(err.error_response().status(), StatusCode::UNAUTHORIZED);
+        let req = TestRequest::post().uri("/chat").to_request();
+        let err = try_call_service(&app, req).await.expect_err("no key");
+        assert_eq!(err.error_response().status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_rt::test]
+    async fn test_user_limits_reject_requests_without_claims() {
+        let config = RateLimitConfig { key: RateLimitKey::UserId, ..config(2, 10) };
+        let limiter = RateLimiter::new(Arc::new(InMemoryRateLimitStore::new()), &config);
+        let app =
+            init_service(App::new().wrap(limiter).route("/chat", web::post().to(HttpResponse::Ok)))
+                .await;
+
+        let req = TestRequest::post().uri("/chat").to_request();
+        let err = try_call_service(&app, req).await.expect_err("no claims");
+        assert_eq!(err.error_response().status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_rt::test]
+    async fn test_concurrent_requests_beyond_max_concurrent_are_rejected() {
+        async fn slow() -> HttpResponse {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            HttpResponse::Ok().finish()
+        }
+
+        let limiter = RateLimiter::new(Arc::new(InMemoryRateLimitStore::new()), &config(100, 1));
+        let app = init_service(App::new().wrap(limiter).route("/chat", web::post().to(slow))).await;
+        let request = || {
+            TestRequest::post().uri("/chat").insert_header((API_KEY_HEADER, "alpha")).to_request()
+        };
+
+        let (first, second) =
+            futures::join!(try_call_service(&app, request()), try_call_service(&app, request()));
+        assert!(first.unwrap().status().is_success());
+        assert_eq!(second.expect_err("no permit left").error_response().status(), 429);
+        assert!(call_service(&app, request()).await.status().is_success());
+    }
+
+    #[actix_rt::test]
+    async fn test_streamed_responses_hold_their_permit_until_sent() {
+        async fn stream() -> HttpResponse {
+            let tokens = futures::stream::iter(["a", "b"].map(|t| Ok::<_, Error>(Bytes::from(t))));
+            HttpResponse::Ok().streaming(tokens)
+        }
+
+        let limiter = RateLimiter::new(Arc::new(InMemoryRateLimitStore::new()), &config(100, 1));
+        let app =
+            init_service(App::new().wrap(limiter).route("/chat", web::post().to(stream))).await;
+        let request = || {
+            TestRequest::post().uri("/chat").insert_header((API_KEY_HEADER, "alpha")).to_request()
+        };
+
+        let streaming = call_service(&app, request()).await;
+        let err = try_call_service(&app, request()).await.expect_err("stream still open");
+        assert_eq!(err.error_response().status(), 429);
+        assert_eq!(actix_web::test::read_body(streaming).await, "ab");
+        assert!(call_service(&app, request()).await.status().is_success());
+    }
+}
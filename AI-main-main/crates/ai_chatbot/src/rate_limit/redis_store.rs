@@ -0,0 +1,133 @@
+use async_trait::async_trait;
+use deadpool_redis::{Config, Pool, Runtime};
+use redis::Script;
+
+use super::{Bucket, Decision, Quota, RateLimitStore, Window};
+use crate::config::RateLimitAlgorithm;
+use crate::errors::ChatbotError;
+
+const DEFAULT_POOL_SIZE: usize = 16;
+const KEY_PREFIX: &str = "rate_limit:";
+
+// Both scripts mirror `Bucket::take` and `Window::hit`, reading the clock from Redis so that
+// instances with skewed clocks still agree. Tokens are returned as a string because Redis
+// truncates Lua numbers to integers.
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local t = redis.call('TIME')
+local now = t[1] * 1000 + math.floor(t[2] / 1000)
+local capacity = tonumber(ARGV[1])
+local rate = tonumber(ARGV[2])
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'updated')
+local tokens = tonumber(state[1]) or capacity
+local updated = tonumber(state[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - updated) * rate)
+updated = math.max(updated, now)
+local allowed = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated', updated)
+redis.call('PEXPIRE', KEYS[1], ARGV[3])
+return {allowed, tostring(tokens), updated}
+"#;
+
+const SLIDING_WINDOW_SCRIPT: &str = r#"
+local t = redis.call('TIME')
+local now = t[1] * 1000 + math.floor(t[2] / 1000)
+local limit = tonumber(ARGV[1])
+local len = tonumber(ARGV[2])
+local state = redis.call('HMGET', KEYS[1], 'start', 'current', 'previous')
+local start = tonumber(state[1]) or now
+local current = tonumber(state[2]) or 0
+local previous = tonumber(state[3]) or 0
+local elapsed = math.max(0, now - start)
+if elapsed >= 2 * len then
+    start = now - elapsed % len
+    current = 0
+    previous = 0
+elseif elapsed >= len then
+    start = start + len
+    previous = current
+    current = 0
+end
+elapsed = math.max(0, now - start)
+local allowed = 0
+if previous * math.max(0, (len - elapsed) / len) + current + 1 <= limit then
+    current = current + 1
+    allowed = 1
+end
+redis.call('HSET', KEYS[1], 'start', start, 'current', current, 'previous', previous)
+redis.call('PEXPIRE', KEYS[1], 2 * len)
+return {allowed, start, current, previous, now}
+"#;
+
+/// Redis-backed rate limit store, so that all instances share each client's allowance.
+///
+/// Each check is a single script invocation and therefore atomic.
+pub struct RedisRateLimitStore {
+    pool: Pool,
+    token_bucket: Script,
+    sliding_window: Script,
+}
+
+impl RedisRateLimitStore {
+    /// Connections are opened lazily, so this succeeds even if Redis is not reachable yet.
+    pub fn new(redis_url: &str) -> Result<Self, ChatbotError> {
+        redis::Client::open(redis_url)
+            .map_err(|e| ChatbotError::ConfigError(format!("Invalid Redis URL: {}", e)))?;
+        let pool = Config::from_url(redis_url)
+            .builder()
+            .map_err(|e| ChatbotError::ConfigError(format!("Invalid Redis config: {}", e)))?
+            .max_size(DEFAULT_POOL_SIZE)
+            .runtime(Runtime::Tokio1)
+            .build()
+            .map_err(|e| ChatbotError::ConfigError(format!("Failed to build Redis pool: {}", e)))?;
+        Ok(Self {
+            pool,
+            token_bucket: Script::new(TOKEN_BUCKET_SCRIPT),
+            sliding_window: Script::new(SLIDING_WINDOW_SCRIPT),
+        })
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for RedisRateLimitStore {
+    async fn check(&self, key: &str, quota: &Quota) -> Result<Decision, ChatbotError> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| ChatbotError::ConnectionError(format!("Redis pool: {}", e)))?;
+        let key = format!("{}{}", KEY_PREFIX, key);
+
+        match quota.algorithm {
+            RateLimitAlgorithm::TokenBucket => {
+                // A bucket left alone until it has refilled from empty is full, so it can go.
+                let (allowed, tokens, updated_ms): (i64, String, u64) = self
+                    .token_bucket
+                    .key(&key)
+                    .arg(quota.burst)
+                    .arg(quota.refill_per_ms())
+                    .arg(quota.refill_ms())
+                    .invoke_async(&mut conn)
+                    .await?;
+                let tokens = tokens.parse::<f64>().map_err(|e| {
+                    ChatbotError::CacheError(format!("Invalid bucket state {:?}: {}", tokens, e))
+                })?;
+                Ok(Bucket { tokens, updated_ms }.decision(quota, allowed == 1))
+            }
+            RateLimitAlgorithm::SlidingWindow => {
+                let (allowed, start_ms, current, previous, now_ms): (i64, u64, u32, u32, u64) =
+                    self.sliding_window
+                        .key(&key)
+                        .arg(quota.limit)
+                        .arg(quota.window_ms())
+                        .invoke_async(&mut conn)
+                        .await?;
+                let window = Window { start_ms, current, previous };
+                Ok(window.decision(quota, allowed == 1, now_ms))
+            }
+        }
+    }
+}
@@ -0,0 +1,500 @@
+//! Module: Response Cache
+//! Caches chatbot responses in front of `ChatbotService::generate_response`.
+//!
+//! One-off messages are looked up by their normalised text together with the model,
+//! temperature and token limit. With `CacheConfig::semantic_threshold` set, a message that
+//! misses may still be answered from a cached near-duplicate whose embedding is similar
+//! enough. Messages within a session depend on their history and are never cached.
+//! Changing the configuration through `update_config` invalidates every cached response.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use common::cache::{Cache, MemoryCache, MemoryCacheConfig};
+use metrics::{Counter, MetricsError, MetricsRegistry};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::debug;
+
+use crate::config::CacheConfig;
+use crate::errors::ChatbotError;
+use crate::models::{ChatConfig, ChatMessage, ChatResponse, MessageMetadata, ModelInfo};
+use crate::services::ChatbotService;
+use crate::sessions::{ApproximateTokenCounter, TokenCounter};
+use crate::streaming::{TokenStream, replay_tokens, split_tokens};
+
+/// Most near-duplicate candidates kept; the oldest are dropped first.
+const SEMANTIC_INDEX_CAPACITY: usize = 1024;
+
+/// Maps text to a vector whose cosine similarity reflects how alike two texts are.
+pub trait Embedder: Send + Sync {
+    fn embed(&self, text: &str) -> Vec<f32>;
+}
+
+/// Words that carry no meaning for matching questions.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "the", "is", "are", "was", "were", "be", "am", "do", "does", "did", "to", "of",
+    "in", "on", "at", "for", "and", "or", "please", "it", "this", "that",
+];
+
+/// Words that negate the rest of their clause.
+const NEGATIONS: &[&str] = &["not", "no", "never", "without", "cannot", "nor"];
+
+/// Bag-of-words embedding using the hashing trick; needs no model.
+///
+/// It catches rephrasings that share most words, not paraphrases with different wording.
+/// Stop words are ignored, and words following a negation up to the end of the clause
+/// count as different words, so "is it safe" and "is it not safe" are far apart.
+pub struct HashingEmbedder {
+    dimensions: usize,
+}
+
+impl HashingEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        Self { dimensions: dimensions.max(1) }
+    }
+}
+
+impl Default for HashingEmbedder {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl Embedder for HashingEmbedder {
+    fn embed(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; self.dimensions];
+        for clause in text.to_lowercase().split([',', '.', ';', ':', '!', '?']) {
+            let mut negated = false;
+            let words = clause.split(|c: char| !c.is_alphanumeric() && c != '\'' && c != '’');
+            for word in words.map(|word| word.trim_matches(['\'', '’'])) {
+                if word.is_empty() || STOP_WORDS.contains(&word) {
+                    continue;
+                }
+                if NEGATIONS.contains(&word) || word.ends_with("n't") || word.ends_with("n’t") {
+                    negated = true;
+                    continue;
+                }
+                let hash = if negated { fnv1a(&format!("not {}", word)) } else { fnv1a(word) };
+                vector[(hash % self.dimensions as u64) as usize] += 1.0;
+            }
+        }
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        vector
+    }
+}
+
+/// Stable across processes, unlike the std hasher.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norms =
+        a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norms > 0.0 { dot / norms } else { 0.0 }
+}
+
+/// Lowercases, collapses whitespace and drops trailing punctuation, so trivially different
+/// spellings of a question share an entry.
+pub fn normalize_prompt(text: &str) -> String {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    collapsed.trim_end_matches(|c: char| c.is_ascii_punctuation()).to_string()
+}
+
+/// Response cache counters, as `<ns>_response_cache_lookups_total{result}` with `result`
+/// one of `hit`, `semantic_hit` or `miss`.
+#[derive(Clone)]
+pub struct CacheMetrics {
+    lookups: Counter,
+}
+
+impl CacheMetrics {
+    pub fn new(registry: &MetricsRegistry) -> Result<Self, MetricsError> {
+        Ok(Self {
+            lookups: registry.counter(
+                "response_cache_lookups_total",
+                "Response cache lookups by result",
+                &["result"],
+            )?,
+        })
+    }
+
+    fn record(&self, result: &str) {
+        self.lookups.inc(&[result]);
+    }
+}
+
+struct IndexEntry {
+    locale: String,
+    embedding: Vec<f32>,
+    key: String,
+}
+
+struct CacheState {
+    config: ChatConfig,
+    /// Bumped by `update_config`; part of every key so older entries are unreachable
+    generation: u64,
+    index: VecDeque<IndexEntry>,
+}
+
+/// A [`ChatbotService`] answering repeated questions from a cache.
+pub struct CachedChatbot {
+    inner: Arc<dyn ChatbotService>,
+    cache: MemoryCache,
+    ttl: Duration,
+    semantic_threshold: Option<f32>,
+    embedder: Arc<dyn Embedder>,
+    state: RwLock<CacheState>,
+    metrics: Option<CacheMetrics>,
+}
+
+impl CachedChatbot {
+    /// `chat_config` is the generation configuration `inner` starts with.
+    pub fn new(
+        inner: Arc<dyn ChatbotService>,
+        config: &CacheConfig,
+        chat_config: ChatConfig,
+    ) -> Self {
+        let max_bytes = usize::try_from(config.max_size_mb.saturating_mul(1024 * 1024)).ok();
+        Self {
+            inner,
+            cache: MemoryCache::new(MemoryCacheConfig {
+                max_bytes,
+                ..MemoryCacheConfig::default()
+            }),
+            ttl: Duration::from_secs(config.ttl_seconds),
+            semantic_threshold: config.semantic_threshold,
+            embedder: Arc::new(HashingEmbedder::default()),
+            state: RwLock::new(CacheState {
+                config: chat_config,
+                generation: 0,
+                index: VecDeque::new(),
+            }),
+            metrics: None,
+        }
+    }
+
+    pub fn with_embedder(mut self, embedder: Arc<dyn Embedder>) -> Self {
+        self.embedder = embedder;
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: CacheMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    fn cacheable(message: &ChatMessage) -> bool {
+        message.metadata.as_ref().is_none_or(|metadata| metadata.session_id.is_none())
+    }
+
+    /// Responses depend on the locale through its system message.
+    fn locale(message: &ChatMessage) -> &str {
+        message.metadata.as_ref().and_then(|metadata| metadata.locale.as_deref()).unwrap_or("")
+    }
+
+    fn key(state: &CacheState, locale: &str, prompt: &str) -> String {
+        let config = &state.config;
+        format!(
+            "response:{}:{}:{:.3}:{}:{}:{}",
+            state.generation, config.model, config.temperature, config.max_tokens, locale, prompt
+        )
+    }
+
+    fn record(&self, result: &str) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record(result);
+        }
+    }
+
+    /// Returns the cached response for `prompt`, then the most similar cached one in `locale`.
+    async fn lookup(&self, locale: &str, prompt: &str) -> Option<ChatResponse> {
+        let (key, candidates) = {
+            let state = self.state.read().await;
+            let candidates = match self.semantic_threshold {
+                Some(threshold) => {
+                    let embedding = self.embedder.embed(prompt);
+                    let mut similar: Vec<_> = state
+                        .index
+                        .iter()
+                        .filter(|entry| entry.locale == locale)
+                        .map(|entry| (cosine(&embedding, &entry.embedding), entry.key.clone()))
+                        .filter(|(similarity, _)| *similarity >= threshold)
+                        .collect();
+                    similar.sort_by(|a, b| b.0.total_cmp(&a.0));
+                    similar
+                }
+                None => Vec::new(),
+            };
+            (Self::key(&state, locale, prompt), candidates)
+        };
+
+        if let Some(response) = self.cache.get(&key).await {
+            self.record("hit");
+            return Some(response);
+        }
+        for (similarity, key) in candidates {
+            if let Some(response) = self.cache.get(&key).await {
+                debug!("Answering from a cached near-duplicate (similarity {:.3})", similarity);
+                self.record("semantic_hit");
+                return Some(response);
+            }
+        }
+        self.record("miss");
+        None
+    }
+
+    /// Caches `response` unless the configuration changed while it was generated, or it
+    /// called tools, whose results may depend on the caller and on when they ran.
+    async fn store(&self, locale: &str, prompt: &str, generation: u64, response: &ChatResponse) {
+        if !response.metadata.tool_invocations.is_empty() {
+            return;
+        }
+        let mut state = self.state.write().await;
+        if state.generation != generation {
+            return;
+        }
+        let key = Self::key(&state, locale, prompt);
+        if !self.cache.set(&key, response, self.ttl).await {
+            return;
+        }
+        if self.semantic_threshold.is_some() {
+            if state.index.len() >= SEMANTIC_INDEX_CAPACITY {
+                state.index.pop_front();
+            }
+            state.index.push_back(IndexEntry {
+                locale: locale.to_string(),
+                embedding: self.embedder.embed(prompt),
+                key,
+            });
+        }
+    }
+
+    /// Adapts a cached response to the request it now answers, dropping what moderation
+    /// found in the request that produced it.
+    fn restamp(
+        mut response: ChatResponse,
+        request: &ChatMessage,
+        started: Instant,
+    ) -> ChatResponse {
+        response.message.metadata = request.metadata.as_ref().map(|metadata| MessageMetadata {
+            timestamp: Utc::now(),
+            user_id: metadata.user_id.clone(),
+            session_id: None,
+            locale: metadata.locale.clone(),
+        });
+        response.metadata.timestamp = Utc::now();
+        response.metadata.moderation = None;
+        response.metadata.processing_time_ms = started.elapsed().as_millis() as u64;
+        response
+    }
+}
+
+#[async_trait]
+impl ChatbotService for CachedChatbot {
+    async fn generate_response(&self, message: &ChatMessage) -> Result<ChatResponse, ChatbotError> {
+        if !Self::cacheable(message) {
+            return self.inner.generate_response(message).await;
+        }
+        let started = Instant::now();
+        let prompt = normalize_prompt(&message.content);
+        if let Some(response) = self.lookup(Self::locale(message), &prompt).await {
+            return Ok(Self::restamp(response, message, started));
+        }
+
+        let generation = self.state.read().await.generation;
+        let response = self.inner.generate_response(message).await?;
+        self.store(Self::locale(message), &prompt, generation, &response).await;
+        Ok(response)
+    }
+
+    /// Replays cached responses; misses are streamed by the inner service and not cached.
+    async fn generate_stream(&self, message: &ChatMessage) -> Result<TokenStream, ChatbotError> {
+        if Self::cacheable(message) {
+            let started = Instant::now();
+            if let Some(response) =
+                self.lookup(Self::locale(message), &normalize_prompt(&message.content)).await
+            {
+                return Ok(replay_tokens(
+                    split_tokens(&response.message.content),
+                    Duration::ZERO,
+                    response.metadata.model_version,
+                    ApproximateTokenCounter.count(&message.content),
+                    started,
+                ));
+            }
+        }
+        self.inner.generate_stream(message).await
+    }
+
+    async fn get_model_info(&self) -> Result<ModelInfo, ChatbotError> {
+        self.inner.get_model_info().await
+    }
+
+    async fn update_config(&self, config: ChatConfig) -> Result<(), ChatbotError> {
+        let mut state = self.state.write().await;
+        self.inner.update_config(config.clone()).await?;
+        state.config = config;
+        state.generation += 1;
+        state.index.clear();
+        debug!("Invalidated the response cache (generation {})", state.generation);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backends::ScriptedBackend;
+    use crate::services::LlmChatbot;
+
+    fn chat_config(temperature: f32) -> ChatConfig {
+        ChatConfig { model: "mock".to_string(), max_tokens: 100, temperature, stream: false }
+    }
+
+    fn cache_config(semantic_threshold: Option<f32>) -> CacheConfig {
+        CacheConfig { semantic_threshold, ..CacheConfig::default() }
+    }
+
+    fn message(content: &str, session_id: Option<&str>) -> ChatMessage {
+        ChatMessage {
+            role: "user".to_string(),
+            content: content.to_string(),
+            metadata: Some(MessageMetadata {
+                timestamp: Utc::now(),
+                user_id: Some("u1".to_string()),
+                session_id: session_id.map(str::to_string),
+                locale: None,
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exact_hits_bypass_the_backend_until_the_config_changes() {
+        let backend = Arc::new(ScriptedBackend::new());
+        let registry = MetricsRegistry::new("chatbot").unwrap();
+        let metrics = CacheMetrics::new(&registry).unwrap();
+        let chatbot = CachedChatbot::new(
+            Arc::new(LlmChatbot::new(backend.clone())),
+            &cache_config(None),
+            chat_config(0.7),
+        )
+        .with_metrics(metrics.clone());
+
+        chatbot.generate_response(&message("Hello  there!", None)).await.unwrap();
+        let cached = chatbot.generate_response(&message("hello there", None)).await.unwrap();
+        assert_eq!(cached.message.content, "Echo: Hello  there!");
+        assert_eq!(backend.calls().len(), 1);
+
+        // Session messages depend on history and always reach the backend.
+        chatbot.generate_response(&message("hello there", Some("s1"))).await.unwrap();
+        assert_eq!(backend.calls().len(), 2);
+
+        chatbot.update_config(chat_config(0.2)).await.unwrap();
+        chatbot.generate_response(&message("hello there", None)).await.unwrap();
+        assert_eq!(backend.calls().len(), 3);
+
+        assert_eq!(metrics.lookups.get(&["hit"]), 1.0);
+        assert_eq!(metrics.lookups.get(&["miss"]), 2.0);
+        assert!(registry.render().unwrap().contains("chatbot_response_cache_lookups_total"));
+    }
+
+    #[tokio::test]
+    async fn test_responses_that_called_tools_are_not_cached() {
+        use crate::backends::Generation;
+        use crate::models::ToolCall;
+        use crate::tools::{Tool, ToolRegistry};
+        use serde_json::{Value, json};
+
+        let call =
+            ToolCall { id: "call_1".to_string(), name: "time".to_string(), arguments: json!({}) };
+        let tools = ToolRegistry::new()
+            .with_tool(Tool::from_fn(
+                "time",
+                "The current time",
+                json!({"type": "object"}),
+                |_: Value| async move { Ok(Utc::now().to_rfc3339()) },
+            ))
+            .unwrap();
+        let backend = Arc::new(
+            ScriptedBackend::new()
+                .with_reply(Ok(Generation::tool_calls(vec![call.clone()])))
+                .with_reply(Ok(Generation::text("It is noon")))
+                .with_reply(Ok(Generation::tool_calls(vec![call])))
+                .with_reply(Ok(Generation::text("It is one o'clock"))),
+        );
+        let chatbot = CachedChatbot::new(
+            Arc::new(LlmChatbot::new(backend.clone()).with_tools(Arc::new(tools))),
+            &cache_config(None),
+            chat_config(0.7),
+        );
+
+        chatbot.generate_response(&message("What time is it?", None)).await.unwrap();
+        let again = chatbot.generate_response(&message("What time is it?", None)).await.unwrap();
+        assert_eq!(again.message.content, "It is one o'clock");
+        assert_eq!(backend.calls().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_near_duplicates_are_answered_when_similar_enough() {
+        let backend = Arc::new(ScriptedBackend::new());
+        let chatbot = CachedChatbot::new(
+            Arc::new(LlmChatbot::new(backend.clone())),
+            &cache_config(Some(0.9)),
+            chat_config(0.7),
+        );
+
+        let question = "What is the capital city of France?";
+        chatbot.generate_response(&message(question, None)).await.unwrap();
+        let similar = chatbot
+            .generate_response(&message("Please, what is the capital city of France", None))
+            .await
+            .unwrap();
+        assert_eq!(similar.message.content, format!("Echo: {}", question));
+        assert_eq!(backend.calls().len(), 1);
+
+        let other = "What is the capital city of Spain?";
+        chatbot.generate_response(&message(other, None)).await.unwrap();
+        assert_eq!(backend.calls().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_negated_questions_are_not_near_duplicates() {
+        let embedder = HashingEmbedder::default();
+        let similarity = |a: &str, b: &str| cosine(&embedder.embed(a), &embedder.embed(b));
+        assert!(
+            similarity(
+                "Is it safe to mix bleach and vinegar?",
+                "Is it safe to mix bleach and vinegar"
+            ) > 0.99
+        );
+        assert!(similarity("Is ibuprofen safe", "Is ibuprofen not safe") < 0.6);
+        assert!(similarity("Is ibuprofen safe", "Isn't ibuprofen safe") < 0.6);
+        assert!(similarity("It is not safe, is it?", "It is safe, is it?") < 0.6);
+
+        let backend = Arc::new(ScriptedBackend::new());
+        let chatbot = CachedChatbot::new(
+            Arc::new(LlmChatbot::new(backend.clone())),
+            &cache_config(Some(0.9)),
+            chat_config(0.7),
+        );
+        chatbot
+            .generate_response(&message("Is ibuprofen safe during pregnancy?", None))
+            .await
+            .unwrap();
+        let negated = chatbot
+            .generate_response(&message("Is ibuprofen not safe during pregnancy?", None))
+            .await
+            .unwrap();
+        assert_eq!(negated.message.content, "Echo: Is ibuprofen not safe during pregnancy?");
+        assert_eq!(backend.calls().len(), 2);
+    }
+}
@@ -1,13 +1,24 @@
-use anyhow::Result;
 use async_trait::async_trait;
-use rust_bert::pipelines::text_generation::{TextGenerationConfig, TextGenerationModel};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, info, warn};
 
+use crate::backends::{self, Generation, GenerationParams, LlmBackend};
+use crate::config::{ServiceConfig, SessionConfig};
 use crate::errors::ChatbotError;
-use crate::models::{ChatConfig, ChatMessage, ChatResponse, ResponseMetadata};
+use crate::models::{
+    ChatConfig, ChatMessage, ChatResponse, MessageMetadata, ModelInfo, ResponseMetadata,
+    ToolInvocation,
+};
+use crate::prompts::PromptLibrary;
+use crate::sessions::{ApproximateTokenCounter, ConversationContext, SessionManager, TokenCounter};
+use crate::streaming::{OnComplete, TokenStream, relay_tokens, replay_tokens, split_tokens};
+use crate::tools::ToolRegistry;
+
+/// Default for how often the model may call tools before it has to answer
+pub const DEFAULT_MAX_TOOL_ITERATIONS: usize = 5;
 
 /// Configuration for the HuggingFace chatbot model
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,111 +53,328 @@ impl Default for HuggingFaceConfig {
 pub trait ChatbotService: Send + Sync {
     /// Generate a response for the given message
     async fn generate_response(&self, message: &ChatMessage) -> Result<ChatResponse, ChatbotError>;
+    /// Generate a response as a stream of tokens followed by a metadata frame.
+    ///
+    /// Dropping the stream cancels generation. The default implementation, for services
+    /// that cannot stream, generates the whole response first and replays it token by token.
+    async fn generate_stream(&self, message: &ChatMessage) -> Result<TokenStream, ChatbotError> {
+        let started = Instant::now();
+        let response = self.generate_response(message).await?;
+        Ok(replay_tokens(
+            split_tokens(&response.message.content),
+            Duration::ZERO,
+            response.metadata.model_version,
+            ApproximateTokenCounter.count(&message.content),
+            started,
+        ))
+    }
     /// Get information about the model being used
-    async fn get_model_info(&self) -> Result<String, ChatbotError>;
+    async fn get_model_info(&self) -> Result<ModelInfo, ChatbotError>;
     /// Update the model configuration
     async fn update_config(&self, config: ChatConfig) -> Result<(), ChatbotError>;
 }
 
-/// Implementation of the HuggingFace-based chatbot
-pub struct HuggingFaceChatbot {
-    model: Arc<RwLock<TextGenerationModel>>,
-    config: Arc<RwLock<HuggingFaceConfig>>,
+/// Screens complete replies before they are stored in the session history.
+pub trait ReplyFilter: Send + Sync {
+    /// The reply to store, or `None` if the exchange must not be stored at all.
+    fn filter(&self, reply: &str) -> Option<String>;
 }
 
-impl HuggingFaceChatbot {
-    /// Create a new instance of the HuggingFace chatbot
-    pub async fn new(config: Option<HuggingFaceConfig>) -> Result<Self, ChatbotError> {
-        let config = config.unwrap_or_default();
-        info!("Initializing HuggingFace chatbot with model: {}", config.model_name);
-
-        let generation_config = TextGenerationConfig {
-            max_length: config.max_length,
-            num_beams: config.num_beams,
-            temperature: config.temperature,
-            top_k: config.top_k,
-            top_p: config.top_p,
-            do_sample: config.do_sample,
-            repetition_penalty: config.repetition_penalty,
-            ..Default::default()
-        };
+/// Chat service on top of a [`LlmBackend`].
+///
+/// Keeps conversation history in its [`SessionManager`], builds the context for each
+/// message and turns backend output into a [`ChatResponse`]. When the backend asks for
+/// tool calls, they are run and their results sent back until it answers.
+///
+/// Streams are relayed from the backend as it generates, unless the backend cannot stream
+/// or tools are registered; the response is then generated in full and replayed.
+pub struct LlmChatbot {
+    backend: Arc<dyn LlmBackend>,
+    params: RwLock<GenerationParams>,
+    token_delay: Duration,
+    sessions: Arc<SessionManager>,
+    prompts: Option<Arc<PromptLibrary>>,
+    tools: Arc<ToolRegistry>,
+    max_tool_iterations: usize,
+    reply_filter: Option<Arc<dyn ReplyFilter>>,
+}
 
-        let model = TextGenerationModel::new(Default::default()).map_err(|e| {
-            error!("Failed to initialize model: {:?}", e);
-            ChatbotError::ModelInitializationError(e.to_string())
-        })?;
+impl LlmChatbot {
+    pub fn new(backend: Arc<dyn LlmBackend>) -> Self {
+        Self {
+            backend,
+            params: RwLock::new(GenerationParams::default()),
+            token_delay: Duration::ZERO,
+            sessions: Arc::new(SessionManager::new(SessionConfig::default())),
+            prompts: None,
+            tools: Arc::new(ToolRegistry::new()),
+            max_tool_iterations: DEFAULT_MAX_TOOL_ITERATIONS,
+            reply_filter: None,
+        }
+    }
+
+    /// Creates the backend selected in `config` with its generation settings
+    pub async fn from_config(config: &ServiceConfig) -> Result<Self, ChatbotError> {
+        let huggingface = &config.model_config.huggingface;
+        Ok(Self::new(backends::from_config(config).await?)
+            .with_params(GenerationParams {
+                max_tokens: huggingface.max_length,
+                temperature: huggingface.temperature,
+            })
+            .with_sessions(Arc::new(SessionManager::new(config.sessions.clone())))
+            .with_max_tool_iterations(config.model_config.max_tool_iterations))
+    }
+
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = RwLock::new(params);
+        self
+    }
+
+    /// Wait `delay` before each streamed token, to exercise slow clients and cancellation
+    pub fn with_token_delay(mut self, delay: Duration) -> Self {
+        self.token_delay = delay;
+        self
+    }
 
-        Ok(Self { model: Arc::new(RwLock::new(model)), config: Arc::new(RwLock::new(config)) })
+    /// Use `sessions` for conversation history, e.g. to share it with the session endpoints
+    pub fn with_sessions(mut self, sessions: Arc<SessionManager>) -> Self {
+        self.sessions = sessions;
+        self
     }
 
-    /// Preprocess the input message before generation
-    async fn preprocess_message(&self, message: &ChatMessage) -> String {
+    pub fn sessions(&self) -> &Arc<SessionManager> {
+        &self.sessions
+    }
+
+    /// Send the system message of each request's locale ahead of the conversation
+    pub fn with_prompts(mut self, prompts: Arc<PromptLibrary>) -> Self {
+        self.prompts = Some(prompts);
+        self
+    }
+
+    /// Offer the tools of `tools` to the backend
+    pub fn with_tools(mut self, tools: Arc<ToolRegistry>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    /// Pass replies through `filter` before recording them, e.g. to keep moderated content
+    /// out of the history
+    pub fn with_reply_filter(mut self, filter: Arc<dyn ReplyFilter>) -> Self {
+        self.reply_filter = Some(filter);
+        self
+    }
+
+    /// Fail with a model error if the backend still calls tools after `iterations` rounds
+    pub fn with_max_tool_iterations(mut self, iterations: usize) -> Self {
+        self.max_tool_iterations = iterations;
+        self
+    }
+
+    /// Generates until the backend answers instead of calling tools.
+    async fn generate_with_tools(
+        &self,
+        context: &mut ConversationContext,
+        params: &GenerationParams,
+    ) -> Result<(Generation, Vec<ToolInvocation>), ChatbotError> {
+        context.tools = self.tools.definitions();
+        let mut generation = self.backend.generate(context, params).await?;
+        let mut invocations = Vec::new();
+        while !generation.tool_calls.is_empty() {
+            if context.tool_rounds.len() == self.max_tool_iterations {
+                warn!("Model still calling tools after {} rounds", self.max_tool_iterations);
+                return Err(ChatbotError::ModelError(format!(
+                    "No answer after {} rounds of tool calls",
+                    self.max_tool_iterations
+                )));
+            }
+            let calls = std::mem::take(&mut generation.tool_calls);
+            debug!("Running {} tool calls", calls.len());
+            let round =
+                futures::future::join_all(calls.into_iter().map(|call| self.tools.invoke(call)))
+                    .await;
+            invocations.extend(round.iter().cloned());
+            context.tool_rounds.push(round);
+            generation = self.backend.generate(context, params).await?;
+        }
+        Ok((generation, invocations))
+    }
+
+    /// The context and generation parameters for answering `message`.
+    async fn prepare(
+        &self,
+        message: &ChatMessage,
+    ) -> Result<(ConversationContext, GenerationParams), ChatbotError> {
         debug!("Preprocessing message: {:?}", message);
-        // Add any necessary preprocessing steps here
-        message.content.clone()
+        // Not part of the history, so it is sent with every turn and comes off its budget.
+        let system = self.system_message(message);
+        let system_tokens =
+            system.as_ref().map_or(0, |system| ApproximateTokenCounter.count(&system.content));
+        let mut context = self.sessions.build_context(message, system_tokens).await?;
+        if let Some(system) = system {
+            context.tokens += system_tokens;
+            context.messages.insert(0, system);
+        }
+        Ok((context, *self.params.read().await))
+    }
+
+    /// Generates the answer to `message` in full and records the exchange.
+    async fn respond(
+        &self,
+        message: &ChatMessage,
+        mut context: ConversationContext,
+        params: GenerationParams,
+        start_time: Instant,
+    ) -> Result<ChatResponse, ChatbotError> {
+        let (generation, tool_invocations) =
+            self.generate_with_tools(&mut context, &params).await?;
+        if generation.text.trim().is_empty() {
+            warn!("Model generated empty response");
+            return Err(ChatbotError::EmptyResponse);
+        }
+
+        let processing_time = start_time.elapsed().as_millis() as u64;
+        debug!("Response generated in {}ms", processing_time);
+        let response = self.format_response(message, generation, tool_invocations, processing_time);
+        record_exchange(
+            &self.sessions,
+            self.reply_filter.as_deref(),
+            message,
+            response.message.clone(),
+        )
+        .await?;
+        Ok(response)
+    }
+
+    /// The system message for `message`, in its locale or the default one
+    fn system_message(&self, message: &ChatMessage) -> Option<ChatMessage> {
+        let prompts = self.prompts.as_ref()?;
+        let locale = message
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.locale.as_deref())
+            .unwrap_or_else(|| prompts.catalog().default_locale());
+        Some(ChatMessage {
+            role: "system".to_string(),
+            content: prompts.system_message(locale)?.to_string(),
+            metadata: None,
+        })
     }
 
-    /// Convert model output to a structured response
-    async fn format_response(&self, output: String, processing_time: u64) -> ChatResponse {
-        let config = self.config.read().await;
+    /// Convert backend output to a structured response
+    fn format_response(
+        &self,
+        request: &ChatMessage,
+        generation: Generation,
+        tool_invocations: Vec<ToolInvocation>,
+        processing_time: u64,
+    ) -> ChatResponse {
+        let confidence = generation.confidence();
         ChatResponse {
-            message: ChatMessage { role: "assistant".to_string(), content: output, metadata: None },
+            message: reply_to(request, generation.text),
             metadata: ResponseMetadata {
                 timestamp: chrono::Utc::now(),
-                model_version: config.model_name.clone(),
+                model_version: generation.model.unwrap_or_else(|| self.backend.model_info().model),
                 processing_time_ms: processing_time,
-                confidence: Some(0.95), // TODO: Implement proper confidence calculation
+                confidence,
+                moderation: None,
+                tool_invocations,
             },
         }
     }
 }
 
+/// The assistant message answering `request` with `content`.
+fn reply_to(request: &ChatMessage, content: String) -> ChatMessage {
+    let metadata = request.metadata.as_ref().map(|request| MessageMetadata {
+        timestamp: chrono::Utc::now(),
+        user_id: request.user_id.clone(),
+        session_id: request.session_id.clone(),
+        locale: request.locale.clone(),
+    });
+    ChatMessage { role: "assistant".to_string(), content, metadata }
+}
+
+/// Adds `request` and `reply` to the request's session, if it names one and `filter`
+/// lets the reply through.
+async fn record_exchange(
+    sessions: &SessionManager,
+    filter: Option<&dyn ReplyFilter>,
+    request: &ChatMessage,
+    mut reply: ChatMessage,
+) -> Result<(), ChatbotError> {
+    let Some(metadata) = &request.metadata else { return Ok(()) };
+    let Some(session_id) = &metadata.session_id else { return Ok(()) };
+    if let Some(filter) = filter {
+        match filter.filter(&reply.content) {
+            Some(content) => reply.content = content,
+            None => return Ok(()),
+        }
+    }
+    sessions.record_exchange(session_id, metadata.user_id.as_deref(), request.clone(), reply).await
+}
+
 #[async_trait]
-impl ChatbotService for HuggingFaceChatbot {
+impl ChatbotService for LlmChatbot {
     async fn generate_response(&self, message: &ChatMessage) -> Result<ChatResponse, ChatbotError> {
-        let start_time = std::time::Instant::now();
-        let input_text = self.preprocess_message(message).await;
-
-        debug!("Generating response for input: {}", input_text);
-        let model = self.model.read().await;
-        let config = self.config.read().await;
-
-        let generation_config = TextGenerationConfig {
-            max_length: config.max_length,
-            num_beams: config.num_beams,
-            temperature: config.temperature,
-            top_k: config.top_k,
-            top_p: config.top_p,
-            do_sample: config.do_sample,
-            repetition_penalty: config.repetition_penalty,
-            ..Default::default()
-        };
-
-        let output = model.generate(&[input_text], Some(&generation_config)).map_err(|e| {
-            error!("Model generation error: {:?}", e);
-            ChatbotError::ModelError(e.to_string())
-        })?;
+        let start_time = Instant::now();
+        let (context, params) = self.prepare(message).await?;
+        self.respond(message, context, params, start_time).await
+    }
 
-        if let Some(response) = output.first() {
-            let processing_time = start_time.elapsed().as_millis() as u64;
-            debug!("Response generated in {}ms", processing_time);
-            Ok(self.format_response(response.clone(), processing_time).await)
+    async fn generate_stream(&self, message: &ChatMessage) -> Result<TokenStream, ChatbotError> {
+        let started = Instant::now();
+        let (context, params) = self.prepare(message).await?;
+        // Tool calls must run before the answer, so those requests are not streamed.
+        let deltas = if self.tools.is_empty() {
+            self.backend.generate_stream(&context, &params).await?
         } else {
-            warn!("Model generated empty response");
-            Err(ChatbotError::EmptyResponse)
-        }
+            None
+        };
+        let Some(deltas) = deltas else {
+            let response = self.respond(message, context, params, started).await?;
+            return Ok(replay_tokens(
+                split_tokens(&response.message.content),
+                self.token_delay,
+                response.metadata.model_version,
+                ApproximateTokenCounter.count(&message.content),
+                started,
+            ));
+        };
+
+        // The exchange is recorded once the whole reply has been generated.
+        let sessions = self.sessions.clone();
+        let filter = self.reply_filter.clone();
+        let request = message.clone();
+        let on_complete: OnComplete = Box::new(move |text: String| {
+            Box::pin(async move {
+                if text.trim().is_empty() {
+                    warn!("Model generated empty response");
+                    return Err(ChatbotError::EmptyResponse);
+                }
+                let reply = reply_to(&request, text);
+                record_exchange(&sessions, filter.as_deref(), &request, reply).await
+            })
+        });
+        Ok(relay_tokens(
+            deltas,
+            self.token_delay,
+            self.backend.model_info().model,
+            context.tokens,
+            started,
+            Some(on_complete),
+        ))
     }
 
-    async fn get_model_info(&self) -> Result<String, ChatbotError> {
-        let config = self.config.read().await;
-        Ok(format!("HuggingFace Model: {}", config.model_name))
+    async fn get_model_info(&self) -> Result<ModelInfo, ChatbotError> {
+        Ok(self.backend.model_info())
     }
 
     async fn update_config(&self, chat_config: ChatConfig) -> Result<(), ChatbotError> {
-        let mut config = self.config.write().await;
-        config.temperature = chat_config.temperature;
-        config.max_length = chat_config.max_tokens;
-        info!("Updated model configuration: {:?}", config);
+        let mut params = self.params.write().await;
+        params.temperature = chat_config.temperature;
+        params.max_tokens = chat_config.max_tokens;
+        info!("Updated generation parameters: {:?}", params);
         Ok(())
     }
 }
@@ -154,17 +382,23 @@ impl ChatbotService for HuggingFaceChatbot {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::backends::{RustBertBackend, ScriptedBackend};
+    use crate::models::StreamEvent;
     use chrono::Utc;
 
+    async fn local_chatbot() -> LlmChatbot {
+        LlmChatbot::new(Arc::new(RustBertBackend::new(None).await.unwrap()))
+    }
+
     #[tokio::test]
     async fn test_chatbot_initialization() {
-        let result = HuggingFaceChatbot::new(None).await;
+        let result = RustBertBackend::new(None).await;
         assert!(result.is_ok());
     }
 
     #[tokio::test]
     async fn test_generate_response() {
-        let chatbot = HuggingFaceChatbot::new(None).await.unwrap();
+        let chatbot = local_chatbot().await;
         let message = ChatMessage {
             role: "user".to_string(),
             content: "Hello, how are you?".to_string(),
@@ -177,7 +411,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_config_update() {
-        let chatbot = HuggingFaceChatbot::new(None).await.unwrap();
+        let chatbot = local_chatbot().await;
         let new_config = ChatConfig {
             model: "gpt2".to_string(),
             max_tokens: 150,
@@ -188,4 +422,175 @@ mod tests {
         let result = chatbot.update_config(new_config).await;
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_mock_streams_deterministically() {
+        use futures::StreamExt;
+
+        let chatbot = LlmChatbot::new(Arc::new(ScriptedBackend::new()))
+            .with_token_delay(Duration::from_millis(5));
+        let message = ChatMessage {
+            role: "user".to_string(),
+            content: "one two three".to_string(),
+            metadata: None,
+        };
+
+        let events: Vec<_> = chatbot.generate_stream(&message).await.unwrap().collect().await;
+        let text: String = events
+            .iter()
+            .filter_map(|event| match event {
+                Ok(StreamEvent::Token { text }) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text, "Echo: one two three");
+        assert!(matches!(events.last(), Some(Ok(StreamEvent::Done(_)))));
+    }
+
+    #[tokio::test]
+    async fn test_backend_receives_history_and_reports_confidence() {
+        let reply = Generation {
+            token_logprobs: Some(vec![0.0, 0.0]),
+            ..Generation::text("Nice to meet you")
+        };
+        let backend = Arc::new(ScriptedBackend::new().with_reply(Ok(reply)));
+        let chatbot = LlmChatbot::new(backend.clone());
+        let message = |content: &str| ChatMessage {
+            role: "user".to_string(),
+            content: content.to_string(),
+            metadata: Some(MessageMetadata {
+                timestamp: Utc::now(),
+                user_id: Some("u1".to_string()),
+                session_id: Some("s1".to_string()),
+                locale: None,
+            }),
+        };
+
+        let first = chatbot.generate_response(&message("Hi, I'm Ada")).await.unwrap();
+        assert_eq!(first.metadata.confidence, Some(1.0));
+        assert_eq!(first.metadata.model_version, "mock");
+
+        let second = chatbot.generate_response(&message("Who am I?")).await.unwrap();
+        assert_eq!(second.message.content, "Echo: Who am I?");
+        assert_eq!(second.metadata.confidence, None);
+
+        let calls = backend.calls();
+        let contents: Vec<_> = calls[1].messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["Hi, I'm Ada", "Nice to meet you", "Who am I?"]);
+        let info = chatbot.get_model_info().await.unwrap();
+        assert_eq!(info.backend, "scripted");
+    }
+
+    #[tokio::test]
+    async fn test_tool_results_are_fed_back_until_the_model_answers() {
+        use crate::models::ToolCall;
+        use crate::tools::{Tool, ToolRegistry};
+        use serde_json::{Value, json};
+
+        let add = |id: &str| ToolCall {
+            id: id.to_string(),
+            name: "add".to_string(),
+            arguments: json!({"a": 2, "b": 3}),
+        };
+        let tools = ToolRegistry::new()
+            .with_tool(Tool::from_fn(
+                "add",
+                "Adds two integers",
+                json!({"type": "object", "required": ["a", "b"]}),
+                |args: Value| async move {
+                    Ok(args["a"].as_i64().unwrap() + args["b"].as_i64().unwrap())
+                },
+            ))
+            .unwrap();
+        let backend = Arc::new(
+            ScriptedBackend::new()
+                .with_reply(Ok(Generation::tool_calls(vec![add("call_1")])))
+                .with_reply(Ok(Generation::text("2 + 3 = 5")))
+                .with_reply(Ok(Generation::tool_calls(vec![add("call_2")])))
+                .with_reply(Ok(Generation::tool_calls(vec![add("call_3")]))),
+        );
+        let chatbot = LlmChatbot::new(backend.clone())
+            .with_tools(Arc::new(tools))
+            .with_max_tool_iterations(1);
+        let message = ChatMessage {
+            role: "user".to_string(),
+            content: "What is 2 + 3?".to_string(),
+            metadata: None,
+        };
+
+        let response = chatbot.generate_response(&message).await.unwrap();
+        assert_eq!(response.message.content, "2 + 3 = 5");
+        let invocations = &response.metadata.tool_invocations;
+        assert_eq!(invocations.len(), 1);
+        assert_eq!(invocations[0].call.id, "call_1");
+        assert_eq!(invocations[0].output, Some(json!(5)));
+        let calls = backend.calls();
+        assert_eq!(calls[0].tools[0].name, "add");
+        assert!(calls[0].tool_rounds.is_empty());
+        assert_eq!(calls[1].tool_rounds.concat(), *invocations);
+
+        let looping = chatbot.generate_response(&message).await;
+        assert!(matches!(looping, Err(ChatbotError::ModelError(_))));
+    }
+
+    /// Streams whatever is sent through its channel; `generate` must not be called.
+    struct ChannelBackend(std::sync::Mutex<Option<crate::streaming::DeltaStream>>);
+
+    #[async_trait]
+    impl LlmBackend for ChannelBackend {
+        async fn generate(
+            &self,
+            _context: &ConversationContext,
+            _params: &GenerationParams,
+        ) -> Result<Generation, ChatbotError> {
+            panic!("streamed requests must not be generated in full")
+        }
+
+        async fn generate_stream(
+            &self,
+            _context: &ConversationContext,
+            _params: &GenerationParams,
+        ) -> Result<Option<crate::streaming::DeltaStream>, ChatbotError> {
+            Ok(self.0.lock().unwrap().take())
+        }
+
+        fn model_info(&self) -> ModelInfo {
+            ScriptedBackend::new().model_info()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tokens_are_relayed_while_the_backend_generates() {
+        use futures::StreamExt;
+        use futures::channel::mpsc;
+
+        let (deltas, receiver) = mpsc::unbounded();
+        let backend = ChannelBackend(std::sync::Mutex::new(Some(Box::pin(receiver))));
+        let chatbot = LlmChatbot::new(Arc::new(backend));
+        let message = ChatMessage {
+            role: "user".to_string(),
+            content: "Hi".to_string(),
+            metadata: Some(MessageMetadata {
+                timestamp: Utc::now(),
+                user_id: Some("u1".to_string()),
+                session_id: Some("s1".to_string()),
+                locale: None,
+            }),
+        };
+
+        let mut stream = chatbot.generate_stream(&message).await.unwrap();
+        deltas.unbounded_send(Ok("Hel".to_string())).unwrap();
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!(first, StreamEvent::Token { text: "Hel".to_string() });
+        // Nothing is recorded until the reply is complete.
+        assert!(chatbot.sessions().get("s1", Some("u1")).await.is_none());
+
+        deltas.unbounded_send(Ok("lo".to_string())).unwrap();
+        drop(deltas);
+        let rest: Vec<_> = stream.map(Result::unwrap).collect().await;
+        assert_eq!(rest[0], StreamEvent::Token { text: "lo".to_string() });
+        assert!(matches!(rest[1], StreamEvent::Done(_)));
+        let session = chatbot.sessions().get("s1", Some("u1")).await.unwrap();
+        assert_eq!(session.messages[1].content, "Hello");
+    }
 }
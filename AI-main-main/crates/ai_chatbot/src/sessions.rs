@@ -0,0 +1,752 @@
+//! Module: Sessions
+//! Server-side conversation sessions and token-budgeted context assembly.
+//!
+//! Sessions are keyed by the `session_id` carried in [`MessageMetadata`]. Each request is
+//! answered with as much recent history as fits the model's context window; older turns are
+//! either left out or folded into a running summary, depending on [`ContextOverflow`].
+//!
+//! [`MessageMetadata`]: crate::models::MessageMetadata
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+
+use crate::config::{ContextOverflow, SessionConfig};
+use crate::errors::ChatbotError;
+use crate::models::{ChatMessage, MessageMetadata, ToolDefinition, ToolInvocation};
+
+/// Introduces the summary of older turns in the prompt.
+const SUMMARY_PREFIX: &str = "Summary of the earlier conversation:\n";
+
+/// Counts how many model tokens a piece of text occupies.
+pub trait TokenCounter: Send + Sync {
+    fn count(&self, text: &str) -> usize;
+}
+
+/// Estimates roughly four characters per token, and never fewer tokens than words.
+pub struct ApproximateTokenCounter;
+
+impl TokenCounter for ApproximateTokenCounter {
+    fn count(&self, text: &str) -> usize {
+        text.chars().count().div_ceil(4).max(text.split_whitespace().count())
+    }
+}
+
+/// Condenses turns that no longer fit the context window.
+#[async_trait]
+pub trait Summarizer: Send + Sync {
+    /// Returns a summary covering `previous` (if any) followed by `turns`.
+    async fn summarize(
+        &self,
+        previous: Option<&str>,
+        turns: &[ChatMessage],
+    ) -> Result<String, ChatbotError>;
+}
+
+/// Keeps the first sentence of every turn. Needs no model, so it is the default.
+pub struct ExtractiveSummarizer;
+
+#[async_trait]
+impl Summarizer for ExtractiveSummarizer {
+    async fn summarize(
+        &self,
+        previous: Option<&str>,
+        turns: &[ChatMessage],
+    ) -> Result<String, ChatbotError> {
+        let mut lines: Vec<String> = previous.map(str::to_string).into_iter().collect();
+        for turn in turns {
+            let content = turn.content.trim();
+            let end = content.find(['.', '!', '?', '\n']).map_or(content.len(), |i| i + 1);
+            lines.push(format!("{}: {}", turn.role, content[..end].trim()));
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// A conversation and the history retained for it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    pub id: String,
+    /// The user that opened the session; other users cannot see or continue it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Summary of turns that were folded out of `messages`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    pub messages: Vec<ChatMessage>,
+}
+
+/// What session listings return for each session.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub message_count: usize,
+}
+
+impl From<&Session> for SessionInfo {
+    fn from(session: &Session) -> Self {
+        Self {
+            id: session.id.clone(),
+            user_id: session.user_id.clone(),
+            created_at: session.created_at,
+            updated_at: session.updated_at,
+            message_count: session.messages.len(),
+        }
+    }
+}
+
+/// The messages to send to the model for one request, oldest first.
+#[derive(Debug, Clone)]
+pub struct ConversationContext {
+    pub session_id: Option<String>,
+    pub messages: Vec<ChatMessage>,
+    /// Tokens used by `messages`
+    pub tokens: usize,
+    /// Tools the model may call
+    pub tools: Vec<ToolDefinition>,
+    /// Tool calls made for the current message and their results, oldest first
+    pub tool_rounds: Vec<Vec<ToolInvocation>>,
+}
+
+impl ConversationContext {
+    /// A context without tools.
+    pub fn new(session_id: Option<String>, messages: Vec<ChatMessage>, tokens: usize) -> Self {
+        Self { session_id, messages, tokens, tools: Vec::new(), tool_rounds: Vec::new() }
+    }
+
+    /// Renders the context as a plain-text transcript ending with the assistant's turn.
+    pub fn to_prompt(&self) -> String {
+        let mut prompt = String::new();
+        for message in &self.messages {
+            prompt.push_str(&format!("{}: {}\n", message.role, message.content));
+        }
+        prompt.push_str("assistant:");
+        prompt
+    }
+}
+
+/// Stores conversation sessions in memory and assembles per-request context from them.
+pub struct SessionManager {
+    config: SessionConfig,
+    sessions: RwLock<HashMap<String, Session>>,
+    tokens: Arc<dyn TokenCounter>,
+    summarizer: Arc<dyn Summarizer>,
+}
+
+impl SessionManager {
+    pub fn new(config: SessionConfig) -> Self {
+        Self {
+            config,
+            sessions: RwLock::new(HashMap::new()),
+            tokens: Arc::new(ApproximateTokenCounter),
+            summarizer: Arc::new(ExtractiveSummarizer),
+        }
+    }
+
+    /// Replaces the token estimate, e.g. with the model's own tokenizer.
+    pub fn with_token_counter(mut self, tokens: Arc<dyn TokenCounter>) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    /// Replaces the summarizer used when [`ContextOverflow::Summarize`] is configured.
+    pub fn with_summarizer(mut self, summarizer: Arc<dyn Summarizer>) -> Self {
+        self.summarizer = summarizer;
+        self
+    }
+
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    /// Tokens available to the prompt once the response reserve is set aside.
+    pub fn prompt_budget(&self) -> usize {
+        self.config.context_window_tokens.saturating_sub(self.config.reserved_response_tokens)
+    }
+
+    /// Builds the context for `message` from its session's history, leaving
+    /// `reserved_tokens` of the prompt budget for what is sent besides it, such as a system
+    /// prompt.
+    ///
+    /// Fails with [`ChatbotError::ContextWindowExceeded`] if the message alone does not fit,
+    /// and with [`ChatbotError::SessionNotFound`] if the session belongs to another user.
+    pub async fn build_context(
+        &self,
+        message: &ChatMessage,
+        reserved_tokens: usize,
+    ) -> Result<ConversationContext, ChatbotError> {
+        let budget = self.prompt_budget().saturating_sub(reserved_tokens);
+        let message_tokens = self.tokens.count(&message.content);
+        if message_tokens > budget {
+            return Err(ChatbotError::ContextWindowExceeded {
+                tokens: message_tokens,
+                limit: budget,
+            });
+        }
+
+        let Some(session_id) = session_id_of(message) else {
+            return Ok(ConversationContext::new(None, vec![message.clone()], message_tokens));
+        };
+
+        let summarizing = self.config.overflow == ContextOverflow::Summarize;
+        let (history, mut summary, folded, omitted) = {
+            let mut sessions = self.sessions.write().await;
+            let session = match sessions.get_mut(session_id) {
+                Some(session) if !self.is_expired(session, Utc::now()) => {
+                    check_owner(session, message)?;
+                    session
+                }
+                _ => {
+                    return Ok(ConversationContext::new(
+                        Some(session_id.to_string()),
+                        vec![message.clone()],
+                        message_tokens,
+                    ));
+                }
+            };
+
+            let mut remaining = budget - message_tokens;
+            if summarizing {
+                remaining = remaining.saturating_sub(self.config.summary_tokens);
+            }
+
+            // Walk back from the newest turn and keep whatever fits.
+            let mut kept = 0;
+            for turn in session.messages.iter().rev() {
+                let cost = self.tokens.count(&turn.content);
+                if cost > remaining {
+                    break;
+                }
+                remaining -= cost;
+                kept += 1;
+            }
+            let omitted = session.messages.len() - kept;
+
+            // Copied, not removed: the turns stay in the session until their summary is stored.
+            let folded =
+                if summarizing { session.messages[..omitted].to_vec() } else { Vec::new() };
+            let history = session.messages[session.messages.len() - kept..].to_vec();
+            (history, session.summary.clone(), folded, omitted)
+        };
+        if !folded.is_empty() {
+            summary = self.fold_oldest_turns(session_id, summary, &folded).await?;
+        }
+
+        let mut messages = Vec::with_capacity(history.len() + 2);
+        if let Some(summary) = summary.filter(|_| summarizing) {
+            messages.push(ChatMessage {
+                role: "system".to_string(),
+                content: format!("{}{}", SUMMARY_PREFIX, summary),
+                metadata: None,
+            });
+        }
+        messages.extend(history);
+        messages.push(message.clone());
+
+        let tokens = messages.iter().map(|m| self.tokens.count(&m.content)).sum();
+        debug!(
+            "Built context for session {}: {} messages, {} tokens, {} turns omitted",
+            session_id,
+            messages.len(),
+            tokens,
+            omitted
+        );
+        Ok(ConversationContext::new(Some(session_id.to_string()), messages, tokens))
+    }
+
+    /// Appends a completed exchange to the session, creating it on first use, and applies
+    /// the retention limit.
+    pub async fn record_exchange(
+        &self,
+        session_id: &str,
+        user_id: Option<&str>,
+        request: ChatMessage,
+        response: ChatMessage,
+    ) -> Result<(), ChatbotError> {
+        let now = Utc::now();
+        let (previous, evicted) = {
+            let mut sessions = self.sessions.write().await;
+            if sessions.get(session_id).is_some_and(|session| self.is_expired(session, now)) {
+                sessions.remove(session_id);
+            }
+            let session = sessions.entry(session_id.to_string()).or_insert_with(|| Session {
+                id: session_id.to_string(),
+                user_id: user_id.map(str::to_string),
+                created_at: now,
+                updated_at: now,
+                summary: None,
+                messages: Vec::new(),
+            });
+            check_owner(session, &request)?;
+
+            session.messages.push(request);
+            session.messages.push(response);
+            session.updated_at = now;
+            (session.summary.clone(), self.evict(session))
+        };
+        if !evicted.is_empty() {
+            // The reply is already out; the turns stay and are folded on a later exchange.
+            if let Err(e) = self.fold_oldest_turns(session_id, previous, &evicted).await {
+                warn!("Failed to summarise the oldest turns of session {}: {}", session_id, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the session `session_id` for `user_id` with `messages` as its history.
+    ///
+    /// Only user and assistant turns are accepted; system prompts are the service's own.
+    /// Messages keep their metadata; those without any are stamped with the import time
+    /// and `user_id`. Messages attributed to another user are rejected, as is an existing
+    /// session. The retention limit applies as if the messages had been exchanged.
+    pub async fn import(
+        &self,
+        session_id: &str,
+        user_id: Option<&str>,
+        messages: Vec<ChatMessage>,
+    ) -> Result<SessionInfo, ChatbotError> {
+        let now = Utc::now();
+        let mut messages = messages;
+        for message in &mut messages {
+            if !matches!(message.role.as_str(), "user" | "assistant") {
+                return Err(ChatbotError::InvalidInput(format!(
+                    "Unsupported message role: {}",
+                    message.role
+                )));
+            }
+            let metadata = message.metadata.get_or_insert_with(|| MessageMetadata {
+                timestamp: now,
+                user_id: user_id.map(str::to_string),
+                session_id: None,
+                locale: None,
+            });
+            if metadata.user_id.is_some() && metadata.user_id.as_deref() != user_id {
+                return Err(ChatbotError::InvalidInput(
+                    "Transcript contains messages of another user".to_string(),
+                ));
+            }
+            metadata.session_id = Some(session_id.to_string());
+        }
+
+        if self
+            .sessions
+            .read()
+            .await
+            .get(session_id)
+            .is_some_and(|session| !self.is_expired(session, now))
+        {
+            return Err(ChatbotError::SessionExists(session_id.to_string()));
+        }
+        let created_at = messages
+            .iter()
+            .filter_map(|message| message.metadata.as_ref().map(|metadata| metadata.timestamp))
+            .min()
+            .unwrap_or(now);
+        let mut session = Session {
+            id: session_id.to_string(),
+            user_id: user_id.map(str::to_string),
+            created_at,
+            updated_at: now,
+            summary: None,
+            messages,
+        };
+        // The session is not shared yet, so it can be summarised without the lock.
+        let evicted = self.evict(&mut session);
+        if !evicted.is_empty() {
+            session.summary = Some(self.summarize(None, &evicted).await?);
+            session.messages.drain(..evicted.len());
+        }
+
+        let info = SessionInfo::from(&session);
+        let mut sessions = self.sessions.write().await;
+        if sessions.get(session_id).is_some_and(|session| !self.is_expired(session, now)) {
+            return Err(ChatbotError::SessionExists(session_id.to_string()));
+        }
+        sessions.insert(session_id.to_string(), session);
+        Ok(info)
+    }
+
+    /// Returns the session if it exists, has not expired and is visible to `user_id`.
+    pub async fn get(&self, session_id: &str, user_id: Option<&str>) -> Option<Session> {
+        let sessions = self.sessions.read().await;
+        sessions
+            .get(session_id)
+            .filter(|session| !self.is_expired(session, Utc::now()))
+            .filter(|session| visible_to(session, user_id))
+            .cloned()
+    }
+
+    /// Lists live sessions, most recently active first, optionally only those of `user_id`.
+    pub async fn list(&self, user_id: Option<&str>) -> Vec<SessionInfo> {
+        let now = Utc::now();
+        let sessions = self.sessions.read().await;
+        let mut infos: Vec<SessionInfo> = sessions
+            .values()
+            .filter(|session| !self.is_expired(session, now))
+            .filter(|session| user_id.is_none() || session.user_id.as_deref() == user_id)
+            .map(SessionInfo::from)
+            .collect();
+        infos.sort_by_key(|info| std::cmp::Reverse(info.updated_at));
+        infos
+    }
+
+    /// Deletes the session and its history.
+    pub async fn delete(
+        &self,
+        session_id: &str,
+        user_id: Option<&str>,
+    ) -> Result<(), ChatbotError> {
+        let mut sessions = self.sessions.write().await;
+        match sessions.get(session_id) {
+            Some(session) if visible_to(session, user_id) => {
+                sessions.remove(session_id);
+                Ok(())
+            }
+            _ => Err(ChatbotError::SessionNotFound(session_id.to_string())),
+        }
+    }
+
+    /// Removes sessions that have been idle for longer than the configured TTL.
+    /// Returns how many were removed.
+    pub async fn purge_expired(&self) -> usize {
+        let now = Utc::now();
+        let mut sessions = self.sessions.write().await;
+        let before = sessions.len();
+        sessions.retain(|_, session| !self.is_expired(session, now));
+        before - sessions.len()
+    }
+
+    fn is_expired(&self, session: &Session, now: DateTime<Utc>) -> bool {
+        let ttl = Duration::seconds(self.config.idle_ttl_seconds.min(i64::MAX as u64) as i64);
+        session.updated_at + ttl < now
+    }
+
+    /// Drops the oldest messages beyond `max_messages`. When they are to be summarised they
+    /// are returned instead, and stay in the session until their summary is stored.
+    fn evict(&self, session: &mut Session) -> Vec<ChatMessage> {
+        let excess = session.messages.len().saturating_sub(self.config.max_messages);
+        if self.config.overflow == ContextOverflow::Summarize {
+            session.messages[..excess].to_vec()
+        } else {
+            session.messages.drain(..excess);
+            Vec::new()
+        }
+    }
+
+    /// Folds `turns`, the oldest of the session, into its summary and then removes them.
+    ///
+    /// The summarizer may call a model, so it runs without the session lock; `previous` is
+    /// the summary the turns are folded into. If another request replaced the summary in
+    /// the meantime, the turns are folded into that one instead. The turns are only removed
+    /// once the summary that covers them is stored, so a failing summarizer loses nothing.
+    /// Returns the summary to prompt with; if another request folded the turns meanwhile,
+    /// that is the summary it stored.
+    async fn fold_oldest_turns(
+        &self,
+        session_id: &str,
+        mut previous: Option<String>,
+        turns: &[ChatMessage],
+    ) -> Result<Option<String>, ChatbotError> {
+        loop {
+            let summary = self.summarize(previous.as_deref(), turns).await?;
+            let mut sessions = self.sessions.write().await;
+            match sessions.get_mut(session_id) {
+                Some(session) if !session.messages.starts_with(turns) => {
+                    return Ok(session.summary.clone());
+                }
+                Some(session) if session.summary != previous => {
+                    previous = session.summary.clone();
+                }
+                Some(session) => {
+                    session.messages.drain(..turns.len());
+                    session.summary = Some(summary.clone());
+                    return Ok(Some(summary));
+                }
+                // Deleted meanwhile; the summary only serves the current request.
+                None => return Ok(Some(summary)),
+            }
+        }
+    }
+
+    async fn summarize(
+        &self,
+        previous: Option<&str>,
+        turns: &[ChatMessage],
+    ) -> Result<String, ChatbotError> {
+        let summary = self.summarizer.summarize(previous, turns).await?;
+        let limit = self.config.summary_tokens.saturating_sub(self.tokens.count(SUMMARY_PREFIX));
+        Ok(self.clip(&summary, limit))
+    }
+
+    /// Drops the oldest words of `text` until it fits in `limit` tokens.
+    fn clip(&self, text: &str, limit: usize) -> String {
+        let mut clipped = text;
+        while self.tokens.count(clipped) > limit {
+            match clipped.split_once(char::is_whitespace) {
+                Some((_, rest)) => clipped = rest.trim_start(),
+                None => return String::new(),
+            }
+        }
+        clipped.to_string()
+    }
+}
+
+fn session_id_of(message: &ChatMessage) -> Option<&str> {
+    message.metadata.as_ref()?.session_id.as_deref()
+}
+
+fn user_id_of(message: &ChatMessage) -> Option<&str> {
+    message.metadata.as_ref()?.user_id.as_deref()
+}
+
+/// Sessions are visible to their owner only; anonymous ones only to anonymous callers.
+fn visible_to(session: &Session, user_id: Option<&str>) -> bool {
+    session.user_id.as_deref() == user_id
+}
+
+/// Reports another user's session as missing rather than revealing that it exists.
+fn check_owner(session: &Session, message: &ChatMessage) -> Result<(), ChatbotError> {
+    if visible_to(session, user_id_of(message)) {
+        Ok(())
+    } else {
+        Err(ChatbotError::SessionNotFound(session.id.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str, session_id: Option<&str>) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+            metadata: Some(MessageMetadata {
+                timestamp: Utc::now(),
+                user_id: Some("alice".to_string()),
+                session_id: session_id.map(str::to_string),
+                locale: None,
+            }),
+        }
+    }
+
+    fn config(overflow: ContextOverflow) -> SessionConfig {
+        SessionConfig {
+            max_messages: 50,
+            idle_ttl_seconds: 60,
+            context_window_tokens: 40,
+            reserved_response_tokens: 10,
+            summary_tokens: 12,
+            overflow,
+        }
+    }
+
+    async fn chat(manager: &SessionManager, turns: &[&str]) {
+        for turn in turns {
+            let request = message("user", turn, Some("s1"));
+            let reply = message("assistant", &format!("re {}", turn), Some("s1"));
+            manager.record_exchange("s1", Some("alice"), request, reply).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_message_larger_than_the_window_is_rejected() {
+        let manager = SessionManager::new(config(ContextOverflow::Truncate));
+        let long = "word ".repeat(31);
+        let err = manager.build_context(&message("user", &long, None), 0).await.unwrap_err();
+        assert!(matches!(err, ChatbotError::ContextWindowExceeded { limit: 30, .. }));
+
+        // A system prompt of 10 tokens leaves 20 for the message
+        let shorter = message("user", &"a ".repeat(25), None);
+        assert!(manager.build_context(&shorter, 0).await.is_ok());
+        let err = manager.build_context(&shorter, 10).await.unwrap_err();
+        assert!(matches!(err, ChatbotError::ContextWindowExceeded { limit: 20, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_truncation_keeps_the_newest_turns_that_fit() {
+        let manager = SessionManager::new(config(ContextOverflow::Truncate));
+        chat(&manager, &["one two three four five", "six seven eight", "nine ten"]).await;
+
+        let request = message("user", &"next ".repeat(12), Some("s1"));
+        let context = manager.build_context(&request, 0).await.unwrap();
+        let contents: Vec<_> = context.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(
+            contents,
+            vec![
+                "six seven eight",
+                "re six seven eight",
+                "nine ten",
+                "re nine ten",
+                request.content.as_str()
+            ]
+        );
+        assert!(context.tokens <= manager.prompt_budget());
+        // Truncation only affects the prompt; the history is retained.
+        assert_eq!(manager.get("s1", Some("alice")).await.unwrap().messages.len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_summarization_folds_older_turns_into_a_summary() {
+        let mut config = config(ContextOverflow::Summarize);
+        config.context_window_tokens = 60;
+        config.summary_tokens = 24;
+        let manager = SessionManager::new(config);
+        chat(&manager, &["my name is Ada. I like maths", "what is two plus two", "thanks"]).await;
+
+        let request = message("user", "and three plus three", Some("s1"));
+        let context = manager.build_context(&request, 0).await.unwrap();
+        assert_eq!(context.messages[0].role, "system");
+        assert!(
+            context.messages[0]
+                .content
+                .contains("user: my name is Ada.\nassistant: re my name is Ada.")
+        );
+        assert_eq!(context.messages.last().unwrap().content, request.content);
+        assert!(context.tokens <= manager.prompt_budget());
+
+        let session = manager.get("s1", Some("alice")).await.unwrap();
+        assert!(session.summary.is_some());
+        assert_eq!(session.messages.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_private_and_can_be_deleted() {
+        let manager = SessionManager::new(config(ContextOverflow::Truncate));
+        chat(&manager, &["hello"]).await;
+
+        assert_eq!(manager.list(Some("alice")).await.len(), 1);
+        assert!(manager.list(Some("bob")).await.is_empty());
+        assert!(manager.get("s1", Some("bob")).await.is_none());
+        let mut intruder = message("user", "hi", Some("s1"));
+        intruder.metadata.as_mut().unwrap().user_id = Some("bob".to_string());
+        assert!(matches!(
+            manager.build_context(&intruder, 0).await,
+            Err(ChatbotError::SessionNotFound(_))
+        ));
+
+        let mut anonymous = message("user", "hi", Some("s2"));
+        anonymous.metadata.as_mut().unwrap().user_id = None;
+        let reply = message("assistant", "hello", Some("s2"));
+        manager.record_exchange("s2", None, anonymous, reply).await.unwrap();
+        assert!(manager.get("s2", Some("bob")).await.is_none());
+        assert!(manager.delete("s2", Some("bob")).await.is_err());
+
+        assert!(manager.delete("s1", Some("bob")).await.is_err());
+        manager.delete("s1", Some("alice")).await.unwrap();
+        manager.delete("s2", None).await.unwrap();
+        assert!(manager.list(None).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_retention_and_expiry() {
+        let mut config = config(ContextOverflow::Truncate);
+        config.max_messages = 4;
+        let manager = SessionManager::new(config);
+        chat(&manager, &["a", "b", "c"]).await;
+        let session = manager.get("s1", Some("alice")).await.unwrap();
+        assert_eq!(session.messages.first().unwrap().content, "b");
+
+        manager.sessions.write().await.get_mut("s1").unwrap().updated_at -= Duration::hours(1);
+        assert!(manager.get("s1", Some("alice")).await.is_none());
+        assert_eq!(manager.purge_expired().await, 1);
+    }
+
+    struct FailingSummarizer;
+
+    #[async_trait]
+    impl Summarizer for FailingSummarizer {
+        async fn summarize(
+            &self,
+            _previous: Option<&str>,
+            _turns: &[ChatMessage],
+        ) -> Result<String, ChatbotError> {
+            Err(ChatbotError::ModelError("summarizer unavailable".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_summarization_keeps_the_turns() {
+        let mut config = config(ContextOverflow::Summarize);
+        config.context_window_tokens = 60;
+        config.summary_tokens = 24;
+        let manager = SessionManager::new(config).with_summarizer(Arc::new(FailingSummarizer));
+        chat(&manager, &["my name is Ada. I like maths", "what is two plus two", "thanks"]).await;
+
+        let request = message("user", "and three plus three", Some("s1"));
+        assert!(matches!(
+            manager.build_context(&request, 0).await,
+            Err(ChatbotError::ModelError(_))
+        ));
+        let session = manager.get("s1", Some("alice")).await.unwrap();
+        assert!(session.summary.is_none());
+        assert_eq!(session.messages.len(), 6);
+        assert_eq!(session.messages[0].content, "my name is Ada. I like maths");
+
+        // Evicting turns past the retention limit neither fails the exchange nor drops them
+        let mut config = config(ContextOverflow::Summarize);
+        config.max_messages = 2;
+        let manager = SessionManager::new(config).with_summarizer(Arc::new(FailingSummarizer));
+        chat(&manager, &["first", "second"]).await;
+        let session = manager.get("s1", Some("alice")).await.unwrap();
+        assert!(session.summary.is_none());
+        assert_eq!(session.messages.len(), 4);
+        assert_eq!(session.messages[0].content, "first");
+    }
+
+    /// Blocks in `summarize` until released, so tests can act while a summary is pending.
+    struct GatedSummarizer {
+        started: tokio::sync::Notify,
+        release: tokio::sync::Notify,
+    }
+
+    #[async_trait]
+    impl Summarizer for GatedSummarizer {
+        async fn summarize(
+            &self,
+            previous: Option<&str>,
+            turns: &[ChatMessage],
+        ) -> Result<String, ChatbotError> {
+            self.started.notify_one();
+            self.release.notified().await;
+            ExtractiveSummarizer.summarize(previous, turns).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sessions_stay_available_while_summarizing() {
+        let mut config = config(ContextOverflow::Summarize);
+        config.max_messages = 2;
+        config.summary_tokens = 24;
+        let gate = Arc::new(GatedSummarizer {
+            started: tokio::sync::Notify::new(),
+            release: tokio::sync::Notify::new(),
+        });
+        let manager = Arc::new(SessionManager::new(config).with_summarizer(gate.clone()));
+        chat(&manager, &["first"]).await;
+
+        let pending = tokio::spawn({
+            let manager = manager.clone();
+            async move { chat(&manager, &["second"]).await }
+        });
+        gate.started.notified().await;
+        let listed = tokio::time::timeout(std::time::Duration::from_secs(1), manager.list(None));
+        assert_eq!(listed.await.expect("lock held while summarizing").len(), 1);
+
+        gate.release.notify_one();
+        pending.await.unwrap();
+        let session = manager.get("s1", Some("alice")).await.unwrap();
+        assert_eq!(session.summary.as_deref(), Some("user: first\nassistant: re first"));
+        assert_eq!(session.messages.len(), 2);
+    }
+}
@@ -0,0 +1,191 @@
+//! Module: Streaming
+//! Token streams and their Server-Sent Events and WebSocket encodings.
+//!
+//! A [`TokenStream`] yields [`StreamEvent::Token`] frames followed by one
+//! [`StreamEvent::Done`] frame carrying usage and latency. Streams are lazy: dropping one,
+//! which is what actix does when the client disconnects, stops generation.
+//!
+//! Backends that generate incrementally produce a [`DeltaStream`], which
+//! [`relay_tokens`] forwards as it arrives; complete responses are replayed with
+//! [`replay_tokens`].
+
+use actix_web::{HttpResponse, http::header, web};
+use common::errors::ApiError;
+use futures::future::BoxFuture;
+use futures::stream::{self, Stream, StreamExt};
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use crate::errors::ChatbotError;
+use crate::models::{StreamEvent, StreamMetadata, Usage};
+use crate::sessions::{ApproximateTokenCounter, TokenCounter};
+
+pub type TokenStream = Pin<Box<dyn Stream<Item = Result<StreamEvent, ChatbotError>> + Send>>;
+
+/// Pieces of generated text, in order, as a backend produces them.
+pub type DeltaStream = Pin<Box<dyn Stream<Item = Result<String, ChatbotError>> + Send>>;
+
+/// Receives the complete text once a relayed stream has ended; an error is sent to the
+/// client instead of the final frame.
+pub type OnComplete =
+    Box<dyn FnOnce(String) -> BoxFuture<'static, Result<(), ChatbotError>> + Send>;
+
+/// Splits text into word-sized tokens that concatenate back to the original.
+pub fn split_tokens(text: &str) -> Vec<String> {
+    text.split_inclusive(char::is_whitespace).map(str::to_string).collect()
+}
+
+struct Relay {
+    deltas: DeltaStream,
+    delay: Duration,
+    model_version: String,
+    prompt_tokens: usize,
+    started: Instant,
+    generated: String,
+    first_token: Option<Duration>,
+    on_complete: Option<OnComplete>,
+    finished: bool,
+}
+
+/// Streams already generated `tokens`, waiting `delay` before each one.
+///
+/// `started` is when the request arrived, so latencies include the time spent generating.
+pub fn replay_tokens(
+    tokens: Vec<String>,
+    delay: Duration,
+    model_version: String,
+    prompt_tokens: usize,
+    started: Instant,
+) -> TokenStream {
+    let deltas = Box::pin(stream::iter(tokens.into_iter().map(Ok)));
+    relay_tokens(deltas, delay, model_version, prompt_tokens, started, None)
+}
+
+/// Forwards `deltas` as token frames while they are generated, waiting `delay` before
+/// each one, and ends with the final frame once they run out.
+///
+/// `on_complete` runs before the final frame, e.g. to record the exchange. The stream ends
+/// after the first error, from either of them.
+pub fn relay_tokens(
+    deltas: DeltaStream,
+    delay: Duration,
+    model_version: String,
+    prompt_tokens: usize,
+    started: Instant,
+    on_complete: Option<OnComplete>,
+) -> TokenStream {
+    let relay = Relay {
+        deltas,
+        delay,
+        model_version,
+        prompt_tokens,
+        started,
+        generated: String::new(),
+        first_token: None,
+        on_complete,
+        finished: false,
+    };
+
+    Box::pin(stream::unfold(relay, |mut relay| async move {
+        if relay.finished {
+            return None;
+        }
+        match relay.deltas.next().await {
+            Some(Ok(text)) => {
+                if !relay.delay.is_zero() {
+                    tokio::time::sleep(relay.delay).await;
+                }
+                relay.first_token.get_or_insert_with(|| relay.started.elapsed());
+                relay.generated.push_str(&text);
+                Some((Ok(StreamEvent::Token { text }), relay))
+            }
+            Some(Err(err)) => {
+                relay.finished = true;
+                Some((Err(err), relay))
+            }
+            None => {
+                relay.finished = true;
+                if let Some(on_complete) = relay.on_complete.take() {
+                    if let Err(err) = on_complete(relay.generated.clone()).await {
+                        return Some((Err(err), relay));
+                    }
+                }
+                let latency = relay.started.elapsed();
+                let completion_tokens = ApproximateTokenCounter.count(&relay.generated);
+                let metadata = StreamMetadata {
+                    model_version: relay.model_version.clone(),
+                    usage: Usage {
+                        prompt_tokens: relay.prompt_tokens,
+                        completion_tokens,
+                        total_tokens: relay.prompt_tokens + completion_tokens,
+                    },
+                    time_to_first_token_ms: relay.first_token.unwrap_or(latency).as_millis() as u64,
+                    latency_ms: latency.as_millis() as u64,
+                    moderation: None,
+                };
+                Some((Ok(StreamEvent::Done(metadata)), relay))
+            }
+        }
+    }))
+}
+
+/// Encodes a stream event as JSON; errors become `{"type": "error", "error": <problem>}`.
+pub fn event_json(event: &Result<StreamEvent, ChatbotError>) -> String {
+    let value = match event {
+        Ok(event) => serde_json::to_value(event),
+        Err(err) => serde_json::to_value(ApiError::from(err).to_problem(None))
+            .map(|problem| serde_json::json!({ "type": "error", "error": problem })),
+    };
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// Wraps a token stream in a `text/event-stream` response.
+///
+/// Each frame is sent as an SSE event named `token`, `done` or `error` whose data is the
+/// frame's JSON. The stream ends after `done` or the first error.
+pub fn sse_response(tokens: TokenStream) -> HttpResponse {
+    let mut failed = false;
+    let body = tokens
+        .take_while(move |event| {
+            let emit = !failed;
+            failed |= event.is_err();
+            futures::future::ready(emit)
+        })
+        .map(|event| {
+            let name = match &event {
+                Ok(StreamEvent::Token { .. }) => "token",
+                Ok(StreamEvent::Done(_)) => "done",
+                Err(_) => "error",
+            };
+            let frame = format!("event: {}\ndata: {}\n\n", name, event_json(&event));
+            Ok::<_, Infallible>(web::Bytes::from(frame))
+        });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_replay_ends_with_usage_and_latency() {
+        let tokens = split_tokens("Hello there, friend");
+        assert_eq!(tokens.concat(), "Hello there, friend");
+
+        let stream =
+            replay_tokens(tokens, Duration::from_millis(1), "mock".into(), 4, Instant::now());
+        let events: Vec<_> = stream.map(Result::unwrap).collect().await;
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], StreamEvent::Token { text: "Hello ".to_string() });
+        let StreamEvent::Done(metadata) = &events[3] else { panic!("missing final frame") };
+        assert_eq!(metadata.usage.prompt_tokens, 4);
+        assert_eq!(metadata.usage.completion_tokens, 5);
+        assert_eq!(metadata.usage.total_tokens, 9);
+        assert!(metadata.latency_ms >= metadata.time_to_first_token_ms);
+    }
+}
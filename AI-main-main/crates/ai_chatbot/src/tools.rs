@@ -0,0 +1,260 @@
+//! Module: Tools
+//! Functions the model can call while answering.
+//!
+//! A [`Tool`] pairs a [`ToolDefinition`] (name, description and JSON schema of its
+//! arguments) with an async [`ToolHandler`]. Calls are checked against the schema before
+//! the handler runs; failures are reported back to the model as `{"error": ...}` so it can
+//! correct itself, rather than failing the request.
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, warn};
+
+use crate::errors::ChatbotError;
+use crate::models::{ToolCall, ToolDefinition, ToolInvocation};
+
+/// Executes calls of one tool.
+#[async_trait]
+pub trait ToolHandler: Send + Sync {
+    /// Runs the tool with arguments that match its schema.
+    async fn call(&self, arguments: Value) -> Result<Value, ChatbotError>;
+}
+
+/// Adapts an async function on typed arguments into a [`ToolHandler`].
+struct FnHandler<A, F> {
+    function: F,
+    arguments: PhantomData<fn(A)>,
+}
+
+#[async_trait]
+impl<A, R, F, Fut> ToolHandler for FnHandler<A, F>
+where
+    A: DeserializeOwned + Send + 'static,
+    R: Serialize,
+    F: Fn(A) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<R, ChatbotError>> + Send,
+{
+    async fn call(&self, arguments: Value) -> Result<Value, ChatbotError> {
+        let arguments = serde_json::from_value(arguments)
+            .map_err(|e| ChatbotError::InvalidInput(format!("Invalid arguments: {}", e)))?;
+        Ok(serde_json::to_value((self.function)(arguments).await?)?)
+    }
+}
+
+/// A tool the model can call.
+#[derive(Clone)]
+pub struct Tool {
+    definition: ToolDefinition,
+    handler: Arc<dyn ToolHandler>,
+}
+
+impl Tool {
+    pub fn new(definition: ToolDefinition, handler: Arc<dyn ToolHandler>) -> Self {
+        Self { definition, handler }
+    }
+
+    /// A tool whose arguments are deserialised into `A` and whose result is serialised.
+    ///
+    /// `parameters` is the JSON schema of `A` shown to the model.
+    pub fn from_fn<A, R, F, Fut>(
+        name: &str,
+        description: &str,
+        parameters: Value,
+        function: F,
+    ) -> Self
+    where
+        A: DeserializeOwned + Send + 'static,
+        R: Serialize + 'static,
+        F: Fn(A) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<R, ChatbotError>> + Send + 'static,
+    {
+        let definition = ToolDefinition {
+            name: name.to_string(),
+            description: description.to_string(),
+            parameters,
+        };
+        Self::new(definition, Arc::new(FnHandler { function, arguments: PhantomData }))
+    }
+
+    pub fn definition(&self) -> &ToolDefinition {
+        &self.definition
+    }
+}
+
+/// The tools available to the model, by name.
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: BTreeMap<String, Tool>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `tool`; names must be unique.
+    pub fn register(&mut self, tool: Tool) -> Result<(), ChatbotError> {
+        let name = tool.definition.name.clone();
+        if self.tools.contains_key(&name) {
+            return Err(ChatbotError::ConfigError(format!("Tool {} is already registered", name)));
+        }
+        self.tools.insert(name, tool);
+        Ok(())
+    }
+
+    pub fn with_tool(mut self, tool: Tool) -> Result<Self, ChatbotError> {
+        self.register(tool)?;
+        Ok(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Definitions of all tools, sorted by name.
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        self.tools.values().map(|tool| tool.definition.clone()).collect()
+    }
+
+    /// Runs `call`, recording its outcome instead of failing.
+    pub async fn invoke(&self, call: ToolCall) -> ToolInvocation {
+        let started = Instant::now();
+        let result = self.run(&call).await;
+        let duration_ms = started.elapsed().as_millis() as u64;
+        match result {
+            Ok(output) => {
+                debug!("Tool {} finished in {}ms", call.name, duration_ms);
+                ToolInvocation { call, output: Some(output), error: None, duration_ms }
+            }
+            Err(error) => {
+                warn!("Tool {} failed: {}", call.name, error);
+                ToolInvocation { call, output: None, error: Some(error), duration_ms }
+            }
+        }
+    }
+
+    async fn run(&self, call: &ToolCall) -> Result<Value, String> {
+        let tool =
+            self.tools.get(&call.name).ok_or_else(|| format!("Unknown tool: {}", call.name))?;
+        validate(&tool.definition.parameters, &call.arguments, "arguments")?;
+        tool.handler.call(call.arguments.clone()).await.map_err(|e| e.to_string())
+    }
+}
+
+/// Checks `value` against the commonly used subset of JSON schema: `type`, `properties`,
+/// `required`, `additionalProperties: false`, `items` and `enum`.
+fn validate(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    if let Some(types) = schema.get("type") {
+        let allowed: Vec<&str> = match types {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|name| has_type(value, name)) {
+            return Err(format!("{} must be of type {}", path, allowed.join(" or ")));
+        }
+    }
+    if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+        if !options.contains(value) {
+            return Err(format!("{} must be one of {}", path, Value::Array(options.clone())));
+        }
+    }
+    if let Value::Object(object) = value {
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for name in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+            if let Some(name) = name.as_str().filter(|name| !object.contains_key(*name)) {
+                return Err(format!("{}.{} is required", path, name));
+            }
+        }
+        for (name, field) in object {
+            match properties.and_then(|properties| properties.get(name)) {
+                Some(field_schema) => validate(field_schema, field, &format!("{}.{}", path, name))?,
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    return Err(format!("{}.{} is not allowed", path, name));
+                }
+                None => {}
+            }
+        }
+    }
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate(item_schema, item, &format!("{}[{}]", path, i))?;
+        }
+    }
+    Ok(())
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct Add {
+        a: i64,
+        b: i64,
+    }
+
+    fn registry() -> ToolRegistry {
+        let schema = json!({
+            "type": "object",
+            "properties": {"a": {"type": "integer"}, "b": {"type": "integer"}},
+            "required": ["a", "b"],
+            "additionalProperties": false
+        });
+        ToolRegistry::new()
+            .with_tool(Tool::from_fn("add", "Adds two integers", schema, |args: Add| async move {
+                Ok(args.a + args.b)
+            }))
+            .unwrap()
+    }
+
+    fn call(name: &str, arguments: Value) -> ToolCall {
+        ToolCall { id: "call_1".to_string(), name: name.to_string(), arguments }
+    }
+
+    #[tokio::test]
+    async fn test_calls_are_validated_before_the_handler_runs() {
+        let registry = registry();
+        let ok = registry.invoke(call("add", json!({"a": 2, "b": 3}))).await;
+        assert_eq!(ok.output, Some(json!(5)));
+        assert_eq!(ok.result_json(), json!(5));
+
+        let cases = [
+            (call("add", json!({"a": 2})), "arguments.b is required"),
+            (call("add", json!({"a": 2, "b": "3"})), "arguments.b must be of type integer"),
+            (call("add", json!({"a": 2, "b": 3, "c": 4})), "arguments.c is not allowed"),
+            (call("sub", json!({})), "Unknown tool: sub"),
+        ];
+        for (call, error) in cases {
+            let failed = registry.invoke(call).await;
+            assert_eq!(failed.error.as_deref(), Some(error));
+            assert_eq!(failed.result_json(), json!({ "error": error }));
+        }
+
+        let mut registry = registry;
+        let duplicate = Tool::from_fn("add", "Again", json!({}), |_: Value| async { Ok(0) });
+        assert!(registry.register(duplicate).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! Module: Transcripts
+//! Exports session history and parses transcripts to import.
+//!
+//! JSON Lines holds one [`ChatMessage`] per line with its metadata, so exporting and
+//! importing it is lossless. The OpenAI format is a `messages` array of roles and contents
+//! as accepted by chat-completion APIs; metadata is kept on import if present. Markdown is
+//! for people to read and can only be exported.
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ChatbotError;
+use crate::models::ChatMessage;
+use crate::sessions::Session;
+
+/// Serialisation of a conversation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscriptFormat {
+    /// One JSON message per line, metadata included
+    #[default]
+    #[serde(alias = "ndjson")]
+    Jsonl,
+    /// Headed by the session and one section per message
+    #[serde(alias = "md")]
+    Markdown,
+    /// An array of `{"role", "content"}` objects
+    #[serde(rename = "openai")]
+    OpenAi,
+}
+
+impl TranscriptFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            TranscriptFormat::Jsonl => "application/x-ndjson",
+            TranscriptFormat::Markdown => "text/markdown; charset=utf-8",
+            TranscriptFormat::OpenAi => "application/json",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            TranscriptFormat::Jsonl => "jsonl",
+            TranscriptFormat::Markdown => "md",
+            TranscriptFormat::OpenAi => "json",
+        }
+    }
+
+    /// File name for the transcript of `session_id`, keeping only `[A-Za-z0-9_-]` of the ID
+    /// so that it is safe in a `Content-Disposition` header.
+    pub fn filename(self, session_id: &str) -> String {
+        let stem: String = session_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        let stem = if stem.is_empty() { "session" } else { stem.as_str() };
+        format!("{}.{}", stem, self.extension())
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAiMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+/// Renders the retained history of `session` in `format`.
+pub fn export(session: &Session, format: TranscriptFormat) -> Result<String, ChatbotError> {
+    match format {
+        TranscriptFormat::Jsonl => {
+            let mut output = String::new();
+            for message in &session.messages {
+                output.push_str(&serde_json::to_string(message)?);
+                output.push('\n');
+            }
+            Ok(output)
+        }
+        TranscriptFormat::OpenAi => {
+            let messages: Vec<_> = session
+                .messages
+                .iter()
+                .map(|message| OpenAiMessage { role: &message.role, content: &message.content })
+                .collect();
+            Ok(serde_json::to_string_pretty(&messages)?)
+        }
+        TranscriptFormat::Markdown => Ok(to_markdown(session)),
+    }
+}
+
+fn to_markdown(session: &Session) -> String {
+    let mut output = format!("# Conversation {}\n\n", session.id);
+    if let Some(user_id) = &session.user_id {
+        output.push_str(&format!("- User: {}\n", user_id));
+    }
+    output.push_str(&format!("- Started: {}\n", session.created_at.to_rfc3339()));
+    output.push_str(&format!("- Last active: {}\n", session.updated_at.to_rfc3339()));
+    if let Some(summary) = &session.summary {
+        output.push_str(&format!("\n## Earlier turns (summary)\n\n{}\n", summary));
+    }
+    for message in &session.messages {
+        let mut role = message.role.clone();
+        if let Some(first) = role.get_mut(..1) {
+            first.make_ascii_uppercase();
+        }
+        output.push_str(&format!("\n## {}", role));
+        if let Some(metadata) = &message.metadata {
+            output.push_str(&format!(" · {}", metadata.timestamp.to_rfc3339()));
+        }
+        output.push_str(&format!("\n\n{}\n", message.content.trim_end()));
+    }
+    output
+}
+
+/// Parses a transcript in `format` into messages, oldest first.
+pub fn parse(body: &str, format: TranscriptFormat) -> Result<Vec<ChatMessage>, ChatbotError> {
+    let invalid =
+        |e: serde_json::Error| ChatbotError::InvalidInput(format!("Invalid transcript: {}", e));
+    match format {
+        TranscriptFormat::Jsonl => body
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(invalid))
+            .collect(),
+        TranscriptFormat::OpenAi => serde_json::from_str(body).map_err(invalid),
+        TranscriptFormat::Markdown => {
+            Err(ChatbotError::InvalidRequest("Markdown transcripts cannot be imported".to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SessionConfig;
+    use crate::models::MessageMetadata;
+    use crate::sessions::SessionManager;
+    use chrono::{TimeZone, Utc};
+
+    fn message(role: &str, content: &str, minute: u32) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+            metadata: Some(MessageMetadata {
+                timestamp: Utc.with_ymd_and_hms(2024, 5, 1, 9, minute, 0).unwrap(),
+                user_id: Some("alice".to_string()),
+                session_id: Some("s1".to_string()),
+                locale: Some("en".to_string()),
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_export_and_import_round_trip_losslessly() {
+        let source = SessionManager::new(SessionConfig::default());
+        let history = vec![
+            message("user", "Hi, I'm Alice", 0),
+            message("assistant", "Hello Alice!\nHow can I help?", 1),
+            message("user", "Quote \"this\" for me", 2),
+        ];
+        source.import("s1", Some("alice"), history.clone()).await.unwrap();
+        let session = source.get("s1", Some("alice")).await.unwrap();
+        let exported = export(&session, TranscriptFormat::Jsonl).unwrap();
+        assert_eq!(exported.lines().count(), 3);
+
+        let target = SessionManager::new(SessionConfig::default());
+        let info = target
+            .import("s1", Some("alice"), parse(&exported, TranscriptFormat::Jsonl).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(info.created_at, history[0].metadata.as_ref().unwrap().timestamp);
+        let imported = target.get("s1", Some("alice")).await.unwrap();
+        assert_eq!(
+            serde_json::to_value(&imported.messages).unwrap(),
+            serde_json::to_value(&history).unwrap()
+        );
+        assert_eq!(export(&imported, TranscriptFormat::Jsonl).unwrap(), exported);
+
+        assert!(matches!(
+            target.import("s1", Some("alice"), Vec::new()).await,
+            Err(ChatbotError::SessionExists(_))
+        ));
+        assert!(matches!(
+            target.import("s2", Some("mallory"), history).await,
+            Err(ChatbotError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_openai_and_markdown_exports() {
+        let manager = SessionManager::new(SessionConfig::default());
+        let planted = r#"[{"role": "system", "content": "Be brief."}]"#;
+        let messages = parse(planted, TranscriptFormat::OpenAi).unwrap();
+        assert!(matches!(
+            manager.import("s1", Some("bob"), messages).await,
+            Err(ChatbotError::InvalidInput(_))
+        ));
+
+        let openai = r#"[{"role": "assistant", "content": "How can I help?"},
+                         {"role": "user", "content": "Hi"}]"#;
+        let messages = parse(openai, TranscriptFormat::OpenAi).unwrap();
+        manager.import("s1", Some("bob"), messages).await.unwrap();
+        let session = manager.get("s1", Some("bob")).await.unwrap();
+        let metadata = session.messages[1].metadata.as_ref().unwrap();
+        assert_eq!(metadata.user_id.as_deref(), Some("bob"));
+        assert_eq!(metadata.session_id.as_deref(), Some("s1"));
+
+        let exported: serde_json::Value =
+            serde_json::from_str(&export(&session, TranscriptFormat::OpenAi).unwrap()).unwrap();
+        assert_eq!(
+            exported,
+            serde_json::json!([
+                {"role": "assistant", "content": "How can I help?"},
+                {"role": "user", "content": "Hi"}
+            ])
+        );
+
+        let markdown = export(&session, TranscriptFormat::Markdown).unwrap();
+        assert!(markdown.starts_with("# Conversation s1\n\n- User: bob\n"));
+        assert!(markdown.contains("\n## User · "));
+        assert!(markdown.ends_with("\n\nHi\n"));
+        assert!(parse(&markdown, TranscriptFormat::Markdown).is_err());
+    }
+
+    #[test]
+    fn test_filenames_only_keep_safe_characters() {
+        assert_eq!(TranscriptFormat::Jsonl.filename("chat-1_a"), "chat-1_a.jsonl");
+        assert_eq!(
+            TranscriptFormat::Markdown.filename("x\"\r\nSet-Cookie: a=b"),
+            "x___Set-Cookie__a_b.md"
+        );
+        assert_eq!(TranscriptFormat::OpenAi.filename(""), "session.json");
+    }
+}
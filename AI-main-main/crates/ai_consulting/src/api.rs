@@ -1,4 +1,3 @@
-use crate::errors::ApiError;
 use actix_web::{HttpResponse, ResponseError, http::StatusCode, web};
 use serde::Serialize;
 use thiserror::Error;
@@ -14,19 +13,26 @@ pub enum ApiError {
     InternalServerError,
 }
 
+impl From<&ApiError> for common::errors::ApiError {
+    fn from(err: &ApiError) -> Self {
+        use common::errors::{ApiError as Problem, ErrorCode};
+
+        match err {
+            ApiError::InvalidInput(msg) => Problem::bad_request(msg.clone()),
+            ApiError::PredictionFailed(_) => {
+                Problem::new(ErrorCode::UnprocessableEntity, err.to_string())
+            }
+            ApiError::InternalServerError => Problem::internal(err),
+        }
+    }
+}
+
 impl ResponseError for ApiError {
     fn status_code(&self) -> StatusCode {
-        match self {
-            ApiError::InvalidInput(_) => StatusCode::BAD_REQUEST,
-            ApiError::PredictionFailed(_) => StatusCode::UNPROCESSABLE_ENTITY,
-            ApiError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
-        }
+        common::errors::ApiError::from(self).status_code()
     }
 
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code()).json(serde_json::json!({
-            "error": self.to_string(),
-            "code": self.status_code().as_u16()
-        }))
+        common::errors::ApiError::from(self).error_response()
     }
 }
@@ -1,6 +1,5 @@
-use actix_web::{HttpResponse, ResponseError};
-use serde::Serialize;
-use std::fmt;
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use common::errors::ApiError;
 use thiserror::Error;
 
 /// Application-wide errors.
@@ -16,36 +15,30 @@ pub enum AppError {
     Unauthorized,
 }
 
-/// Standardized error response for JSON serialization.
-#[derive(Serialize)]
-pub struct ErrorResponse {
-    pub error: String,
-    pub code: u16,
-    pub timestamp: String,
+impl From<&AppError> for ApiError {
+    fn from(err: &AppError) -> Self {
+        match err {
+            AppError::BadRequest(msg) => ApiError::bad_request(msg.clone()),
+            AppError::InternalError(_) => ApiError::internal(err),
+            AppError::NotFound(msg) => ApiError::not_found(msg.clone()),
+            AppError::Unauthorized => ApiError::unauthorized(err.to_string()),
+        }
+    }
 }
 
-impl ErrorResponse {
-    /// Creates a new ErrorResponse instance.
-    pub fn new(message: String, code: u16) -> Self {
-        Self { error: message, code, timestamp: chrono::Utc::now().to_rfc3339() }
+impl From<AppError> for ApiError {
+    fn from(err: AppError) -> Self {
+        ApiError::from(&err)
     }
 }
 
-/// Map application errors to HTTP responses.
+/// Map application errors to problem+json responses.
 impl ResponseError for AppError {
-    fn status_code(&self) -> actix_web::http::StatusCode {
-        match self {
-            AppError::BadRequest(_) => actix_web::http::StatusCode::BAD_REQUEST,
-            AppError::InternalError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::NotFound(_) => actix_web::http::StatusCode::NOT_FOUND,
-            AppError::Unauthorized => actix_web::http::StatusCode::UNAUTHORIZED,
-        }
+    fn status_code(&self) -> StatusCode {
+        ApiError::from(self).status_code()
     }
 
     fn error_response(&self) -> HttpResponse {
-        let error_message = self.to_string();
-        let status_code = self.status_code().as_u16();
-        let error_response = ErrorResponse::new(error_message, status_code);
-        HttpResponse::build(self.status_code()).json(error_response)
+        ApiError::from(self).error_response()
     }
 }
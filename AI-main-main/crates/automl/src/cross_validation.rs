@@ -0,0 +1,302 @@
+//! Resampling of training rows for scoring trials.
+//!
+//! A trial's model is trained and scored once per [`Fold`], and [`aggregate`] combines
+//! the fold metrics, so rankings of trials do not hinge on one lucky split. Folds hold row
+//! indices and leave it to the caller to select the rows.
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::errors::AutoMLError;
+use crate::models::{CrossValidationStrategy, ModelMetrics, TaskType};
+
+/// Rows to train on and rows to score the trained model on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fold {
+    pub train: Vec<usize>,
+    pub validation: Vec<usize>,
+}
+
+impl Fold {
+    /// A single shuffled split holding out `validation_fraction` of `n_rows`, keeping at
+    /// least one row on each side.
+    pub fn holdout(
+        n_rows: usize,
+        validation_fraction: f64,
+        seed: Option<u64>,
+    ) -> Result<Self, AutoMLError> {
+        if n_rows < 2 {
+            return Err(AutoMLError::InvalidInput(format!(
+                "Cannot hold out rows from {} rows",
+                n_rows
+            )));
+        }
+        let mut rows = shuffled(n_rows, seed);
+        let n_validation =
+            ((n_rows as f64 * validation_fraction).round() as usize).clamp(1, n_rows - 1);
+        let train = rows.split_off(n_validation);
+        Ok(Self { train, validation: rows })
+    }
+}
+
+/// The strategy for `task_type` when none is configured: time-series splits for time
+/// series, group k-fold when rows have groups, stratified k-fold for classification and
+/// k-fold otherwise.
+pub fn strategy_for(task_type: &TaskType, grouped: bool) -> CrossValidationStrategy {
+    match task_type {
+        TaskType::TimeSeries => CrossValidationStrategy::TimeSeriesSplit,
+        _ if grouped => CrossValidationStrategy::GroupKFold,
+        TaskType::BinaryClassification | TaskType::MultiClassification => {
+            CrossValidationStrategy::StratifiedKFold
+        }
+        _ => CrossValidationStrategy::KFold,
+    }
+}
+
+/// Splits the rows of `targets` into `n_splits` folds.
+///
+/// Stratification uses the targets as classes, and group k-fold needs one group per row.
+/// Time-series splits keep row order and ignore `seed`; the other strategies shuffle rows
+/// first.
+pub fn split(
+    strategy: CrossValidationStrategy,
+    n_splits: usize,
+    targets: &[f64],
+    groups: Option<&[u64]>,
+    seed: Option<u64>,
+) -> Result<Vec<Fold>, AutoMLError> {
+    let n_rows = targets.len();
+    if n_splits < 2 || n_rows < n_splits {
+        return Err(AutoMLError::ConfigError(format!(
+            "Cannot split {} rows into {} folds, need at least 2 folds with a row each",
+            n_rows, n_splits
+        )));
+    }
+
+    // Fold of every row, or the folds themselves for time series
+    let assignment = match strategy {
+        CrossValidationStrategy::KFold => {
+            let mut assignment = vec![0; n_rows];
+            for (position, row) in shuffled(n_rows, seed).into_iter().enumerate() {
+                assignment[row] = position * n_splits / n_rows;
+            }
+            assignment
+        }
+        CrossValidationStrategy::StratifiedKFold => {
+            // Dealing out each class in turn spreads it evenly over the folds
+            let mut classes: BTreeMap<i64, Vec<usize>> = BTreeMap::new();
+            for row in shuffled(n_rows, seed) {
+                classes.entry(targets[row].round() as i64).or_default().push(row);
+            }
+            let mut assignment = vec![0; n_rows];
+            for (position, row) in classes.into_values().flatten().enumerate() {
+                assignment[row] = position % n_splits;
+            }
+            assignment
+        }
+        CrossValidationStrategy::GroupKFold => {
+            let groups = groups.filter(|groups| groups.len() == n_rows).ok_or_else(|| {
+                AutoMLError::InvalidInput("Group k-fold needs one group per row".to_string())
+            })?;
+            group_assignment(groups, n_splits)?
+        }
+        CrossValidationStrategy::TimeSeriesSplit => return Ok(time_series_folds(n_rows, n_splits)),
+    };
+
+    Ok((0..n_splits)
+        .map(|fold| {
+            let (validation, train) = (0..n_rows).partition(|&row| assignment[row] == fold);
+            Fold { train, validation }
+        })
+        .collect())
+}
+
+/// Puts the largest groups first, each into the fold with the fewest rows so far.
+fn group_assignment(groups: &[u64], n_splits: usize) -> Result<Vec<usize>, AutoMLError> {
+    let mut sizes: HashMap<u64, usize> = HashMap::new();
+    for group in groups {
+        *sizes.entry(*group).or_default() += 1;
+    }
+    if sizes.len() < n_splits {
+        return Err(AutoMLError::InvalidInput(format!(
+            "Cannot split {} groups into {} folds",
+            sizes.len(),
+            n_splits
+        )));
+    }
+
+    let mut by_size: Vec<(u64, usize)> = sizes.into_iter().collect();
+    by_size.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let mut fold_sizes = vec![0; n_splits];
+    let mut fold_of_group = HashMap::new();
+    for (group, size) in by_size {
+        let fold = (0..n_splits).min_by_key(|&fold| fold_sizes[fold]).unwrap_or(0);
+        fold_sizes[fold] += size;
+        fold_of_group.insert(group, fold);
+    }
+
+    Ok(groups.iter().map(|group| fold_of_group[group]).collect())
+}
+
+/// Equal validation windows at the end of the rows, each trained on every row before it.
+fn time_series_folds(n_rows: usize, n_splits: usize) -> Vec<Fold> {
+    let window = (n_rows / (n_splits + 1)).max(1);
+    let first_end = n_rows.saturating_sub(n_splits * window).max(1);
+    (0..n_splits)
+        .map(|fold| first_end + fold * window)
+        .take_while(|&train_end| train_end < n_rows)
+        .map(|train_end| Fold {
+            train: (0..train_end).collect(),
+            validation: (train_end..(train_end + window).min(n_rows)).collect(),
+        })
+        .collect()
+}
+
+fn shuffled(n_rows: usize, seed: Option<u64>) -> Vec<usize> {
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let mut rows: Vec<usize> = (0..n_rows).collect();
+    rows.shuffle(&mut rng);
+    rows
+}
+
+/// The mean of every metric over `folds`, with its population standard deviation in
+/// `custom_metrics` as `<metric>_std`.
+///
+/// A metric missing from some folds is averaged over the folds that report it.
+pub fn aggregate(folds: &[ModelMetrics]) -> ModelMetrics {
+    let mut custom_metrics = HashMap::new();
+    let mut summarise = |name: &str, values: Vec<f64>| -> Option<f64> {
+        if values.is_empty() {
+            return None;
+        }
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / n;
+        custom_metrics.insert(format!("{}_std", name), variance.sqrt());
+        Some(mean)
+    };
+    let mut standard = |name: &str, metric: fn(&ModelMetrics) -> Option<f64>| {
+        summarise(name, folds.iter().filter_map(metric).collect())
+    };
+
+    let mut metrics = ModelMetrics {
+        accuracy: standard("accuracy", |m| m.accuracy),
+        precision: standard("precision", |m| m.precision),
+        recall: standard("recall", |m| m.recall),
+        f1_score: standard("f1_score", |m| m.f1_score),
+        auc_roc: standard("auc_roc", |m| m.auc_roc),
+        mse: standard("mse", |m| m.mse),
+        rmse: standard("rmse", |m| m.rmse),
+        mae: standard("mae", |m| m.mae),
+        r2_score: standard("r2_score", |m| m.r2_score),
+        custom_metrics: HashMap::new(),
+    };
+
+    let names: std::collections::BTreeSet<&String> = folds
+        .iter()
+        .flat_map(|fold| fold.custom_metrics.keys())
+        .filter(|name| !name.ends_with("_std"))
+        .collect();
+    let mut custom = HashMap::new();
+    for name in names {
+        let values = folds.iter().filter_map(|fold| fold.custom_metrics.get(name).copied());
+        if let Some(mean) = summarise(name, values.collect()) {
+            custom.insert(name.clone(), mean);
+        }
+    }
+    custom.extend(custom_metrics);
+    metrics.custom_metrics = custom;
+    metrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validation_rows(folds: &[Fold]) -> Vec<usize> {
+        let mut rows: Vec<usize> = folds.iter().flat_map(|fold| fold.validation.clone()).collect();
+        rows.sort_unstable();
+        rows
+    }
+
+    #[test]
+    fn test_folds_partition_rows_by_strategy() {
+        let targets: Vec<f64> = (0..30).map(|row| if row < 10 { 1.0 } else { 0.0 }).collect();
+        let all: Vec<usize> = (0..30).collect();
+
+        for strategy in [CrossValidationStrategy::KFold, CrossValidationStrategy::StratifiedKFold] {
+            let folds = split(strategy, 5, &targets, None, Some(3)).unwrap();
+            assert_eq!(validation_rows(&folds), all);
+            for fold in &folds {
+                assert_eq!(fold.validation.len(), 6);
+                assert_eq!(fold.train.len() + fold.validation.len(), 30);
+            }
+            assert_eq!(folds, split(strategy, 5, &targets, None, Some(3)).unwrap());
+        }
+        let stratified =
+            split(CrossValidationStrategy::StratifiedKFold, 5, &targets, None, Some(3)).unwrap();
+        for fold in &stratified {
+            let positives = fold.validation.iter().filter(|&&row| targets[row] == 1.0).count();
+            assert_eq!(positives, 2);
+        }
+
+        let groups: Vec<u64> = (0..30).map(|row| row / 4).collect();
+        let folds =
+            split(CrossValidationStrategy::GroupKFold, 3, &targets, Some(&groups), None).unwrap();
+        assert_eq!(validation_rows(&folds), all);
+        for fold in &folds {
+            for row in &fold.validation {
+                assert!(fold.train.iter().all(|train| groups[*train] != groups[*row]));
+            }
+        }
+        assert!(split(CrossValidationStrategy::GroupKFold, 3, &targets, None, None).is_err());
+
+        let folds =
+            split(CrossValidationStrategy::TimeSeriesSplit, 4, &targets, None, None).unwrap();
+        assert_eq!(folds.len(), 4);
+        assert_eq!(folds[0].train, (0..6).collect::<Vec<_>>());
+        assert_eq!(folds[0].validation, (6..12).collect::<Vec<_>>());
+        assert_eq!(folds[3].train.len(), 24);
+        assert_eq!(folds[3].validation, (24..30).collect::<Vec<_>>());
+
+        assert_eq!(
+            strategy_for(&TaskType::TimeSeries, true),
+            CrossValidationStrategy::TimeSeriesSplit
+        );
+        assert_eq!(strategy_for(&TaskType::Regression, true), CrossValidationStrategy::GroupKFold);
+        assert_eq!(
+            strategy_for(&TaskType::MultiClassification, false),
+            CrossValidationStrategy::StratifiedKFold
+        );
+        assert!(split(CrossValidationStrategy::KFold, 1, &targets, None, None).is_err());
+    }
+
+    #[test]
+    fn test_aggregate_reports_mean_and_standard_deviation() {
+        let fold = |accuracy: f64, log_loss: f64| ModelMetrics {
+            accuracy: Some(accuracy),
+            precision: None,
+            recall: None,
+            f1_score: None,
+            auc_roc: None,
+            mse: None,
+            rmse: None,
+            mae: None,
+            r2_score: None,
+            custom_metrics: [("log_loss".to_string(), log_loss)].into(),
+        };
+
+        let metrics = aggregate(&[fold(0.7, 0.6), fold(0.9, 0.2)]);
+        assert!((metrics.accuracy.unwrap() - 0.8).abs() < 1e-12);
+        assert!((metrics.custom_metrics["accuracy_std"] - 0.1).abs() < 1e-12);
+        assert!((metrics.custom_metrics["log_loss"] - 0.4).abs() < 1e-12);
+        assert!((metrics.custom_metrics["log_loss_std"] - 0.2).abs() < 1e-12);
+        assert_eq!(metrics.mse, None);
+        assert!(!metrics.custom_metrics.contains_key("mse_std"));
+    }
+}
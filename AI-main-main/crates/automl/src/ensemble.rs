@@ -0,0 +1,603 @@
+//! Ensembles of the best trials of a study.
+//!
+//! Ensembles are built from the out-of-fold predictions trials made while being
+//! cross-validated, so vote weights and meta-learners are fitted, and ensembles scored, on
+//! rows none of their members was trained on. Bagging lets the members vote: by majority,
+//! by averaging, or with weights from greedy ensemble selection (Caruana et al., 2004).
+//! Stacking fits a linear meta-learner on the predictions of the members.
+
+use serde::{Deserialize, Serialize};
+use std::iter;
+
+use crate::cross_validation::{self, Fold};
+use crate::errors::AutoMLError;
+use crate::models::{
+    CrossValidationStrategy, EnsembleConfig, EnsembleMember, EnsembleResult, EnsembleType,
+    OptimizationDirection, VotingMethod,
+};
+use crate::trees::{self, Predictor, TreeTask, argmax, softmax};
+
+/// Members added by greedy ensemble selection, counting repeats.
+pub const SELECTION_ROUNDS: usize = 50;
+
+/// The meta-learner of a stacked ensemble is scored by cross-validating it on this many
+/// folds of the out-of-fold predictions.
+const STACKING_FOLDS: usize = 5;
+
+const META_L2_REGULARIZATION: f64 = 1e-3;
+const META_ITERATIONS: usize = 300;
+
+/// A completed trial with the predictions it made for every scored row.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub trial_number: usize,
+    pub value: f64,
+    pub predictions: Vec<Vec<f64>>,
+}
+
+/// How the predictions of the members of an ensemble are combined.
+#[derive(Debug, Clone)]
+pub enum Combiner {
+    /// The majority class, or the median value
+    Vote,
+    /// The weighted mean of class probabilities or values, one weight per member
+    Average(Vec<f64>),
+    Stack(MetaLearner),
+}
+
+impl Combiner {
+    /// Combines one prediction per member into class probabilities or a single value.
+    pub fn combine(&self, task: TreeTask, predictions: &[&[f64]]) -> Vec<f64> {
+        match self {
+            Combiner::Vote => match task {
+                TreeTask::Classification { n_classes } => {
+                    // Shares of the vote, so the most probable class is the winner
+                    let mut votes = vec![0.0; n_classes];
+                    for prediction in predictions {
+                        votes[argmax(prediction)] += 1.0 / predictions.len() as f64;
+                    }
+                    votes
+                }
+                TreeTask::Regression => {
+                    let mut values: Vec<f64> = predictions.iter().map(|p| p[0]).collect();
+                    values.sort_by(f64::total_cmp);
+                    let middle = values.len() / 2;
+                    if values.len().is_multiple_of(2) {
+                        vec![(values[middle - 1] + values[middle]) / 2.0]
+                    } else {
+                        vec![values[middle]]
+                    }
+                }
+            },
+            Combiner::Average(weights) => {
+                let mut combined = vec![0.0; predictions[0].len()];
+                for (prediction, weight) in predictions.iter().zip(weights) {
+                    for (total, value) in combined.iter_mut().zip(prediction.iter()) {
+                        *total += weight * value;
+                    }
+                }
+                combined
+            }
+            Combiner::Stack(meta_learner) => meta_learner.predict(&predictions.concat()),
+        }
+    }
+
+    /// The combiner an ensemble was built with, for its members in the order of
+    /// [`EnsembleResult::members`].
+    pub fn from_result(result: &EnsembleResult) -> Result<Self, AutoMLError> {
+        match result.ensemble_type {
+            EnsembleType::Stacking => {
+                result.meta_learner.clone().map(Combiner::Stack).ok_or_else(|| {
+                    AutoMLError::InvalidInput(
+                        "A stacked ensemble needs its meta-learner".to_string(),
+                    )
+                })
+            }
+            EnsembleType::Bagging => match result.voting_method {
+                VotingMethod::Hard => Ok(Combiner::Vote),
+                VotingMethod::Soft | VotingMethod::Weighted => result
+                    .members
+                    .iter()
+                    .map(|member| member.weight)
+                    .collect::<Option<Vec<f64>>>()
+                    .map(Combiner::Average)
+                    .ok_or_else(|| {
+                        AutoMLError::InvalidInput("Every member needs a weight".to_string())
+                    }),
+            },
+            EnsembleType::Boosting => Err(cannot_boost()),
+        }
+    }
+}
+
+/// A linear model on the concatenated predictions of the members: ridge regression for
+/// regression, and softmax regression with an L2 penalty for classification.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetaLearner {
+    task: TreeTask,
+    /// The score of every class, or the value
+    outputs: Vec<LinearModel>,
+}
+
+/// One output of a meta-learner, with a weight per concatenated member prediction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinearModel {
+    pub intercept: f64,
+    pub weights: Vec<f64>,
+}
+
+impl LinearModel {
+    fn predict(&self, input: &[f64]) -> f64 {
+        self.intercept + self.weights.iter().zip(input).map(|(w, x)| w * x).sum::<f64>()
+    }
+}
+
+impl MetaLearner {
+    pub fn fit(task: TreeTask, inputs: &[Vec<f64>], targets: &[f64]) -> Self {
+        let width = inputs.first().map_or(0, Vec::len) + 1;
+        let n = inputs.len() as f64;
+        let rows: Vec<Vec<f64>> = inputs
+            .iter()
+            .map(|input| iter::once(1.0).chain(input.iter().copied()).collect())
+            .collect();
+
+        let weights = match task {
+            TreeTask::Regression => {
+                // Normal equations, leaving the intercept unpenalised
+                let mut gram = vec![vec![0.0; width]; width];
+                let mut moments = vec![0.0; width];
+                for (x, target) in rows.iter().zip(targets) {
+                    for i in 0..width {
+                        moments[i] += x[i] * target;
+                        for j in 0..width {
+                            gram[i][j] += x[i] * x[j];
+                        }
+                    }
+                }
+                for (i, row) in gram.iter_mut().enumerate().skip(1) {
+                    row[i] += META_L2_REGULARIZATION * n;
+                }
+                vec![solve(gram, moments)]
+            }
+            TreeTask::Classification { n_classes } => {
+                // Gradient descent with a step below the inverse smoothness of the loss
+                let max_norm =
+                    rows.iter().map(|x| x.iter().map(|v| v * v).sum::<f64>()).fold(0.0, f64::max);
+                let step = 1.0 / (0.5 * max_norm + META_L2_REGULARIZATION);
+                let mut weights = vec![vec![0.0; width]; n_classes];
+                for _ in 0..META_ITERATIONS {
+                    let mut gradient = vec![vec![0.0; width]; n_classes];
+                    for (x, target) in rows.iter().zip(targets) {
+                        let probabilities = softmax(&scores(&weights, x));
+                        for (class, p) in probabilities.iter().enumerate() {
+                            let residual = p - f64::from(u8::from(class == *target as usize));
+                            for (g, value) in gradient[class].iter_mut().zip(x) {
+                                *g += residual * value / n;
+                            }
+                        }
+                    }
+                    for (class_weights, class_gradient) in weights.iter_mut().zip(&gradient) {
+                        for (i, (w, g)) in class_weights.iter_mut().zip(class_gradient).enumerate()
+                        {
+                            let penalty = if i == 0 { 0.0 } else { META_L2_REGULARIZATION * *w };
+                            *w -= step * (g + penalty);
+                        }
+                    }
+                }
+                weights
+            }
+        };
+
+        let outputs = weights
+            .into_iter()
+            .map(|w| LinearModel { intercept: w[0], weights: w[1..].to_vec() })
+            .collect();
+        Self { task, outputs }
+    }
+
+    pub fn predict(&self, input: &[f64]) -> Vec<f64> {
+        let scores: Vec<f64> = self.outputs.iter().map(|output| output.predict(input)).collect();
+        match self.task {
+            TreeTask::Classification { .. } => softmax(&scores),
+            TreeTask::Regression => scores,
+        }
+    }
+}
+
+fn scores(weights: &[Vec<f64>], x: &[f64]) -> Vec<f64> {
+    weights.iter().map(|w| w.iter().zip(x).map(|(w, x)| w * x).sum()).collect()
+}
+
+/// Solves `a * x = b` by Gaussian elimination with partial pivoting.
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Vec<f64> {
+    let n = b.len();
+    for column in 0..n {
+        let pivot = (column..n).max_by(|i, j| a[*i][column].abs().total_cmp(&a[*j][column].abs()));
+        let pivot = pivot.unwrap_or(column);
+        a.swap(column, pivot);
+        b.swap(column, pivot);
+        if a[column][column].abs() < 1e-12 {
+            continue;
+        }
+        let (above, below) = a.split_at_mut(column + 1);
+        let pivot_row = &above[column];
+        for (offset, row) in below.iter_mut().enumerate() {
+            let factor = row[column] / pivot_row[column];
+            for (value, pivot_value) in row[column..].iter_mut().zip(&pivot_row[column..]) {
+                *value -= factor * pivot_value;
+            }
+            b[column + 1 + offset] -= factor * b[column];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let rest: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = if a[row][row].abs() < 1e-12 { 0.0 } else { (b[row] - rest) / a[row][row] };
+    }
+    x
+}
+
+/// The members of an ensemble, refitted on every row, combined as the ensemble was built.
+pub struct Ensemble {
+    task: TreeTask,
+    members: Vec<Box<dyn Predictor>>,
+    combiner: Combiner,
+}
+
+impl Ensemble {
+    /// Members must be in the order of [`EnsembleResult::members`].
+    pub fn new(members: Vec<Box<dyn Predictor>>, combiner: Combiner) -> Result<Self, AutoMLError> {
+        let task = members
+            .first()
+            .map(|member| member.task())
+            .ok_or_else(|| AutoMLError::InvalidInput("An ensemble needs members".to_string()))?;
+        if members.iter().any(|member| member.task() != task) {
+            return Err(AutoMLError::InvalidInput(
+                "Ensemble members must share their task".to_string(),
+            ));
+        }
+        if let Combiner::Average(weights) = &combiner {
+            if weights.len() != members.len() {
+                return Err(AutoMLError::InvalidInput(format!(
+                    "Expected one weight per member, got {} weights for {} members",
+                    weights.len(),
+                    members.len()
+                )));
+            }
+        }
+        Ok(Self { task, members, combiner })
+    }
+}
+
+impl Predictor for Ensemble {
+    fn task(&self) -> TreeTask {
+        self.task
+    }
+
+    fn predict(&self, features: &[f64]) -> Vec<f64> {
+        let predictions: Vec<Vec<f64>> =
+            self.members.iter().map(|member| member.predict(features)).collect();
+        let predictions: Vec<&[f64]> = predictions.iter().map(Vec::as_slice).collect();
+        self.combiner.combine(self.task, &predictions)
+    }
+}
+
+/// Rejects ensembles that cannot be built from trials.
+pub fn check(config: &EnsembleConfig) -> Result<(), AutoMLError> {
+    match config.ensemble_type {
+        EnsembleType::Boosting => Err(cannot_boost()),
+        EnsembleType::Stacking | EnsembleType::Bagging => Ok(()),
+    }
+}
+
+fn cannot_boost() -> AutoMLError {
+    AutoMLError::ConfigError(
+        "Trials cannot be boosted after the fact, use Bagging or Stacking ensembles".to_string(),
+    )
+}
+
+/// Builds the ensemble of `config` from the best `n_models` candidates.
+///
+/// Candidates are ranked by objective value, and their predictions must be for the rows
+/// of `targets`, in order. [`Combiner::from_result`] rebuilds the combiner of the members
+/// from the result. Greedy selection may leave out some of the candidates.
+pub fn build(
+    config: &EnsembleConfig,
+    task: TreeTask,
+    direction: &OptimizationDirection,
+    mut candidates: Vec<Candidate>,
+    targets: &[f64],
+    seed: Option<u64>,
+) -> Result<EnsembleResult, AutoMLError> {
+    check(config)?;
+    candidates.retain(|candidate| {
+        candidate.value.is_finite() && candidate.predictions.len() == targets.len()
+    });
+    candidates.sort_by(|a, b| match direction {
+        OptimizationDirection::Minimize => a.value.total_cmp(&b.value),
+        OptimizationDirection::Maximize => b.value.total_cmp(&a.value),
+    });
+    candidates.truncate(config.n_models.max(1));
+    if candidates.is_empty() || targets.is_empty() {
+        return Err(AutoMLError::InvalidInput(
+            "No completed trial has predictions to ensemble".to_string(),
+        ));
+    }
+
+    let (meta_learner, weights, predictions) = match config.ensemble_type {
+        EnsembleType::Stacking => {
+            let inputs: Vec<Vec<f64>> = (0..targets.len())
+                .map(|row| candidates.iter().flat_map(|c| c.predictions[row].clone()).collect())
+                .collect();
+            let predictions = cross_validate_meta_learner(task, &inputs, targets, seed)?;
+            (Some(MetaLearner::fit(task, &inputs, targets)), None, predictions)
+        }
+        _ => {
+            let weights = match config.voting_method {
+                VotingMethod::Weighted => selection_weights(task, &candidates, targets),
+                VotingMethod::Hard | VotingMethod::Soft => {
+                    vec![1.0 / candidates.len() as f64; candidates.len()]
+                }
+            };
+            // Members greedy selection never picked are left out
+            let (kept, weights): (Vec<Candidate>, Vec<f64>) =
+                candidates.into_iter().zip(weights).filter(|(_, weight)| *weight > 0.0).unzip();
+            candidates = kept;
+            let combiner = match config.voting_method {
+                VotingMethod::Hard => Combiner::Vote,
+                VotingMethod::Soft | VotingMethod::Weighted => Combiner::Average(weights.clone()),
+            };
+            let predictions = (0..targets.len())
+                .map(|row| {
+                    let members: Vec<&[f64]> =
+                        candidates.iter().map(|c| c.predictions[row].as_slice()).collect();
+                    combiner.combine(task, &members)
+                })
+                .collect();
+            (None, Some(weights), predictions)
+        }
+    };
+
+    let members = candidates
+        .iter()
+        .enumerate()
+        .map(|(index, candidate)| EnsembleMember {
+            trial_number: candidate.trial_number,
+            value: candidate.value,
+            weight: weights.as_ref().map(|weights| weights[index]),
+        })
+        .collect();
+    let result = EnsembleResult {
+        ensemble_type: config.ensemble_type.clone(),
+        voting_method: config.voting_method.clone(),
+        members,
+        metrics: trees::evaluate_predictions(task, &predictions, targets),
+        meta_learner,
+    };
+    Ok(result)
+}
+
+/// Weights from greedy selection with replacement: every round adds the candidate that
+/// most lowers the loss of the running average, and each weight is the share of rounds,
+/// up to the one with the lowest loss, that picked the candidate.
+fn selection_weights(task: TreeTask, candidates: &[Candidate], targets: &[f64]) -> Vec<f64> {
+    let width = candidates[0].predictions[0].len();
+    let mut sum = vec![vec![0.0; width]; targets.len()];
+    let mut counts = vec![0; candidates.len()];
+    let mut best = (f64::INFINITY, counts.clone());
+
+    for round in 1..=SELECTION_ROUNDS {
+        let loss_with = |candidate: &Candidate| -> f64 {
+            sum.iter()
+                .zip(&candidate.predictions)
+                .zip(targets)
+                .map(|((total, prediction), target)| {
+                    let average: Vec<f64> = total
+                        .iter()
+                        .zip(prediction)
+                        .map(|(total, value)| (total + value) / round as f64)
+                        .collect();
+                    loss(task, &average, *target)
+                })
+                .sum::<f64>()
+        };
+        let losses: Vec<f64> = candidates.iter().map(loss_with).collect();
+        let Some(pick) = (0..candidates.len()).min_by(|a, b| losses[*a].total_cmp(&losses[*b]))
+        else {
+            break;
+        };
+
+        for (total, value) in
+            sum.iter_mut().flatten().zip(candidates[pick].predictions.iter().flatten())
+        {
+            *total += value;
+        }
+        counts[pick] += 1;
+        if losses[pick] < best.0 {
+            best = (losses[pick], counts.clone());
+        }
+    }
+
+    let rounds: usize = best.1.iter().sum();
+    best.1.iter().map(|count| *count as f64 / rounds.max(1) as f64).collect()
+}
+
+/// Squared error for regression and log loss for classification.
+fn loss(task: TreeTask, prediction: &[f64], target: f64) -> f64 {
+    match task {
+        TreeTask::Classification { .. } => -prediction[target as usize].clamp(1e-15, 1.0).ln(),
+        TreeTask::Regression => (prediction[0] - target).powi(2),
+    }
+}
+
+/// Predictions of meta-learners fitted on the other folds, for every row of `inputs`.
+fn cross_validate_meta_learner(
+    task: TreeTask,
+    inputs: &[Vec<f64>],
+    targets: &[f64],
+    seed: Option<u64>,
+) -> Result<Vec<Vec<f64>>, AutoMLError> {
+    let strategy = match task {
+        TreeTask::Classification { .. } => CrossValidationStrategy::StratifiedKFold,
+        TreeTask::Regression => CrossValidationStrategy::KFold,
+    };
+    let folds =
+        cross_validation::split(strategy, STACKING_FOLDS.min(targets.len()), targets, None, seed)?;
+
+    let mut predictions = vec![Vec::new(); targets.len()];
+    for Fold { train, validation } in folds {
+        let train_inputs: Vec<Vec<f64>> = train.iter().map(|row| inputs[*row].clone()).collect();
+        let train_targets: Vec<f64> = train.iter().map(|row| targets[*row]).collect();
+        let meta_learner = MetaLearner::fit(task, &train_inputs, &train_targets);
+        for row in validation {
+            predictions[row] = meta_learner.predict(&inputs[row]);
+        }
+    }
+    Ok(predictions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TaskType;
+    use crate::trees::{
+        Dataset, GradientBoosting, GradientBoostingParams, RandomForest, RandomForestParams,
+    };
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// The class is whether the sum of two of three noisy features is positive.
+    fn data(n: usize) -> Dataset {
+        let mut rng = StdRng::seed_from_u64(5);
+        let features: Vec<Vec<f64>> =
+            (0..n).map(|_| (0..3).map(|_| rng.gen_range(-1.0..1.0)).collect()).collect();
+        let targets =
+            features.iter().map(|row| f64::from(u8::from(row[0] + row[1] > 0.0))).collect();
+        Dataset::new(features, targets).unwrap()
+    }
+
+    type Fit = fn(TreeTask, &Dataset) -> Box<dyn Predictor>;
+
+    fn models() -> Vec<Fit> {
+        vec![
+            |task, train| {
+                let params =
+                    RandomForestParams { n_estimators: 20, seed: Some(1), ..Default::default() };
+                Box::new(RandomForest::fit(&params, task, train).unwrap())
+            },
+            |task, train| {
+                let params = GradientBoostingParams {
+                    n_estimators: 30,
+                    seed: Some(1),
+                    ..Default::default()
+                };
+                Box::new(GradientBoosting::fit(&params, task, train, None).unwrap())
+            },
+            // A stump, much worse than the others
+            |task, train| {
+                let params = RandomForestParams {
+                    n_estimators: 5,
+                    max_depth: Some(1),
+                    seed: Some(1),
+                    ..Default::default()
+                };
+                Box::new(RandomForest::fit(&params, task, train).unwrap())
+            },
+        ]
+    }
+
+    #[test]
+    fn test_ensembles_of_out_of_fold_predictions() {
+        let data = data(400);
+        let task = TreeTask::new(&TaskType::BinaryClassification, &data).unwrap();
+        let folds = cross_validation::split(
+            CrossValidationStrategy::KFold,
+            4,
+            &data.targets,
+            None,
+            Some(2),
+        )
+        .unwrap();
+        let candidates: Vec<Candidate> = models()
+            .into_iter()
+            .enumerate()
+            .map(|(number, fit)| {
+                let mut predictions = vec![Vec::new(); data.len()];
+                for fold in &folds {
+                    let model = fit(task, &data.select(&fold.train));
+                    for row in &fold.validation {
+                        predictions[*row] = model.predict(&data.features[*row]);
+                    }
+                }
+                let metrics = trees::evaluate_predictions(task, &predictions, &data.targets);
+                Candidate {
+                    trial_number: number,
+                    value: 1.0 - metrics.accuracy.unwrap(),
+                    predictions,
+                }
+            })
+            .collect();
+        let best_log_loss = candidates
+            .iter()
+            .map(|c| trees::evaluate_predictions(task, &c.predictions, &data.targets))
+            .map(|metrics| metrics.custom_metrics["log_loss"])
+            .fold(f64::INFINITY, f64::min);
+
+        let build = |ensemble_type, voting_method, n_models| {
+            let config = EnsembleConfig { ensemble_type, n_models, voting_method };
+            build(
+                &config,
+                task,
+                &OptimizationDirection::Minimize,
+                candidates.clone(),
+                &data.targets,
+                Some(3),
+            )
+        };
+
+        let weighted = build(EnsembleType::Bagging, VotingMethod::Weighted, 3).unwrap();
+        let weights: Vec<f64> = weighted.members.iter().map(|m| m.weight.unwrap()).collect();
+        assert!((weights.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(weighted.metrics.custom_metrics["log_loss"] <= best_log_loss + 1e-12);
+
+        let soft = build(EnsembleType::Bagging, VotingMethod::Soft, 2).unwrap();
+        assert_eq!(soft.members.len(), 2);
+        assert!(soft.members.iter().all(|m| m.trial_number != 2));
+        let hard = build(EnsembleType::Bagging, VotingMethod::Hard, 3).unwrap();
+        assert!(hard.metrics.accuracy.unwrap() > 0.85);
+
+        assert!(matches!(Combiner::from_result(&hard), Ok(Combiner::Vote)));
+        assert!(weighted.meta_learner.is_none());
+
+        let stacked = build(EnsembleType::Stacking, VotingMethod::Soft, 3).unwrap();
+        assert!(stacked.members.iter().all(|m| m.weight.is_none()));
+        assert!(stacked.metrics.accuracy.unwrap() > 0.85, "{:?}", stacked.metrics);
+        // The meta-learner is stored with the result, so a stored ensemble can be rebuilt
+        let stored: EnsembleResult =
+            serde_json::from_str(&serde_json::to_string(&stacked).unwrap()).unwrap();
+        assert_eq!(stored.meta_learner, stacked.meta_learner);
+        let members = stored
+            .members
+            .iter()
+            .map(|member| models()[member.trial_number](task, &data))
+            .collect();
+        let ensemble = Ensemble::new(members, Combiner::from_result(&stored).unwrap()).unwrap();
+        let probabilities = ensemble.predict(&data.features[0]);
+        assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+
+        assert!(matches!(
+            build(EnsembleType::Boosting, VotingMethod::Soft, 3),
+            Err(AutoMLError::ConfigError(_))
+        ));
+    }
+
+    #[test]
+    fn test_meta_learner_recovers_linear_combination() {
+        let inputs: Vec<Vec<f64>> = (0..50).map(|i| vec![i as f64, (i * i % 7) as f64]).collect();
+        let targets: Vec<f64> = inputs.iter().map(|x| 1.0 + 2.0 * x[0] - 0.5 * x[1]).collect();
+        let meta_learner = MetaLearner::fit(TreeTask::Regression, &inputs, &targets);
+        let prediction = meta_learner.predict(&[10.0, 3.0]);
+        assert!((prediction[0] - 19.5).abs() < 0.05, "{:?}", prediction);
+    }
+}
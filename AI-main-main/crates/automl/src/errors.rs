@@ -24,12 +24,18 @@ pub enum AutoMLError {
     #[error("Invalid input: {0}")]
     InvalidInput(String),
 
+    #[error("Not found: {0}")]
+    NotFound(String),
+
     #[error("Feature extraction error: {0}")]
     FeatureExtractionError(String),
 
     #[error("Training error: {0}")]
     TrainingError(String),
 
+    #[error("Trial pruned: {0}")]
+    TrialPruned(String),
+
     #[error("Validation error: {0}")]
     ValidationError(String),
 
@@ -80,6 +86,7 @@ pub fn error_to_response(error: AutoMLError) -> actix_web::HttpResponse {
 
     let status = match error {
         AutoMLError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+        AutoMLError::NotFound(_) => StatusCode::NOT_FOUND,
         AutoMLError::ResourceExhausted(_) => StatusCode::TOO_MANY_REQUESTS,
         AutoMLError::HardwareError(_) => StatusCode::SERVICE_UNAVAILABLE,
         AutoMLError::DatabaseError(_) => StatusCode::SERVICE_UNAVAILABLE,
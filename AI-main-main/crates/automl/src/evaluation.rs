@@ -18,7 +18,7 @@ pub mod evaluation {
     /// Calculates the precision of predictions.
     pub fn precision(predictions: &[i32], targets: &[i32]) -> f64 {
         let true_positive =
-            predictions.iter().zip(targets.iter()).filter(|(p, t)| *p == 1 && *t == 1).count();
+            predictions.iter().zip(targets.iter()).filter(|(p, t)| **p == 1 && **t == 1).count();
         let predicted_positive = predictions.iter().filter(|&&p| p == 1).count();
         if predicted_positive == 0 { 0.0 } else { true_positive as f64 / predicted_positive as f64 }
     }
@@ -26,7 +26,7 @@ pub mod evaluation {
     /// Calculates the recall of predictions.
     pub fn recall(predictions: &[i32], targets: &[i32]) -> f64 {
         let true_positive =
-            predictions.iter().zip(targets.iter()).filter(|(p, t)| *p == 1 && *t == 1).count();
+            predictions.iter().zip(targets.iter()).filter(|(p, t)| **p == 1 && **t == 1).count();
         let actual_positive = targets.iter().filter(|&&t| t == 1).count();
         if actual_positive == 0 { 0.0 } else { true_positive as f64 / actual_positive as f64 }
     }
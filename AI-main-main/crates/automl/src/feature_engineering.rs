@@ -1,6 +1,829 @@
-/// Manages feature engineering processes for data preprocessing.
+//! Feature engineering of tabular rows, fitted on training rows and replayed on any other.
+//!
+//! A [`FeaturePipeline`] imputes missing values, decomposes timestamps, encodes categories,
+//! scales, adds products of features and selects among them, in that order. Every step
+//! stores what it learnt from the training rows, so a deserialised pipeline transforms new
+//! rows exactly as it transformed those of the trial it was fitted for. Only the training
+//! rows themselves are target encoded out of fold, so that none sees its own target.
+//!
+//! The choices of a pipeline are [`PipelineParams`], sampled by trials like any other
+//! parameter of the search space.
 
-pub fn engineer_features() {
-    println!("Feature engineering is underway...");
-    // Implement your feature engineering logic here.
+use chrono::{DateTime, Datelike, Timelike};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::errors::AutoMLError;
+use crate::models::{
+    CategoricalEncoding, ColumnKind, FeatureEngineeringConfig, ImputationStrategy, ParameterRange,
+    PolynomialFeatures, ScalingMethod, SearchSpace,
+};
+use crate::optimization::suggest_parameter;
+use crate::trees::{Dataset, TreeTask};
+
+/// Weight of the mean target of all rows in the target encoding of a category, in rows.
+const TARGET_SMOOTHING: f64 = 10.0;
+
+/// Folds the training rows are split into, each target encoded from the others.
+const TARGET_ENCODING_FOLDS: usize = 5;
+
+/// Calendar parts a timestamp column is decomposed into.
+const DATETIME_PARTS: usize = 5;
+
+const IMPUTATION: &str = "imputation";
+const SCALING: &str = "scaling";
+const CATEGORICAL_ENCODING: &str = "categorical_encoding";
+const POLYNOMIAL_FEATURES: &str = "polynomial_features";
+const CORRELATION_THRESHOLD: &str = "correlation_threshold";
+
+/// The choices of a pipeline made by a trial.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PipelineParams {
+    pub imputation: ImputationStrategy,
+    pub scaling: ScalingMethod,
+    pub categorical_encoding: CategoricalEncoding,
+    pub polynomial_features: PolynomialFeatures,
+    /// Features are only selected when the model configuration asks for it
+    pub selection: Option<Selection>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Selection {
+    pub variance_threshold: f64,
+    pub correlation_threshold: f64,
+}
+
+/// The parameters trials sample the choices of `config` from.
+///
+/// Lists with a single choice and an empty range of correlation thresholds are fixed
+/// rather than sampled.
+pub fn parameters(
+    config: &FeatureEngineeringConfig,
+    feature_selection: bool,
+) -> Result<Vec<(String, ParameterRange)>, AutoMLError> {
+    let mut parameters = Vec::new();
+    categorical(&mut parameters, IMPUTATION, &config.imputation)?;
+    categorical(&mut parameters, SCALING, &config.scaling)?;
+    categorical(&mut parameters, CATEGORICAL_ENCODING, &config.categorical_encoding)?;
+    categorical(&mut parameters, POLYNOMIAL_FEATURES, &config.polynomial_features)?;
+
+    if feature_selection {
+        let (low, high) = config.correlation_threshold;
+        if !(0.0 < low && low <= high && high <= 1.0) {
+            return Err(AutoMLError::ConfigError(format!(
+                "Correlation thresholds must be within (0, 1], got ({}, {})",
+                low, high
+            )));
+        }
+        if low < high {
+            parameters.push((
+                CORRELATION_THRESHOLD.to_string(),
+                ParameterRange::Continuous { low, high, log: false },
+            ));
+        }
+    }
+
+    Ok(parameters)
+}
+
+fn categorical<T: Serialize>(
+    parameters: &mut Vec<(String, ParameterRange)>,
+    name: &str,
+    choices: &[T],
+) -> Result<(), AutoMLError> {
+    if choices.len() < 2 {
+        return Ok(());
+    }
+    let choices = choices
+        .iter()
+        .map(|choice| match serde_json::to_value(choice)? {
+            Value::String(choice) => Ok(choice),
+            other => {
+                Err(AutoMLError::ConfigError(format!("Invalid choice of {}: {}", name, other)))
+            }
+        })
+        .collect::<Result<_, AutoMLError>>()?;
+    parameters.push((name.to_string(), ParameterRange::Categorical { choices }));
+    Ok(())
+}
+
+/// Adds the parameters of `config` to `space`, keeping parameters it already has.
+pub fn extend_search_space(
+    space: &mut SearchSpace,
+    config: &FeatureEngineeringConfig,
+    feature_selection: bool,
+) -> Result<(), AutoMLError> {
+    for (name, range) in parameters(config, feature_selection)? {
+        space.parameters.entry(name).or_insert(range);
+    }
+    Ok(())
+}
+
+impl PipelineParams {
+    /// The choices of a trial with `parameters`, taking the first choice, or the default
+    /// for an empty list, of those it did not sample.
+    pub fn from_parameters(
+        config: &FeatureEngineeringConfig,
+        feature_selection: bool,
+        parameters: &HashMap<String, Value>,
+    ) -> Result<Self, AutoMLError> {
+        let selection = feature_selection.then(|| Selection {
+            variance_threshold: config.variance_threshold,
+            correlation_threshold: parameters
+                .get(CORRELATION_THRESHOLD)
+                .and_then(Value::as_f64)
+                .unwrap_or(config.correlation_threshold.0),
+        });
+
+        Ok(Self {
+            imputation: choice(parameters, IMPUTATION, &config.imputation)?,
+            scaling: choice(parameters, SCALING, &config.scaling)?,
+            categorical_encoding: choice(
+                parameters,
+                CATEGORICAL_ENCODING,
+                &config.categorical_encoding,
+            )?,
+            polynomial_features: choice(
+                parameters,
+                POLYNOMIAL_FEATURES,
+                &config.polynomial_features,
+            )?,
+            selection,
+        })
+    }
+
+    /// Samples the choices of `config` in `trial` from their ranges in `space`, which
+    /// [`extend_search_space`] has added them to.
+    pub fn suggest(
+        trial: &optuna::Trial,
+        space: &SearchSpace,
+        config: &FeatureEngineeringConfig,
+        feature_selection: bool,
+    ) -> Result<Self, AutoMLError> {
+        let mut sampled = HashMap::new();
+        for (name, range) in parameters(config, feature_selection)? {
+            let range = space.parameters.get(&name).unwrap_or(&range);
+            sampled.insert(name.clone(), suggest_parameter(trial, &name, range)?);
+        }
+        Self::from_parameters(config, feature_selection, &sampled)
+    }
+}
+
+fn choice<T: DeserializeOwned + Copy + Default>(
+    parameters: &HashMap<String, Value>,
+    name: &str,
+    choices: &[T],
+) -> Result<T, AutoMLError> {
+    match parameters.get(name) {
+        Some(value) => serde_json::from_value(value.clone()).map_err(|_| {
+            AutoMLError::OptimizationError(format!("`{}` is not a valid choice: {}", name, value))
+        }),
+        None => Ok(choices.first().copied().unwrap_or_default()),
+    }
+}
+
+/// Fits a pipeline to the rows of `data` and returns it with the transformed rows.
+///
+/// Missing values are NaN. `column_kinds` has the kind of every column of `data`, or is
+/// empty if all of them are numeric.
+pub fn engineer_features(
+    params: PipelineParams,
+    column_kinds: &[ColumnKind],
+    task: TreeTask,
+    data: &Dataset,
+) -> Result<(FeaturePipeline, Dataset), AutoMLError> {
+    let (pipeline, features) = FeaturePipeline::fit_rows(params, column_kinds, task, data)?;
+    let transformed = Dataset::new(features, data.targets.clone())?;
+    Ok((pipeline, transformed))
+}
+
+/// Transforms fitted to training rows, applied in order to every row.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeaturePipeline {
+    pub params: PipelineParams,
+    n_inputs: usize,
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Step {
+    /// The value replacing missing values, by column
+    Impute {
+        values: Vec<f64>,
+    },
+    /// Whether each column is a timestamp, replaced by its calendar parts
+    DecomposeDatetime {
+        columns: Vec<bool>,
+    },
+    Encode {
+        encodings: Vec<Encoding>,
+    },
+    /// `(value - offset) / scale` of every column
+    Scale {
+        offsets: Vec<f64>,
+        scales: Vec<f64>,
+    },
+    /// Pairs of columns whose products are appended to the row
+    Products {
+        pairs: Vec<(usize, usize)>,
+    },
+    /// The columns kept, in order
+    Select {
+        columns: Vec<usize>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Encoding {
+    Keep,
+    /// Indicators of the categories seen in training; unseen categories have none
+    OneHot {
+        categories: Vec<i64>,
+    },
+    /// Encoded values by category, and of categories not seen in training
+    Target {
+        values: BTreeMap<i64, Vec<f64>>,
+        prior: Vec<f64>,
+    },
+}
+
+impl Step {
+    fn apply(&self, row: Vec<f64>) -> Vec<f64> {
+        match self {
+            Step::Impute { values } => row
+                .into_iter()
+                .zip(values)
+                .map(|(value, fill)| if value.is_nan() { *fill } else { value })
+                .collect(),
+            Step::DecomposeDatetime { columns } => row
+                .into_iter()
+                .zip(columns)
+                .flat_map(
+                    |(value, &datetime)| {
+                        if datetime { datetime_parts(value).to_vec() } else { vec![value] }
+                    },
+                )
+                .collect(),
+            Step::Encode { encodings } => row
+                .into_iter()
+                .zip(encodings)
+                .flat_map(|(value, encoding)| match encoding {
+                    Encoding::Keep => vec![value],
+                    Encoding::OneHot { categories } => {
+                        let category = value.round() as i64;
+                        categories.iter().map(|c| if *c == category { 1.0 } else { 0.0 }).collect()
+                    }
+                    Encoding::Target { values, prior } => {
+                        values.get(&(value.round() as i64)).unwrap_or(prior).clone()
+                    }
+                })
+                .collect(),
+            Step::Scale { offsets, scales } => row
+                .into_iter()
+                .zip(offsets.iter().zip(scales))
+                .map(|(value, (offset, scale))| (value - offset) / scale)
+                .collect(),
+            Step::Products { pairs } => {
+                let products: Vec<f64> = pairs.iter().map(|&(a, b)| row[a] * row[b]).collect();
+                let mut row = row;
+                row.extend(products);
+                row
+            }
+            Step::Select { columns } => columns.iter().map(|&column| row[column]).collect(),
+        }
+    }
+}
+
+impl FeaturePipeline {
+    /// Fits a pipeline to the rows of `data`, as [`engineer_features`] does.
+    pub fn fit(
+        params: PipelineParams,
+        column_kinds: &[ColumnKind],
+        task: TreeTask,
+        data: &Dataset,
+    ) -> Result<Self, AutoMLError> {
+        Ok(Self::fit_rows(params, column_kinds, task, data)?.0)
+    }
+
+    fn fit_rows(
+        params: PipelineParams,
+        column_kinds: &[ColumnKind],
+        task: TreeTask,
+        data: &Dataset,
+    ) -> Result<(Self, Vec<Vec<f64>>), AutoMLError> {
+        let n_inputs = data.n_features();
+        if data.is_empty() || data.features.len() != data.targets.len() {
+            return Err(AutoMLError::InvalidInput(
+                "Expected rows with one target each to engineer features from".to_string(),
+            ));
+        }
+        if n_inputs == 0 || data.features.iter().any(|row| row.len() != n_inputs) {
+            return Err(AutoMLError::InvalidInput(
+                "Rows must have the same, non-zero number of features".to_string(),
+            ));
+        }
+        let mut kinds = match column_kinds.len() {
+            0 => vec![ColumnKind::Numeric; n_inputs],
+            n if n == n_inputs => column_kinds.to_vec(),
+            n => {
+                return Err(AutoMLError::ConfigError(format!(
+                    "Expected the kinds of {} columns, got {}",
+                    n_inputs, n
+                )));
+            }
+        };
+
+        let mut pipeline = Self { params, n_inputs, steps: Vec::new() };
+        let mut rows = data.features.clone();
+        let mut push = |step: Step, rows: &mut Vec<Vec<f64>>| {
+            *rows = std::mem::take(rows).into_iter().map(|row| step.apply(row)).collect();
+            pipeline.steps.push(step);
+        };
+
+        let values = (0..n_inputs)
+            .map(|column| impute_value(&rows, column, kinds[column], pipeline.params.imputation))
+            .collect();
+        push(Step::Impute { values }, &mut rows);
+
+        if kinds.contains(&ColumnKind::Datetime) {
+            let columns = kinds.iter().map(|kind| *kind == ColumnKind::Datetime).collect();
+            push(Step::DecomposeDatetime { columns }, &mut rows);
+            kinds = kinds
+                .into_iter()
+                .flat_map(|kind| match kind {
+                    ColumnKind::Datetime => vec![ColumnKind::Numeric; DATETIME_PARTS],
+                    kind => vec![kind],
+                })
+                .collect();
+        }
+
+        let encoding = pipeline.params.categorical_encoding;
+        if encoding != CategoricalEncoding::Ordinal && kinds.contains(&ColumnKind::Categorical) {
+            let encodings: Vec<Encoding> = kinds
+                .iter()
+                .enumerate()
+                .map(|(column, kind)| match (kind, encoding) {
+                    (ColumnKind::Categorical, CategoricalEncoding::OneHot) => {
+                        one_hot(&rows, column)
+                    }
+                    (ColumnKind::Categorical, _) => {
+                        target_encoding(&rows, &data.targets, column, task, |_| true)
+                    }
+                    _ => Encoding::Keep,
+                })
+                .collect();
+            kinds = encodings
+                .iter()
+                .zip(kinds)
+                .flat_map(|(encoding, kind)| match encoding {
+                    Encoding::Keep => vec![kind],
+                    // Indicators are categories of their own, never scaled or multiplied
+                    Encoding::OneHot { categories } => {
+                        vec![ColumnKind::Categorical; categories.len()]
+                    }
+                    Encoding::Target { prior, .. } => vec![ColumnKind::Numeric; prior.len()],
+                })
+                .collect();
+            // Training rows are encoded without their own targets, new rows with every one
+            let out_of_fold = encode_out_of_fold(&rows, &encodings, task, &data.targets);
+            push(Step::Encode { encodings }, &mut rows);
+            if let Some(encoded) = out_of_fold {
+                rows = encoded;
+            }
+        }
+
+        // Category codes keep their values, only continuous features are scaled
+        let scaling = pipeline.params.scaling;
+        if scaling != ScalingMethod::None {
+            let (offsets, scales) = (0..kinds.len())
+                .map(|column| match kinds[column] {
+                    ColumnKind::Numeric => scale(&column_values(&rows, column), scaling),
+                    _ => (0.0, 1.0),
+                })
+                .unzip();
+            push(Step::Scale { offsets, scales }, &mut rows);
+        }
+
+        let continuous: Vec<usize> =
+            (0..kinds.len()).filter(|column| kinds[*column] == ColumnKind::Numeric).collect();
+        let squares = match pipeline.params.polynomial_features {
+            PolynomialFeatures::None => None,
+            PolynomialFeatures::Interactions => Some(false),
+            PolynomialFeatures::Quadratic => Some(true),
+        };
+        if let Some(squares) = squares {
+            let pairs: Vec<(usize, usize)> = continuous
+                .iter()
+                .enumerate()
+                .flat_map(|(i, &a)| {
+                    let first = if squares { i } else { i + 1 };
+                    continuous[first..].iter().map(move |&b| (a, b))
+                })
+                .collect();
+            kinds.extend(vec![ColumnKind::Numeric; pairs.len()]);
+            push(Step::Products { pairs }, &mut rows);
+        }
+
+        if let Some(selection) = pipeline.params.selection {
+            let columns = select(&rows, kinds.len(), selection);
+            push(Step::Select { columns }, &mut rows);
+        }
+
+        Ok((pipeline, rows))
+    }
+
+    /// Transforms `row` of raw features as the training rows were.
+    pub fn transform_row(&self, row: &[f64]) -> Result<Vec<f64>, AutoMLError> {
+        if row.len() != self.n_inputs {
+            return Err(AutoMLError::InvalidInput(format!(
+                "Expected {} features, got {}",
+                self.n_inputs,
+                row.len()
+            )));
+        }
+        Ok(self.steps.iter().fold(row.to_vec(), |row, step| step.apply(row)))
+    }
+
+    /// The rows of `data` transformed, checked to be finite.
+    pub fn transform(&self, data: &Dataset) -> Result<Dataset, AutoMLError> {
+        let features =
+            data.features.iter().map(|row| self.transform_row(row)).collect::<Result<_, _>>()?;
+        Dataset::new(features, data.targets.clone())
+    }
+}
+
+fn column_values(rows: &[Vec<f64>], column: usize) -> Vec<f64> {
+    rows.iter().map(|row| row[column]).collect()
+}
+
+/// The value missing values of `column` are replaced with; 0 if none is known.
+fn impute_value(
+    rows: &[Vec<f64>],
+    column: usize,
+    kind: ColumnKind,
+    strategy: ImputationStrategy,
+) -> f64 {
+    let mut observed: Vec<f64> =
+        rows.iter().map(|row| row[column]).filter(|value| !value.is_nan()).collect();
+    if observed.is_empty() {
+        return 0.0;
+    }
+    match (kind, strategy) {
+        (ColumnKind::Categorical, _) | (ColumnKind::Numeric, ImputationStrategy::MostFrequent) => {
+            most_frequent(&mut observed)
+        }
+        (ColumnKind::Datetime, _) | (ColumnKind::Numeric, ImputationStrategy::Median) => {
+            quantile(&mut observed, 0.5)
+        }
+        (ColumnKind::Numeric, ImputationStrategy::Mean) => mean(&observed),
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// The most frequent of `values`, the smallest of those tied.
+fn most_frequent(values: &mut [f64]) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let mut best = (values[0], 0);
+    for run in values.chunk_by(|a, b| a == b) {
+        if run.len() > best.1 {
+            best = (run[0], run.len());
+        }
+    }
+    best.0
+}
+
+/// Linearly interpolated quantile of `values`.
+fn quantile(values: &mut [f64], q: f64) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let position = q * (values.len() - 1) as f64;
+    let (low, high) = (position.floor() as usize, position.ceil() as usize);
+    values[low] + (values[high] - values[low]) * (position - low as f64)
+}
+
+/// Year, month, day, day of the week from Monday and hour of a Unix timestamp in seconds,
+/// all zero if it is out of range.
+fn datetime_parts(timestamp: f64) -> [f64; DATETIME_PARTS] {
+    match DateTime::from_timestamp(timestamp.floor() as i64, 0) {
+        Some(datetime) => [
+            datetime.year() as f64,
+            datetime.month() as f64,
+            datetime.day() as f64,
+            datetime.weekday().num_days_from_monday() as f64,
+            datetime.hour() as f64,
+        ],
+        None => [0.0; DATETIME_PARTS],
+    }
+}
+
+fn one_hot(rows: &[Vec<f64>], column: usize) -> Encoding {
+    let mut categories: Vec<i64> = rows.iter().map(|row| row[column].round() as i64).collect();
+    categories.sort_unstable();
+    categories.dedup();
+    Encoding::OneHot { categories }
+}
+
+/// The mean target of every category, or its class frequencies with more than two
+/// classes, shrunk towards those of all rows. Only rows whose index is `fitted` count.
+fn target_encoding(
+    rows: &[Vec<f64>],
+    targets: &[f64],
+    column: usize,
+    task: TreeTask,
+    fitted: impl Fn(usize) -> bool,
+) -> Encoding {
+    let width = match task {
+        TreeTask::Classification { n_classes } if n_classes > 2 => n_classes,
+        _ => 1,
+    };
+    let encode = |target: f64| -> Vec<f64> {
+        if width == 1 {
+            return vec![target];
+        }
+        let mut classes = vec![0.0; width];
+        classes[(target.max(0.0) as usize).min(width - 1)] = 1.0;
+        classes
+    };
+
+    let mut prior = vec![0.0; width];
+    let mut n_rows = 0.0;
+    let mut sums: BTreeMap<i64, (Vec<f64>, f64)> = BTreeMap::new();
+    for (index, (row, target)) in rows.iter().zip(targets).enumerate() {
+        if !fitted(index) {
+            continue;
+        }
+        let encoded = encode(*target);
+        let (sum, count) =
+            sums.entry(row[column].round() as i64).or_insert_with(|| (vec![0.0; width], 0.0));
+        for k in 0..width {
+            sum[k] += encoded[k];
+            prior[k] += encoded[k];
+        }
+        *count += 1.0;
+        n_rows += 1.0;
+    }
+    for prior in &mut prior {
+        *prior /= n_rows;
+    }
+
+    let values = sums
+        .into_iter()
+        .map(|(category, (sum, count))| {
+            let smoothed = sum
+                .iter()
+                .zip(&prior)
+                .map(|(sum, prior)| (sum + TARGET_SMOOTHING * prior) / (count + TARGET_SMOOTHING))
+                .collect();
+            (category, smoothed)
+        })
+        .collect();
+    Encoding::Target { values, prior }
+}
+
+/// `rows` encoded by `encodings`, with the target encodings of each row fitted to the rows
+/// of the other [`TARGET_ENCODING_FOLDS`] folds, so that no target leaks into its own
+/// features. `None` when nothing is target encoded or there are too few rows to fold.
+fn encode_out_of_fold(
+    rows: &[Vec<f64>],
+    encodings: &[Encoding],
+    task: TreeTask,
+    targets: &[f64],
+) -> Option<Vec<Vec<f64>>> {
+    let n_folds = TARGET_ENCODING_FOLDS.min(rows.len());
+    if n_folds < 2 || !encodings.iter().any(|encoding| matches!(encoding, Encoding::Target { .. }))
+    {
+        return None;
+    }
+    let steps: Vec<Step> = (0..n_folds)
+        .map(|fold| {
+            let encodings = encodings
+                .iter()
+                .enumerate()
+                .map(|(column, encoding)| match encoding {
+                    Encoding::Target { .. } => {
+                        target_encoding(rows, targets, column, task, |row| row % n_folds != fold)
+                    }
+                    encoding => encoding.clone(),
+                })
+                .collect();
+            Step::Encode { encodings }
+        })
+        .collect();
+    Some(
+        rows.iter()
+            .enumerate()
+            .map(|(row, values)| steps[row % n_folds].apply(values.clone()))
+            .collect(),
+    )
+}
+
+/// The offset and scale of `values` for `method`; constant values are only shifted.
+fn scale(values: &[f64], method: ScalingMethod) -> (f64, f64) {
+    let mut values = values.to_vec();
+    let (offset, scale) = match method {
+        ScalingMethod::None => (0.0, 1.0),
+        ScalingMethod::Standard => {
+            let mean = mean(&values);
+            let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>()
+                / values.len() as f64;
+            (mean, variance.sqrt())
+        }
+        ScalingMethod::MinMax => {
+            let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            (min, max - min)
+        }
+        ScalingMethod::Robust => {
+            let median = quantile(&mut values, 0.5);
+            (median, quantile(&mut values, 0.75) - quantile(&mut values, 0.25))
+        }
+    };
+    (offset, if scale > 1e-12 { scale } else { 1.0 })
+}
+
+/// Columns varying more than the variance threshold, skipping any correlated beyond the
+/// correlation threshold with an earlier column kept; the most varying column if none is.
+fn select(rows: &[Vec<f64>], n_columns: usize, selection: Selection) -> Vec<usize> {
+    let centred: Vec<(Vec<f64>, f64)> = (0..n_columns)
+        .map(|column| {
+            let values = column_values(rows, column);
+            let mean = mean(&values);
+            let centred: Vec<f64> = values.iter().map(|value| value - mean).collect();
+            let variance =
+                centred.iter().map(|value| value * value).sum::<f64>() / rows.len() as f64;
+            (centred, variance)
+        })
+        .collect();
+    let correlation = |a: usize, b: usize| -> f64 {
+        let ((x, x_variance), (y, y_variance)) = (&centred[a], &centred[b]);
+        let covariance = x.iter().zip(y).map(|(x, y)| x * y).sum::<f64>() / rows.len() as f64;
+        let denominator = (x_variance * y_variance).sqrt();
+        if denominator > 0.0 { covariance / denominator } else { 0.0 }
+    };
+
+    let mut kept: Vec<usize> = Vec::new();
+    for (column, (_, variance)) in centred.iter().enumerate() {
+        if *variance > selection.variance_threshold
+            && kept
+                .iter()
+                .all(|&other| correlation(column, other).abs() <= selection.correlation_threshold)
+        {
+            kept.push(column);
+        }
+    }
+    if kept.is_empty() {
+        kept.extend((0..n_columns).max_by(|a, b| centred[*a].1.total_cmp(&centred[*b].1)));
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> FeatureEngineeringConfig {
+        FeatureEngineeringConfig {
+            column_kinds: vec![ColumnKind::Numeric, ColumnKind::Categorical, ColumnKind::Datetime],
+            imputation: vec![ImputationStrategy::Median, ImputationStrategy::Mean],
+            scaling: vec![ScalingMethod::Standard],
+            categorical_encoding: vec![CategoricalEncoding::OneHot, CategoricalEncoding::Target],
+            polynomial_features: vec![PolynomialFeatures::Interactions],
+            variance_threshold: 1e-9,
+            correlation_threshold: (0.9, 0.99),
+        }
+    }
+
+    #[test]
+    fn test_pipeline_engineers_and_replays_features() {
+        // 2024-01-01 00:00 UTC, a Monday, plus some hours
+        let monday = 1_704_067_200.0;
+        let features = vec![
+            vec![1.0, 0.0, monday],
+            vec![f64::NAN, 1.0, monday + 3600.0],
+            vec![3.0, 2.0, f64::NAN],
+            vec![5.0, 1.0, monday + 7200.0],
+        ];
+        let data = Dataset { features, targets: vec![0.0, 1.0, 1.0, 0.0] };
+        let config = config();
+        let params = PipelineParams::from_parameters(&config, false, &HashMap::new()).unwrap();
+        assert_eq!(params.imputation, ImputationStrategy::Median);
+        assert_eq!(params.selection, None);
+
+        let task = TreeTask::Classification { n_classes: 2 };
+        let (pipeline, transformed) =
+            engineer_features(params, &config.column_kinds, task, &data).unwrap();
+        // A scaled number, three indicators, five calendar parts and their interactions,
+        // of which the year, month, day and weekday are constant
+        let continuous = 1 + DATETIME_PARTS;
+        assert_eq!(transformed.n_features(), continuous + 3 + continuous * (continuous - 1) / 2);
+        assert_eq!(transformed.features[1][0], 0.0);
+        assert_eq!(&transformed.features[1][1..4], &[0.0, 1.0, 0.0]);
+        assert_eq!(transformed.features[2][4..9], transformed.features[1][4..9]);
+
+        let restored: FeaturePipeline =
+            serde_json::from_str(&serde_json::to_string(&pipeline).unwrap()).unwrap();
+        let row = [2.0, 7.0, monday + 3600.0];
+        let replayed = restored.transform_row(&row).unwrap();
+        assert_eq!(replayed, pipeline.transform_row(&row).unwrap());
+        assert_eq!(&replayed[1..4], &[0.0, 0.0, 0.0]);
+        assert!(pipeline.transform_row(&row[..2]).is_err());
+
+        // Selection drops constant and duplicated columns
+        let params = PipelineParams {
+            polynomial_features: PolynomialFeatures::None,
+            selection: Some(Selection { variance_threshold: 1e-9, correlation_threshold: 0.95 }),
+            ..pipeline.params.clone()
+        };
+        let (_, selected) = engineer_features(params, &config.column_kinds, task, &data).unwrap();
+        assert_eq!(selected.n_features(), 4);
+    }
+
+    #[test]
+    fn test_choices_join_the_search_space() {
+        let config = config();
+        let mut space = SearchSpace {
+            parameters: HashMap::from([(
+                IMPUTATION.to_string(),
+                ParameterRange::Categorical { choices: vec!["Mean".to_string()] },
+            )]),
+            constraints: vec![],
+            conditions: HashMap::new(),
+            infeasible_strategy: Default::default(),
+        };
+        extend_search_space(&mut space, &config, true).unwrap();
+        let mut names: Vec<&String> = space.parameters.keys().collect();
+        names.sort();
+        assert_eq!(names, [CATEGORICAL_ENCODING, CORRELATION_THRESHOLD, IMPUTATION]);
+        assert!(matches!(
+            &space.parameters[IMPUTATION],
+            ParameterRange::Categorical { choices } if choices.len() == 1
+        ));
+
+        let parameters = HashMap::from([
+            (CATEGORICAL_ENCODING.to_string(), Value::from("Target")),
+            (CORRELATION_THRESHOLD.to_string(), Value::from(0.95)),
+        ]);
+        let params = PipelineParams::from_parameters(&config, true, &parameters).unwrap();
+        assert_eq!(params.categorical_encoding, CategoricalEncoding::Target);
+        assert_eq!(params.polynomial_features, PolynomialFeatures::Interactions);
+        assert_eq!(params.selection.unwrap().correlation_threshold, 0.95);
+        let invalid = HashMap::from([(SCALING.to_string(), Value::from("Log"))]);
+        assert!(PipelineParams::from_parameters(&config, true, &invalid).is_err());
+
+        // Three classes are encoded by their smoothed frequencies
+        let data = Dataset {
+            features: vec![vec![0.0], vec![0.0], vec![1.0], vec![1.0]],
+            targets: vec![0.0, 0.0, 1.0, 2.0],
+        };
+        let params = PipelineParams {
+            scaling: ScalingMethod::None,
+            polynomial_features: PolynomialFeatures::None,
+            selection: None,
+            ..params
+        };
+        let task = TreeTask::Classification { n_classes: 3 };
+        let pipeline =
+            FeaturePipeline::fit(params, &[ColumnKind::Categorical], task, &data).unwrap();
+        let encoded = pipeline.transform_row(&[0.0]).unwrap();
+        let expected = [(2.0 + 5.0) / 12.0, 2.5 / 12.0, 2.5 / 12.0];
+        for (value, expected) in encoded.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-12);
+        }
+        assert_eq!(pipeline.transform_row(&[9.0]).unwrap(), [0.5, 0.25, 0.25]);
+    }
+
+    #[test]
+    fn test_training_rows_are_target_encoded_out_of_fold() {
+        // Every row has a category of its own, so only its own target could set it apart
+        let data = Dataset {
+            features: (0..10).map(|row| vec![row as f64]).collect(),
+            targets: (0..10).map(|row| (row * row) as f64).collect(),
+        };
+        let params = PipelineParams {
+            imputation: ImputationStrategy::Mean,
+            scaling: ScalingMethod::None,
+            categorical_encoding: CategoricalEncoding::Target,
+            polynomial_features: PolynomialFeatures::None,
+            selection: None,
+        };
+        let (pipeline, transformed) =
+            engineer_features(params, &[ColumnKind::Categorical], TreeTask::Regression, &data)
+                .unwrap();
+        for (row, features) in transformed.features.iter().enumerate() {
+            let others: Vec<f64> = (0..10)
+                .filter(|other| other % TARGET_ENCODING_FOLDS != row % TARGET_ENCODING_FOLDS)
+                .map(|other| data.targets[other])
+                .collect();
+            assert!((features[0] - mean(&others)).abs() < 1e-12);
+        }
+
+        // New rows are encoded from every training row
+        let prior = mean(&data.targets);
+        let expected = (data.targets[3] + TARGET_SMOOTHING * prior) / (1.0 + TARGET_SMOOTHING);
+        assert!((pipeline.transform_row(&[3.0]).unwrap()[0] - expected).abs() < 1e-12);
+    }
 }
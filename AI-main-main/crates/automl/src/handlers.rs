@@ -4,7 +4,8 @@ use std::sync::Arc;
 use tracing::{error, info};
 
 use crate::errors::{AutoMLError, error_to_response};
-use crate::models::{AutoMLConfig, ModelConfig};
+use crate::models::AutoMLConfig;
+use crate::repository::StudyQuery;
 use crate::services::AutoMLService;
 
 #[derive(Debug, Serialize)]
@@ -83,6 +84,39 @@ pub async fn get_best_model(
     }
 }
 
+pub async fn list_studies(
+    data: web::Data<AppState>,
+    query: web::Query<StudyQuery>,
+) -> Result<HttpResponse, AutoMLError> {
+    info!("Listing studies matching: {:?}", query);
+
+    match data.optimizer.list_studies(query.into_inner()).await {
+        Ok(studies) => Ok(HttpResponse::Ok().json(studies)),
+        Err(e) => {
+            error!("Error listing studies: {:?}", e);
+            Ok(error_to_response(e))
+        }
+    }
+}
+
+pub async fn resume_study(
+    data: web::Data<AppState>,
+    study_id: web::Path<String>,
+) -> Result<HttpResponse, AutoMLError> {
+    info!("Resuming study: {}", study_id);
+
+    match data.optimizer.resume_study(study_id.to_string()).await {
+        Ok(result) => {
+            info!("Resumed study completed successfully");
+            Ok(HttpResponse::Ok().json(result))
+        }
+        Err(e) => {
+            error!("Error resuming study: {:?}", e);
+            Ok(error_to_response(e))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -108,7 +142,17 @@ mod tests {
             async fn get_best_model(
                 &self,
                 study_id: String,
-            ) -> Result<ModelConfig, AutoMLError>;
+            ) -> Result<crate::models::BestModel, AutoMLError>;
+
+            async fn resume_study(
+                &self,
+                study_id: String,
+            ) -> Result<crate::models::StudyResult, AutoMLError>;
+
+            async fn list_studies(
+                &self,
+                query: StudyQuery,
+            ) -> Result<Vec<crate::models::StudyRecord>, AutoMLError>;
         }
     }
 
@@ -123,6 +167,9 @@ mod tests {
                 best_model_path: "models/test.pt".to_string(),
                 trials: vec![],
                 optimization_history: vec![],
+                infeasible_attempts: 0,
+                ensemble: None,
+                feature_pipeline: None,
                 datetime_start: chrono::Utc::now(),
                 datetime_complete: Some(chrono::Utc::now()),
                 metadata: Default::default(),
@@ -157,6 +204,9 @@ mod tests {
                 best_model_path: "models/test.pt".to_string(),
                 trials: vec![],
                 optimization_history: vec![],
+                infeasible_attempts: 0,
+                ensemble: None,
+                feature_pipeline: None,
                 datetime_start: chrono::Utc::now(),
                 datetime_complete: Some(chrono::Utc::now()),
                 metadata: Default::default(),
@@ -169,4 +219,18 @@ mod tests {
 
         assert!(resp.is_ok());
     }
+
+    #[actix_rt::test]
+    async fn test_missing_study_is_not_found() {
+        let mut mock_service = MockAutoMLService::new();
+        mock_service
+            .expect_resume_study()
+            .returning(|id| Err(AutoMLError::NotFound(format!("Study {}", id))));
+
+        let app_state = web::Data::new(AppState { optimizer: Arc::new(mock_service) });
+
+        let resp = resume_study(app_state, web::Path::from("missing".to_string())).await;
+
+        assert_eq!(resp.unwrap().status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
 }
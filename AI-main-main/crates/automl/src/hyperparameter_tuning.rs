@@ -64,11 +64,13 @@ impl HyperParameterTuner for BasicTuner {
                 best_value = value;
                 best_trial = Some(TrialResult {
                     trial_id: trial_id.to_string(),
+                    number: trial_id,
                     parameters: parameters
                         .into_iter()
                         .map(|(k, v)| (k, serde_json::Value::from(v)))
                         .collect(),
                     value,
+                    intermediate_values: Default::default(),
                     state: crate::models::TrialState::Completed,
                     datetime_start: chrono::Utc::now(),
                     datetime_complete: Some(chrono::Utc::now()),
@@ -1,6 +1,8 @@
 pub mod api;
 pub mod config;
+pub mod cross_validation;
 pub mod data_processing;
+pub mod ensemble;
 pub mod errors;
 pub mod evaluation;
 pub mod feature_engineering;
@@ -10,6 +12,8 @@ pub mod metrics;
 pub mod model_search;
 pub mod models;
 pub mod optimization;
+pub mod repository;
 pub mod services;
 pub mod telemetry;
+pub mod trees;
 pub mod utils;
@@ -6,7 +6,11 @@ use opentelemetry::{global, sdk::propagation::TraceContextPropagator};
 use metrics_exporter_prometheus::PrometheusBuilder;
 use dotenv::dotenv;
 
+mod cross_validation;
+mod ensemble;
 mod errors;
+mod evaluation;
+mod feature_engineering;
 mod handlers;
 mod models;
 mod optimization;
@@ -14,11 +18,11 @@ mod repository;
 mod services;
 mod config;
 mod telemetry;
+mod trees;
 
 use config::Config;
 use errors::AutoMLError;
 use handlers::AppState;
-use repository::AutoMLRepository;
 use services::AutoMLOptimizer;
 use telemetry::{init_telemetry, shutdown_telemetry};
 
@@ -41,16 +45,19 @@ async fn main() -> Result<(), AutoMLError> {
         .install()
         .map_err(|e| AutoMLError::ConfigError(format!("Failed to install metrics: {}", e)))?;
 
-    // Initialize database connection
-    let repository = AutoMLRepository::new(&config.database_url, &config.redis_url)
+    // Initialize study storage
+    let repository = repository::open(&config.database_url)
         .await
         .map_err(|e| AutoMLError::DatabaseError(format!("Failed to initialize repository: {}", e)))?;
 
     // Initialize optimizer service
-    let optimizer = AutoMLOptimizer::new(Arc::new(repository))
+    let optimizer = AutoMLOptimizer::new(repository)
         .await
         .map_err(|e| AutoMLError::ModelInitializationError(format!("Failed to initialize optimizer: {}", e)))?;
 
+    // Nothing loads training data yet, so the optimizer rejects tree ensemble studies
+    warn!("No training data loaded; studies of tree ensembles will be rejected");
+
     // Create application state
     let app_state = web::Data::new(AppState::new(Arc::new(optimizer)));
 
@@ -68,7 +75,9 @@ async fn main() -> Result<(), AutoMLError> {
                     .route("/optimize", web::post().to(handlers::optimize_model))
                     .route("/health", web::get().to(handlers::health_check))
                     .route("/metrics", web::get().to(handlers::metrics))
+                    .route("/studies", web::get().to(handlers::list_studies))
                     .route("/studies/{study_id}", web::get().to(handlers::get_study_info))
+                    .route("/studies/{study_id}/resume", web::post().to(handlers::resume_study))
                     .route(
                         "/studies/{study_id}/best_model",
                         web::get().to(handlers::get_best_model),
@@ -109,11 +118,11 @@ mod tests {
     async fn test_server_startup() {
         let config = Config::new().expect("Failed to load test config");
         
-        let repository = AutoMLRepository::new(&config.database_url, &config.redis_url)
+        let repository = repository::open(&config.database_url)
             .await
             .expect("Failed to initialize repository");
 
-        let optimizer = AutoMLOptimizer::new(Arc::new(repository))
+        let optimizer = AutoMLOptimizer::new(repository)
             .await
             .expect("Failed to initialize optimizer");
 
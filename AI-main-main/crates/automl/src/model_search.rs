@@ -45,6 +45,7 @@ impl AutoModelSearcher {
                 architecture_search: true,
                 feature_selection: true,
                 ensemble_config: None,
+                feature_engineering: None,
             })
             .collect()
     }
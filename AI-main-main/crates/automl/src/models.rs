@@ -1,6 +1,9 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::ensemble::MetaLearner;
+use crate::feature_engineering::FeaturePipeline;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AutoMLConfig {
@@ -25,6 +28,8 @@ pub enum TaskType {
 pub struct OptimizationConfig {
     pub n_trials: usize,
     pub timeout_seconds: Option<u64>,
+    #[serde(default)]
+    pub trial_timeout_seconds: Option<u64>,
     pub n_jobs: usize,
     pub optimization_direction: OptimizationDirection,
     pub search_space: SearchSpace,
@@ -42,6 +47,11 @@ pub enum OptimizationDirection {
 pub struct SearchSpace {
     pub parameters: HashMap<String, ParameterRange>,
     pub constraints: Vec<Constraint>,
+    /// Expressions a parameter is only sampled under, by parameter name
+    #[serde(default)]
+    pub conditions: HashMap<String, String>,
+    #[serde(default)]
+    pub infeasible_strategy: InfeasibleStrategy,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,6 +61,16 @@ pub enum ParameterRange {
     Categorical { choices: Vec<String> },
 }
 
+/// What samplers do with configurations that violate a constraint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InfeasibleStrategy {
+    /// Discard the configuration and sample another
+    #[default]
+    Reject,
+    /// Resample only the parameters of the violated constraints
+    Repair,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Constraint {
     pub expression: String,
@@ -59,17 +79,24 @@ pub struct Constraint {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ConstraintType {
+    /// The value of the expression must be below the bound
     LessThan(f64),
     GreaterThan(f64),
     Equal(f64),
+    /// The expression must be true; the string describes the constraint
     Custom(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PrunerConfig {
     pub pruner_type: PrunerType,
+    /// Steps never pruned at the start of a trial; Hyperband uses its rungs instead
     pub n_warmup_steps: usize,
+    /// Completed trials that must have reached a step before others are compared there
     pub n_min_trials: usize,
+    /// Steps after the warm-up are checked this often, every step if 0
+    #[serde(default)]
+    pub interval_steps: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -83,26 +110,66 @@ pub enum PrunerType {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SamplerConfig {
+    #[serde(deserialize_with = "deserialize_sampler_type")]
     pub sampler_type: SamplerType,
     pub seed: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SamplerType {
-    TPE { n_ei_candidates: usize },
+    TPE {
+        n_ei_candidates: usize,
+    },
     RandomSearch,
-    GridSearch,
-    CmaEs { sigma: f64 },
+    /// Every combination of values, with continuous ranges split into `resolution` values
+    GridSearch {
+        resolution: usize,
+    },
+    CmaEs {
+        sigma: f64,
+    },
+    /// Scrambled Sobol sequence
     Sobol,
+    /// Scrambled Halton sequence
     QMC,
 }
 
+/// Also accepts `"GridSearch"` as a unit variant, as written before it had a resolution;
+/// such grids use [`crate::optimization::DEFAULT_GRID_RESOLUTION`].
+fn deserialize_sampler_type<'de, D>(deserializer: D) -> Result<SamplerType, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    enum LegacySamplerType {
+        GridSearch,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum AnySamplerType {
+        Current(SamplerType),
+        Legacy(LegacySamplerType),
+    }
+
+    Ok(match AnySamplerType::deserialize(deserializer)? {
+        AnySamplerType::Current(sampler_type) => sampler_type,
+        AnySamplerType::Legacy(LegacySamplerType::GridSearch) => {
+            SamplerType::GridSearch { resolution: crate::optimization::DEFAULT_GRID_RESOLUTION }
+        }
+    })
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelConfig {
     pub model_type: ModelType,
     pub architecture_search: bool,
+    /// Drops low-variance and correlated features when engineering features
     pub feature_selection: bool,
     pub ensemble_config: Option<EnsembleConfig>,
+    /// Features of tree ensembles are used as given if unset
+    #[serde(default)]
+    pub feature_engineering: Option<FeatureEngineeringConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -143,6 +210,80 @@ pub struct RandomForestConfig {
     pub min_samples_split: (i32, i32),
 }
 
+/// How tabular features are engineered before a model is fitted, with the choices trials
+/// sample from.
+///
+/// Every list of choices becomes a categorical parameter of the search space, unless the
+/// search space already has a parameter of that name. An empty list leaves the default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeatureEngineeringConfig {
+    /// Kind of every input column, all numeric if empty
+    #[serde(default)]
+    pub column_kinds: Vec<ColumnKind>,
+    pub imputation: Vec<ImputationStrategy>,
+    pub scaling: Vec<ScalingMethod>,
+    pub categorical_encoding: Vec<CategoricalEncoding>,
+    pub polynomial_features: Vec<PolynomialFeatures>,
+    /// Features varying less than this are dropped when selecting features
+    pub variance_threshold: f64,
+    /// Range of the absolute correlation above which the later of two features is dropped
+    pub correlation_threshold: (f64, f64),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColumnKind {
+    #[default]
+    Numeric,
+    /// Integer category codes
+    Categorical,
+    /// Unix timestamps in seconds
+    Datetime,
+}
+
+/// What missing numeric values are replaced with; categorical columns always take their
+/// most frequent value and timestamps their median.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ImputationStrategy {
+    #[default]
+    Mean,
+    Median,
+    MostFrequent,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScalingMethod {
+    #[default]
+    None,
+    /// Zero mean and unit variance
+    Standard,
+    /// Between 0 and 1 on the training rows
+    MinMax,
+    /// Zero median and unit interquartile range
+    Robust,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CategoricalEncoding {
+    /// The category codes as they are
+    #[default]
+    Ordinal,
+    /// One indicator per category seen in training
+    OneHot,
+    /// The smoothed mean target of the category, or its class frequencies
+    Target,
+}
+
+/// Products of pairs of continuous features added to them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PolynomialFeatures {
+    #[default]
+    None,
+    /// Products of distinct features
+    Interactions,
+    /// Products of distinct features and squares
+    Quadratic,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnsembleConfig {
     pub ensemble_type: EnsembleType,
@@ -152,25 +293,77 @@ pub struct EnsembleConfig {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EnsembleType {
+    /// A meta-learner combines the predictions of the members
     Stacking,
+    /// The members vote as set by the voting method
     Bagging,
     Boosting,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum VotingMethod {
+    /// Majority class, or median value for regression
     Hard,
+    /// Mean of the class probabilities or values
     Soft,
+    /// Weighted mean, with weights found by greedy ensemble selection
     Weighted,
 }
 
+/// An ensemble of the best trials of a study.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsembleResult {
+    pub ensemble_type: EnsembleType,
+    pub voting_method: VotingMethod,
+    pub members: Vec<EnsembleMember>,
+    /// Scores of the ensemble on the out-of-fold predictions of its members
+    pub metrics: ModelMetrics,
+    /// Combines the predictions of the members when stacked
+    #[serde(default)]
+    pub meta_learner: Option<MetaLearner>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsembleMember {
+    pub trial_number: usize,
+    /// Objective value of the trial
+    pub value: f64,
+    /// Share of the vote, or `None` when stacked
+    pub weight: Option<f64>,
+}
+
+/// The model configuration of a study with its best trial and ensemble, if any.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BestModel {
+    pub model_config: ModelConfig,
+    pub best_trial: TrialResult,
+    pub ensemble: Option<EnsembleResult>,
+    /// Transforms rows before the model of the best trial sees them
+    pub feature_pipeline: Option<FeaturePipeline>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrainingConfig {
     pub batch_size_range: (usize, usize),
     pub epochs_range: (usize, usize),
     pub early_stopping_patience: usize,
     pub validation_split: f64,
+    /// Trials are scored on a single validation split when below 2
     pub cross_validation_folds: usize,
+    /// Chosen from the task type if unset
+    #[serde(default)]
+    pub cross_validation_strategy: Option<CrossValidationStrategy>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CrossValidationStrategy {
+    KFold,
+    /// Keeps the class proportions of every fold close to those of the data
+    StratifiedKFold,
+    /// Keeps every group of rows in a single fold
+    GroupKFold,
+    /// Validates on successive windows, training on all rows before each
+    TimeSeriesSplit,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -183,8 +376,14 @@ pub struct HardwareConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrialResult {
     pub trial_id: String,
+    /// Position of the trial in its study, kept when a study is resumed
+    #[serde(default)]
+    pub number: usize,
     pub parameters: HashMap<String, serde_json::Value>,
     pub value: f64,
+    /// Values reported while the trial ran, by step
+    #[serde(default)]
+    pub intermediate_values: BTreeMap<u64, f64>,
     pub state: TrialState,
     pub datetime_start: DateTime<Utc>,
     pub datetime_complete: Option<DateTime<Utc>>,
@@ -206,11 +405,92 @@ pub struct StudyResult {
     pub best_model_path: String,
     pub trials: Vec<TrialResult>,
     pub optimization_history: Vec<f64>,
+    /// Sampled configurations discarded or repaired for violating a constraint
+    #[serde(default)]
+    pub infeasible_attempts: usize,
+    /// Built once the study finishes when the model configuration asks for one
+    #[serde(default)]
+    pub ensemble: Option<EnsembleResult>,
+    /// Fitted with the parameters of the best trial when features are engineered
+    #[serde(default)]
+    pub feature_pipeline: Option<FeaturePipeline>,
+    pub datetime_start: DateTime<Utc>,
+    pub datetime_complete: Option<DateTime<Utc>>,
+    pub metadata: HashMap<String, String>,
+}
+
+impl StudyResult {
+    /// Assembles the result of a stored study from its trials.
+    ///
+    /// Returns `None` while no trial has completed with a finite value.
+    pub fn from_record(study: &StudyRecord, trials: Vec<TrialResult>) -> Option<Self> {
+        let direction = &study.config.optimization_config.optimization_direction;
+        let best_trial = trials
+            .iter()
+            .filter(|trial| matches!(trial.state, TrialState::Completed) && trial.value.is_finite())
+            .min_by(|a, b| match direction {
+                OptimizationDirection::Minimize => a.value.total_cmp(&b.value),
+                OptimizationDirection::Maximize => b.value.total_cmp(&a.value),
+            })?
+            .clone();
+
+        Some(Self {
+            study_id: study.study_id.clone(),
+            task_type: study.config.task_type.clone(),
+            best_trial,
+            best_model_path: format!("models/best_model_{}.pt", study.study_id),
+            optimization_history: trials.iter().map(|trial| trial.value).collect(),
+            trials,
+            infeasible_attempts: study.infeasible_attempts,
+            ensemble: study.ensemble.clone(),
+            feature_pipeline: study.feature_pipeline.clone(),
+            datetime_start: study.datetime_start,
+            datetime_complete: study.datetime_complete,
+            metadata: study.metadata.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StudyState {
+    Running,
+    Completed,
+    Failed,
+}
+
+/// A stored study, without its trials.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StudyRecord {
+    pub study_id: String,
+    pub config: AutoMLConfig,
+    pub state: StudyState,
+    pub infeasible_attempts: usize,
+    #[serde(default)]
+    pub ensemble: Option<EnsembleResult>,
+    #[serde(default)]
+    pub feature_pipeline: Option<FeaturePipeline>,
     pub datetime_start: DateTime<Utc>,
     pub datetime_complete: Option<DateTime<Utc>>,
     pub metadata: HashMap<String, String>,
 }
 
+impl StudyRecord {
+    /// A study about to start running.
+    pub fn new(study_id: String, config: AutoMLConfig) -> Self {
+        Self {
+            study_id,
+            config,
+            state: StudyState::Running,
+            infeasible_attempts: 0,
+            ensemble: None,
+            feature_pipeline: None,
+            datetime_start: Utc::now(),
+            datetime_complete: None,
+            metadata: HashMap::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelMetrics {
     pub accuracy: Option<f64>,
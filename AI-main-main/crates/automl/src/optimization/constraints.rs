@@ -0,0 +1,429 @@
+//! Conditional parameters and constraints of a [`SearchSpace`].
+//!
+//! A parameter with a condition is only part of a configuration while the condition holds,
+//! such as `dropout` under `model_type == 'nn'`. Conditions may refer to other conditional
+//! parameters as long as they do not form a cycle. Constraints are checked once conditions
+//! are applied; a constraint on a parameter that is not active does not apply.
+
+use rand::Rng;
+use rand::rngs::StdRng;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tracing::{debug, info, warn};
+
+use super::expression::Expr;
+use super::samplers::{self, ParameterPoint};
+use crate::errors::AutoMLError;
+use crate::models::{ConstraintType, InfeasibleStrategy, ParameterRange, SearchSpace};
+
+/// Infeasible configurations in a row after which sampling gives up.
+pub const MAX_INFEASIBLE_ATTEMPTS: usize = 100;
+
+/// Repeated configurations in a row after which a sampler is taken to have no new ones; no
+/// grid is larger.
+const MAX_REPEATED_POINTS: usize = samplers::MAX_GRID_POINTS;
+
+/// Relative tolerance of [`ConstraintType::Equal`].
+const EQUAL_TOLERANCE: f64 = 1e-9;
+
+#[derive(Debug, Clone)]
+struct CompiledConstraint {
+    expression: Expr,
+    constraint_type: ConstraintType,
+    description: String,
+}
+
+impl CompiledConstraint {
+    fn holds(&self, point: &ParameterPoint) -> Result<bool, AutoMLError> {
+        let value = self.expression.eval(point)?;
+        Ok(match &self.constraint_type {
+            ConstraintType::LessThan(bound) => value.as_number()? < *bound,
+            ConstraintType::GreaterThan(bound) => value.as_number()? > *bound,
+            ConstraintType::Equal(target) => {
+                (value.as_number()? - target).abs() <= EQUAL_TOLERANCE * target.abs().max(1.0)
+            }
+            ConstraintType::Custom(_) => value.as_bool()?,
+        })
+    }
+
+    fn applies_to(&self, point: &ParameterPoint) -> bool {
+        self.expression.variables().iter().all(|name| point.contains_key(*name))
+    }
+}
+
+/// A validated search space with its conditions and constraints parsed.
+#[derive(Debug, Clone)]
+pub struct ConstrainedSpace {
+    /// Parameters ordered so each comes after those its condition refers to
+    parameters: Vec<(String, ParameterRange)>,
+    conditions: HashMap<String, Expr>,
+    constraints: Vec<CompiledConstraint>,
+    strategy: InfeasibleStrategy,
+}
+
+impl ConstrainedSpace {
+    pub fn new(space: &SearchSpace) -> Result<Self, AutoMLError> {
+        let ranges = samplers::parameters(space)?;
+
+        let mut conditions = HashMap::new();
+        for (name, source) in &space.conditions {
+            if !ranges.iter().any(|(parameter, _)| parameter == name) {
+                return Err(AutoMLError::ConfigError(format!(
+                    "Condition on unknown parameter {}",
+                    name
+                )));
+            }
+            let expr = Expr::parse(source)?;
+            check_variables(&expr, source, &ranges)?;
+            conditions.insert(name.clone(), expr);
+        }
+
+        let mut constraints = Vec::new();
+        for constraint in &space.constraints {
+            let expression = Expr::parse(&constraint.expression)?;
+            check_variables(&expression, &constraint.expression, &ranges)?;
+            let description = match &constraint.constraint_type {
+                ConstraintType::LessThan(bound) => format!("{} < {}", constraint.expression, bound),
+                ConstraintType::GreaterThan(bound) => {
+                    format!("{} > {}", constraint.expression, bound)
+                }
+                ConstraintType::Equal(target) => format!("{} == {}", constraint.expression, target),
+                ConstraintType::Custom(description) if !description.is_empty() => {
+                    description.clone()
+                }
+                ConstraintType::Custom(_) => constraint.expression.clone(),
+            };
+            constraints.push(CompiledConstraint {
+                expression,
+                constraint_type: constraint.constraint_type.clone(),
+                description,
+            });
+        }
+
+        Ok(Self {
+            parameters: dependency_order(ranges, &conditions)?,
+            conditions,
+            constraints,
+            strategy: space.infeasible_strategy,
+        })
+    }
+
+    /// Parameters in the order they should be sampled.
+    pub fn parameters(&self) -> impl Iterator<Item = (&str, &ParameterRange)> {
+        self.parameters.iter().map(|(name, range)| (name.as_str(), range))
+    }
+
+    /// Whether `name` is sampled, given the parameters `decided` before it.
+    pub fn is_active(&self, name: &str, decided: &ParameterPoint) -> Result<bool, AutoMLError> {
+        match self.conditions.get(name) {
+            None => Ok(true),
+            Some(condition) => {
+                if condition.variables().iter().any(|variable| !decided.contains_key(*variable)) {
+                    return Ok(false);
+                }
+                condition.eval(decided)?.as_bool()
+            }
+        }
+    }
+
+    /// The values of `point` for the parameters that are active.
+    pub fn apply_conditions(&self, point: &ParameterPoint) -> Result<ParameterPoint, AutoMLError> {
+        let mut active = ParameterPoint::new();
+        for (name, _) in &self.parameters {
+            if let Some(value) = point.get(name) {
+                if self.is_active(name, &active)? {
+                    active.insert(name.clone(), value.clone());
+                }
+            }
+        }
+        Ok(active)
+    }
+
+    fn violated(&self, point: &ParameterPoint) -> Result<Vec<&CompiledConstraint>, AutoMLError> {
+        let mut violated = Vec::new();
+        for constraint in &self.constraints {
+            if constraint.applies_to(point) && !constraint.holds(point)? {
+                violated.push(constraint);
+            }
+        }
+        Ok(violated)
+    }
+
+    /// The constraints `point` violates, described.
+    pub fn violations(&self, point: &ParameterPoint) -> Result<Vec<&str>, AutoMLError> {
+        Ok(self
+            .violated(point)?
+            .into_iter()
+            .map(|constraint| constraint.description.as_str())
+            .collect())
+    }
+
+    /// A feasible replacement for the infeasible `point`, if the strategy allows one.
+    ///
+    /// Repairing resamples the parameters of the violated constraints, keeping the others,
+    /// until the constraints hold or [`MAX_INFEASIBLE_ATTEMPTS`] is reached. Parameters
+    /// `point` lacks are sampled first.
+    pub fn repair(
+        &self,
+        point: &ParameterPoint,
+        rng: &mut StdRng,
+    ) -> Result<Option<ParameterPoint>, AutoMLError> {
+        if self.strategy == InfeasibleStrategy::Reject {
+            return Ok(None);
+        }
+
+        let mut full = point.clone();
+        for (name, range) in &self.parameters {
+            full.entry(name.clone()).or_insert_with(|| samplers::value_at(range, rng.gen()));
+        }
+        for _ in 0..MAX_INFEASIBLE_ATTEMPTS {
+            let active = self.apply_conditions(&full)?;
+            let violated = self.violated(&active)?;
+            if violated.is_empty() {
+                return Ok(Some(active));
+            }
+            for constraint in violated {
+                for name in constraint.expression.variables() {
+                    let range = self.range(name);
+                    full.insert(name.to_string(), samplers::value_at(range, rng.gen()));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn range(&self, name: &str) -> &ParameterRange {
+        self.parameters
+            .iter()
+            .find(|(parameter, _)| parameter == name)
+            .map(|(_, range)| range)
+            .expect("constraint variables are checked to be parameters")
+    }
+}
+
+fn check_variables(
+    expr: &Expr,
+    source: &str,
+    ranges: &[(String, ParameterRange)],
+) -> Result<(), AutoMLError> {
+    for name in expr.variables() {
+        if !ranges.iter().any(|(parameter, _)| parameter == name) {
+            return Err(AutoMLError::ConfigError(format!(
+                "Expression `{}` refers to unknown parameter {}",
+                source, name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Orders `ranges` so each parameter follows those its condition depends on, keeping the
+/// name order otherwise.
+fn dependency_order(
+    mut pending: Vec<(String, ParameterRange)>,
+    conditions: &HashMap<String, Expr>,
+) -> Result<Vec<(String, ParameterRange)>, AutoMLError> {
+    let mut ordered: Vec<(String, ParameterRange)> = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|(name, _)| {
+            conditions.get(name).is_none_or(|condition| {
+                condition
+                    .variables()
+                    .iter()
+                    .all(|variable| ordered.iter().any(|(placed, _)| placed == variable))
+            })
+        });
+        match ready {
+            Some(index) => ordered.push(pending.remove(index)),
+            None => {
+                let names: Vec<_> = pending.iter().map(|(name, _)| name.as_str()).collect();
+                return Err(AutoMLError::ConfigError(format!(
+                    "Conditions of {} depend on each other",
+                    names.join(", ")
+                )));
+            }
+        }
+    }
+    Ok(ordered)
+}
+
+/// Passes on the feasible points of a sampler, repairing or skipping the others.
+pub struct ConstrainedSampler<I> {
+    inner: I,
+    space: ConstrainedSpace,
+    rng: StdRng,
+    infeasible_attempts: usize,
+    /// Configurations passed on so far, as JSON with sorted keys.
+    produced: HashSet<String>,
+}
+
+impl<I: Iterator<Item = ParameterPoint>> ConstrainedSampler<I> {
+    pub fn new(inner: I, space: ConstrainedSpace, seed: Option<u64>) -> Self {
+        Self {
+            inner,
+            space,
+            rng: samplers::rng(seed),
+            infeasible_attempts: 0,
+            produced: HashSet::new(),
+        }
+    }
+
+    /// Configurations found infeasible so far.
+    pub fn infeasible_attempts(&self) -> usize {
+        self.infeasible_attempts
+    }
+
+    /// The next feasible configuration, with conditions applied, that was not passed on
+    /// before.
+    ///
+    /// Points that only differ in inactive parameters are the same configuration, so a grid
+    /// over conditional parameters yields each of them once. Returns `None` when the sampler
+    /// runs out, after [`MAX_INFEASIBLE_ATTEMPTS`] infeasible configurations in a row, or
+    /// once it only repeats itself.
+    pub fn next_feasible(&mut self) -> Result<Option<ParameterPoint>, AutoMLError> {
+        let mut infeasible = 0;
+        let mut repeated = 0;
+        while infeasible < MAX_INFEASIBLE_ATTEMPTS {
+            let Some(point) = self.inner.next() else {
+                return Ok(None);
+            };
+            let active = self.space.apply_conditions(&point)?;
+            let violations = self.space.violations(&active)?;
+            let feasible = if violations.is_empty() {
+                Some(active)
+            } else {
+                infeasible += 1;
+                self.infeasible_attempts += 1;
+                debug!("Sampled configuration violates {}", violations.join(", "));
+                self.space.repair(&point, &mut self.rng)?
+            };
+
+            if let Some(feasible) = feasible {
+                let sorted: BTreeMap<_, _> = feasible.iter().collect();
+                if self.produced.insert(serde_json::to_string(&sorted)?) {
+                    return Ok(Some(feasible));
+                }
+                repeated += 1;
+                if repeated >= MAX_REPEATED_POINTS {
+                    info!("No new configuration in {} samples", repeated);
+                    return Ok(None);
+                }
+            }
+        }
+        warn!("No feasible configuration in {} attempts", MAX_INFEASIBLE_ATTEMPTS);
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Constraint;
+    use crate::optimization::samplers::{GridSampler, SobolSampler};
+    use serde_json::json;
+
+    fn space(strategy: InfeasibleStrategy) -> SearchSpace {
+        let parameters = [
+            (
+                "model_type",
+                ParameterRange::Categorical { choices: vec!["nn".to_string(), "gbm".to_string()] },
+            ),
+            ("n_layers", ParameterRange::Discrete { low: 1, high: 8, step: 1 }),
+            ("units", ParameterRange::Discrete { low: 64, high: 1024, step: 64 }),
+            ("dropout", ParameterRange::Continuous { low: 0.0, high: 0.5, log: false }),
+            ("learning_rate", ParameterRange::Continuous { low: 1e-4, high: 1e-1, log: true }),
+        ];
+        let conditions = [
+            ("n_layers", "model_type == 'nn'"),
+            ("units", "model_type == 'nn'"),
+            ("dropout", "n_layers > 2"),
+        ];
+        SearchSpace {
+            parameters: parameters
+                .into_iter()
+                .map(|(name, range)| (name.to_string(), range))
+                .collect(),
+            constraints: vec![Constraint {
+                expression: "n_layers * units".to_string(),
+                constraint_type: ConstraintType::LessThan(2048.0),
+            }],
+            conditions: conditions
+                .into_iter()
+                .map(|(name, condition)| (name.to_string(), condition.to_string()))
+                .collect(),
+            infeasible_strategy: strategy,
+        }
+    }
+
+    fn point(entries: serde_json::Value) -> ParameterPoint {
+        serde_json::from_value(entries).unwrap()
+    }
+
+    #[test]
+    fn test_conditions_decide_which_parameters_are_active() {
+        let space = ConstrainedSpace::new(&space(InfeasibleStrategy::Reject)).unwrap();
+        let order: Vec<_> = space.parameters().map(|(name, _)| name).collect();
+        assert_eq!(order, ["learning_rate", "model_type", "n_layers", "dropout", "units"]);
+
+        let full = point(json!({
+            "model_type": "gbm", "n_layers": 4, "units": 512, "dropout": 0.2, "learning_rate": 0.01
+        }));
+        assert_eq!(
+            space.apply_conditions(&full).unwrap(),
+            point(json!({"model_type": "gbm", "learning_rate": 0.01}))
+        );
+        let mut nn = full.clone();
+        nn.insert("model_type".to_string(), json!("nn"));
+        assert_eq!(space.apply_conditions(&nn).unwrap(), nn);
+        assert_eq!(space.violations(&nn).unwrap(), ["n_layers * units < 2048"]);
+        nn.insert("n_layers".to_string(), json!(2));
+        assert_eq!(
+            space.apply_conditions(&nn).unwrap(),
+            point(json!({"model_type": "nn", "n_layers": 2, "units": 512, "learning_rate": 0.01}))
+        );
+
+        let mut cyclic = self::space(InfeasibleStrategy::Reject);
+        cyclic.conditions.insert("model_type".to_string(), "dropout < 0.3".to_string());
+        assert!(ConstrainedSpace::new(&cyclic).is_err());
+        let mut unknown = self::space(InfeasibleStrategy::Reject);
+        unknown.constraints[0].expression = "n_layers * width".to_string();
+        assert!(ConstrainedSpace::new(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_samplers_reject_or_repair_infeasible_configurations() {
+        let search_space = space(InfeasibleStrategy::Reject);
+        let grid = GridSampler::new(&search_space, 2, Some(1)).unwrap();
+        let mut sampler =
+            ConstrainedSampler::new(grid, ConstrainedSpace::new(&search_space).unwrap(), None);
+        let mut distinct = std::collections::HashSet::new();
+        let mut gbm = 0;
+        while let Some(point) = sampler.next_feasible().unwrap() {
+            if point["model_type"] == json!("nn") {
+                let size = point["n_layers"].as_i64().unwrap() * point["units"].as_i64().unwrap();
+                assert!(size < 2048);
+                assert_eq!(point.contains_key("dropout"), point["n_layers"].as_i64() > Some(2));
+            } else {
+                assert_eq!(point.len(), 2);
+                gbm += 1;
+            }
+            let sorted: BTreeMap<_, _> = point.iter().collect();
+            assert!(distinct.insert(serde_json::to_string(&sorted).unwrap()), "{:?}", point);
+        }
+        assert!(sampler.infeasible_attempts() > 0);
+        // One per learning rate, whatever the grid holds for the inactive parameters
+        assert_eq!(gbm, 2);
+        assert!(distinct.len() > 2);
+
+        let search_space = space(InfeasibleStrategy::Repair);
+        let sobol = SobolSampler::new(&search_space, Some(5)).unwrap();
+        let mut sampler =
+            ConstrainedSampler::new(sobol, ConstrainedSpace::new(&search_space).unwrap(), Some(5));
+        for _ in 0..64 {
+            let point = sampler.next_feasible().unwrap().unwrap();
+            if let (Some(layers), Some(units)) = (point.get("n_layers"), point.get("units")) {
+                assert!(layers.as_i64().unwrap() * units.as_i64().unwrap() < 2048);
+            }
+        }
+        assert!(sampler.infeasible_attempts() > 0);
+    }
+}
@@ -0,0 +1,428 @@
+//! A small expression language over parameter names.
+//!
+//! Expressions combine numbers, quoted strings, `true`/`false` and parameter names with
+//! arithmetic (`+ - * / %`), comparisons (`< <= > >= == !=`) and boolean logic (`&&` or
+//! `and`, `||` or `or`, `!` or `not`), for example
+//! `model_type == 'nn' && n_layers * units <= 4096`.
+
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt;
+
+use super::samplers::ParameterPoint;
+use crate::errors::AutoMLError;
+
+/// The value of an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+}
+
+impl Operand {
+    fn from_value(name: &str, value: &Value) -> Result<Self, AutoMLError> {
+        match value {
+            Value::Number(number) => Ok(Operand::Number(number.as_f64().unwrap_or(f64::NAN))),
+            Value::String(text) => Ok(Operand::Text(text.clone())),
+            Value::Bool(flag) => Ok(Operand::Bool(*flag)),
+            other => Err(AutoMLError::ValidationError(format!(
+                "Parameter {} has unsupported value {}",
+                name, other
+            ))),
+        }
+    }
+
+    pub fn as_number(&self) -> Result<f64, AutoMLError> {
+        match self {
+            Operand::Number(number) => Ok(*number),
+            other => Err(AutoMLError::ValidationError(format!("Expected a number, got {}", other))),
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool, AutoMLError> {
+        match self {
+            Operand::Bool(flag) => Ok(*flag),
+            other => {
+                Err(AutoMLError::ValidationError(format!("Expected a boolean, got {}", other)))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Number(number) => write!(f, "{}", number),
+            Operand::Text(text) => write!(f, "'{}'", text),
+            Operand::Bool(flag) => write!(f, "{}", flag),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+/// A parsed expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Operand),
+    Variable(String),
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self, AutoMLError> {
+        let invalid = |reason: String| {
+            AutoMLError::ConfigError(format!("Invalid expression `{}`: {}", source, reason))
+        };
+        let mut parser = Parser { tokens: tokenize(source).map_err(invalid)?, position: 0 };
+        let expr = parser.or().map_err(invalid)?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(invalid(format!("unexpected {}", token))),
+        }
+    }
+
+    /// Names of the parameters the expression refers to.
+    pub fn variables(&self) -> BTreeSet<&str> {
+        let mut variables = BTreeSet::new();
+        self.collect_variables(&mut variables);
+        variables
+    }
+
+    fn collect_variables<'a>(&'a self, variables: &mut BTreeSet<&'a str>) {
+        match self {
+            Expr::Literal(_) => {}
+            Expr::Variable(name) => {
+                variables.insert(name);
+            }
+            Expr::Negate(inner) | Expr::Not(inner) => inner.collect_variables(variables),
+            Expr::Binary(_, left, right) => {
+                left.collect_variables(variables);
+                right.collect_variables(variables);
+            }
+        }
+    }
+
+    /// Evaluates the expression with the parameter values of `point`.
+    ///
+    /// `&&` and `||` only evaluate their right side when it decides the result.
+    pub fn eval(&self, point: &ParameterPoint) -> Result<Operand, AutoMLError> {
+        match self {
+            Expr::Literal(operand) => Ok(operand.clone()),
+            Expr::Variable(name) => match point.get(name) {
+                Some(value) => Operand::from_value(name, value),
+                None => {
+                    Err(AutoMLError::ValidationError(format!("Parameter {} has no value", name)))
+                }
+            },
+            Expr::Negate(inner) => Ok(Operand::Number(-inner.eval(point)?.as_number()?)),
+            Expr::Not(inner) => Ok(Operand::Bool(!inner.eval(point)?.as_bool()?)),
+            Expr::Binary(BinaryOp::And, left, right) => {
+                Ok(Operand::Bool(left.eval(point)?.as_bool()? && right.eval(point)?.as_bool()?))
+            }
+            Expr::Binary(BinaryOp::Or, left, right) => {
+                Ok(Operand::Bool(left.eval(point)?.as_bool()? || right.eval(point)?.as_bool()?))
+            }
+            Expr::Binary(op, left, right) => apply(*op, left.eval(point)?, right.eval(point)?),
+        }
+    }
+}
+
+fn apply(op: BinaryOp, left: Operand, right: Operand) -> Result<Operand, AutoMLError> {
+    use BinaryOp::*;
+
+    if matches!(op, Eq | Ne) {
+        let equal = match (&left, &right) {
+            (Operand::Number(a), Operand::Number(b)) => a == b,
+            (Operand::Text(a), Operand::Text(b)) => a == b,
+            (Operand::Bool(a), Operand::Bool(b)) => a == b,
+            _ => {
+                return Err(AutoMLError::ValidationError(format!(
+                    "Cannot compare {} with {}",
+                    left, right
+                )));
+            }
+        };
+        return Ok(Operand::Bool(equal == (op == Eq)));
+    }
+
+    let (a, b) = (left.as_number()?, right.as_number()?);
+    Ok(match op {
+        Add => Operand::Number(a + b),
+        Sub => Operand::Number(a - b),
+        Mul => Operand::Number(a * b),
+        Div => Operand::Number(a / b),
+        Rem => Operand::Number(a % b),
+        Lt => Operand::Bool(a < b),
+        Le => Operand::Bool(a <= b),
+        Gt => Operand::Bool(a > b),
+        Ge => Operand::Bool(a >= b),
+        Eq | Ne | And | Or => unreachable!("handled above"),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Identifier(String),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{}", number),
+            Token::Text(text) => write!(f, "'{}'", text),
+            Token::Identifier(name) => write!(f, "{}", name),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+/// Length of the number at the start of `text`, with an optional exponent such as `1e-3`.
+fn number_length(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut length = 0;
+    while length < bytes.len() {
+        let byte = bytes[length];
+        let exponent_sign =
+            matches!(byte, b'+' | b'-') && length > 0 && matches!(bytes[length - 1], b'e' | b'E');
+        if !(byte.is_ascii_digit() || matches!(byte, b'.' | b'e' | b'E') || exponent_sign) {
+            break;
+        }
+        length += 1;
+    }
+    length
+}
+
+/// Symbols, longest first so `<=` is not read as `<`.
+const SYMBOLS: [&str; 16] =
+    ["<=", ">=", "==", "!=", "&&", "||", "<", ">", "+", "-", "*", "/", "%", "!", "(", ")"];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let length = if c.is_ascii_digit() || c == '.' {
+            let length = number_length(rest);
+            let number = rest[..length]
+                .parse()
+                .map_err(|_| format!("invalid number {}", &rest[..length]))?;
+            tokens.push(Token::Number(number));
+            length
+        } else if c.is_alphabetic() || c == '_' {
+            let length =
+                rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            tokens.push(Token::Identifier(rest[..length].to_string()));
+            length
+        } else if c == '\'' || c == '"' {
+            let end = rest[1..].find(c).ok_or("unterminated string")? + 1;
+            tokens.push(Token::Text(rest[1..end].to_string()));
+            end + 1
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(**symbol))
+                .ok_or_else(|| format!("unexpected character {}", c))?;
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        };
+        rest = rest[length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+const COMPARISONS: [&str; 6] = ["<=", ">=", "==", "!=", "<", ">"];
+
+fn operator(symbol: &str) -> BinaryOp {
+    match symbol {
+        "||" => BinaryOp::Or,
+        "&&" => BinaryOp::And,
+        "<" => BinaryOp::Lt,
+        "<=" => BinaryOp::Le,
+        ">" => BinaryOp::Gt,
+        ">=" => BinaryOp::Ge,
+        "==" => BinaryOp::Eq,
+        "!=" => BinaryOp::Ne,
+        "+" => BinaryOp::Add,
+        "-" => BinaryOp::Sub,
+        "*" => BinaryOp::Mul,
+        "/" => BinaryOp::Div,
+        _ => BinaryOp::Rem,
+    }
+}
+
+/// Recursive descent over the tokens, one method per precedence level from lowest to
+/// highest.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    /// Consumes the next token if it is one of `symbols`, as a symbol or keyword.
+    fn eat(&mut self, symbols: &[&str]) -> Option<&'static str> {
+        let matched = match self.peek()? {
+            Token::Symbol(symbol) => symbols.iter().find(|s| *s == symbol).map(|_| *symbol),
+            Token::Identifier(word) => match word.as_str() {
+                "and" if symbols.contains(&"&&") => Some("&&"),
+                "or" if symbols.contains(&"||") => Some("||"),
+                "not" if symbols.contains(&"!") => Some("!"),
+                _ => None,
+            },
+            _ => None,
+        };
+        if matched.is_some() {
+            self.position += 1;
+        }
+        matched
+    }
+
+    fn binary(
+        &mut self,
+        symbols: &[&str],
+        operand: fn(&mut Self) -> Result<Expr, String>,
+    ) -> Result<Expr, String> {
+        let mut expr = operand(self)?;
+        while let Some(symbol) = self.eat(symbols) {
+            expr = Expr::Binary(operator(symbol), Box::new(expr), Box::new(operand(self)?));
+        }
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        self.binary(&["||"], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        self.binary(&["&&"], Self::not)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.eat(&["!"]).is_some() {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    /// Comparisons do not chain: `a < b < c` is an error.
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.additive()?;
+        let Some(symbol) = self.eat(&COMPARISONS) else {
+            return Ok(left);
+        };
+        let right = self.additive()?;
+        if let Some(next) = self.eat(&COMPARISONS) {
+            return Err(format!("comparisons cannot be chained, found {}", next));
+        }
+        Ok(Expr::Binary(operator(symbol), Box::new(left), Box::new(right)))
+    }
+
+    fn additive(&mut self) -> Result<Expr, String> {
+        self.binary(&["+", "-"], Self::term)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        self.binary(&["*", "/", "%"], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat(&["-"]).is_some() {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self.peek().cloned().ok_or("unexpected end of expression")?;
+        self.position += 1;
+        match token {
+            Token::Number(number) => Ok(Expr::Literal(Operand::Number(number))),
+            Token::Text(text) => Ok(Expr::Literal(Operand::Text(text))),
+            Token::Identifier(name) => Ok(match name.as_str() {
+                "true" => Expr::Literal(Operand::Bool(true)),
+                "false" => Expr::Literal(Operand::Bool(false)),
+                _ => Expr::Variable(name),
+            }),
+            Token::Symbol("(") => {
+                let expr = self.or()?;
+                match self.eat(&[")"]) {
+                    Some(_) => Ok(expr),
+                    None => Err("missing )".to_string()),
+                }
+            }
+            Token::Symbol(symbol) => Err(format!("unexpected {}", symbol)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn eval(source: &str) -> Result<Operand, AutoMLError> {
+        let point: ParameterPoint = serde_json::from_value(json!({
+            "model_type": "nn", "n_layers": 3, "units": 256, "dropout": 0.25, "batch_norm": true
+        }))
+        .unwrap();
+        Expr::parse(source)?.eval(&point)
+    }
+
+    #[test]
+    fn test_expressions_follow_precedence_and_types() {
+        assert_eq!(eval("n_layers * units + 2 * -3").unwrap(), Operand::Number(762.0));
+        assert_eq!(eval("(n_layers + 1) % 3 / 2").unwrap(), Operand::Number(0.5));
+        assert_eq!(eval("dropout <= 2.5e-1").unwrap(), Operand::Bool(true));
+        assert_eq!(
+            eval("model_type == 'nn' && n_layers * units <= 4096 or dropout > 1").unwrap(),
+            Operand::Bool(true)
+        );
+        assert_eq!(eval("not batch_norm || model_type != \"nn\"").unwrap(), Operand::Bool(false));
+        assert_eq!(eval("model_type == 'gbm' && missing > 1").unwrap(), Operand::Bool(false));
+
+        let expr = Expr::parse("dropout > 0 && (units >= n_layers * 64)").unwrap();
+        assert_eq!(
+            expr.variables().into_iter().collect::<Vec<_>>(),
+            ["dropout", "n_layers", "units"]
+        );
+
+        for invalid in ["n_layers <", "1 < n_layers < 5", "(units", "units $ 2", "'open"] {
+            assert!(
+                matches!(Expr::parse(invalid), Err(AutoMLError::ConfigError(_))),
+                "{}",
+                invalid
+            );
+        }
+        for ill_typed in ["model_type == 1", "model_type + 1", "units && true", "missing > 1"] {
+            assert!(
+                matches!(eval(ill_typed), Err(AutoMLError::ValidationError(_))),
+                "{}",
+                ill_typed
+            );
+        }
+    }
+}
@@ -1,5 +1,22 @@
+mod constraints;
+mod expression;
 mod nas;
 mod optuna;
+mod pruners;
+mod samplers;
+mod scheduler;
+
+pub use self::optuna::suggest_parameter;
+pub use constraints::{ConstrainedSampler, ConstrainedSpace, MAX_INFEASIBLE_ATTEMPTS};
+pub use expression::{Expr, Operand};
+pub use pruners::{
+    Curve, HyperbandPruner, NopPruner, PercentilePruner, Pruner, PruningHistory, PruningSchedule,
+    StudyPruner, ThresholdPruner, TrialReporter,
+};
+pub use samplers::{
+    DEFAULT_GRID_RESOLUTION, GridSampler, HaltonSampler, ParameterPoint, SobolSampler,
+};
+pub use scheduler::{TrialOutcome, TrialScheduler};
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
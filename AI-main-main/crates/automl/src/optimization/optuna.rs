@@ -4,22 +4,46 @@ use optuna::{Study, StudyDirection, Trial};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::{error, info};
+use tokio::sync::{RwLock, mpsc};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
 
+use super::constraints::{ConstrainedSampler, ConstrainedSpace, MAX_INFEASIBLE_ATTEMPTS};
+use super::pruners::{StudyPruner, TrialReporter};
+use super::samplers::{self, GridSampler, HaltonSampler, ParameterPoint, SobolSampler};
+use super::scheduler::{TrialOutcome, TrialScheduler};
 use crate::errors::AutoMLError;
+use crate::feature_engineering;
 use crate::models::{
     AutoMLConfig, ModelMetrics, OptimizationConfig, ParameterRange, SearchSpace, StudyResult,
-    TaskType, TrialResult,
+    TaskType, TrialResult, TrialState,
 };
+use crate::repository::AutoMLRepository;
+
+/// A trial being evaluated: its number in the study, the study's trial and its parameters.
+type RunningTrial = (usize, Trial, HashMap<String, Value>);
 
 pub struct OptunaOptimizer {
     study: Arc<RwLock<Study>>,
     config: AutoMLConfig,
+    space: ConstrainedSpace,
+    study_id: String,
+    history: Vec<TrialResult>,
+    repository: Option<Arc<dyn AutoMLRepository>>,
 }
 
 impl OptunaOptimizer {
-    pub async fn new(config: AutoMLConfig) -> Result<Self, AutoMLError> {
+    /// A study of `config`, whose search space also holds the choices of its feature
+    /// engineering.
+    pub async fn new(mut config: AutoMLConfig) -> Result<Self, AutoMLError> {
+        if let Some(feature_engineering) = &config.model_config.feature_engineering {
+            feature_engineering::extend_search_space(
+                &mut config.optimization_config.search_space,
+                feature_engineering,
+                config.model_config.feature_selection,
+            )?;
+        }
+
         let study_direction = match config.optimization_config.optimization_direction {
             crate::models::OptimizationDirection::Minimize => StudyDirection::Minimize,
             crate::models::OptimizationDirection::Maximize => StudyDirection::Maximize,
@@ -27,13 +51,50 @@ impl OptunaOptimizer {
 
         let study = Study::create(study_direction)
             .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?;
+        let space = ConstrainedSpace::new(&config.optimization_config.search_space)?;
+
+        let study_id = study.id().to_string();
+
+        Ok(Self {
+            study: Arc::new(RwLock::new(study)),
+            config,
+            space,
+            study_id,
+            history: Vec::new(),
+            repository: None,
+        })
+    }
+
+    pub fn with_study_id(mut self, study_id: String) -> Self {
+        self.study_id = study_id;
+        self
+    }
 
-        Ok(Self { study: Arc::new(RwLock::new(study)), config })
+    /// Resumes a study from its stored trials.
+    ///
+    /// Finished trials are replayed into the study and count towards `n_trials`. Trials
+    /// that were still running are run again under their original numbers.
+    pub fn with_history(mut self, trials: Vec<TrialResult>) -> Self {
+        self.history = trials;
+        self
     }
 
+    /// Saves every trial when it starts and when it finishes.
+    pub fn with_repository(mut self, repository: Arc<dyn AutoMLRepository>) -> Self {
+        self.repository = Some(repository);
+        self
+    }
+
+    /// Runs the study, evaluating up to `n_jobs` trials at a time.
+    ///
+    /// Trials exceeding the per-trial or study timeout are recorded as failed. Configurations
+    /// violating a constraint are rejected or repaired before they are evaluated.
+    ///
+    /// The objective reports intermediate values through its [`TrialReporter`]. A trial it
+    /// was told to stop and that then returns an error is recorded as pruned.
     pub async fn optimize<F>(&self, objective: F) -> Result<StudyResult, AutoMLError>
     where
-        F: Fn(&Trial) -> Result<f64, AutoMLError> + Send + Sync,
+        F: Fn(&Trial, &TrialReporter) -> Result<f64, AutoMLError> + Send + Sync + 'static,
     {
         let study = self.study.read().await;
         let n_trials = self.config.optimization_config.n_trials;
@@ -42,70 +103,194 @@ impl OptunaOptimizer {
         self.configure_sampler(&study).await?;
 
         // Configure pruner
-        self.configure_pruner(&study).await?;
+        let pruner = self.configure_pruner(&study).await?;
+
+        // Resumed studies replay their finished trials so the sampler learns from them
+        let mut finished = Vec::new();
+        let mut rerun = Vec::new();
+        // Stored trial ids by the ids their replays got in this study
+        let mut stored_ids = HashMap::new();
+        for trial in &self.history {
+            match trial.state {
+                TrialState::Running => rerun.push(trial),
+                _ => {
+                    stored_ids.insert(self.replay(&study, trial)?, trial.trial_id.clone());
+                    pruner.replay(trial);
+                    finished.push(trial.clone());
+                }
+            }
+        }
+        if !self.history.is_empty() {
+            info!(
+                "Resuming study {} with {} finished and {} interrupted trials",
+                self.study_id,
+                finished.len(),
+                rerun.len()
+            );
+        }
+        let n_trials = n_trials.saturating_sub(finished.len());
+        let next_number = self.history.iter().map(|trial| trial.number + 1).max().unwrap_or(0);
+        let (writer, writer_task) = self.trial_writer().unzip();
+
+        // Native samplers choose every trial's parameters up front
+        let seed = self.config.optimization_config.sampler_config.seed;
+        let mut points = self
+            .native_points()?
+            .map(|points| ConstrainedSampler::new(points, self.space.clone(), seed));
+        let mut repair_rng = samplers::rng(seed);
+        let mut infeasible_attempts = 0;
 
         // Run optimization
         let start_time = chrono::Utc::now();
         let mut trials = Vec::new();
 
-        for trial_number in 0..n_trials {
-            info!("Starting trial {}/{}", trial_number + 1, n_trials);
+        // Trials are asked for and told about here only, so the sampler sees results in
+        // the order they arrive rather than the order trials started
+        let ask = |launched: usize| {
+            if let Some(interrupted) = rerun.get(launched) {
+                study
+                    .enqueue_trial(interrupted.parameters.clone())
+                    .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?;
+                let trial =
+                    study.ask().map_err(|e| AutoMLError::OptimizationError(e.to_string()))?;
+                let parameters = self.get_trial_params(&trial)?;
+                return Ok(Some(self.start_trial(
+                    interrupted.number,
+                    trial,
+                    parameters,
+                    writer.as_ref(),
+                )));
+            }
+
+            let number = next_number + launched - rerun.len();
+            for _ in 0..MAX_INFEASIBLE_ATTEMPTS {
+                if let Some(points) = points.as_mut() {
+                    match points.next_feasible()? {
+                        Some(point) => study
+                            .enqueue_trial(point)
+                            .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?,
+                        None => {
+                            info!("Search space exhausted after {} trials", launched);
+                            return Ok(None);
+                        }
+                    }
+                }
+
+                let trial =
+                    study.ask().map_err(|e| AutoMLError::OptimizationError(e.to_string()))?;
+                let parameters = self.get_trial_params(&trial)?;
+                let violations = self.space.violations(&parameters)?;
+                if violations.is_empty() {
+                    return Ok(Some(self.start_trial(number, trial, parameters, writer.as_ref())));
+                }
 
-            let trial_start = chrono::Utc::now();
-            let trial = study.ask().map_err(|e| AutoMLError::OptimizationError(e.to_string()))?;
+                // Only the study's own samplers get here; native points are already feasible
+                debug!("Trial {} violates {}", trial.id, violations.join(", "));
+                infeasible_attempts += 1;
+                study
+                    .tell_failed(trial.id)
+                    .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?;
+                if let Some(repaired) = self.space.repair(&parameters, &mut repair_rng)? {
+                    study
+                        .enqueue_trial(repaired)
+                        .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?;
+                }
+            }
 
-            let result = match objective(&trial) {
+            warn!("No feasible configuration in {} attempts", MAX_INFEASIBLE_ATTEMPTS);
+            Ok(None)
+        };
+        let reporters = pruner.clone();
+        let evaluate = Arc::new(move |(number, trial, _): &RunningTrial, deadline| {
+            objective(trial, &reporters.reporter(*number).with_deadline(deadline))
+        });
+        let tell = |outcome: TrialOutcome<RunningTrial>| {
+            let (number, trial, parameters) = outcome.trial.as_ref();
+            let (intermediate_values, pruned) = pruner.finish(*number, outcome.result.is_ok());
+            let (value, state) = match outcome.result {
                 Ok(value) => {
                     study
                         .tell(trial.id, value)
                         .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?;
-
-                    TrialResult {
-                        trial_id: trial.id.to_string(),
-                        parameters: self.get_trial_params(&trial)?,
-                        value,
-                        state: crate::models::TrialState::Completed,
-                        datetime_start: trial_start,
-                        datetime_complete: Some(chrono::Utc::now()),
-                    }
+                    (value, TrialState::Completed)
                 }
-                Err(e) => {
+                // Pruned trials keep their last reported value but the study, which only
+                // learns from completed trials, sees them fail
+                Err(_) if pruned => {
                     study
                         .tell_failed(trial.id)
                         .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?;
-
-                    TrialResult {
-                        trial_id: trial.id.to_string(),
-                        parameters: self.get_trial_params(&trial)?,
-                        value: f64::NAN,
-                        state: crate::models::TrialState::Failed(e.to_string()),
-                        datetime_start: trial_start,
-                        datetime_complete: Some(chrono::Utc::now()),
-                    }
+                    let last = intermediate_values.values().next_back().copied();
+                    (last.unwrap_or(f64::NAN), TrialState::Pruned)
+                }
+                Err(reason) => {
+                    study
+                        .tell_failed(trial.id)
+                        .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?;
+                    (f64::NAN, TrialState::Failed(reason))
                 }
             };
 
+            let result = TrialResult {
+                trial_id: trial.id.to_string(),
+                number: *number,
+                parameters: parameters.clone(),
+                value,
+                intermediate_values,
+                state,
+                datetime_start: outcome.datetime_start,
+                datetime_complete: Some(outcome.datetime_complete),
+            };
+            if let Some(writer) = &writer {
+                // The writer only stops once every sender is dropped
+                let _ = writer.send(result.clone());
+            }
             trials.push(result);
+            Ok(())
+        };
+        let run = TrialScheduler::from_config(&self.config.optimization_config)
+            .run(n_trials, ask, evaluate, tell)
+            .await;
+
+        // Let the writer save what was sent before it, even when the run failed
+        drop(writer);
+        if let Some(writer_task) = writer_task {
+            writer_task
+                .await
+                .map_err(|e| AutoMLError::DatabaseError(format!("Trial writer failed: {}", e)))?;
         }
+        run?;
+
+        infeasible_attempts += points.map_or(0, |points| points.infeasible_attempts());
+        trials.extend(finished);
+        trials.sort_by_key(|trial| trial.number);
 
         // Get best trial
         let best_trial =
             study.best_trial().map_err(|e| AutoMLError::OptimizationError(e.to_string()))?;
 
+        // The best trial may be a replay of one from before the study was resumed
+        let best_id = best_trial.id.to_string();
+        let best_id = stored_ids.remove(&best_id).unwrap_or(best_id);
+        let best = trials.iter().find(|trial| trial.trial_id == best_id);
         let best_trial_result = TrialResult {
-            trial_id: best_trial.id.to_string(),
+            trial_id: best_id,
+            number: best.map_or(0, |trial| trial.number),
             parameters: self.get_trial_params(&best_trial)?,
             value: best_trial.value,
-            state: crate::models::TrialState::Completed,
+            intermediate_values: best
+                .map(|trial| trial.intermediate_values.clone())
+                .unwrap_or_default(),
+            state: TrialState::Completed,
             datetime_start: start_time,
             datetime_complete: Some(chrono::Utc::now()),
         };
 
         Ok(StudyResult {
-            study_id: study.id().to_string(),
+            study_id: self.study_id.clone(),
             task_type: self.config.task_type.clone(),
             best_trial: best_trial_result,
-            best_model_path: format!("models/best_model_{}.pt", study.id()),
+            best_model_path: format!("models/best_model_{}.pt", self.study_id),
             trials,
             optimization_history: study
                 .trials()
@@ -113,12 +298,73 @@ impl OptunaOptimizer {
                 .iter()
                 .map(|t| t.value)
                 .collect(),
+            infeasible_attempts,
+            ensemble: None,
+            feature_pipeline: None,
             datetime_start: start_time,
             datetime_complete: Some(chrono::Utc::now()),
             metadata: Default::default(),
         })
     }
 
+    /// Adds a finished trial of an earlier run to the study, returning the id it got there.
+    fn replay(&self, study: &Study, trial: &TrialResult) -> Result<String, AutoMLError> {
+        study
+            .enqueue_trial(trial.parameters.clone())
+            .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?;
+        let replayed = study.ask().map_err(|e| AutoMLError::OptimizationError(e.to_string()))?;
+        self.get_trial_params(&replayed)?;
+
+        match trial.state {
+            TrialState::Completed => study.tell(replayed.id, trial.value),
+            _ => study.tell_failed(replayed.id),
+        }
+        .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?;
+        Ok(replayed.id.to_string())
+    }
+
+    /// Records that trial `number` is running before it is evaluated.
+    fn start_trial(
+        &self,
+        number: usize,
+        trial: Trial,
+        parameters: HashMap<String, Value>,
+        writer: Option<&mpsc::UnboundedSender<TrialResult>>,
+    ) -> RunningTrial {
+        if let Some(writer) = writer {
+            let _ = writer.send(TrialResult {
+                trial_id: trial.id.to_string(),
+                number,
+                parameters: parameters.clone(),
+                value: f64::NAN,
+                intermediate_values: Default::default(),
+                state: TrialState::Running,
+                datetime_start: Utc::now(),
+                datetime_complete: None,
+            });
+        }
+        (number, trial, parameters)
+    }
+
+    /// Saves the trials sent to the returned channel in order, in the background.
+    ///
+    /// Trials are asked for and told about synchronously, so they are saved from a task
+    /// of their own. A trial that fails to save is logged and does not stop the study.
+    fn trial_writer(&self) -> Option<(mpsc::UnboundedSender<TrialResult>, JoinHandle<()>)> {
+        let repository = self.repository.clone()?;
+        let study_id = self.study_id.clone();
+        let (sender, mut receiver) = mpsc::unbounded_channel::<TrialResult>();
+
+        let task = tokio::spawn(async move {
+            while let Some(trial) = receiver.recv().await {
+                if let Err(e) = repository.save_trial(&study_id, &trial).await {
+                    error!("Failed to save trial {} of study {}: {}", trial.number, study_id, e);
+                }
+            }
+        });
+        Some((sender, task))
+    }
+
     async fn configure_sampler(&self, study: &Study) -> Result<(), AutoMLError> {
         use optuna::samplers::*;
 
@@ -135,9 +381,13 @@ impl OptunaOptimizer {
                     self.config.optimization_config.sampler_config.seed,
                 ));
             }
-            crate::models::SamplerType::GridSearch => {
-                // Implement grid search sampler
-                unimplemented!("Grid search sampler not implemented yet");
+            crate::models::SamplerType::GridSearch { .. }
+            | crate::models::SamplerType::Sobol
+            | crate::models::SamplerType::QMC => {
+                // Parameters come from the points enqueued by `native_points`
+                study.set_sampler(RandomSampler::new(
+                    self.config.optimization_config.sampler_config.seed,
+                ));
             }
             crate::models::SamplerType::CmaEs { sigma } => {
                 study.set_sampler(
@@ -146,87 +396,164 @@ impl OptunaOptimizer {
                         .with_seed(self.config.optimization_config.sampler_config.seed),
                 );
             }
-            _ => {
-                // Default to TPE
-                study.set_sampler(
-                    TPESampler::new()
-                        .with_seed(self.config.optimization_config.sampler_config.seed),
-                );
-            }
         }
 
         Ok(())
     }
 
-    async fn configure_pruner(&self, study: &Study) -> Result<(), AutoMLError> {
-        use optuna::pruners::*;
-
-        match &self.config.optimization_config.pruner_config.pruner_type {
-            crate::models::PrunerType::MedianPruner => {
-                study.set_pruner(
-                    MedianPruner::new()
-                        .with_n_warmup_steps(
-                            self.config.optimization_config.pruner_config.n_warmup_steps,
-                        )
-                        .with_n_min_trials(
-                            self.config.optimization_config.pruner_config.n_min_trials,
-                        ),
-                );
-            }
-            crate::models::PrunerType::PercentilePruner { percentile } => {
-                study.set_pruner(
-                    PercentilePruner::new(*percentile)
-                        .with_n_warmup_steps(
-                            self.config.optimization_config.pruner_config.n_warmup_steps,
-                        )
-                        .with_n_min_trials(
-                            self.config.optimization_config.pruner_config.n_min_trials,
-                        ),
-                );
-            }
-            crate::models::PrunerType::HyperbandPruner { min_resource, reduction_factor } => {
-                study.set_pruner(
-                    HyperbandPruner::new()
-                        .with_min_resource(*min_resource)
-                        .with_reduction_factor(*reduction_factor),
-                );
-            }
-            crate::models::PrunerType::ThresholdPruner { lower, upper } => {
-                study.set_pruner(ThresholdPruner::new(*lower, *upper));
-            }
-            crate::models::PrunerType::NopPruner => {
-                study.set_pruner(NopPruner::new());
+    /// The parameter points of a native sampler, or `None` when the study samples itself.
+    fn native_points(
+        &self,
+    ) -> Result<Option<Box<dyn Iterator<Item = ParameterPoint> + Send>>, AutoMLError> {
+        let space = &self.config.optimization_config.search_space;
+        let seed = self.config.optimization_config.sampler_config.seed;
+
+        Ok(match &self.config.optimization_config.sampler_config.sampler_type {
+            crate::models::SamplerType::GridSearch { resolution } => {
+                let grid = GridSampler::new(space, *resolution, seed)?;
+                info!("Grid search over {} points", grid.len());
+                Some(Box::new(grid))
             }
-        }
+            crate::models::SamplerType::Sobol => Some(Box::new(SobolSampler::new(space, seed)?)),
+            crate::models::SamplerType::QMC => Some(Box::new(HaltonSampler::new(space, seed)?)),
+            _ => None,
+        })
+    }
 
-        Ok(())
+    /// The pruner deciding which trials stop early.
+    ///
+    /// Trials are pruned natively as they report, so the study itself never prunes.
+    async fn configure_pruner(&self, study: &Study) -> Result<StudyPruner, AutoMLError> {
+        study.set_pruner(optuna::pruners::NopPruner::new());
+        StudyPruner::from_config(
+            &self.config.optimization_config.pruner_config,
+            &self.config.optimization_config.optimization_direction,
+        )
     }
 
+    /// Suggests the active parameters of `trial`, skipping those whose condition fails.
     fn get_trial_params(
         &self,
         trial: &Trial,
     ) -> Result<std::collections::HashMap<String, serde_json::Value>, AutoMLError> {
         let mut params = std::collections::HashMap::new();
 
-        for (name, range) in &self.config.optimization_config.search_space.parameters {
-            let value = match range {
-                ParameterRange::Continuous { low, high, log } => if *log {
-                    trial.suggest_log_float(name, *low, *high)
-                } else {
-                    trial.suggest_float(name, *low, *high)
-                }
-                .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?,
-                ParameterRange::Discrete { low, high, step } => trial
-                    .suggest_int(name, *low, *high, *step)
-                    .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?,
-                ParameterRange::Categorical { choices } => trial
-                    .suggest_categorical(name, choices.as_slice())
-                    .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?,
-            };
+        for (name, range) in self.space.parameters() {
+            if !self.space.is_active(name, &params)? {
+                continue;
+            }
 
-            params.insert(name.clone(), serde_json::to_value(value)?);
+            params.insert(name.to_string(), suggest_parameter(trial, name, range)?);
         }
 
         Ok(params)
     }
 }
+
+/// Suggests the value of `name` in `trial` from `range`.
+///
+/// A trial suggests the same value every time it is asked for a parameter, so objectives
+/// get the values the study recorded for the trial.
+pub fn suggest_parameter(
+    trial: &Trial,
+    name: &str,
+    range: &ParameterRange,
+) -> Result<Value, AutoMLError> {
+    let value = match range {
+        ParameterRange::Continuous { low, high, log } => if *log {
+            trial.suggest_log_float(name, *low, *high)
+        } else {
+            trial.suggest_float(name, *low, *high)
+        }
+        .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?,
+        ParameterRange::Discrete { low, high, step } => trial
+            .suggest_int(name, *low, *high, *step)
+            .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?,
+        ParameterRange::Categorical { choices } => trial
+            .suggest_categorical(name, choices.as_slice())
+            .map_err(|e| AutoMLError::OptimizationError(e.to_string()))?,
+    };
+    Ok(serde_json::to_value(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> AutoMLConfig {
+        serde_json::from_value(json!({
+            "task_type": "Regression",
+            "optimization_config": {
+                "n_trials": 4,
+                "timeout_seconds": null,
+                "n_jobs": 1,
+                "optimization_direction": "Minimize",
+                "search_space": {
+                    "parameters": { "x": { "Continuous": { "low": 0.0, "high": 1.0, "log": false } } },
+                    "constraints": []
+                },
+                "pruner_config": {
+                    "pruner_type": "NopPruner",
+                    "n_warmup_steps": 0,
+                    "n_min_trials": 0
+                },
+                "sampler_config": { "sampler_type": "RandomSearch", "seed": 1 }
+            },
+            "model_config": {
+                "model_type": { "Custom": "test" },
+                "architecture_search": false,
+                "feature_selection": false,
+                "ensemble_config": null
+            },
+            "training_config": {
+                "batch_size_range": [32, 32],
+                "epochs_range": [1, 1],
+                "early_stopping_patience": 1,
+                "validation_split": 0.2,
+                "cross_validation_folds": 1
+            },
+            "hardware_config": { "n_gpus": 0, "n_cpu_threads": 1, "memory_limit_mb": 512 }
+        }))
+        .unwrap()
+    }
+
+    fn stored(number: usize, value: f64) -> TrialResult {
+        TrialResult {
+            trial_id: format!("stored-{}", number),
+            number,
+            parameters: [("x".to_string(), json!(0.5))].into(),
+            value,
+            intermediate_values: [(0, value)].into(),
+            state: TrialState::Completed,
+            datetime_start: Utc::now(),
+            datetime_complete: Some(Utc::now()),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_the_best_trial_may_come_from_before_the_resume() {
+        let optimizer = OptunaOptimizer::new(config())
+            .await
+            .unwrap()
+            .with_history(vec![stored(0, 5.0), stored(1, -1.0)]);
+        // Every new trial scores worse than the stored trial 1
+        let result = optimizer
+            .optimize(|trial, _| {
+                let x = suggest_parameter(
+                    trial,
+                    "x",
+                    &ParameterRange::Continuous { low: 0.0, high: 1.0, log: false },
+                )?;
+                Ok(1.0 + x.as_f64().unwrap())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(result.trials.len(), 4);
+        assert_eq!(result.best_trial.number, 1);
+        assert_eq!(result.best_trial.trial_id, "stored-1");
+        assert_eq!(result.best_trial.value, -1.0);
+        assert_eq!(result.best_trial.intermediate_values[&0], -1.0);
+    }
+}
@@ -0,0 +1,488 @@
+//! Native pruners deciding from the intermediate values trials report.
+//!
+//! An objective reports a value per step through its [`TrialReporter`] and asks whether to
+//! stop. [`PercentilePruner`] stops trials doing worse than most completed trials did at
+//! the same step, [`ThresholdPruner`] stops those leaving a fixed range, and
+//! [`HyperbandPruner`] runs successive halving in several brackets, promoting the best
+//! trials of each bracket from one rung of steps to the next. Decisions are made inside the
+//! optimizer, whatever pruner the study itself is given.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::errors::AutoMLError;
+use crate::models::{OptimizationDirection, PrunerConfig, PrunerType, TrialResult, TrialState};
+
+/// Values a trial reported while running, by step.
+pub type Curve = BTreeMap<u64, f64>;
+
+/// The intermediate values of every trial of a study so far.
+#[derive(Debug, Default)]
+pub struct PruningHistory {
+    curves: HashMap<usize, Curve>,
+    completed: HashSet<usize>,
+    pruned: HashSet<usize>,
+}
+
+impl PruningHistory {
+    pub fn curve(&self, number: usize) -> Option<&Curve> {
+        self.curves.get(&number)
+    }
+
+    /// Curves of the trials that ran to completion.
+    pub fn completed(&self) -> impl Iterator<Item = &Curve> {
+        self.completed.iter().filter_map(|number| self.curves.get(number))
+    }
+
+    /// Curves of all trials, whether running, pruned, failed or completed, by number.
+    pub fn trials(&self) -> impl Iterator<Item = (usize, &Curve)> {
+        self.curves.iter().map(|(number, curve)| (*number, curve))
+    }
+}
+
+/// Decides whether a trial stops after reporting a value.
+pub trait Pruner: Send + Sync {
+    /// Whether trial `number` should stop now that its value at `step` is in `history`.
+    fn prune(&self, number: usize, step: u64, history: &PruningHistory) -> bool;
+}
+
+/// The steps at which pruners comparing values step by step are consulted.
+#[derive(Debug, Clone, Copy)]
+pub struct PruningSchedule {
+    /// Steps before this one are never pruned
+    pub n_warmup_steps: usize,
+    /// Steps after the warm-up are checked this often, every step if 0
+    pub interval_steps: usize,
+}
+
+impl PruningSchedule {
+    pub fn checks(&self, step: u64) -> bool {
+        let step = step as usize;
+        step >= self.n_warmup_steps
+            && (step - self.n_warmup_steps).is_multiple_of(self.interval_steps.max(1))
+    }
+}
+
+/// Prunes a trial whose best value so far is worse than the given percentile of the values
+/// completed trials reported at the same step. The median pruner is its 50th percentile.
+#[derive(Debug, Clone)]
+pub struct PercentilePruner {
+    percentile: f64,
+    direction: OptimizationDirection,
+    schedule: PruningSchedule,
+    /// Completed trials that must have reported the step before it is compared
+    n_min_trials: usize,
+}
+
+impl PercentilePruner {
+    pub fn new(
+        percentile: f64,
+        direction: OptimizationDirection,
+        schedule: PruningSchedule,
+        n_min_trials: usize,
+    ) -> Result<Self, AutoMLError> {
+        if !(0.0..=100.0).contains(&percentile) {
+            return Err(AutoMLError::ConfigError(format!(
+                "Pruning percentile must be between 0 and 100, got {}",
+                percentile
+            )));
+        }
+        Ok(Self { percentile, direction, schedule, n_min_trials: n_min_trials.max(1) })
+    }
+}
+
+impl Pruner for PercentilePruner {
+    fn prune(&self, number: usize, step: u64, history: &PruningHistory) -> bool {
+        let Some(curve) = history.curve(number) else { return false };
+        let Some(value) = curve.get(&step) else { return false };
+        if !self.schedule.checks(step) {
+            return false;
+        }
+        if value.is_nan() {
+            return true;
+        }
+
+        let mut others: Vec<f64> = history
+            .completed()
+            .filter_map(|other| other.get(&step).copied())
+            .filter(|value| !value.is_nan())
+            .collect();
+        if others.len() < self.n_min_trials {
+            return false;
+        }
+        others.sort_by(f64::total_cmp);
+
+        let so_far = curve.range(..=step).map(|(_, value)| *value).filter(|value| !value.is_nan());
+        match self.direction {
+            OptimizationDirection::Minimize => {
+                let best = so_far.fold(f64::INFINITY, f64::min);
+                best > percentile(&others, self.percentile)
+            }
+            OptimizationDirection::Maximize => {
+                let best = so_far.fold(f64::NEG_INFINITY, f64::max);
+                best < percentile(&others, 100.0 - self.percentile)
+            }
+        }
+    }
+}
+
+/// The `p`th percentile of sorted `values`, interpolating between neighbours.
+fn percentile(values: &[f64], p: f64) -> f64 {
+    let rank = p / 100.0 * (values.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    values[low] + (values[high] - values[low]) * (rank - low as f64)
+}
+
+/// Prunes a trial once a value falls outside `lower..=upper`.
+#[derive(Debug, Clone)]
+pub struct ThresholdPruner {
+    lower: f64,
+    upper: f64,
+    schedule: PruningSchedule,
+}
+
+impl ThresholdPruner {
+    pub fn new(lower: f64, upper: f64, schedule: PruningSchedule) -> Result<Self, AutoMLError> {
+        if lower.is_nan() || upper.is_nan() || lower > upper {
+            return Err(AutoMLError::ConfigError(format!(
+                "Invalid pruning thresholds {} and {}",
+                lower, upper
+            )));
+        }
+        Ok(Self { lower, upper, schedule })
+    }
+}
+
+impl Pruner for ThresholdPruner {
+    fn prune(&self, number: usize, step: u64, history: &PruningHistory) -> bool {
+        let Some(value) = history.curve(number).and_then(|curve| curve.get(&step)) else {
+            return false;
+        };
+        self.schedule.checks(step) && !(self.lower..=self.upper).contains(value)
+    }
+}
+
+/// Successive halving over several brackets, each trading how many trials it starts
+/// against how many steps it lets them run before the first cut.
+///
+/// The most steps any completed trial reported is the maximum resource; no trial is
+/// pruned before one completes. With `n` brackets, bracket `b` has rungs at
+/// `min_resource * reduction_factor^(b + k)` steps, and a trial reaching a rung carries on
+/// only if it is in the best `1 / reduction_factor` of the trials of its bracket that
+/// reached that rung. Trials are dealt to brackets by number, in proportion to the trials
+/// Hyperband starts in each.
+#[derive(Debug, Clone)]
+pub struct HyperbandPruner {
+    min_resource: u64,
+    reduction_factor: u64,
+    direction: OptimizationDirection,
+}
+
+impl HyperbandPruner {
+    pub fn new(
+        min_resource: u64,
+        reduction_factor: u64,
+        direction: OptimizationDirection,
+    ) -> Result<Self, AutoMLError> {
+        if min_resource == 0 || reduction_factor < 2 {
+            return Err(AutoMLError::ConfigError(format!(
+                "Hyperband needs a minimum resource of at least 1 and a reduction factor of at \
+                 least 2, got {} and {}",
+                min_resource, reduction_factor
+            )));
+        }
+        Ok(Self { min_resource, reduction_factor, direction })
+    }
+
+    fn n_brackets(&self, max_resource: u64) -> u32 {
+        let mut n_brackets = 1;
+        let mut resource = self.min_resource;
+        while resource.saturating_mul(self.reduction_factor) <= max_resource {
+            resource *= self.reduction_factor;
+            n_brackets += 1;
+        }
+        n_brackets
+    }
+
+    /// The bracket of trial `number`; bracket `b` starts
+    /// `ceil(n / (n - b) * reduction_factor^(n - 1 - b))` trials per round.
+    fn bracket(&self, number: usize, n_brackets: u32) -> u32 {
+        let budgets: Vec<u64> = (0..n_brackets)
+            .map(|bracket| {
+                let rounds = n_brackets - bracket;
+                let trials = self.reduction_factor.pow(rounds - 1);
+                (u64::from(n_brackets) * trials).div_ceil(u64::from(rounds))
+            })
+            .collect();
+        let mut position = number as u64 % budgets.iter().sum::<u64>();
+        for (bracket, budget) in budgets.iter().enumerate() {
+            if position < *budget {
+                return bracket as u32;
+            }
+            position -= budget;
+        }
+        0
+    }
+}
+
+impl Pruner for HyperbandPruner {
+    fn prune(&self, number: usize, step: u64, history: &PruningHistory) -> bool {
+        let Some(value) = history.curve(number).and_then(|curve| curve.get(&step)).copied() else {
+            return false;
+        };
+        if value.is_nan() {
+            return true;
+        }
+        let Some(max_resource) =
+            history.completed().filter_map(|curve| curve.keys().next_back()).max().map(|s| s + 1)
+        else {
+            return false;
+        };
+
+        // Only trials reaching a rung of their bracket short of the end are judged
+        let n_brackets = self.n_brackets(max_resource);
+        let bracket = self.bracket(number, n_brackets);
+        let resource = step + 1;
+        let mut rung = self.min_resource.saturating_mul(self.reduction_factor.pow(bracket));
+        while rung < resource {
+            rung = rung.saturating_mul(self.reduction_factor);
+        }
+        if rung != resource || resource >= max_resource {
+            return false;
+        }
+
+        let mut competing: Vec<f64> = history
+            .trials()
+            .filter(|(other, _)| self.bracket(*other, n_brackets) == bracket)
+            .filter_map(|(_, curve)| curve.get(&step).copied())
+            .filter(|value| !value.is_nan())
+            .collect();
+        competing.sort_by(f64::total_cmp);
+        if let OptimizationDirection::Maximize = self.direction {
+            competing.reverse();
+        }
+        let promoted = (competing.len() / self.reduction_factor as usize).max(1);
+        let cutoff = competing[promoted - 1];
+        match self.direction {
+            OptimizationDirection::Minimize => value > cutoff,
+            OptimizationDirection::Maximize => value < cutoff,
+        }
+    }
+}
+
+/// Never prunes.
+#[derive(Debug, Clone, Default)]
+pub struct NopPruner;
+
+impl Pruner for NopPruner {
+    fn prune(&self, _: usize, _: u64, _: &PruningHistory) -> bool {
+        false
+    }
+}
+
+/// The pruner of a study and the history it decides from, shared by its running trials.
+#[derive(Clone)]
+pub struct StudyPruner {
+    pruner: Arc<dyn Pruner>,
+    history: Arc<Mutex<PruningHistory>>,
+}
+
+impl StudyPruner {
+    pub fn new(pruner: Arc<dyn Pruner>) -> Self {
+        Self { pruner, history: Arc::default() }
+    }
+
+    pub fn from_config(
+        config: &PrunerConfig,
+        direction: &OptimizationDirection,
+    ) -> Result<Self, AutoMLError> {
+        let schedule = PruningSchedule {
+            n_warmup_steps: config.n_warmup_steps,
+            interval_steps: config.interval_steps,
+        };
+        let direction = direction.clone();
+        let pruner: Arc<dyn Pruner> = match &config.pruner_type {
+            PrunerType::MedianPruner => {
+                Arc::new(PercentilePruner::new(50.0, direction, schedule, config.n_min_trials)?)
+            }
+            PrunerType::PercentilePruner { percentile } => Arc::new(PercentilePruner::new(
+                *percentile,
+                direction,
+                schedule,
+                config.n_min_trials,
+            )?),
+            PrunerType::HyperbandPruner { min_resource, reduction_factor } => {
+                Arc::new(HyperbandPruner::new(*min_resource, *reduction_factor, direction)?)
+            }
+            PrunerType::ThresholdPruner { lower, upper } => {
+                Arc::new(ThresholdPruner::new(*lower, *upper, schedule)?)
+            }
+            PrunerType::NopPruner => Arc::new(NopPruner),
+        };
+        Ok(Self::new(pruner))
+    }
+
+    /// Adds a finished trial of an earlier run, so later trials are compared with it.
+    pub fn replay(&self, trial: &TrialResult) {
+        let mut history = self.history();
+        history.curves.insert(trial.number, trial.intermediate_values.clone());
+        match trial.state {
+            TrialState::Completed => history.completed.insert(trial.number),
+            TrialState::Pruned => history.pruned.insert(trial.number),
+            _ => false,
+        };
+    }
+
+    /// A reporter for trial `number`, forgetting anything it reported in an earlier run.
+    pub fn reporter(&self, number: usize) -> TrialReporter {
+        let mut history = self.history();
+        history.curves.insert(number, Curve::new());
+        history.completed.remove(&number);
+        history.pruned.remove(&number);
+        TrialReporter { number, pruner: self.clone(), deadline: None }
+    }
+
+    /// Records that trial `number` finished, returning what it reported and whether it was
+    /// told to stop.
+    pub fn finish(&self, number: usize, completed: bool) -> (Curve, bool) {
+        let mut history = self.history();
+        if completed {
+            history.completed.insert(number);
+        }
+        let curve = history.curves.get(&number).cloned().unwrap_or_default();
+        (curve, history.pruned.contains(&number))
+    }
+
+    fn history(&self) -> std::sync::MutexGuard<'_, PruningHistory> {
+        // A panicking objective cannot leave the history half-updated
+        self.history.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Reports the intermediate values of one trial to the pruner of its study.
+#[derive(Clone)]
+pub struct TrialReporter {
+    number: usize,
+    pruner: StudyPruner,
+    deadline: Option<Instant>,
+}
+
+impl TrialReporter {
+    /// Tells the trial to stop once `deadline` passes, whatever it reported.
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// The number of the trial in its study.
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// Records `value` at `step`, replacing any value reported there before.
+    pub fn report(&self, value: f64, step: u64) {
+        self.pruner.history().curves.entry(self.number).or_default().insert(step, value);
+    }
+
+    /// Whether the trial should stop, judged on the last step it reported.
+    ///
+    /// A trial told to stop ends as pruned if its objective then fails, and as completed if
+    /// it carries on regardless. Past its deadline a trial is always told to stop, and ends
+    /// as timed out.
+    pub fn should_prune(&self) -> bool {
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return true;
+        }
+        let mut history = self.pruner.history();
+        let Some(step) = history.curve(self.number).and_then(|curve| curve.keys().next_back())
+        else {
+            return false;
+        };
+        let prune = self.pruner.pruner.prune(self.number, *step, &history);
+        if prune {
+            history.pruned.insert(self.number);
+        }
+        prune
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `curves` in order as trials, each reporting its values and stopping when told.
+    /// Returns the number of steps every trial ran.
+    fn run(pruner: impl Pruner + 'static, curves: &[Vec<f64>]) -> Vec<usize> {
+        let study = StudyPruner::new(Arc::new(pruner));
+        curves
+            .iter()
+            .enumerate()
+            .map(|(number, curve)| {
+                let reporter = study.reporter(number);
+                for (step, value) in curve.iter().enumerate() {
+                    reporter.report(*value, step as u64);
+                    if reporter.should_prune() {
+                        study.finish(number, false);
+                        return step + 1;
+                    }
+                }
+                study.finish(number, true);
+                curve.len()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_percentile_and_threshold_pruners_respect_schedule() {
+        let schedule = PruningSchedule { n_warmup_steps: 1, interval_steps: 2 };
+        let median =
+            PercentilePruner::new(50.0, OptimizationDirection::Minimize, schedule, 2).unwrap();
+        let curves = vec![
+            vec![1.0, 0.8, 0.6, 0.4, 0.2],
+            vec![1.2, 0.9, 0.7, 0.5, 0.3],
+            // Worse from the start, but only checked at steps 1 and 3 once two trials finished
+            vec![5.0, 5.0, 5.0, 5.0, 5.0],
+            vec![0.5, 0.4, 0.3, 0.2, 0.1],
+            vec![f64::NAN, 1.0, 1.0],
+        ];
+        assert_eq!(run(median, &curves), vec![5, 5, 2, 5, 2]);
+
+        let maximize =
+            PercentilePruner::new(50.0, OptimizationDirection::Maximize, schedule, 1).unwrap();
+        assert_eq!(run(maximize, &[vec![0.5, 0.9, 0.9], vec![0.5, 0.1, 0.1]]), vec![3, 2]);
+
+        let threshold = ThresholdPruner::new(0.0, 1.0, schedule).unwrap();
+        assert_eq!(run(threshold, &[vec![2.0, 0.5, 2.0, 2.0], vec![0.5, 0.5, 0.5]]), vec![4, 3]);
+        assert!(ThresholdPruner::new(1.0, 0.0, schedule).is_err());
+        assert!(HyperbandPruner::new(1, 1, OptimizationDirection::Minimize).is_err());
+    }
+
+    #[test]
+    fn test_hyperband_halves_trials_at_rungs_of_their_bracket() {
+        let hyperband = HyperbandPruner::new(1, 3, OptimizationDirection::Minimize).unwrap();
+        // Nine steps make three brackets of 9, 5 and 3 trials per round
+        assert_eq!(hyperband.n_brackets(9), 3);
+        let brackets: Vec<u32> = (0..17).map(|number| hyperband.bracket(number, 3)).collect();
+        assert_eq!(brackets.iter().filter(|bracket| **bracket == 0).count(), 9);
+        assert_eq!(brackets.iter().filter(|bracket| **bracket == 2).count(), 3);
+
+        // The first trial sets the budget, and every later one does worse than those before
+        // it, so it is cut at the first rung of its bracket unless it is alone there
+        let curves: Vec<Vec<f64>> = (0..17).map(|number| vec![number as f64; 9]).collect();
+        let steps = run(hyperband, &curves);
+        assert_eq!(steps[0], 9);
+        for (number, steps) in steps.iter().enumerate().skip(1) {
+            let expected = match brackets[number] {
+                // Rungs at 1 and 3 steps
+                0 => 1,
+                // A rung at 3 steps, where trial 9 is the first of its bracket
+                1 if number == 9 => 9,
+                1 => 3,
+                // A rung at 9 steps, which is the end
+                _ => 9,
+            };
+            assert_eq!(*steps, expected, "trial {}", number);
+        }
+    }
+}
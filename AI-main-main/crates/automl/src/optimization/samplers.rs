@@ -0,0 +1,595 @@
+//! Native samplers driven by a [`SearchSpace`].
+//!
+//! [`GridSampler`] enumerates every combination of parameter values, splitting continuous
+//! ranges into a fixed number of points. [`SobolSampler`] and [`HaltonSampler`] draw
+//! scrambled low-discrepancy sequences in the unit cube, which cover the space more evenly
+//! than random search for the same number of trials. All of them are deterministic under a
+//! seed; without one, they are seeded from entropy.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::errors::AutoMLError;
+use crate::models::{ParameterRange, SearchSpace};
+
+/// Values assigned to the parameters of one trial, by name.
+pub type ParameterPoint = HashMap<String, Value>;
+
+/// Continuous ranges are split into this many values unless configured otherwise.
+pub const DEFAULT_GRID_RESOLUTION: usize = 10;
+
+/// Grids larger than this are rejected rather than enumerated.
+pub const MAX_GRID_POINTS: usize = 1_000_000;
+
+pub(crate) fn rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// The parameters of `space` sorted by name, so sequences do not depend on map order.
+pub(crate) fn parameters(
+    space: &SearchSpace,
+) -> Result<Vec<(String, ParameterRange)>, AutoMLError> {
+    let mut parameters: Vec<_> =
+        space.parameters.iter().map(|(name, range)| (name.clone(), range.clone())).collect();
+    parameters.sort_by(|a, b| a.0.cmp(&b.0));
+    for (name, range) in &parameters {
+        let invalid = |reason: &str| {
+            Err(AutoMLError::ConfigError(format!("Invalid range for {}: {}", name, reason)))
+        };
+        match range {
+            ParameterRange::Continuous { low, high, log } => {
+                if !(low.is_finite() && high.is_finite() && low < high) {
+                    return invalid("low must be below high");
+                }
+                if *log && *low <= 0.0 {
+                    return invalid("log ranges must be positive");
+                }
+            }
+            ParameterRange::Discrete { low, high, step } => {
+                if *step <= 0 || low > high {
+                    return invalid("step must be positive and low at most high");
+                }
+            }
+            ParameterRange::Categorical { choices } => {
+                if choices.is_empty() {
+                    return invalid("no choices");
+                }
+            }
+        }
+    }
+    Ok(parameters)
+}
+
+/// Number of values of a discrete range, which may not fit in 64 bits.
+fn discrete_len(low: i64, high: i64, step: i64) -> u128 {
+    ((high as i128 - low as i128) / step as i128) as u128 + 1
+}
+
+/// Every combination of parameter values, in an order shuffled by the seed.
+///
+/// Discrete and categorical ranges contribute all of their values; continuous ranges
+/// `resolution` evenly spaced values including both bounds, spaced geometrically when
+/// `log` is set.
+pub struct GridSampler {
+    axes: Vec<(String, Vec<Value>)>,
+    order: Vec<usize>,
+    position: usize,
+}
+
+impl GridSampler {
+    pub fn new(
+        space: &SearchSpace,
+        resolution: usize,
+        seed: Option<u64>,
+    ) -> Result<Self, AutoMLError> {
+        if resolution < 2 {
+            return Err(AutoMLError::ConfigError("Grid resolution must be at least 2".to_string()));
+        }
+
+        // Sized before any value is made, so oversized grids are never materialised
+        let parameters = parameters(space)?;
+        let size = parameters
+            .iter()
+            .try_fold(1usize, |size, (_, range)| {
+                let len = match range {
+                    ParameterRange::Continuous { .. } => resolution,
+                    ParameterRange::Discrete { low, high, step } => {
+                        usize::try_from(discrete_len(*low, *high, *step)).ok()?
+                    }
+                    ParameterRange::Categorical { choices } => choices.len(),
+                };
+                size.checked_mul(len)
+            })
+            .filter(|size| *size <= MAX_GRID_POINTS)
+            .ok_or_else(|| {
+                AutoMLError::ConfigError(format!(
+                    "Grid has more than {} points; lower the resolution or narrow the ranges",
+                    MAX_GRID_POINTS
+                ))
+            })?;
+
+        let mut axes = Vec::new();
+        for (name, range) in parameters {
+            let values: Vec<Value> = match range {
+                ParameterRange::Continuous { low, high, log } => (0..resolution)
+                    .map(|i| {
+                        let fraction = i as f64 / (resolution - 1) as f64;
+                        Value::from(scale(fraction, low, high, log))
+                    })
+                    .collect(),
+                ParameterRange::Discrete { low, high, step } => (0..discrete_len(low, high, step))
+                    .map(|i| Value::from(low + i as i64 * step))
+                    .collect(),
+                ParameterRange::Categorical { choices } => {
+                    choices.into_iter().map(Value::from).collect()
+                }
+            };
+            axes.push((name, values));
+        }
+
+        let mut order: Vec<usize> = (0..size).collect();
+        order.shuffle(&mut rng(seed));
+
+        Ok(Self { axes, order, position: 0 })
+    }
+
+    /// Number of points in the grid.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+impl Iterator for GridSampler {
+    type Item = ParameterPoint;
+
+    fn next(&mut self) -> Option<ParameterPoint> {
+        let mut index = *self.order.get(self.position)?;
+        self.position += 1;
+        let mut point = ParameterPoint::new();
+        for (name, values) in &self.axes {
+            point.insert(name.clone(), values[index % values.len()].clone());
+            index /= values.len();
+        }
+        Some(point)
+    }
+}
+
+/// Maps `fraction` in [0, 1] onto a continuous range.
+fn scale(fraction: f64, low: f64, high: f64, log: bool) -> f64 {
+    if log {
+        (low.ln() + fraction * (high.ln() - low.ln())).exp()
+    } else {
+        low + fraction * (high - low)
+    }
+}
+
+/// Maps points of the unit cube onto the parameters of a search space.
+struct UnitMapper {
+    parameters: Vec<(String, ParameterRange)>,
+}
+
+impl UnitMapper {
+    fn dimensions(&self) -> usize {
+        self.parameters.len()
+    }
+
+    fn point(&self, unit: &[f64]) -> ParameterPoint {
+        self.parameters
+            .iter()
+            .zip(unit)
+            .map(|((name, range), &u)| (name.clone(), value_at(range, u)))
+            .collect()
+    }
+}
+
+/// The value of `range` at `u` in [0, 1).
+///
+/// Continuous ranges are scaled; discrete and categorical ones are split into equal
+/// intervals, one per value, so an evenly spread sequence picks each value equally often.
+pub(crate) fn value_at(range: &ParameterRange, u: f64) -> Value {
+    let bucket = |len: u128| ((u * len as f64) as u128).min(len - 1);
+    match range {
+        ParameterRange::Continuous { low, high, log } => Value::from(scale(u, *low, *high, *log)),
+        ParameterRange::Discrete { low, high, step } => {
+            let index = bucket(discrete_len(*low, *high, *step));
+            Value::from((*low as i128 + index as i128 * *step as i128) as i64)
+        }
+        ParameterRange::Categorical { choices } => {
+            Value::from(choices[bucket(choices.len() as u128) as usize].clone())
+        }
+    }
+}
+
+/// Primitive polynomials and initial direction numbers for dimensions 2 to 21, from Joe and
+/// Kuo's `new-joe-kuo-6.21201`: degree `s`, coefficients `a` and `m_1..m_s`.
+const SOBOL_PARAMETERS: [(u32, u32, &[u32]); 20] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+    (6, 19, &[1, 1, 1, 15, 7, 5]),
+    (6, 22, &[1, 3, 1, 15, 13, 25]),
+    (6, 25, &[1, 1, 5, 5, 19, 61]),
+    (7, 1, &[1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, &[1, 3, 7, 13, 13, 15, 69]),
+];
+
+/// Dimensions [`SobolSampler`] supports.
+pub const MAX_SOBOL_DIMENSIONS: usize = SOBOL_PARAMETERS.len() + 1;
+
+const SOBOL_BITS: usize = 32;
+
+/// The 32 direction numbers of one Sobol dimension.
+fn sobol_directions(dimension: usize) -> [u32; SOBOL_BITS] {
+    let mut directions = [0u32; SOBOL_BITS];
+    if dimension == 0 {
+        for (k, direction) in directions.iter_mut().enumerate() {
+            *direction = 1 << (SOBOL_BITS - 1 - k);
+        }
+        return directions;
+    }
+
+    let (degree, coefficients, initial) = SOBOL_PARAMETERS[dimension - 1];
+    let degree = degree as usize;
+    let mut m = [0u32; SOBOL_BITS];
+    m[..degree].copy_from_slice(initial);
+    for k in degree..SOBOL_BITS {
+        let mut value = m[k - degree] ^ (m[k - degree] << degree);
+        for j in 1..degree {
+            if (coefficients >> (degree - 1 - j)) & 1 == 1 {
+                value ^= m[k - j] << j;
+            }
+        }
+        m[k] = value;
+    }
+    for (k, direction) in directions.iter_mut().enumerate() {
+        *direction = m[k] << (SOBOL_BITS - 1 - k);
+    }
+    directions
+}
+
+/// Nested uniform (Owen) scrambling of the bits of `x`, after Burley's hash-based scheme.
+///
+/// Each bit is flipped depending only on the bits above it, so points that share a
+/// dyadic interval before scrambling share one after, and stratification is kept.
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits().wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+/// A scrambled Sobol sequence of up to 2^32 points.
+///
+/// Its first 2^k points place exactly one point in each interval of width 2^-k along every
+/// parameter, so powers of two are the best trial budgets.
+pub struct SobolSampler {
+    mapper: UnitMapper,
+    directions: Vec<[u32; SOBOL_BITS]>,
+    scrambles: Vec<u32>,
+    index: u64,
+}
+
+impl SobolSampler {
+    pub fn new(space: &SearchSpace, seed: Option<u64>) -> Result<Self, AutoMLError> {
+        let mapper = UnitMapper { parameters: parameters(space)? };
+        if mapper.dimensions() > MAX_SOBOL_DIMENSIONS {
+            return Err(AutoMLError::ConfigError(format!(
+                "Sobol sampling supports at most {} parameters, got {}",
+                MAX_SOBOL_DIMENSIONS,
+                mapper.dimensions()
+            )));
+        }
+        let mut rng = rng(seed);
+        let directions = (0..mapper.dimensions()).map(sobol_directions).collect();
+        let scrambles = (0..mapper.dimensions()).map(|_| rng.gen()).collect();
+        Ok(Self { mapper, directions, scrambles, index: 0 })
+    }
+
+    fn unit(&self, index: u32) -> Vec<f64> {
+        self.directions
+            .iter()
+            .zip(&self.scrambles)
+            .map(|(directions, &seed)| {
+                let x = (0..SOBOL_BITS)
+                    .filter(|bit| (index >> bit) & 1 == 1)
+                    .fold(0, |x, bit| x ^ directions[bit]);
+                owen_scramble(x, seed) as f64 / (1u64 << SOBOL_BITS) as f64
+            })
+            .collect()
+    }
+}
+
+impl Iterator for SobolSampler {
+    type Item = ParameterPoint;
+
+    fn next(&mut self) -> Option<ParameterPoint> {
+        let index = u32::try_from(self.index).ok()?;
+        self.index += 1;
+        Some(self.mapper.point(&self.unit(index)))
+    }
+}
+
+/// The first `count` primes.
+fn primes(count: usize) -> Vec<u64> {
+    let mut primes: Vec<u64> = Vec::with_capacity(count);
+    let mut candidate = 2;
+    while primes.len() < count {
+        if primes.iter().take_while(|p| *p * *p <= candidate).all(|p| candidate % p != 0) {
+            primes.push(candidate);
+        }
+        candidate += 1;
+    }
+    primes
+}
+
+/// A Halton sequence with each digit scrambled by a random permutation.
+///
+/// Parameter `d` uses the `d`-th prime as its base `b`; the first `b^k` points place
+/// exactly one point in each interval of width `b^-k` along it. Scrambling breaks up the
+/// correlation between the higher bases that makes plain Halton points fall on lines.
+pub struct HaltonSampler {
+    mapper: UnitMapper,
+    bases: Vec<u64>,
+    /// One permutation of the digits `0..base` per parameter and digit position
+    permutations: Vec<Vec<Vec<u64>>>,
+    index: u64,
+}
+
+impl HaltonSampler {
+    pub fn new(space: &SearchSpace, seed: Option<u64>) -> Result<Self, AutoMLError> {
+        let mapper = UnitMapper { parameters: parameters(space)? };
+        let bases = primes(mapper.dimensions());
+        let mut rng = rng(seed);
+        let permutations = bases
+            .iter()
+            .map(|&base| {
+                // Enough digits for an index of 53 bits, the precision of an f64
+                let digits = (53.0 / (base as f64).log2()).ceil() as usize;
+                (0..digits)
+                    .map(|_| {
+                        let mut permutation: Vec<u64> = (0..base).collect();
+                        permutation.shuffle(&mut rng);
+                        permutation
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(Self { mapper, bases, permutations, index: 0 })
+    }
+
+    fn unit(&self, index: u64) -> Vec<f64> {
+        self.bases
+            .iter()
+            .zip(&self.permutations)
+            .map(|(&base, permutations)| {
+                let mut remaining = index;
+                let mut value = 0.0;
+                let mut weight = 1.0 / base as f64;
+                for permutation in permutations {
+                    value += permutation[(remaining % base) as usize] as f64 * weight;
+                    remaining /= base;
+                    weight /= base as f64;
+                }
+                value
+            })
+            .collect()
+    }
+}
+
+impl Iterator for HaltonSampler {
+    type Item = ParameterPoint;
+
+    fn next(&mut self) -> Option<ParameterPoint> {
+        if self.index >= 1 << 53 {
+            return None;
+        }
+        let unit = self.unit(self.index);
+        self.index += 1;
+        Some(self.mapper.point(&unit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn space(parameters: &[(&str, ParameterRange)]) -> SearchSpace {
+        SearchSpace {
+            parameters: parameters
+                .iter()
+                .map(|(name, range)| (name.to_string(), range.clone()))
+                .collect(),
+            constraints: Vec::new(),
+            conditions: HashMap::new(),
+            infeasible_strategy: Default::default(),
+        }
+    }
+
+    fn mixed_space() -> SearchSpace {
+        space(&[
+            ("learning_rate", ParameterRange::Continuous { low: 1e-4, high: 1e-1, log: true }),
+            ("depth", ParameterRange::Discrete { low: 2, high: 16, step: 2 }),
+            (
+                "activation",
+                ParameterRange::Categorical {
+                    choices: vec!["relu".to_string(), "tanh".to_string()],
+                },
+            ),
+        ])
+    }
+
+    fn key(point: &ParameterPoint) -> String {
+        let mut entries: Vec<_> =
+            point.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+        entries.sort();
+        entries.join(",")
+    }
+
+    #[test]
+    fn test_grid_enumerates_every_combination_once() {
+        let grid = GridSampler::new(&mixed_space(), 4, Some(7)).unwrap();
+        assert_eq!(grid.len(), 4 * 8 * 2);
+        let points: Vec<_> = grid.collect();
+        assert_eq!(points.len(), 64);
+        assert_eq!(points.iter().map(key).collect::<HashSet<_>>().len(), 64);
+
+        let rates: HashSet<_> =
+            points.iter().map(|point| point["learning_rate"].to_string()).collect();
+        assert_eq!(rates.len(), 4);
+        let depths: HashSet<_> =
+            points.iter().map(|point| point["depth"].as_i64().unwrap()).collect();
+        assert_eq!(depths, (1..=8).map(|i| i * 2).collect());
+        let bounds: Vec<f64> =
+            points.iter().map(|point| point["learning_rate"].as_f64().unwrap()).collect();
+        assert!(bounds.iter().any(|rate| (rate - 1e-4).abs() < 1e-12));
+        assert!(bounds.iter().any(|rate| (rate - 1e-1).abs() < 1e-12));
+
+        let again: Vec<_> =
+            GridSampler::new(&mixed_space(), 4, Some(7)).unwrap().map(|p| key(&p)).collect();
+        assert_eq!(again, points.iter().map(key).collect::<Vec<_>>());
+        assert!(GridSampler::new(&mixed_space(), 1, None).is_err());
+        assert!(GridSampler::new(&mixed_space(), 100_000, None).is_err());
+        assert!(GridSampler::new(&mixed_space(), usize::MAX, None).is_err());
+        let wide = space(&[(
+            "seed",
+            ParameterRange::Discrete { low: i64::MIN, high: i64::MAX, step: 1 },
+        )]);
+        assert!(GridSampler::new(&wide, 2, None).is_err());
+    }
+
+    /// Whether the first `count` values of `parameter` fall one per interval of `1 / count`.
+    fn stratified(points: &[ParameterPoint], parameter: &str, low: f64, high: f64) -> bool {
+        let count = points.len();
+        let cells: HashSet<_> = points
+            .iter()
+            .map(|point| {
+                let u = (point[parameter].as_f64().unwrap() - low) / (high - low);
+                (u * count as f64) as usize
+            })
+            .collect();
+        cells.len() == count
+    }
+
+    fn unit_space(dimensions: usize) -> SearchSpace {
+        let names: Vec<_> = (0..dimensions).map(|d| format!("x{:02}", d)).collect();
+        space(
+            &names
+                .iter()
+                .map(|name| {
+                    (name.as_str(), ParameterRange::Continuous { low: 0.0, high: 1.0, log: false })
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn test_sobol_points_are_stratified_unique_and_seeded() {
+        let points: Vec<_> = SobolSampler::new(&unit_space(MAX_SOBOL_DIMENSIONS), Some(3))
+            .unwrap()
+            .take(256)
+            .collect();
+        for d in 0..MAX_SOBOL_DIMENSIONS {
+            assert!(stratified(&points, &format!("x{:02}", d), 0.0, 1.0), "dimension {}", d);
+        }
+        assert_eq!(points.iter().map(key).collect::<HashSet<_>>().len(), 256);
+
+        // The first two dimensions form a (0, m, 2)-net: one point per 16 x 16 cell
+        let cells: HashSet<_> = points
+            .iter()
+            .map(|point| {
+                let cell = |name: &str| (point[name].as_f64().unwrap() * 16.0) as usize;
+                (cell("x00"), cell("x01"))
+            })
+            .collect();
+        assert_eq!(cells.len(), 256);
+
+        let same: Vec<_> = SobolSampler::new(&unit_space(MAX_SOBOL_DIMENSIONS), Some(3))
+            .unwrap()
+            .take(256)
+            .collect();
+        assert_eq!(same, points);
+        let other: Vec<_> = SobolSampler::new(&unit_space(MAX_SOBOL_DIMENSIONS), Some(4))
+            .unwrap()
+            .take(256)
+            .collect();
+        assert_ne!(other, points);
+        assert!(SobolSampler::new(&unit_space(MAX_SOBOL_DIMENSIONS + 1), None).is_err());
+
+        let depths: Vec<_> = SobolSampler::new(&mixed_space(), Some(3))
+            .unwrap()
+            .take(64)
+            .map(|point| point["depth"].as_i64().unwrap())
+            .collect();
+        for depth in (2..=16).step_by(2) {
+            assert_eq!(depths.iter().filter(|d| **d == depth).count(), 8);
+        }
+    }
+
+    #[test]
+    fn test_halton_points_are_stratified_unique_and_seeded() {
+        let points: Vec<_> =
+            HaltonSampler::new(&unit_space(3), Some(11)).unwrap().take(243).collect();
+        assert!(stratified(&points, "x01", 0.0, 1.0));
+        assert!(stratified(&points[..128], "x00", 0.0, 1.0));
+        assert!(stratified(&points[..125], "x02", 0.0, 1.0));
+        assert_eq!(points.iter().map(key).collect::<HashSet<_>>().len(), 243);
+
+        let same: Vec<_> =
+            HaltonSampler::new(&unit_space(3), Some(11)).unwrap().take(243).collect();
+        assert_eq!(same, points);
+        let other: Vec<_> =
+            HaltonSampler::new(&unit_space(3), Some(12)).unwrap().take(243).collect();
+        assert_ne!(other, points);
+
+        let activations: Vec<_> = HaltonSampler::new(&mixed_space(), None)
+            .unwrap()
+            .take(32)
+            .map(|point| point["activation"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(activations.iter().filter(|a| *a == "relu").count(), 16);
+    }
+
+    #[test]
+    fn test_grid_sampler_config_accepts_the_unit_form() {
+        use crate::models::{SamplerConfig, SamplerType};
+
+        let json = r#"{"sampler_type": "GridSearch", "seed": null}"#;
+        let config: SamplerConfig = serde_json::from_str(json).unwrap();
+        assert!(matches!(
+            config.sampler_type,
+            SamplerType::GridSearch { resolution: DEFAULT_GRID_RESOLUTION }
+        ));
+        let json = r#"{"sampler_type": {"GridSearch": {"resolution": 4}}, "seed": 1}"#;
+        let config: SamplerConfig = serde_json::from_str(json).unwrap();
+        assert!(matches!(config.sampler_type, SamplerType::GridSearch { resolution: 4 }));
+        let json = r#"{"sampler_type": {"TPE": {"n_ei_candidates": 24}}, "seed": null}"#;
+        let config: SamplerConfig = serde_json::from_str(json).unwrap();
+        assert!(matches!(config.sampler_type, SamplerType::TPE { n_ei_candidates: 24 }));
+        assert!(serde_json::from_str::<SamplerConfig>(r#"{"sampler_type": "Grid"}"#).is_err());
+    }
+}
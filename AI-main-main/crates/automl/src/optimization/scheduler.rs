@@ -0,0 +1,260 @@
+//! Concurrent trial scheduling.
+//!
+//! [`TrialScheduler`] keeps up to `n_jobs` objective evaluations running on the blocking
+//! thread pool. Trials are asked for and told about on the calling task only, one at a
+//! time, so a sampler sees each result before the next trial is asked for, whatever order
+//! evaluations finish in.
+//!
+//! Timeouts are cooperative: a blocking evaluation cannot be interrupted, so it is handed
+//! its deadline to stop at and keeps its slot until it returns.
+
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::errors::AutoMLError;
+use crate::models::OptimizationConfig;
+
+/// A finished trial, told to the caller in completion order.
+#[derive(Debug)]
+pub struct TrialOutcome<T> {
+    /// Position of the trial in launch order
+    pub number: usize,
+    pub trial: Arc<T>,
+    pub datetime_start: DateTime<Utc>,
+    pub datetime_complete: DateTime<Utc>,
+    /// The objective value, or why the trial failed
+    pub result: Result<f64, String>,
+}
+
+/// When a trial has to finish, and what to report if it does not.
+#[derive(Clone)]
+struct Deadline {
+    at: Instant,
+    reason: String,
+}
+
+/// Runs trials concurrently within the study and per-trial time limits.
+#[derive(Debug, Clone)]
+pub struct TrialScheduler {
+    n_jobs: usize,
+    timeout: Option<Duration>,
+    trial_timeout: Option<Duration>,
+}
+
+impl TrialScheduler {
+    /// At least one job runs even if `n_jobs` is 0.
+    pub fn new(n_jobs: usize) -> Self {
+        Self { n_jobs: n_jobs.max(1), timeout: None, trial_timeout: None }
+    }
+
+    pub fn from_config(config: &OptimizationConfig) -> Self {
+        let mut scheduler = Self::new(config.n_jobs);
+        scheduler.timeout = config.timeout_seconds.map(Duration::from_secs);
+        scheduler.trial_timeout = config.trial_timeout_seconds.map(Duration::from_secs);
+        scheduler
+    }
+
+    /// No trials start after `timeout`, and running ones fail if they return after it.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Trials returning more than `trial_timeout` after they started fail.
+    pub fn with_trial_timeout(mut self, trial_timeout: Duration) -> Self {
+        self.trial_timeout = Some(trial_timeout);
+        self
+    }
+
+    /// Runs up to `n_trials` trials.
+    ///
+    /// `ask` creates trial `number`, or returns `None` once there are no trials left to
+    /// run. `evaluate` computes the objective of a trial on the blocking pool, given the
+    /// deadline it should stop by. `tell` receives each outcome once its evaluation has
+    /// returned. The result of an evaluation that returns after its deadline is discarded
+    /// and the trial is reported as failed.
+    pub async fn run<T, A, E, R>(
+        &self,
+        n_trials: usize,
+        mut ask: A,
+        evaluate: Arc<E>,
+        mut tell: R,
+    ) -> Result<(), AutoMLError>
+    where
+        T: Send + Sync + 'static,
+        A: FnMut(usize) -> Result<Option<T>, AutoMLError>,
+        E: Fn(&T, Option<std::time::Instant>) -> Result<f64, AutoMLError> + Send + Sync + 'static,
+        R: FnMut(TrialOutcome<T>) -> Result<(), AutoMLError>,
+    {
+        let started = Instant::now();
+        let study_deadline = self.timeout.map(|timeout| Deadline {
+            at: started + timeout,
+            reason: format!("Study timeout of {}s reached", timeout.as_secs_f64()),
+        });
+        let mut running = JoinSet::new();
+        let mut launched = 0;
+        let mut stopped = false;
+
+        loop {
+            while !stopped && launched < n_trials && running.len() < self.n_jobs {
+                if study_deadline.as_ref().is_some_and(|deadline| Instant::now() >= deadline.at) {
+                    info!("Study timeout reached after {} trials", launched);
+                    stopped = true;
+                    break;
+                }
+                let Some(trial) = ask(launched)? else {
+                    info!("No trials left after {}", launched);
+                    stopped = true;
+                    break;
+                };
+
+                info!("Starting trial {}/{}", launched + 1, n_trials);
+                let deadline = self.deadline(study_deadline.as_ref());
+                running.spawn(evaluate_trial(
+                    launched,
+                    Arc::new(trial),
+                    evaluate.clone(),
+                    deadline,
+                ));
+                launched += 1;
+            }
+
+            let Some(joined) = running.join_next().await else {
+                return Ok(());
+            };
+            let outcome = joined
+                .map_err(|e| AutoMLError::OptimizationError(format!("Trial task failed: {}", e)))?;
+            if let Err(reason) = &outcome.result {
+                warn!("Trial {} failed: {}", outcome.number, reason);
+            }
+            tell(outcome)?;
+        }
+    }
+
+    /// The earlier of the study deadline and the per-trial one for a trial starting now.
+    fn deadline(&self, study_deadline: Option<&Deadline>) -> Option<Deadline> {
+        let trial_deadline = self.trial_timeout.map(|timeout| Deadline {
+            at: Instant::now() + timeout,
+            reason: format!("Trial timed out after {}s", timeout.as_secs_f64()),
+        });
+        match (study_deadline, trial_deadline) {
+            (Some(study), Some(trial)) if study.at < trial.at => Some(study.clone()),
+            (study, trial) => trial.or_else(|| study.cloned()),
+        }
+    }
+}
+
+async fn evaluate_trial<T, E>(
+    number: usize,
+    trial: Arc<T>,
+    evaluate: Arc<E>,
+    deadline: Option<Deadline>,
+) -> TrialOutcome<T>
+where
+    T: Send + Sync + 'static,
+    E: Fn(&T, Option<std::time::Instant>) -> Result<f64, AutoMLError> + Send + Sync + 'static,
+{
+    let datetime_start = Utc::now();
+    let stop_at = deadline.as_ref().map(|deadline| deadline.at.into_std());
+    let joined = tokio::task::spawn_blocking({
+        let trial = trial.clone();
+        move || evaluate(&trial, stop_at)
+    })
+    .await;
+    // Waiting for the evaluation holds its slot, so timed out trials never pile up
+    let result = match deadline {
+        Some(deadline) if Instant::now() >= deadline.at => Err(deadline.reason),
+        _ => objective_result(joined),
+    };
+    TrialOutcome { number, trial, datetime_start, datetime_complete: Utc::now(), result }
+}
+
+fn objective_result(
+    joined: Result<Result<f64, AutoMLError>, tokio::task::JoinError>,
+) -> Result<f64, String> {
+    match joined {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(e) => Err(format!("Objective panicked: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Runs trials that sleep for the given milliseconds and return them as their value.
+    async fn run(scheduler: TrialScheduler, sleeps: Vec<u64>) -> (Vec<TrialOutcome<u64>>, usize) {
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let evaluate = {
+            let (active, peak) = (active.clone(), peak.clone());
+            Arc::new(move |sleep: &u64, _: Option<std::time::Instant>| {
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(*sleep));
+                active.fetch_sub(1, Ordering::SeqCst);
+                Ok(*sleep as f64)
+            })
+        };
+        let mut outcomes = Vec::new();
+        scheduler
+            .run(
+                sleeps.len(),
+                |number| Ok(sleeps.get(number).copied()),
+                evaluate,
+                |outcome| {
+                    outcomes.push(outcome);
+                    Ok(())
+                },
+            )
+            .await
+            .unwrap();
+        (outcomes, peak.load(Ordering::SeqCst))
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_trials_run_concurrently_up_to_n_jobs() {
+        let (outcomes, peak) = run(TrialScheduler::new(3), vec![300, 50, 50, 50, 50]).await;
+        assert_eq!(peak, 3);
+        let order: Vec<_> = outcomes.iter().map(|outcome| outcome.number).collect();
+        assert_eq!(order.len(), 5);
+        assert_eq!(order.last(), Some(&0), "the slow first trial finishes last");
+        for outcome in &outcomes {
+            assert_eq!(outcome.result, Ok(*outcome.trial as f64));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_timeouts_fail_trials_and_stop_the_study() {
+        let scheduler = TrialScheduler::new(2).with_trial_timeout(Duration::from_millis(100));
+        let (outcomes, _) = run(scheduler, vec![10, 400, 10]).await;
+        let mut results: Vec<_> =
+            outcomes.into_iter().map(|outcome| (outcome.number, outcome.result)).collect();
+        results.sort_by_key(|(number, _)| *number);
+        assert_eq!(
+            results,
+            [(0, Ok(10.0)), (1, Err("Trial timed out after 0.1s".to_string())), (2, Ok(10.0)),]
+        );
+
+        let scheduler = TrialScheduler::new(1).with_timeout(Duration::from_millis(150));
+        let (outcomes, _) = run(scheduler, vec![100, 100, 100, 100]).await;
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(outcomes[0].result, Ok(100.0));
+        assert_eq!(outcomes[1].result, Err("Study timeout of 0.15s reached".to_string()));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_timed_out_trials_keep_their_slot_until_they_return() {
+        let scheduler = TrialScheduler::new(1).with_trial_timeout(Duration::from_millis(50));
+        let (outcomes, peak) = run(scheduler, vec![200, 10, 10]).await;
+        assert_eq!(peak, 1);
+        assert_eq!(outcomes[0].result, Err("Trial timed out after 0.05s".to_string()));
+        assert!(outcomes[1].datetime_start >= outcomes[0].datetime_complete);
+        assert_eq!(outcomes[2].result, Ok(10.0));
+    }
+}
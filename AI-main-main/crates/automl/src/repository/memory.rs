@@ -0,0 +1,67 @@
+use async_trait::async_trait;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use tokio::sync::RwLock;
+
+use super::{AutoMLRepository, StudyQuery, study_not_found};
+use crate::errors::AutoMLError;
+use crate::models::{StudyRecord, TrialResult};
+
+/// A study and its trials by number.
+type StoredStudy = (StudyRecord, BTreeMap<usize, TrialResult>);
+
+/// Keeps studies for the lifetime of the process.
+#[derive(Default)]
+pub struct InMemoryRepository {
+    studies: RwLock<HashMap<String, StoredStudy>>,
+}
+
+#[async_trait]
+impl AutoMLRepository for InMemoryRepository {
+    async fn save_study(&self, study: &StudyRecord) -> Result<(), AutoMLError> {
+        let mut studies = self.studies.write().await;
+        match studies.get_mut(&study.study_id) {
+            Some((record, _)) => *record = study.clone(),
+            None => {
+                studies.insert(study.study_id.clone(), (study.clone(), BTreeMap::new()));
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_study(&self, study_id: &str) -> Result<StudyRecord, AutoMLError> {
+        let studies = self.studies.read().await;
+        let (study, _) = studies.get(study_id).ok_or_else(|| study_not_found(study_id))?;
+        Ok(study.clone())
+    }
+
+    async fn list_studies(&self, query: &StudyQuery) -> Result<Vec<StudyRecord>, AutoMLError> {
+        let studies = self.studies.read().await;
+        let mut matching: Vec<_> = studies
+            .values()
+            .map(|(study, _)| study)
+            .filter(|study| query.state.is_none_or(|state| study.state == state))
+            .collect();
+        matching.sort_by_key(|study| Reverse(study.datetime_start));
+
+        Ok(matching
+            .into_iter()
+            .skip(query.offset)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect())
+    }
+
+    async fn save_trial(&self, study_id: &str, trial: &TrialResult) -> Result<(), AutoMLError> {
+        let mut studies = self.studies.write().await;
+        let (_, trials) = studies.get_mut(study_id).ok_or_else(|| study_not_found(study_id))?;
+        trials.insert(trial.number, trial.clone());
+        Ok(())
+    }
+
+    async fn get_trials(&self, study_id: &str) -> Result<Vec<TrialResult>, AutoMLError> {
+        let studies = self.studies.read().await;
+        let (_, trials) = studies.get(study_id).ok_or_else(|| study_not_found(study_id))?;
+        Ok(trials.values().cloned().collect())
+    }
+}
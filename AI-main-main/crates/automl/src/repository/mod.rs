@@ -0,0 +1,198 @@
+//! Durable storage for studies and their trials.
+//!
+//! A study is stored as a [`StudyRecord`] plus one [`TrialResult`] per trial, keyed by
+//! the trial's number. Trials are saved as they start and again when they finish, so a
+//! study interrupted with the process can be resumed from what was stored.
+
+mod memory;
+mod sqlite;
+
+pub use memory::InMemoryRepository;
+pub use sqlite::SqliteRepository;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::errors::AutoMLError;
+use crate::models::{StudyRecord, StudyResult, StudyState, TrialResult};
+
+/// Filters and pages the studies returned by [`AutoMLRepository::list_studies`].
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StudyQuery {
+    pub state: Option<StudyState>,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: usize,
+}
+
+#[async_trait]
+pub trait AutoMLRepository: Send + Sync {
+    /// Creates or replaces the record of a study.
+    async fn save_study(&self, study: &StudyRecord) -> Result<(), AutoMLError>;
+
+    async fn get_study(&self, study_id: &str) -> Result<StudyRecord, AutoMLError>;
+
+    /// Studies matching `query`, most recently started first.
+    async fn list_studies(&self, query: &StudyQuery) -> Result<Vec<StudyRecord>, AutoMLError>;
+
+    /// Creates or replaces trial `trial.number` of a stored study.
+    async fn save_trial(&self, study_id: &str, trial: &TrialResult) -> Result<(), AutoMLError>;
+
+    /// The trials of a study in number order.
+    async fn get_trials(&self, study_id: &str) -> Result<Vec<TrialResult>, AutoMLError>;
+
+    /// Stores the trials of a finished study and marks it completed.
+    async fn save_study_result(&self, result: &StudyResult) -> Result<(), AutoMLError> {
+        let mut study = self.get_study(&result.study_id).await?;
+        for trial in &result.trials {
+            self.save_trial(&result.study_id, trial).await?;
+        }
+
+        study.state = StudyState::Completed;
+        study.infeasible_attempts = result.infeasible_attempts;
+        study.ensemble = result.ensemble.clone();
+        study.feature_pipeline = result.feature_pipeline.clone();
+        study.datetime_complete = result.datetime_complete;
+        study.metadata = result.metadata.clone();
+        self.save_study(&study).await
+    }
+
+    async fn get_study_result(&self, study_id: &str) -> Result<StudyResult, AutoMLError> {
+        let study = self.get_study(study_id).await?;
+        let trials = self.get_trials(study_id).await?;
+        StudyResult::from_record(&study, trials).ok_or_else(|| {
+            AutoMLError::NotFound(format!("Study {} has no completed trials", study_id))
+        })
+    }
+}
+
+/// Opens the storage at `url`: `memory` keeps studies in the process only, and
+/// `sqlite:<path>` stores them in a SQLite database created if missing.
+pub async fn open(url: &str) -> Result<Arc<dyn AutoMLRepository>, AutoMLError> {
+    if url == "memory" {
+        Ok(Arc::new(InMemoryRepository::default()))
+    } else if url.starts_with("sqlite:") {
+        Ok(Arc::new(SqliteRepository::connect(url).await?))
+    } else {
+        Err(AutoMLError::ConfigError(format!(
+            "Unsupported study storage `{}`, expected `memory` or `sqlite:<path>`",
+            url
+        )))
+    }
+}
+
+fn study_not_found(study_id: &str) -> AutoMLError {
+    AutoMLError::NotFound(format!("Study {}", study_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TrialState;
+    use chrono::Utc;
+
+    fn trial(number: usize, value: f64, state: TrialState) -> TrialResult {
+        TrialResult {
+            trial_id: number.to_string(),
+            number,
+            parameters: [("lr".to_string(), serde_json::json!(0.1 * number as f64))].into(),
+            value,
+            intermediate_values: [(1, number as f64 / 2.0), (2, f64::NAN)].into(),
+            state,
+            datetime_start: Utc::now(),
+            datetime_complete: None,
+        }
+    }
+
+    async fn check_round_trip(repository: Arc<dyn AutoMLRepository>) {
+        let config: crate::models::AutoMLConfig = serde_json::from_value(serde_json::json!({
+            "task_type": "Regression",
+            "optimization_config": {
+                "n_trials": 3,
+                "timeout_seconds": null,
+                "n_jobs": 1,
+                "optimization_direction": "Minimize",
+                "search_space": { "parameters": {}, "constraints": [] },
+                "pruner_config": {
+                    "pruner_type": "NopPruner",
+                    "n_warmup_steps": 0,
+                    "n_min_trials": 0
+                },
+                "sampler_config": { "sampler_type": "RandomSearch", "seed": 1 }
+            },
+            "model_config": {
+                "model_type": { "Custom": "test" },
+                "architecture_search": false,
+                "feature_selection": false,
+                "ensemble_config": null
+            },
+            "training_config": {
+                "batch_size_range": [32, 32],
+                "epochs_range": [1, 1],
+                "early_stopping_patience": 1,
+                "validation_split": 0.2,
+                "cross_validation_folds": 1
+            },
+            "hardware_config": { "n_gpus": 0, "n_cpu_threads": 1, "memory_limit_mb": 512 }
+        }))
+        .unwrap();
+
+        let study = StudyRecord::new("a".to_string(), config.clone());
+        repository.save_study(&study).await.unwrap();
+        let mut other = StudyRecord::new("b".to_string(), config);
+        other.datetime_start = study.datetime_start + chrono::Duration::seconds(1);
+        repository.save_study(&other).await.unwrap();
+
+        assert!(matches!(repository.get_study("c").await, Err(AutoMLError::NotFound(_))));
+        assert!(matches!(repository.get_study_result("a").await, Err(AutoMLError::NotFound(_))));
+
+        repository.save_trial("a", &trial(1, 0.0, TrialState::Running)).await.unwrap();
+        repository.save_trial("a", &trial(0, 0.5, TrialState::Completed)).await.unwrap();
+        repository
+            .save_trial("a", &trial(2, f64::NAN, TrialState::Failed("oom".to_string())))
+            .await
+            .unwrap();
+        repository.save_trial("a", &trial(1, 0.25, TrialState::Completed)).await.unwrap();
+
+        let trials = repository.get_trials("a").await.unwrap();
+        let numbers: Vec<_> = trials.iter().map(|trial| trial.number).collect();
+        assert_eq!(numbers, [0, 1, 2]);
+        assert_eq!(trials[1].value, 0.25);
+        assert_eq!(trials[1].intermediate_values[&1], 0.5);
+        assert!(trials[1].intermediate_values[&2].is_nan());
+        assert_eq!(trials[1].parameters["lr"], serde_json::json!(0.1));
+        assert!(trials[2].value.is_nan());
+        assert!(matches!(&trials[2].state, TrialState::Failed(reason) if reason == "oom"));
+
+        let mut result = repository.get_study_result("a").await.unwrap();
+        assert_eq!(result.best_trial.number, 1);
+        result.datetime_complete = Some(Utc::now());
+        result.ensemble = serde_json::from_value(serde_json::json!({
+            "ensemble_type": "Bagging",
+            "voting_method": "Weighted",
+            "members": [{ "trial_number": 1, "value": 0.25, "weight": 1.0 }],
+            "metrics": { "mse": 0.25, "custom_metrics": {} }
+        }))
+        .unwrap();
+        repository.save_study_result(&result).await.unwrap();
+        let ensemble = repository.get_study_result("a").await.unwrap().ensemble.unwrap();
+        assert_eq!(ensemble.members[0].trial_number, 1);
+        assert_eq!(ensemble.metrics.mse, Some(0.25));
+
+        let completed = StudyQuery { state: Some(StudyState::Completed), ..Default::default() };
+        let ids = |studies: Vec<StudyRecord>| -> Vec<String> {
+            studies.into_iter().map(|study| study.study_id).collect()
+        };
+        assert_eq!(ids(repository.list_studies(&completed).await.unwrap()), ["a"]);
+        assert_eq!(ids(repository.list_studies(&StudyQuery::default()).await.unwrap()), ["b", "a"]);
+        let page = StudyQuery { limit: Some(1), offset: 1, ..Default::default() };
+        assert_eq!(ids(repository.list_studies(&page).await.unwrap()), ["a"]);
+    }
+
+    #[tokio::test]
+    async fn test_repositories_round_trip_studies_and_trials() {
+        check_round_trip(open("memory").await.unwrap()).await;
+        check_round_trip(open("sqlite::memory:").await.unwrap()).await;
+    }
+}
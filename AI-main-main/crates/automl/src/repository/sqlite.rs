@@ -0,0 +1,221 @@
+use async_trait::async_trait;
+use sqlx::Row;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use super::{AutoMLRepository, StudyQuery, study_not_found};
+use crate::errors::AutoMLError;
+use crate::models::{StudyRecord, StudyState, TrialResult};
+
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS studies (
+        study_id TEXT PRIMARY KEY,
+        state TEXT NOT NULL,
+        config TEXT NOT NULL,
+        infeasible_attempts INTEGER NOT NULL,
+        ensemble TEXT,
+        feature_pipeline TEXT,
+        datetime_start TEXT NOT NULL,
+        datetime_complete TEXT,
+        metadata TEXT NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS studies_by_start ON studies (datetime_start)",
+    "CREATE TABLE IF NOT EXISTS trials (
+        study_id TEXT NOT NULL REFERENCES studies (study_id) ON DELETE CASCADE,
+        number INTEGER NOT NULL,
+        trial_id TEXT NOT NULL,
+        state TEXT NOT NULL,
+        value REAL,
+        parameters TEXT NOT NULL,
+        intermediate_values TEXT NOT NULL,
+        datetime_start TEXT NOT NULL,
+        datetime_complete TEXT,
+        PRIMARY KEY (study_id, number)
+    )",
+];
+
+/// Stores studies in a SQLite database, creating its tables if missing.
+///
+/// Structured fields are stored as JSON. A missing objective value (`NaN`) is stored as
+/// `NULL`, and so are `NaN` intermediate values, as `null` in their JSON.
+pub struct SqliteRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteRepository {
+    pub async fn connect(url: &str) -> Result<Self, AutoMLError> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        // Every connection to an in-memory database opens a new, empty one
+        let max_connections = if url.contains(":memory:") { 1 } else { 4 };
+        let pool =
+            SqlitePoolOptions::new().max_connections(max_connections).connect_with(options).await?;
+
+        for statement in SCHEMA {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+
+        Ok(Self { pool })
+    }
+}
+
+fn study_state(state: StudyState) -> &'static str {
+    match state {
+        StudyState::Running => "Running",
+        StudyState::Completed => "Completed",
+        StudyState::Failed => "Failed",
+    }
+}
+
+fn study_from_row(row: &SqliteRow) -> Result<StudyRecord, AutoMLError> {
+    let state = match row.try_get::<&str, _>("state")? {
+        "Running" => StudyState::Running,
+        "Completed" => StudyState::Completed,
+        "Failed" => StudyState::Failed,
+        other => return Err(AutoMLError::DatabaseError(format!("Unknown study state {}", other))),
+    };
+
+    Ok(StudyRecord {
+        study_id: row.try_get("study_id")?,
+        config: serde_json::from_str(row.try_get("config")?)?,
+        state,
+        infeasible_attempts: row.try_get::<i64, _>("infeasible_attempts")? as usize,
+        ensemble: row
+            .try_get::<Option<&str>, _>("ensemble")?
+            .map(serde_json::from_str)
+            .transpose()?,
+        feature_pipeline: row
+            .try_get::<Option<&str>, _>("feature_pipeline")?
+            .map(serde_json::from_str)
+            .transpose()?,
+        datetime_start: row.try_get("datetime_start")?,
+        datetime_complete: row.try_get("datetime_complete")?,
+        metadata: serde_json::from_str(row.try_get("metadata")?)?,
+    })
+}
+
+/// JSON has no `NaN`, so missing values are written as `null`.
+fn intermediate_values_to_json(values: &BTreeMap<u64, f64>) -> Result<String, AutoMLError> {
+    let values: BTreeMap<u64, Option<f64>> =
+        values.iter().map(|(&step, &value)| (step, Some(value).filter(|v| !v.is_nan()))).collect();
+    Ok(serde_json::to_string(&values)?)
+}
+
+fn intermediate_values_from_json(json: &str) -> Result<BTreeMap<u64, f64>, AutoMLError> {
+    let values: BTreeMap<u64, Option<f64>> = serde_json::from_str(json)?;
+    Ok(values.into_iter().map(|(step, value)| (step, value.unwrap_or(f64::NAN))).collect())
+}
+
+fn trial_from_row(row: &SqliteRow) -> Result<TrialResult, AutoMLError> {
+    Ok(TrialResult {
+        trial_id: row.try_get("trial_id")?,
+        number: row.try_get::<i64, _>("number")? as usize,
+        parameters: serde_json::from_str(row.try_get("parameters")?)?,
+        value: row.try_get::<Option<f64>, _>("value")?.unwrap_or(f64::NAN),
+        intermediate_values: intermediate_values_from_json(row.try_get("intermediate_values")?)?,
+        state: serde_json::from_str(row.try_get("state")?)?,
+        datetime_start: row.try_get("datetime_start")?,
+        datetime_complete: row.try_get("datetime_complete")?,
+    })
+}
+
+#[async_trait]
+impl AutoMLRepository for SqliteRepository {
+    async fn save_study(&self, study: &StudyRecord) -> Result<(), AutoMLError> {
+        sqlx::query(
+            "INSERT INTO studies
+                (study_id, state, config, infeasible_attempts, ensemble, feature_pipeline,
+                 datetime_start, datetime_complete, metadata)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT (study_id) DO UPDATE SET
+                state = excluded.state,
+                config = excluded.config,
+                infeasible_attempts = excluded.infeasible_attempts,
+                ensemble = excluded.ensemble,
+                feature_pipeline = excluded.feature_pipeline,
+                datetime_start = excluded.datetime_start,
+                datetime_complete = excluded.datetime_complete,
+                metadata = excluded.metadata",
+        )
+        .bind(&study.study_id)
+        .bind(study_state(study.state))
+        .bind(serde_json::to_string(&study.config)?)
+        .bind(study.infeasible_attempts as i64)
+        .bind(study.ensemble.as_ref().map(serde_json::to_string).transpose()?)
+        .bind(study.feature_pipeline.as_ref().map(serde_json::to_string).transpose()?)
+        .bind(study.datetime_start)
+        .bind(study.datetime_complete)
+        .bind(serde_json::to_string(&study.metadata)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_study(&self, study_id: &str) -> Result<StudyRecord, AutoMLError> {
+        let row = sqlx::query("SELECT * FROM studies WHERE study_id = ?")
+            .bind(study_id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| study_not_found(study_id))?;
+        study_from_row(&row)
+    }
+
+    async fn list_studies(&self, query: &StudyQuery) -> Result<Vec<StudyRecord>, AutoMLError> {
+        // A negative limit means no limit to SQLite
+        let limit = query.limit.map_or(-1, |limit| limit.min(i64::MAX as usize) as i64);
+        let rows = sqlx::query(
+            "SELECT * FROM studies
+             WHERE ?1 IS NULL OR state = ?1
+             ORDER BY datetime_start DESC
+             LIMIT ?2 OFFSET ?3",
+        )
+        .bind(query.state.map(study_state))
+        .bind(limit)
+        .bind(query.offset as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(study_from_row).collect()
+    }
+
+    async fn save_trial(&self, study_id: &str, trial: &TrialResult) -> Result<(), AutoMLError> {
+        // Checked here so a missing study is reported like in the other repositories
+        self.get_study(study_id).await?;
+
+        sqlx::query(
+            "INSERT INTO trials
+                (study_id, number, trial_id, state, value, parameters, intermediate_values,
+                 datetime_start, datetime_complete)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT (study_id, number) DO UPDATE SET
+                trial_id = excluded.trial_id,
+                state = excluded.state,
+                value = excluded.value,
+                parameters = excluded.parameters,
+                intermediate_values = excluded.intermediate_values,
+                datetime_start = excluded.datetime_start,
+                datetime_complete = excluded.datetime_complete",
+        )
+        .bind(study_id)
+        .bind(trial.number as i64)
+        .bind(&trial.trial_id)
+        .bind(serde_json::to_string(&trial.state)?)
+        .bind(Some(trial.value).filter(|value| !value.is_nan()))
+        .bind(serde_json::to_string(&trial.parameters)?)
+        .bind(intermediate_values_to_json(&trial.intermediate_values)?)
+        .bind(trial.datetime_start)
+        .bind(trial.datetime_complete)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_trials(&self, study_id: &str) -> Result<Vec<TrialResult>, AutoMLError> {
+        self.get_study(study_id).await?;
+
+        let rows = sqlx::query("SELECT * FROM trials WHERE study_id = ? ORDER BY number")
+            .bind(study_id)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(trial_from_row).collect()
+    }
+}
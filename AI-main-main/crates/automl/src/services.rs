@@ -1,15 +1,43 @@
 use async_trait::async_trait;
-use std::sync::Arc;
+use chrono::Utc;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 use tokio::sync::RwLock;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
+use crate::cross_validation::{self, Fold};
+use crate::ensemble::{self, Candidate};
 use crate::errors::AutoMLError;
+use crate::feature_engineering::{self, FeaturePipeline, PipelineParams};
 use crate::models::{
-    AutoMLConfig, ModelConfig, ModelMetrics, ModelType, StudyResult, TaskType, TrialResult,
+    AutoMLConfig, BestModel, EnsembleConfig, EnsembleResult, FeatureEngineeringConfig, ModelConfig,
+    ModelMetrics, ModelType, ParameterRange, SearchSpace, StudyRecord, StudyResult, StudyState,
+    TaskType, TrialResult, TrialState,
 };
-use crate::optimization::{nas::NeuralArchitectureSearch, optuna::OptunaOptimizer};
-use crate::repository::AutoMLRepository;
+use crate::optimization::{
+    TrialReporter, nas::NeuralArchitectureSearch, optuna::OptunaOptimizer, suggest_parameter,
+};
+use crate::repository::{AutoMLRepository, StudyQuery};
+use crate::trees::{
+    self, Dataset, GradientBoosting, GradientBoostingParams, Predictor, RandomForest,
+    RandomForestParams, TreeTask,
+};
+
+/// Boosting rounds before early stopping.
+const BOOSTING_ROUNDS: usize = 500;
+
+/// Share of the training rows of a fold boosting holds out to stop early on, so that the
+/// rows a trial is scored on play no part in fitting it.
+const EARLY_STOPPING_FRACTION: f64 = 0.1;
+
+/// Rows of features for tree ensembles with their task and the folds trials are scored on.
+type TabularData = (TreeTask, Dataset, Vec<Fold>);
+
+/// Predictions every trial made for the rows it validated on, by trial number; empty for
+/// rows no fold validated on.
+type OutOfFoldPredictions = Arc<Mutex<HashMap<usize, Vec<Vec<f64>>>>>;
 
 #[async_trait]
 pub trait AutoMLService: Send + Sync {
@@ -17,22 +45,111 @@ pub trait AutoMLService: Send + Sync {
 
     async fn get_study_info(&self, study_id: String) -> Result<StudyResult, AutoMLError>;
 
-    async fn get_best_model(&self, study_id: String) -> Result<ModelConfig, AutoMLError>;
+    /// The model configuration of a study with its best trial and, if the configuration
+    /// asks for one, the ensemble of its best trials.
+    async fn get_best_model(&self, study_id: String) -> Result<BestModel, AutoMLError>;
+
+    /// Continues an interrupted or failed study from its stored trials, unless it is still
+    /// running in this process.
+    async fn resume_study(&self, study_id: String) -> Result<StudyResult, AutoMLError>;
+
+    async fn list_studies(&self, query: StudyQuery) -> Result<Vec<StudyRecord>, AutoMLError>;
 }
 
+#[derive(Clone)]
 pub struct AutoMLOptimizer {
     repository: Arc<dyn AutoMLRepository>,
+    training_data: Arc<Vec<(tch::Tensor, tch::Tensor)>>,
+    groups: Option<Arc<Vec<u64>>>,
     current_study: Option<Arc<RwLock<OptunaOptimizer>>>,
     nas: Option<Arc<RwLock<NeuralArchitectureSearch>>>,
+    /// Ids of the studies running in this process
+    running: Arc<Mutex<HashSet<String>>>,
+}
+
+/// Marks a study as running in this process until it is dropped.
+struct RunningStudy {
+    running: Arc<Mutex<HashSet<String>>>,
+    study_id: String,
+}
+
+impl Drop for RunningStudy {
+    fn drop(&mut self) {
+        let mut running = self.running.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        running.remove(&self.study_id);
+    }
 }
 
 impl AutoMLOptimizer {
     pub async fn new(repository: Arc<dyn AutoMLRepository>) -> Result<Self, AutoMLError> {
-        Ok(Self { repository, current_study: None, nas: None })
+        Ok(Self {
+            repository,
+            training_data: Arc::default(),
+            groups: None,
+            current_study: None,
+            nas: None,
+            running: Arc::default(),
+        })
+    }
+
+    /// Sets the `(input, target)` batches models are evaluated on.
+    pub fn with_training_data(mut self, training_data: Vec<(tch::Tensor, tch::Tensor)>) -> Self {
+        self.training_data = Arc::new(training_data);
+        self
     }
 
-    async fn initialize_study(&mut self, config: &AutoMLConfig) -> Result<(), AutoMLError> {
-        let optimizer = OptunaOptimizer::new(config.clone()).await?;
+    /// Sets the group of every training row, keeping each group in a single fold when
+    /// cross-validating.
+    pub fn with_groups(mut self, groups: Vec<u64>) -> Self {
+        self.groups = Some(Arc::new(groups));
+        self
+    }
+
+    /// Marks `study_id` as running, failing if a run of it has already started here.
+    fn claim(&self, study_id: &str) -> Result<RunningStudy, AutoMLError> {
+        let mut running = self.running.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if !running.insert(study_id.to_string()) {
+            return Err(AutoMLError::InvalidInput(format!(
+                "Study {} is already running",
+                study_id
+            )));
+        }
+        Ok(RunningStudy { running: self.running.clone(), study_id: study_id.to_string() })
+    }
+
+    /// Rejects studies of tree ensembles when no training data was set, before anything is
+    /// stored for them.
+    fn check_training_data(&self, config: &AutoMLConfig) -> Result<(), AutoMLError> {
+        match &config.model_config.model_type {
+            ModelType::LightGBM(_) | ModelType::XGBoost(_) | ModelType::RandomForest(_)
+                if self.training_data.is_empty() =>
+            {
+                Err(AutoMLError::InvalidInput(
+                    "Tree ensembles need training data and this service has none loaded"
+                        .to_string(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn initialize_study(
+        &mut self,
+        study: &StudyRecord,
+        history: Vec<TrialResult>,
+    ) -> Result<(), AutoMLError> {
+        let config = &study.config;
+        // Tree hyperparameters are sampled like any other parameter, so trials record them
+        let mut study_config = config.clone();
+        extend_search_space(
+            &mut study_config.optimization_config.search_space,
+            &config.model_config.model_type,
+        );
+        let optimizer = OptunaOptimizer::new(study_config)
+            .await?
+            .with_study_id(study.study_id.clone())
+            .with_history(history)
+            .with_repository(self.repository.clone());
         self.current_study = Some(Arc::new(RwLock::new(optimizer)));
 
         if config.model_config.architecture_search {
@@ -68,17 +185,11 @@ impl AutoMLOptimizer {
                     Err(AutoMLError::ConfigError("NAS not initialized".to_string()))
                 }
             }
-            ModelType::LightGBM(config) => {
-                // Implement LightGBM evaluation
-                unimplemented!("LightGBM evaluation not implemented yet")
-            }
-            ModelType::XGBoost(config) => {
-                // Implement XGBoost evaluation
-                unimplemented!("XGBoost evaluation not implemented yet")
-            }
-            ModelType::RandomForest(config) => {
-                // Implement RandomForest evaluation
-                unimplemented!("RandomForest evaluation not implemented yet")
+            ModelType::LightGBM(_) | ModelType::XGBoost(_) | ModelType::RandomForest(_) => {
+                // Their hyperparameters are sampled per trial, see `TreeEnsembleParams`
+                Err(AutoMLError::ConfigError(
+                    "Tree ensembles are trained by the study objective".to_string(),
+                ))
             }
             ModelType::Custom(name) => {
                 Err(AutoMLError::ConfigError(format!("Custom model type {} not supported", name)))
@@ -87,97 +198,519 @@ impl AutoMLOptimizer {
     }
 
     async fn save_study_result(&self, result: &StudyResult) -> Result<(), AutoMLError> {
-        self.repository
-            .save_study_result(result)
-            .await
-            .map_err(|e| AutoMLError::DatabaseError(e.to_string()))
+        self.repository.save_study_result(result).await
     }
 
     async fn load_study_result(&self, study_id: &str) -> Result<StudyResult, AutoMLError> {
-        self.repository
-            .get_study_result(study_id)
-            .await
-            .map_err(|e| AutoMLError::DatabaseError(e.to_string()))
+        self.repository.get_study_result(study_id).await
     }
-}
 
-#[async_trait]
-impl AutoMLService for AutoMLOptimizer {
-    async fn optimize_model(&self, config: AutoMLConfig) -> Result<StudyResult, AutoMLError> {
-        info!("Starting model optimization with config: {:?}", config);
+    /// Runs a stored study and records whether it completed or failed.
+    async fn run_study(
+        &self,
+        study: StudyRecord,
+        history: Vec<TrialResult>,
+    ) -> Result<StudyResult, AutoMLError> {
+        match self.optimize_study(&study, history).await {
+            Ok(result) => {
+                self.save_study_result(&result).await?;
+                Ok(result)
+            }
+            Err(e) => {
+                error!("Study {} failed: {}", study.study_id, e);
+                let failed = StudyRecord {
+                    state: StudyState::Failed,
+                    datetime_complete: Some(Utc::now()),
+                    ..study
+                };
+                if let Err(save_error) = self.repository.save_study(&failed).await {
+                    error!("Failed to record failure of study {}: {}", failed.study_id, save_error);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn optimize_study(
+        &self,
+        record: &StudyRecord,
+        history: Vec<TrialResult>,
+    ) -> Result<StudyResult, AutoMLError> {
+        let config = record.config.clone();
 
         // Initialize study and NAS if needed
         let mut this = self.clone();
-        this.initialize_study(&config).await?;
+        this.initialize_study(record, history).await?;
 
         let study = this
             .current_study
-            .as_ref()
+            .clone()
             .ok_or_else(|| AutoMLError::ConfigError("Study not initialized".to_string()))?;
+        let training_data = this.training_data.clone();
 
-        // Get training data
-        let training_data = this
-            .repository
-            .get_training_data()
-            .await
-            .map_err(|e| AutoMLError::DatabaseError(e.to_string()))?;
+        // Tree ensembles train on rows of features, split into folds once for every trial
+        let tabular = match &config.model_config.model_type {
+            ModelType::LightGBM(_) | ModelType::XGBoost(_) | ModelType::RandomForest(_) => {
+                let feature_engineering = config.model_config.feature_engineering.is_some();
+                let data = tabular_data(&training_data, feature_engineering)?;
+                if this.groups.as_ref().is_some_and(|groups| groups.len() != data.len()) {
+                    return Err(AutoMLError::InvalidInput(
+                        "Expected one group per training row".to_string(),
+                    ));
+                }
+                let task = TreeTask::new(&config.task_type, &data)?;
+                let folds = folds(&config, &data, this.groups.as_deref().map(Vec::as_slice))?;
+                Some(Arc::new((task, data, folds)))
+            }
+            _ => None,
+        };
+
+        // Ensembles are built from what trials predicted for the rows they did not train on
+        let ensemble_config = config.model_config.ensemble_config.clone();
+        if let Some(ensemble_config) = &ensemble_config {
+            ensemble::check(ensemble_config)?;
+            if tabular.is_none() {
+                warn!("Ensembles are only built from trials of tree ensembles");
+            }
+        }
+        let out_of_fold: Option<OutOfFoldPredictions> =
+            ensemble_config.as_ref().and(tabular.as_ref()).map(|_| Arc::default());
+        let ensemble_inputs = tabular.clone().zip(out_of_fold.clone());
+        let pipeline_data = config.model_config.feature_engineering.as_ref().and(tabular.clone());
 
         // Define objective function
-        let objective = move |trial: &optuna::Trial| {
-            // Sample hyperparameters
-            let model_config = match &config.model_config.model_type {
-                ModelType::NeuralNetwork(nn_config) => {
+        let objective = move |trial: &optuna::Trial, reporter: &TrialReporter| {
+            let metrics = match (&config.model_config.model_type, &tabular) {
+                (ModelType::NeuralNetwork(nn_config), _) => {
                     // Sample neural network hyperparameters
-                    ModelConfig {
+                    let model_config = ModelConfig {
                         model_type: ModelType::NeuralNetwork(nn_config.clone()),
                         architecture_search: config.model_config.architecture_search,
                         feature_selection: config.model_config.feature_selection,
                         ensemble_config: config.model_config.ensemble_config.clone(),
+                        feature_engineering: config.model_config.feature_engineering.clone(),
+                    };
+
+                    // Evaluate model
+                    tokio::runtime::Runtime::new()
+                        .unwrap()
+                        .block_on(this.evaluate_model(&model_config, &training_data))?
+                }
+                (_, Some(tabular)) => {
+                    let (task, data, folds) = tabular.as_ref();
+                    let params = TreeEnsembleParams::suggest(trial, &config)?;
+                    let feature_engineering = match &config.model_config.feature_engineering {
+                        Some(feature_engineering) => Some((
+                            feature_engineering,
+                            PipelineParams::suggest(
+                                trial,
+                                &config.optimization_config.search_space,
+                                feature_engineering,
+                                config.model_config.feature_selection,
+                            )?,
+                        )),
+                        None => None,
+                    };
+                    let mut scores = Vec::with_capacity(folds.len());
+                    let mut predictions = vec![Vec::new(); data.len()];
+                    for (step, fold) in folds.iter().enumerate() {
+                        let (train, validation) =
+                            fold_data(*task, data, fold, feature_engineering.as_ref())?;
+                        let model = params.fit(*task, &train)?;
+                        scores.push(trees::evaluate(model.as_ref(), &validation));
+                        if out_of_fold.is_some() {
+                            for (row, features) in fold.validation.iter().zip(&validation.features)
+                            {
+                                predictions[*row] = model.predict(features);
+                            }
+                        }
+
+                        // The mean so far, so that pruners compare trials at the same fold
+                        let value = objective_value(
+                            &config.task_type,
+                            &cross_validation::aggregate(&scores),
+                        )?;
+                        reporter.report(value, step as u64);
+                        if step + 1 < folds.len() && reporter.should_prune() {
+                            return Err(AutoMLError::TrialPruned(format!(
+                                "Pruned after fold {} of {}",
+                                step + 1,
+                                folds.len()
+                            )));
+                        }
                     }
+                    if let Some(out_of_fold) = &out_of_fold {
+                        let mut out_of_fold =
+                            out_of_fold.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                        out_of_fold.insert(reporter.number(), predictions);
+                    }
+                    cross_validation::aggregate(&scores)
                 }
-                // Add other model types here
                 _ => return Err(AutoMLError::ConfigError("Unsupported model type".to_string())),
             };
 
-            // Evaluate model
-            let metrics = tokio::runtime::Runtime::new()
-                .unwrap()
-                .block_on(this.evaluate_model(&model_config, &training_data))?;
-
-            // Return objective value based on task type
-            match config.task_type {
-                TaskType::BinaryClassification | TaskType::MultiClassification => {
-                    Ok(1.0 - metrics.accuracy.unwrap_or(0.0))
-                }
-                TaskType::Regression => Ok(metrics.mse.unwrap_or(f64::INFINITY)),
-                _ => Err(AutoMLError::ConfigError("Unsupported task type".to_string())),
-            }
+            objective_value(&config.task_type, &metrics)
         };
 
         // Run optimization
-        let result = study.read().await.optimize(objective).await?;
+        let mut result = study.read().await.optimize(objective).await?;
 
-        // Save results
-        this.save_study_result(&result).await?;
+        // The pipeline of the best trial, refitted on every row, transforms rows to predict
+        if let (Some(feature_engineering), Some(tabular)) =
+            (&record.config.model_config.feature_engineering, pipeline_data)
+        {
+            let (task, data, _) = tabular.as_ref();
+            let params = PipelineParams::from_parameters(
+                feature_engineering,
+                record.config.model_config.feature_selection,
+                &result.best_trial.parameters,
+            )?;
+            result.feature_pipeline =
+                Some(FeaturePipeline::fit(params, &feature_engineering.column_kinds, *task, data)?);
+        }
+
+        if let (Some(ensemble_config), Some((tabular, out_of_fold))) =
+            (&ensemble_config, ensemble_inputs)
+        {
+            let out_of_fold =
+                std::mem::take(&mut *out_of_fold.lock().unwrap_or_else(|p| p.into_inner()));
+            // A study is worth keeping without its ensemble
+            match build_ensemble(&record.config, ensemble_config, &tabular, &result, out_of_fold) {
+                Ok(ensemble) => result.ensemble = Some(ensemble),
+                Err(e) => error!("Failed to build ensemble of study {}: {}", record.study_id, e),
+            }
+        }
 
         Ok(result)
     }
+}
+
+/// Builds the ensemble of the best completed trials from their out-of-fold predictions.
+///
+/// Only rows some fold validated on are scored. Trials replayed from an earlier run have
+/// no predictions and are left out.
+fn build_ensemble(
+    config: &AutoMLConfig,
+    ensemble_config: &EnsembleConfig,
+    (task, data, folds): &TabularData,
+    result: &StudyResult,
+    mut out_of_fold: HashMap<usize, Vec<Vec<f64>>>,
+) -> Result<EnsembleResult, AutoMLError> {
+    let mut scored = vec![false; data.len()];
+    for row in folds.iter().flat_map(|fold| &fold.validation) {
+        scored[*row] = true;
+    }
+    let rows: Vec<usize> = (0..data.len()).filter(|row| scored[*row]).collect();
+
+    let candidates = result
+        .trials
+        .iter()
+        .filter(|trial| matches!(trial.state, TrialState::Completed))
+        .filter_map(|trial| {
+            let mut predictions = out_of_fold.remove(&trial.number)?;
+            Some(Candidate {
+                trial_number: trial.number,
+                value: trial.value,
+                predictions: rows
+                    .iter()
+                    .map(|row| std::mem::take(&mut predictions[*row]))
+                    .collect(),
+            })
+        })
+        .collect();
+    let targets: Vec<f64> = rows.iter().map(|row| data.targets[*row]).collect();
+
+    let ensemble = ensemble::build(
+        ensemble_config,
+        *task,
+        &config.optimization_config.optimization_direction,
+        candidates,
+        &targets,
+        config.optimization_config.sampler_config.seed,
+    )?;
+    info!(
+        "Built a {:?} ensemble of {} trials, scoring {:?}",
+        ensemble.ensemble_type,
+        ensemble.members.len(),
+        ensemble.metrics
+    );
+    Ok(ensemble)
+}
+
+/// Flattens `(input, target)` batches into rows of features, one per input row.
+///
+/// Features may be missing, as NaN, when a feature pipeline imputes them.
+fn tabular_data(
+    batches: &[(tch::Tensor, tch::Tensor)],
+    allow_missing: bool,
+) -> Result<Dataset, AutoMLError> {
+    let mut features = Vec::new();
+    let mut targets = Vec::new();
+    for (input, target) in batches {
+        let n_rows = input.size().first().copied().unwrap_or(0) as usize;
+        if n_rows == 0 {
+            continue;
+        }
+        let values = Vec::<f64>::try_from(&input.to_kind(tch::Kind::Double).flatten(0, -1))?;
+        features.extend(values.chunks(values.len() / n_rows).map(<[f64]>::to_vec));
+        targets.extend(Vec::<f64>::try_from(&target.to_kind(tch::Kind::Double).flatten(0, -1))?);
+    }
+    if allow_missing {
+        return Ok(Dataset { features, targets });
+    }
+    Dataset::new(features, targets)
+}
+
+/// The training and validation rows of `fold`, transformed by a pipeline fitted to the
+/// training rows when features are engineered.
+fn fold_data(
+    task: TreeTask,
+    data: &Dataset,
+    fold: &Fold,
+    feature_engineering: Option<&(&FeatureEngineeringConfig, PipelineParams)>,
+) -> Result<(Dataset, Dataset), AutoMLError> {
+    let train = data.select(&fold.train);
+    let validation = data.select(&fold.validation);
+    match feature_engineering {
+        Some((config, params)) => {
+            let (pipeline, train) = feature_engineering::engineer_features(
+                params.clone(),
+                &config.column_kinds,
+                task,
+                &train,
+            )?;
+            Ok((train, pipeline.transform(&validation)?))
+        }
+        None => Ok((train, validation)),
+    }
+}
+
+/// The value a study minimises for `task_type`: the error rate for classification and the
+/// mean squared error otherwise.
+fn objective_value(task_type: &TaskType, metrics: &ModelMetrics) -> Result<f64, AutoMLError> {
+    match task_type {
+        TaskType::BinaryClassification | TaskType::MultiClassification => {
+            Ok(1.0 - metrics.accuracy.unwrap_or(0.0))
+        }
+        TaskType::Regression | TaskType::TimeSeries => Ok(metrics.mse.unwrap_or(f64::INFINITY)),
+        _ => Err(AutoMLError::ConfigError("Unsupported task type".to_string())),
+    }
+}
+
+/// The folds every trial of a study is scored on.
+///
+/// Cross-validates with the configured strategy, or the one suited to the task, when
+/// `cross_validation_folds` is at least 2, and holds out `validation_split` of the rows
+/// otherwise.
+fn folds(
+    config: &AutoMLConfig,
+    data: &Dataset,
+    groups: Option<&[u64]>,
+) -> Result<Vec<Fold>, AutoMLError> {
+    let training = &config.training_config;
+    let seed = config.optimization_config.sampler_config.seed;
+    if training.cross_validation_folds < 2 {
+        return Ok(vec![Fold::holdout(data.len(), training.validation_split, seed)?]);
+    }
+
+    let strategy = training
+        .cross_validation_strategy
+        .unwrap_or_else(|| cross_validation::strategy_for(&config.task_type, groups.is_some()));
+    cross_validation::split(strategy, training.cross_validation_folds, &data.targets, groups, seed)
+}
+
+/// The hyperparameters trials sample for `model_type`, with the ranges it configures; none
+/// unless it is a tree ensemble.
+fn tree_parameters(model_type: &ModelType) -> Vec<(String, ParameterRange)> {
+    let int = |name: &str, (low, high): (i32, i32)| {
+        (name.to_string(), ParameterRange::Discrete { low: low.into(), high: high.into(), step: 1 })
+    };
+    let float = |name: &str, (low, high): (f64, f64), log: bool| {
+        (name.to_string(), ParameterRange::Continuous { low, high, log })
+    };
+    match model_type {
+        ModelType::RandomForest(ranges) => vec![
+            int("n_estimators", ranges.n_estimators),
+            int("max_depth", ranges.max_depth),
+            int("min_samples_split", ranges.min_samples_split),
+        ],
+        ModelType::LightGBM(ranges) => vec![
+            float("learning_rate", ranges.learning_rate, true),
+            int("max_depth", ranges.max_depth),
+            int("num_leaves", ranges.num_leaves),
+        ],
+        ModelType::XGBoost(ranges) => vec![
+            float("eta", ranges.eta, true),
+            int("max_depth", ranges.max_depth),
+            float("gamma", ranges.gamma, false),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+/// Adds the tree hyperparameters of `model_type` to `space`, keeping parameters it already
+/// has.
+fn extend_search_space(space: &mut SearchSpace, model_type: &ModelType) {
+    for (name, range) in tree_parameters(model_type) {
+        space.parameters.entry(name).or_insert(range);
+    }
+}
+
+/// Hyperparameters of a tree ensemble, sampled once per trial and shared by its folds.
+enum TreeEnsembleParams {
+    Forest(RandomForestParams),
+    Boosting(GradientBoostingParams),
+}
+
+impl TreeEnsembleParams {
+    /// Samples the hyperparameters of the tree ensemble of `config` in `trial` from their
+    /// ranges in its search space, which [`extend_search_space`] has added them to.
+    fn suggest(trial: &optuna::Trial, config: &AutoMLConfig) -> Result<Self, AutoMLError> {
+        let space = &config.optimization_config.search_space;
+        let mut sampled = HashMap::new();
+        for (name, range) in tree_parameters(&config.model_config.model_type) {
+            let range = space.parameters.get(&name).unwrap_or(&range);
+            sampled.insert(name.clone(), suggest_parameter(trial, &name, range)?);
+        }
+        Self::from_parameters(config, &sampled)
+    }
+
+    /// The hyperparameters of a trial with `parameters`.
+    ///
+    /// Boosting stops early once the loss on rows held out of its training rows stops
+    /// improving for the configured patience.
+    fn from_parameters(
+        config: &AutoMLConfig,
+        parameters: &HashMap<String, Value>,
+    ) -> Result<Self, AutoMLError> {
+        let seed = config.optimization_config.sampler_config.seed;
+        let early_stopping_rounds = Some(config.training_config.early_stopping_patience.max(1));
+        let int = |name: &str| {
+            parameters.get(name).and_then(Value::as_i64).ok_or_else(|| {
+                AutoMLError::OptimizationError(format!("`{}` is not an integer", name))
+            })
+        };
+        let float = |name: &str| {
+            parameters.get(name).and_then(Value::as_f64).ok_or_else(|| {
+                AutoMLError::OptimizationError(format!("`{}` is not a number", name))
+            })
+        };
+
+        Ok(match &config.model_config.model_type {
+            ModelType::RandomForest(_) => Self::Forest(RandomForestParams {
+                n_estimators: int("n_estimators")?.max(1) as usize,
+                max_depth: depth_limit(int("max_depth")?),
+                min_samples_split: int("min_samples_split")?.max(2) as usize,
+                seed,
+                ..Default::default()
+            }),
+            // Leaf-wise growth bounded by the number of leaves
+            ModelType::LightGBM(_) => Self::Boosting(GradientBoostingParams {
+                n_estimators: BOOSTING_ROUNDS,
+                learning_rate: float("learning_rate")?,
+                max_depth: depth_limit(int("max_depth")?),
+                max_leaves: Some(int("num_leaves")?.max(2) as usize),
+                early_stopping_rounds,
+                seed,
+                ..Default::default()
+            }),
+            // Level-wise growth bounded by depth
+            ModelType::XGBoost(_) => Self::Boosting(GradientBoostingParams {
+                n_estimators: BOOSTING_ROUNDS,
+                learning_rate: float("eta")?,
+                max_depth: depth_limit(int("max_depth")?),
+                min_split_gain: float("gamma")?,
+                early_stopping_rounds,
+                seed,
+                ..Default::default()
+            }),
+            other => {
+                return Err(AutoMLError::ConfigError(format!(
+                    "{:?} is not a tree ensemble",
+                    other
+                )));
+            }
+        })
+    }
+
+    /// Fits the ensemble to `train`. Boosting stops early on [`EARLY_STOPPING_FRACTION`] of
+    /// those rows and trains on the rest.
+    fn fit(&self, task: TreeTask, train: &Dataset) -> Result<Box<dyn Predictor>, AutoMLError> {
+        Ok(match self {
+            Self::Forest(params) => Box::new(RandomForest::fit(params, task, train)?),
+            Self::Boosting(params) if train.len() < 2 => {
+                Box::new(GradientBoosting::fit(params, task, train, None)?)
+            }
+            Self::Boosting(params) => {
+                let (train, stopping) = train.split(EARLY_STOPPING_FRACTION, params.seed);
+                Box::new(GradientBoosting::fit(params, task, &train, Some(&stopping))?)
+            }
+        })
+    }
+}
+
+/// Depths below 1 mean no limit, as in LightGBM.
+fn depth_limit(depth: i64) -> Option<usize> {
+    (depth > 0).then_some(depth as usize)
+}
+
+#[async_trait]
+impl AutoMLService for AutoMLOptimizer {
+    async fn optimize_model(&self, config: AutoMLConfig) -> Result<StudyResult, AutoMLError> {
+        info!("Starting model optimization with config: {:?}", config);
+        self.check_training_data(&config)?;
+
+        let study = StudyRecord::new(Uuid::new_v4().to_string(), config);
+        let _running = self.claim(&study.study_id)?;
+        self.repository.save_study(&study).await?;
+
+        self.run_study(study, Vec::new()).await
+    }
 
     async fn get_study_info(&self, study_id: String) -> Result<StudyResult, AutoMLError> {
         info!("Retrieving study info for: {}", study_id);
         self.load_study_result(&study_id).await
     }
 
-    async fn get_best_model(&self, study_id: String) -> Result<ModelConfig, AutoMLError> {
+    async fn get_best_model(&self, study_id: String) -> Result<BestModel, AutoMLError> {
         info!("Retrieving best model for study: {}", study_id);
 
-        let study_result = self.load_study_result(&study_id).await?;
-        let model_config = self
-            .repository
-            .load_model_config(&study_result.best_model_path)
-            .await
-            .map_err(|e| AutoMLError::DatabaseError(e.to_string()))?;
+        // Fails until the study has a best trial
+        let result = self.load_study_result(&study_id).await?;
+        let study = self.repository.get_study(&study_id).await?;
+
+        Ok(BestModel {
+            model_config: study.config.model_config,
+            best_trial: result.best_trial,
+            ensemble: result.ensemble,
+            feature_pipeline: result.feature_pipeline,
+        })
+    }
+
+    async fn resume_study(&self, study_id: String) -> Result<StudyResult, AutoMLError> {
+        info!("Resuming study: {}", study_id);
+
+        // Two runs of a study would interleave their trials
+        let _running = self.claim(&study_id)?;
+        let mut study = self.repository.get_study(&study_id).await?;
+        if study.state == StudyState::Completed {
+            return Err(AutoMLError::InvalidInput(format!(
+                "Study {} has already completed",
+                study_id
+            )));
+        }
+        self.check_training_data(&study.config)?;
+        let history = self.repository.get_trials(&study_id).await?;
+
+        study.state = StudyState::Running;
+        study.datetime_complete = None;
+        self.repository.save_study(&study).await?;
+
+        self.run_study(study, history).await
+    }
 
-        Ok(model_config)
+    async fn list_studies(&self, query: StudyQuery) -> Result<Vec<StudyRecord>, AutoMLError> {
+        info!("Listing studies matching: {:?}", query);
+        self.repository.list_studies(&query).await
     }
 }
@@ -0,0 +1,256 @@
+use rand::seq::index;
+use tracing::debug;
+
+use super::tree::{Gradients, Tree, TreeParams};
+use super::{Dataset, Predictor, TreeTask, rng};
+use crate::errors::AutoMLError;
+
+#[derive(Debug, Clone)]
+pub struct GradientBoostingParams {
+    pub n_estimators: usize,
+    pub learning_rate: f64,
+    pub max_depth: Option<usize>,
+    /// Grows trees leaf-wise up to this many leaves, like LightGBM, rather than level by
+    /// level
+    pub max_leaves: Option<usize>,
+    pub min_samples_leaf: usize,
+    pub min_child_weight: f64,
+    /// Smallest loss reduction of a split, `gamma` in XGBoost
+    pub min_split_gain: f64,
+    /// L2 penalty on leaf values, `lambda` in XGBoost
+    pub l2_regularization: f64,
+    /// Fraction of the rows each tree is trained on
+    pub subsample: f64,
+    /// Stops once the validation loss has not improved for this many rounds
+    pub early_stopping_rounds: Option<usize>,
+    pub seed: Option<u64>,
+}
+
+impl Default for GradientBoostingParams {
+    fn default() -> Self {
+        Self {
+            n_estimators: 100,
+            learning_rate: 0.1,
+            max_depth: Some(6),
+            max_leaves: None,
+            min_samples_leaf: 1,
+            min_child_weight: 1.0,
+            min_split_gain: 0.0,
+            l2_regularization: 1.0,
+            subsample: 1.0,
+            early_stopping_rounds: None,
+            seed: None,
+        }
+    }
+}
+
+/// Adds up shrunken trees, each fitted to the gradients of the loss of the trees before it.
+///
+/// Regression minimises squared error. Binary classification boosts the log-odds of the
+/// positive class and multi-class classification the logits of every class.
+#[derive(Debug, Clone)]
+pub struct GradientBoosting {
+    task: TreeTask,
+    base_score: Vec<f64>,
+    learning_rate: f64,
+    trees: Vec<Tree>,
+}
+
+impl GradientBoosting {
+    /// Trains up to `n_estimators` trees, keeping those up to the best validation loss
+    /// when early stopping on `validation`.
+    pub fn fit(
+        params: &GradientBoostingParams,
+        task: TreeTask,
+        train: &Dataset,
+        validation: Option<&Dataset>,
+    ) -> Result<Self, AutoMLError> {
+        task.check_targets(train)?;
+        if let Some(validation) = validation {
+            task.check_targets(validation)?;
+        }
+        let valid = params.learning_rate > 0.0 && params.subsample > 0.0 && params.subsample <= 1.0;
+        if !valid {
+            return Err(AutoMLError::ConfigError(format!(
+                "Invalid boosting learning rate {} or subsample {}",
+                params.learning_rate, params.subsample
+            )));
+        }
+
+        let tree_params = TreeParams {
+            max_depth: params.max_depth,
+            max_leaves: params.max_leaves,
+            min_samples_split: 2,
+            min_samples_leaf: params.min_samples_leaf,
+            min_child_weight: params.min_child_weight,
+            min_split_gain: params.min_split_gain,
+            l2_regularization: params.l2_regularization,
+            max_features: None,
+        };
+        let mut model = Self {
+            task,
+            base_score: base_score(task, &train.targets),
+            learning_rate: params.learning_rate,
+            trees: Vec::new(),
+        };
+
+        let mut rng = rng(params.seed);
+        let mut raw = model.initial_scores(train.len());
+        let mut validation_raw = validation.map(|data| model.initial_scores(data.len()));
+        let mut best = (f64::INFINITY, 0);
+        let n_rows = ((train.len() as f64 * params.subsample).ceil() as usize).max(1);
+
+        for round in 0..params.n_estimators {
+            let gradients = gradients(task, &raw, &train.targets);
+            let rows = if n_rows < train.len() {
+                index::sample(&mut rng, train.len(), n_rows).into_vec()
+            } else {
+                (0..train.len()).collect()
+            };
+            let tree = Tree::fit(train, rows, &gradients, &tree_params, &mut rng);
+            model.add_scores(&tree, train, &mut raw);
+
+            if let (Some(data), Some(scores)) = (validation, validation_raw.as_mut()) {
+                model.add_scores(&tree, data, scores);
+                let loss = loss(task, scores, &data.targets);
+                if loss < best.0 {
+                    best = (loss, round + 1);
+                }
+            }
+            model.trees.push(tree);
+
+            if let Some(patience) = params.early_stopping_rounds {
+                if validation.is_some() && round + 1 - best.1 >= patience {
+                    debug!("Early stopping after {} rounds, best was {}", round + 1, best.1);
+                    model.trees.truncate(best.1);
+                    break;
+                }
+            }
+        }
+
+        Ok(model)
+    }
+
+    /// The number of trees kept.
+    pub fn n_trees(&self) -> usize {
+        self.trees.len()
+    }
+
+    fn initial_scores(&self, n_rows: usize) -> Vec<f64> {
+        self.base_score.iter().copied().cycle().take(n_rows * self.base_score.len()).collect()
+    }
+
+    fn add_scores(&self, tree: &Tree, data: &Dataset, scores: &mut [f64]) {
+        let width = self.base_score.len();
+        for (features, scores) in data.features.iter().zip(scores.chunks_mut(width)) {
+            for (score, value) in scores.iter_mut().zip(tree.predict(features)) {
+                *score += self.learning_rate * value;
+            }
+        }
+    }
+}
+
+impl Predictor for GradientBoosting {
+    fn task(&self) -> TreeTask {
+        self.task
+    }
+
+    fn predict(&self, features: &[f64]) -> Vec<f64> {
+        let mut raw = self.base_score.clone();
+        for tree in &self.trees {
+            for (score, value) in raw.iter_mut().zip(tree.predict(features)) {
+                *score += self.learning_rate * value;
+            }
+        }
+        match self.task {
+            TreeTask::Regression => raw,
+            TreeTask::Classification { n_classes: 2 } => {
+                let positive = sigmoid(raw[0]);
+                vec![1.0 - positive, positive]
+            }
+            TreeTask::Classification { .. } => softmax(&raw),
+        }
+    }
+}
+
+/// Raw scores per row before any tree: the mean target, the log-odds of the positive
+/// class or the log of every class frequency.
+fn base_score(task: TreeTask, targets: &[f64]) -> Vec<f64> {
+    let n = targets.len() as f64;
+    match task {
+        TreeTask::Regression => vec![targets.iter().sum::<f64>() / n],
+        TreeTask::Classification { n_classes } => {
+            let mut counts = vec![0.0; n_classes];
+            for target in targets {
+                counts[*target as usize] += 1.0;
+            }
+            // Smoothed so a class missing from the data still gets a finite score
+            let frequencies: Vec<f64> =
+                counts.iter().map(|count| (count + 0.5) / (n + 0.5 * n_classes as f64)).collect();
+            if n_classes == 2 {
+                vec![(frequencies[1] / frequencies[0]).ln()]
+            } else {
+                frequencies.into_iter().map(f64::ln).collect()
+            }
+        }
+    }
+}
+
+fn gradients(task: TreeTask, raw: &[f64], targets: &[f64]) -> Gradients {
+    let mut grad = Vec::with_capacity(raw.len());
+    let mut hess = Vec::with_capacity(raw.len());
+    match task {
+        TreeTask::Regression => {
+            for (score, target) in raw.iter().zip(targets) {
+                grad.push(score - target);
+                hess.push(1.0);
+            }
+        }
+        TreeTask::Classification { n_classes: 2 } => {
+            for (score, target) in raw.iter().zip(targets) {
+                let p = sigmoid(*score);
+                grad.push(p - target);
+                hess.push((p * (1.0 - p)).max(1e-16));
+            }
+        }
+        TreeTask::Classification { n_classes } => {
+            for (scores, target) in raw.chunks(n_classes).zip(targets) {
+                for (class, p) in softmax(scores).into_iter().enumerate() {
+                    let label = if class == *target as usize { 1.0 } else { 0.0 };
+                    grad.push(p - label);
+                    hess.push((p * (1.0 - p)).max(1e-16));
+                }
+            }
+        }
+    }
+    Gradients { n_outputs: raw.len() / targets.len(), grad, hess }
+}
+
+/// Mean squared error for regression and mean log loss for classification.
+fn loss(task: TreeTask, raw: &[f64], targets: &[f64]) -> f64 {
+    let width = raw.len() / targets.len();
+    let total: f64 = raw
+        .chunks(width)
+        .zip(targets)
+        .map(|(scores, target)| match task {
+            TreeTask::Regression => (scores[0] - target).powi(2),
+            TreeTask::Classification { n_classes: 2 } => {
+                let p = sigmoid(scores[0]).clamp(1e-15, 1.0 - 1e-15);
+                -(target * p.ln() + (1.0 - target) * (1.0 - p).ln())
+            }
+            TreeTask::Classification { .. } => -softmax(scores)[*target as usize].max(1e-15).ln(),
+        })
+        .sum();
+    total / targets.len() as f64
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+pub(crate) fn softmax(scores: &[f64]) -> Vec<f64> {
+    let max = scores.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let exp: Vec<f64> = scores.iter().map(|score| (score - max).exp()).collect();
+    let sum: f64 = exp.iter().sum();
+    exp.iter().map(|value| value / sum).collect()
+}
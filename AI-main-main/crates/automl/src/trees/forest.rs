@@ -0,0 +1,120 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use super::tree::{Gradients, Tree, TreeParams};
+use super::{Dataset, Predictor, TreeTask, rng};
+use crate::errors::AutoMLError;
+
+#[derive(Debug, Clone)]
+pub struct RandomForestParams {
+    pub n_estimators: usize,
+    pub max_depth: Option<usize>,
+    pub min_samples_split: usize,
+    pub min_samples_leaf: usize,
+    /// Features considered per split; the square root of their number for classification
+    /// and a third of it for regression if `None`
+    pub max_features: Option<usize>,
+    /// Trains every tree on a bootstrap sample rather than on all rows
+    pub bootstrap: bool,
+    pub seed: Option<u64>,
+}
+
+impl Default for RandomForestParams {
+    fn default() -> Self {
+        Self {
+            n_estimators: 100,
+            max_depth: None,
+            min_samples_split: 2,
+            min_samples_leaf: 1,
+            max_features: None,
+            bootstrap: true,
+            seed: None,
+        }
+    }
+}
+
+/// Averages fully grown trees trained on bootstrap samples and random feature subsets.
+#[derive(Debug, Clone)]
+pub struct RandomForest {
+    task: TreeTask,
+    trees: Vec<Tree>,
+}
+
+impl RandomForest {
+    /// Trains the trees in parallel.
+    pub fn fit(
+        params: &RandomForestParams,
+        task: TreeTask,
+        data: &Dataset,
+    ) -> Result<Self, AutoMLError> {
+        task.check_targets(data)?;
+        if params.n_estimators == 0 {
+            return Err(AutoMLError::ConfigError(
+                "A random forest needs at least one tree".to_string(),
+            ));
+        }
+
+        let n_features = data.n_features();
+        let max_features = params.max_features.unwrap_or(match task {
+            TreeTask::Classification { .. } => (n_features as f64).sqrt().round() as usize,
+            TreeTask::Regression => n_features / 3,
+        });
+        let tree_params = TreeParams {
+            max_depth: params.max_depth,
+            max_leaves: None,
+            min_samples_split: params.min_samples_split,
+            min_samples_leaf: params.min_samples_leaf,
+            min_child_weight: 0.0,
+            min_split_gain: 0.0,
+            l2_regularization: 0.0,
+            max_features: Some(max_features.clamp(1, n_features)),
+        };
+
+        // Leaves hold the mean target, or the class frequencies for one-hot targets
+        let n_outputs = task.n_outputs();
+        let mut grad = vec![0.0; data.len() * n_outputs];
+        for (row, target) in data.targets.iter().enumerate() {
+            match task {
+                TreeTask::Classification { .. } => {
+                    grad[row * n_outputs + *target as usize] = -1.0;
+                }
+                TreeTask::Regression => grad[row] = -target,
+            }
+        }
+        let gradients = Gradients { n_outputs, grad, hess: vec![1.0; data.len() * n_outputs] };
+
+        let mut seeds = rng(params.seed);
+        let seeds: Vec<u64> = (0..params.n_estimators).map(|_| seeds.gen()).collect();
+        let trees = seeds
+            .into_par_iter()
+            .map(|seed| {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let rows = if params.bootstrap {
+                    (0..data.len()).map(|_| rng.gen_range(0..data.len())).collect()
+                } else {
+                    (0..data.len()).collect()
+                };
+                Tree::fit(data, rows, &gradients, &tree_params, &mut rng)
+            })
+            .collect();
+
+        Ok(Self { task, trees })
+    }
+}
+
+impl Predictor for RandomForest {
+    fn task(&self) -> TreeTask {
+        self.task
+    }
+
+    fn predict(&self, features: &[f64]) -> Vec<f64> {
+        let mut sum = vec![0.0; self.task.n_outputs()];
+        for tree in &self.trees {
+            for (total, value) in sum.iter_mut().zip(tree.predict(features)) {
+                *total += value;
+            }
+        }
+        sum.iter().map(|total| total / self.trees.len() as f64).collect()
+    }
+}
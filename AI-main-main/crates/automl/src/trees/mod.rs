@@ -0,0 +1,359 @@
+//! Tree ensembles trained on the CPU.
+//!
+//! [`RandomForest`] averages deep trees grown on bootstrap samples, and
+//! [`GradientBoosting`] adds up shallow trees fitted to the gradients of the loss, growing
+//! them level by level like XGBoost or leaf by leaf like LightGBM. Both handle
+//! classification and regression of tabular [`Dataset`]s.
+
+mod boosting;
+mod forest;
+mod tree;
+
+pub(crate) use boosting::softmax;
+pub use boosting::{GradientBoosting, GradientBoostingParams};
+pub use forest::{RandomForest, RandomForestParams};
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::errors::AutoMLError;
+use crate::models::{ModelMetrics, TaskType};
+
+/// Rows of features with one target each; class targets are indices from 0.
+#[derive(Debug, Clone, Default)]
+pub struct Dataset {
+    pub features: Vec<Vec<f64>>,
+    pub targets: Vec<f64>,
+}
+
+impl Dataset {
+    /// Checks every row has the same number of finite features and a finite target.
+    pub fn new(features: Vec<Vec<f64>>, targets: Vec<f64>) -> Result<Self, AutoMLError> {
+        if features.is_empty() || features.len() != targets.len() {
+            return Err(AutoMLError::InvalidInput(format!(
+                "Expected one target per row, got {} rows and {} targets",
+                features.len(),
+                targets.len()
+            )));
+        }
+        let n_features = features[0].len();
+        if n_features == 0 || features.iter().any(|row| row.len() != n_features) {
+            return Err(AutoMLError::InvalidInput(
+                "Rows must have the same, non-zero number of features".to_string(),
+            ));
+        }
+        if !features.iter().flatten().chain(&targets).all(|value| value.is_finite()) {
+            return Err(AutoMLError::InvalidInput(
+                "Features and targets must be finite".to_string(),
+            ));
+        }
+
+        Ok(Self { features, targets })
+    }
+
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    pub fn n_features(&self) -> usize {
+        self.features.first().map_or(0, Vec::len)
+    }
+
+    /// Shuffles the rows and holds out `validation_fraction` of them, keeping at least one
+    /// row on each side.
+    pub fn split(&self, validation_fraction: f64, seed: Option<u64>) -> (Dataset, Dataset) {
+        let mut rows: Vec<usize> = (0..self.len()).collect();
+        rows.shuffle(&mut rng(seed));
+        let n_validation =
+            ((self.len() as f64 * validation_fraction).round() as usize).clamp(1, self.len() - 1);
+        let (validation, train) = rows.split_at(n_validation);
+        (self.select(train), self.select(validation))
+    }
+
+    /// The given rows, in order.
+    pub fn select(&self, rows: &[usize]) -> Dataset {
+        Dataset {
+            features: rows.iter().map(|&row| self.features[row].clone()).collect(),
+            targets: rows.iter().map(|&row| self.targets[row]).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TreeTask {
+    Classification { n_classes: usize },
+    Regression,
+}
+
+impl TreeTask {
+    /// The task of `task_type` on `data`, counting classes from the targets.
+    pub fn new(task_type: &TaskType, data: &Dataset) -> Result<Self, AutoMLError> {
+        let n_classes =
+            || data.targets.iter().fold(0.0_f64, |max, target| max.max(*target)) as usize + 1;
+        let task = match task_type {
+            TaskType::BinaryClassification => TreeTask::Classification { n_classes: 2 },
+            TaskType::MultiClassification => {
+                TreeTask::Classification { n_classes: n_classes().max(2) }
+            }
+            // Lagged values are expected among the features
+            TaskType::Regression | TaskType::TimeSeries => TreeTask::Regression,
+            other => {
+                return Err(AutoMLError::ConfigError(format!(
+                    "Tree ensembles do not support {:?} tasks",
+                    other
+                )));
+            }
+        };
+        task.check_targets(data)?;
+        Ok(task)
+    }
+
+    /// The width of a prediction: one probability per class, or the regression value.
+    pub fn n_outputs(&self) -> usize {
+        match self {
+            TreeTask::Classification { n_classes } => *n_classes,
+            TreeTask::Regression => 1,
+        }
+    }
+
+    fn check_targets(&self, data: &Dataset) -> Result<(), AutoMLError> {
+        if data.is_empty() {
+            return Err(AutoMLError::InvalidInput("No rows to train on".to_string()));
+        }
+        if let TreeTask::Classification { n_classes } = self {
+            let is_class = |target: &f64| {
+                target.fract() == 0.0 && *target >= 0.0 && *target < *n_classes as f64
+            };
+            if let Some(target) = data.targets.iter().find(|target| !is_class(target)) {
+                return Err(AutoMLError::InvalidInput(format!(
+                    "Class targets must be integers below {}, got {}",
+                    n_classes, target
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub trait Predictor: Send + Sync {
+    fn task(&self) -> TreeTask;
+
+    /// Class probabilities for classification, or the single predicted value.
+    fn predict(&self, features: &[f64]) -> Vec<f64>;
+
+    /// The most probable class, or the predicted value.
+    fn predict_value(&self, features: &[f64]) -> f64 {
+        let prediction = self.predict(features);
+        match self.task() {
+            TreeTask::Classification { .. } => argmax(&prediction) as f64,
+            TreeTask::Regression => prediction[0],
+        }
+    }
+}
+
+/// Scores `model` on `data`.
+///
+/// Classification reports accuracy, and for two classes precision, recall, F1 and ROC
+/// AUC of the positive class; its log loss is in `custom_metrics`. Regression reports
+/// the squared, root squared and absolute errors and R².
+pub fn evaluate(model: &dyn Predictor, data: &Dataset) -> ModelMetrics {
+    let predictions: Vec<Vec<f64>> = data.features.iter().map(|row| model.predict(row)).collect();
+    evaluate_predictions(model.task(), &predictions, &data.targets)
+}
+
+/// Scores predictions made for `task`, one per target, as [`evaluate`] does.
+pub fn evaluate_predictions(
+    task: TreeTask,
+    predictions: &[Vec<f64>],
+    targets: &[f64],
+) -> ModelMetrics {
+    let mut metrics = ModelMetrics {
+        accuracy: None,
+        precision: None,
+        recall: None,
+        f1_score: None,
+        auc_roc: None,
+        mse: None,
+        rmse: None,
+        mae: None,
+        r2_score: None,
+        custom_metrics: HashMap::new(),
+    };
+    let n = targets.len() as f64;
+
+    match task {
+        TreeTask::Classification { n_classes } => {
+            let predicted: Vec<i32> = predictions.iter().map(|p| argmax(p) as i32).collect();
+            let actual: Vec<i32> = targets.iter().map(|target| *target as i32).collect();
+            metrics.accuracy = Some(crate::evaluation::evaluation::accuracy(&predicted, &actual));
+            if n_classes == 2 {
+                use crate::evaluation::evaluation::{f1_score, precision, recall};
+                metrics.precision = Some(precision(&predicted, &actual));
+                metrics.recall = Some(recall(&predicted, &actual));
+                metrics.f1_score = Some(f1_score(&predicted, &actual));
+                let scores: Vec<f64> = predictions.iter().map(|p| p[1]).collect();
+                metrics.auc_roc = auc_roc(&scores, &actual);
+            }
+            let log_loss = predictions
+                .iter()
+                .zip(&actual)
+                .map(|(p, class)| -p[*class as usize].clamp(1e-15, 1.0).ln())
+                .sum::<f64>()
+                / n;
+            metrics.custom_metrics.insert("log_loss".to_string(), log_loss);
+        }
+        TreeTask::Regression => {
+            let errors: Vec<f64> =
+                predictions.iter().zip(targets).map(|(p, target)| p[0] - target).collect();
+            let mse = errors.iter().map(|e| e * e).sum::<f64>() / n;
+            let mean = targets.iter().sum::<f64>() / n;
+            let variance = targets.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / n;
+            metrics.mse = Some(mse);
+            metrics.rmse = Some(mse.sqrt());
+            metrics.mae = Some(errors.iter().map(|e| e.abs()).sum::<f64>() / n);
+            metrics.r2_score = (variance > 0.0).then(|| 1.0 - mse / variance);
+        }
+    }
+
+    metrics
+}
+
+/// The probability that a random positive scores above a random negative, with ties
+/// counting half; `None` unless both classes are present.
+fn auc_roc(scores: &[f64], classes: &[i32]) -> Option<f64> {
+    let mut ranked: Vec<(f64, i32)> = scores.iter().copied().zip(classes.iter().copied()).collect();
+    ranked.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let (mut rank_sum, mut positives) = (0.0, 0.0);
+    let mut start = 0;
+    while start < ranked.len() {
+        let end = start + ranked[start..].iter().take_while(|(s, _)| *s == ranked[start].0).count();
+        // Tied scores share the mean of their 1-based ranks
+        let rank = (start + end + 1) as f64 / 2.0;
+        for (_, class) in &ranked[start..end] {
+            if *class == 1 {
+                rank_sum += rank;
+                positives += 1.0;
+            }
+        }
+        start = end;
+    }
+
+    let negatives = ranked.len() as f64 - positives;
+    (positives > 0.0 && negatives > 0.0)
+        .then(|| (rank_sum - positives * (positives + 1.0) / 2.0) / (positives * negatives))
+}
+
+pub(crate) fn argmax(values: &[f64]) -> usize {
+    values.iter().enumerate().max_by(|(_, a), (_, b)| a.total_cmp(b)).map_or(0, |(index, _)| index)
+}
+
+fn rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    /// Two noisy features: the class is whether their sum is positive, and the regression
+    /// target is a step function of the first plus a line in the second.
+    fn data(task_type: &TaskType, n: usize, seed: u64) -> Dataset {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut features = Vec::new();
+        let mut targets = Vec::new();
+        for _ in 0..n {
+            let (x, y, noise): (f64, f64, f64) =
+                (rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+            features.push(vec![x, y, noise]);
+            targets.push(match task_type {
+                TaskType::BinaryClassification => f64::from(u8::from(x + y > 0.0)),
+                TaskType::MultiClassification => ((x + 1.0) * 1.5).floor().min(2.0),
+                _ => (if x > 0.0 { 2.0 } else { -1.0 }) + y,
+            });
+        }
+        Dataset::new(features, targets).unwrap()
+    }
+
+    #[test]
+    fn test_ensembles_learn_classification_and_regression() {
+        let forest = RandomForestParams { n_estimators: 30, seed: Some(1), ..Default::default() };
+        let boosting = GradientBoostingParams {
+            max_depth: None,
+            max_leaves: Some(8),
+            early_stopping_rounds: Some(10),
+            seed: Some(1),
+            ..Default::default()
+        };
+
+        for task_type in
+            [TaskType::BinaryClassification, TaskType::MultiClassification, TaskType::Regression]
+        {
+            let (train, test) = data(&task_type, 600, 7).split(0.25, Some(3));
+            let task = TreeTask::new(&task_type, &train).unwrap();
+            let models: Vec<Box<dyn Predictor>> = vec![
+                Box::new(RandomForest::fit(&forest, task, &train).unwrap()),
+                Box::new(GradientBoosting::fit(&boosting, task, &train, Some(&test)).unwrap()),
+            ];
+
+            for model in models {
+                let metrics = evaluate(model.as_ref(), &test);
+                match task {
+                    TreeTask::Classification { n_classes } => {
+                        assert!(metrics.accuracy.unwrap() > 0.85, "{:?}: {:?}", task, metrics);
+                        assert_eq!(metrics.auc_roc.is_some(), n_classes == 2);
+                        let probabilities = model.predict(&test.features[0]);
+                        assert_eq!(probabilities.len(), n_classes);
+                        assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+                    }
+                    TreeTask::Regression => {
+                        assert!(metrics.r2_score.unwrap() > 0.8, "{:?}: {:?}", task, metrics)
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_boosting_stops_early_and_checks_targets() {
+        let (train, validation) = data(&TaskType::Regression, 300, 11).split(0.3, Some(5));
+        let params = GradientBoostingParams {
+            n_estimators: 500,
+            learning_rate: 0.5,
+            early_stopping_rounds: Some(5),
+            seed: Some(2),
+            ..Default::default()
+        };
+        let model = GradientBoosting::fit(&params, TreeTask::Regression, &train, Some(&validation))
+            .unwrap();
+        assert!(model.n_trees() < 500);
+
+        let seeded = |seed| {
+            let params = RandomForestParams { n_estimators: 5, seed, ..Default::default() };
+            let forest = RandomForest::fit(&params, TreeTask::Regression, &train).unwrap();
+            validation.features.iter().map(|row| forest.predict_value(row)).collect::<Vec<_>>()
+        };
+        assert_eq!(seeded(Some(9)), seeded(Some(9)));
+
+        let regression = data(&TaskType::Regression, 20, 1);
+        assert!(matches!(
+            TreeTask::new(&TaskType::BinaryClassification, &regression),
+            Err(AutoMLError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            TreeTask::new(&TaskType::Clustering, &regression),
+            Err(AutoMLError::ConfigError(_))
+        ));
+    }
+}
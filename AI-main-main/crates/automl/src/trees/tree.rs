@@ -0,0 +1,251 @@
+//! Decision trees fitted to first and second order gradients.
+//!
+//! Every tree in the crate is a multi-output regression tree on gradients: leaf values are
+//! Newton steps `-G / (H + lambda)` and splits maximise the matching reduction in loss.
+//! With `g = -y`, `h = 1` and no regularisation this is the usual variance reduction of a
+//! regression tree, and with one-hot targets it is Gini impurity, so forests and boosting
+//! share the same trees.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use super::Dataset;
+
+/// Limits on how a tree grows.
+#[derive(Debug, Clone)]
+pub(crate) struct TreeParams {
+    /// Depth of the deepest leaf, unlimited if `None`
+    pub max_depth: Option<usize>,
+    /// Leaves are split best first until there are this many
+    pub max_leaves: Option<usize>,
+    pub min_samples_split: usize,
+    pub min_samples_leaf: usize,
+    /// Smallest mean hessian sum of a child
+    pub min_child_weight: f64,
+    /// Smallest loss reduction of a split
+    pub min_split_gain: f64,
+    pub l2_regularization: f64,
+    /// Features considered per split, all of them if `None`
+    pub max_features: Option<usize>,
+}
+
+/// Gradients of the loss for every sample, `n_outputs` values per sample.
+pub(crate) struct Gradients {
+    pub n_outputs: usize,
+    pub grad: Vec<f64>,
+    pub hess: Vec<f64>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Leaf { values: Vec<f64> },
+    Split { feature: usize, threshold: f64, left: usize, right: usize },
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Tree {
+    nodes: Vec<Node>,
+}
+
+/// The best split of a leaf, queued until the leaf is expanded.
+struct Candidate {
+    gain: f64,
+    node: usize,
+    depth: usize,
+    feature: usize,
+    threshold: f64,
+    left: Vec<usize>,
+    right: Vec<usize>,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    /// Highest gain first, then the earliest node so growth is deterministic.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.gain.total_cmp(&other.gain).then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+impl Tree {
+    /// Grows a tree on `rows` of `data`.
+    pub fn fit(
+        data: &Dataset,
+        rows: Vec<usize>,
+        gradients: &Gradients,
+        params: &TreeParams,
+        rng: &mut StdRng,
+    ) -> Self {
+        let builder = Builder { data, gradients, params };
+        let mut tree = Tree { nodes: vec![Node::Leaf { values: builder.leaf_values(&rows) }] };
+        let mut candidates = BinaryHeap::new();
+        candidates.extend(builder.best_split(0, 0, rows, rng));
+
+        let mut leaves = 1;
+        while let Some(candidate) = candidates.pop() {
+            if params.max_leaves.is_some_and(|max_leaves| leaves >= max_leaves) {
+                break;
+            }
+
+            let depth = candidate.depth + 1;
+            let mut children = [0; 2];
+            for (child, rows) in children.iter_mut().zip([candidate.left, candidate.right]) {
+                *child = tree.nodes.len();
+                tree.nodes.push(Node::Leaf { values: builder.leaf_values(&rows) });
+                if params.max_depth.is_none_or(|max_depth| depth < max_depth) {
+                    candidates.extend(builder.best_split(*child, depth, rows, rng));
+                }
+            }
+            tree.nodes[candidate.node] = Node::Split {
+                feature: candidate.feature,
+                threshold: candidate.threshold,
+                left: children[0],
+                right: children[1],
+            };
+            leaves += 1;
+        }
+
+        tree
+    }
+
+    /// The values of the leaf `features` falls in.
+    pub fn predict(&self, features: &[f64]) -> &[f64] {
+        let mut node = 0;
+        loop {
+            match &self.nodes[node] {
+                Node::Leaf { values } => return values,
+                Node::Split { feature, threshold, left, right } => {
+                    node = if features[*feature] <= *threshold { *left } else { *right };
+                }
+            }
+        }
+    }
+}
+
+struct Builder<'a> {
+    data: &'a Dataset,
+    gradients: &'a Gradients,
+    params: &'a TreeParams,
+}
+
+impl Builder<'_> {
+    /// Gradient and hessian sums of `rows`, per output.
+    fn sums(&self, rows: &[usize]) -> (Vec<f64>, Vec<f64>) {
+        let n_outputs = self.gradients.n_outputs;
+        let (mut grad, mut hess) = (vec![0.0; n_outputs], vec![0.0; n_outputs]);
+        for &row in rows {
+            self.add(row, &mut grad, &mut hess);
+        }
+        (grad, hess)
+    }
+
+    fn add(&self, row: usize, grad: &mut [f64], hess: &mut [f64]) {
+        let n_outputs = self.gradients.n_outputs;
+        let offset = row * n_outputs;
+        for k in 0..n_outputs {
+            grad[k] += self.gradients.grad[offset + k];
+            hess[k] += self.gradients.hess[offset + k];
+        }
+    }
+
+    fn leaf_values(&self, rows: &[usize]) -> Vec<f64> {
+        let (grad, hess) = self.sums(rows);
+        grad.iter()
+            .zip(&hess)
+            .map(|(g, h)| {
+                let denominator = h + self.params.l2_regularization;
+                if denominator > 0.0 { -g / denominator } else { 0.0 }
+            })
+            .collect()
+    }
+
+    /// How much a node with these sums lowers the loss, up to a factor of two.
+    fn score(&self, grad: &[f64], hess: &[f64]) -> f64 {
+        grad.iter()
+            .zip(hess)
+            .map(|(g, h)| {
+                let denominator = h + self.params.l2_regularization;
+                if denominator > 0.0 { g * g / denominator } else { 0.0 }
+            })
+            .sum()
+    }
+
+    fn best_split(
+        &self,
+        node: usize,
+        depth: usize,
+        mut rows: Vec<usize>,
+        rng: &mut StdRng,
+    ) -> Option<Candidate> {
+        let params = self.params;
+        if rows.len() < params.min_samples_split.max(2 * params.min_samples_leaf.max(1)) {
+            return None;
+        }
+
+        let n_outputs = self.gradients.n_outputs;
+        let (grad, hess) = self.sums(&rows);
+        let parent_score = self.score(&grad, &hess);
+
+        let mut features: Vec<usize> = (0..self.data.n_features()).collect();
+        if let Some(max_features) = params.max_features {
+            features.partial_shuffle(rng, max_features.max(1));
+            features.truncate(max_features.max(1));
+        }
+
+        // Best gain so far, with the feature, threshold and number of rows going left
+        let mut best: Option<(f64, usize, f64, usize)> = None;
+        for feature in features {
+            let value = |row: usize| self.data.features[row][feature];
+            rows.sort_by(|a, b| value(*a).total_cmp(&value(*b)));
+
+            let (mut left_grad, mut left_hess) = (vec![0.0; n_outputs], vec![0.0; n_outputs]);
+            let mut right_grad = grad.clone();
+            let mut right_hess = hess.clone();
+            for split in 1..rows.len() {
+                let row = rows[split - 1];
+                self.add(row, &mut left_grad, &mut left_hess);
+                for k in 0..n_outputs {
+                    right_grad[k] = grad[k] - left_grad[k];
+                    right_hess[k] = hess[k] - left_hess[k];
+                }
+
+                let (low, high) = (value(row), value(rows[split]));
+                if low == high
+                    || split < params.min_samples_leaf
+                    || rows.len() - split < params.min_samples_leaf
+                    || left_hess.iter().sum::<f64>() / (n_outputs as f64) < params.min_child_weight
+                    || right_hess.iter().sum::<f64>() / (n_outputs as f64) < params.min_child_weight
+                {
+                    continue;
+                }
+
+                let gain = 0.5
+                    * (self.score(&left_grad, &left_hess) + self.score(&right_grad, &right_hess)
+                        - parent_score)
+                    - params.min_split_gain;
+                if gain > 1e-12 && best.is_none_or(|(best_gain, ..)| gain > best_gain) {
+                    best = Some((gain, feature, low + (high - low) / 2.0, split));
+                }
+            }
+        }
+
+        let (gain, feature, threshold, _) = best?;
+        let (left, right) =
+            rows.into_iter().partition(|&row| self.data.features[row][feature] <= threshold);
+        Some(Candidate { gain, node, depth, feature, threshold, left, right })
+    }
+}
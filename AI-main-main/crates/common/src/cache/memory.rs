@@ -0,0 +1,261 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use lru::LruCache;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use super::{Cache, CacheStats, CacheStatsSnapshot};
+use crate::config::CacheConfig;
+
+/// Limits for the in-process cache tier.
+#[derive(Debug, Clone)]
+pub struct MemoryCacheConfig {
+    /// Maximum number of entries kept before the least recently used one is evicted.
+    pub max_entries: usize,
+    /// Optional bound on the total size of the serialised values, in bytes.
+    pub max_bytes: Option<usize>,
+}
+
+impl Default for MemoryCacheConfig {
+    fn default() -> Self {
+        Self { max_entries: 10_000, max_bytes: None }
+    }
+}
+
+impl From<&CacheConfig> for MemoryCacheConfig {
+    fn from(config: &CacheConfig) -> Self {
+        Self { max_entries: config.max_entries.max(1), max_bytes: config.max_bytes }
+    }
+}
+
+struct Entry {
+    value: String,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|deadline| deadline <= now)
+    }
+}
+
+struct Inner {
+    entries: LruCache<String, Entry>,
+    used_bytes: usize,
+}
+
+impl Inner {
+    fn remove(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.entries.pop(key)?;
+        self.used_bytes -= entry.value.len();
+        Some(entry)
+    }
+}
+
+/// In-process LRU cache with per-entry TTL and entry/byte bounds.
+pub struct MemoryCache {
+    inner: Mutex<Inner>,
+    config: MemoryCacheConfig,
+    stats: CacheStats,
+}
+
+impl MemoryCache {
+    /// Creates an empty cache with the given limits.
+    pub fn new(config: MemoryCacheConfig) -> Self {
+        Self {
+            inner: Mutex::new(Inner { entries: LruCache::unbounded(), used_bytes: 0 }),
+            config,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Returns the hit/miss counters of this cache.
+    pub fn stats(&self) -> CacheStatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// Returns the number of live entries, including expired ones not yet swept.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    /// Returns `true` if the cache holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops every expired entry and returns how many were removed.
+    pub fn purge_expired(&self) -> usize {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        let expired: Vec<String> = inner
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.is_expired(now))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in &expired {
+            inner.remove(key);
+            self.stats.record_expiration();
+        }
+        expired.len()
+    }
+
+    /// Looks up the serialised value stored under `key`.
+    pub(crate) fn get_raw(&self, key: &str) -> Option<String> {
+        let mut inner = self.inner.lock().unwrap();
+        let expired = match inner.entries.get(key) {
+            Some(entry) if !entry.is_expired(Instant::now()) => {
+                self.stats.record_hit();
+                return Some(entry.value.clone());
+            }
+            Some(_) => true,
+            None => false,
+        };
+
+        if expired {
+            inner.remove(key);
+            self.stats.record_expiration();
+        }
+        self.stats.record_miss();
+        None
+    }
+
+    /// Stores an already serialised value, evicting entries until the limits hold.
+    ///
+    /// Values larger than `max_bytes` are rejected rather than flushing the whole cache.
+    pub(crate) fn set_raw(&self, key: &str, value: String, ttl: Duration) -> bool {
+        if self.config.max_bytes.is_some_and(|max| value.len() > max) {
+            return false;
+        }
+
+        let expires_at = (!ttl.is_zero()).then(|| Instant::now() + ttl);
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove(key);
+        inner.used_bytes += value.len();
+        inner.entries.put(key.to_string(), Entry { value, expires_at });
+
+        while inner.entries.len() > self.config.max_entries
+            || self.config.max_bytes.is_some_and(|max| inner.used_bytes > max)
+        {
+            match inner.entries.pop_lru() {
+                Some((_, evicted)) => {
+                    inner.used_bytes -= evicted.value.len();
+                    self.stats.record_eviction();
+                }
+                None => break,
+            }
+        }
+        true
+    }
+
+    pub(crate) fn delete_raw(&self, key: &str) -> bool {
+        self.inner.lock().unwrap().remove(key).is_some()
+    }
+}
+
+#[async_trait]
+impl Cache for MemoryCache {
+    async fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.get_raw(key).and_then(|d| serde_json::from_str(&d).ok())
+    }
+
+    async fn set<T: Serialize + Send + Sync>(&self, key: &str, value: T, ttl: Duration) -> bool {
+        match serde_json::to_string(&value) {
+            Ok(serialized) => self.set_raw(key, serialized, ttl),
+            Err(_) => false,
+        }
+    }
+
+    async fn delete(&self, key: &str) -> bool {
+        self.delete_raw(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(max_entries: usize, max_bytes: Option<usize>) -> MemoryCache {
+        MemoryCache::new(MemoryCacheConfig { max_entries, max_bytes })
+    }
+
+    #[tokio::test]
+    async fn test_set_get_delete() {
+        let cache = cache(8, None);
+        assert!(cache.set("key", "value".to_string(), Duration::from_secs(60)).await);
+        assert_eq!(cache.get::<String>("key").await, Some("value".to_string()));
+        assert!(cache.delete("key").await);
+        assert_eq!(cache.get::<String>("key").await, None);
+        assert!(!cache.delete("key").await);
+    }
+
+    #[tokio::test]
+    async fn test_entry_expires_after_ttl() {
+        let cache = cache(8, None);
+        cache.set("short", 1u8, Duration::from_millis(20)).await;
+        cache.set("forever", 2u8, Duration::ZERO).await;
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+
+        assert_eq!(cache.get::<u8>("short").await, None);
+        assert_eq!(cache.get::<u8>("forever").await, Some(2));
+        assert_eq!(cache.stats().expirations, 1);
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used_entry_is_evicted() {
+        let cache = cache(2, None);
+        cache.set("a", 1u8, Duration::ZERO).await;
+        cache.set("b", 2u8, Duration::ZERO).await;
+        // Touch "a" so that "b" becomes the eviction candidate.
+        assert_eq!(cache.get::<u8>("a").await, Some(1));
+        cache.set("c", 3u8, Duration::ZERO).await;
+
+        assert_eq!(cache.get::<u8>("b").await, None);
+        assert_eq!(cache.get::<u8>("a").await, Some(1));
+        assert_eq!(cache.get::<u8>("c").await, Some(3));
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[tokio::test]
+    async fn test_byte_bound_evicts_and_rejects_oversized_values() {
+        // Each serialised string below is 7 bytes including the quotes.
+        let cache = cache(100, Some(16));
+        cache.set("a", "aaaaa", Duration::ZERO).await;
+        cache.set("b", "bbbbb", Duration::ZERO).await;
+        cache.set("c", "ccccc", Duration::ZERO).await;
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get::<String>("a").await, None);
+        assert!(!cache.set("big", "x".repeat(32), Duration::ZERO).await);
+    }
+
+    #[tokio::test]
+    async fn test_hit_and_miss_counters() {
+        let cache = cache(8, None);
+        cache.set("key", true, Duration::ZERO).await;
+        cache.get::<bool>("key").await;
+        cache.get::<bool>("missing").await;
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+    }
+
+    #[tokio::test]
+    async fn test_purge_expired() {
+        let cache = cache(8, None);
+        cache.set("a", 1u8, Duration::from_millis(10)).await;
+        cache.set("b", 2u8, Duration::from_millis(10)).await;
+        cache.set("c", 3u8, Duration::ZERO).await;
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+
+        assert_eq!(cache.purge_expired(), 2);
+        assert_eq!(cache.len(), 1);
+    }
+}
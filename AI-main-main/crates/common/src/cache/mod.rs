@@ -0,0 +1,227 @@
+//! Module: Cache
+//! Provides the `Cache` trait together with Redis, in-process LRU and tiered backends.
+//!
+//! Services pick a backend through `CacheConfig::backend` and `build_cache`, so swapping
+//! implementations is a configuration change rather than a code change.
+
+mod memory;
+mod redis_cache;
+mod tiered;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+pub use self::memory::{MemoryCache, MemoryCacheConfig};
+pub use self::redis_cache::RedisCache;
+pub use self::tiered::{TieredCache, TieredCacheStats};
+use crate::config::CacheConfig;
+
+/// A key/value cache storing JSON-serialised values.
+///
+/// A `ttl` of `Duration::ZERO` stores the entry without expiry.
+#[async_trait]
+pub trait Cache {
+    async fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T>;
+    async fn set<T: Serialize + Send + Sync>(&self, key: &str, value: T, ttl: Duration) -> bool;
+    async fn delete(&self, key: &str) -> bool;
+}
+
+/// Which cache implementation a service should use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheBackendKind {
+    /// In-process LRU only; no external dependencies.
+    Memory,
+    /// Pooled Redis only.
+    Redis,
+    /// In-process LRU in front of pooled Redis.
+    #[default]
+    Tiered,
+}
+
+/// Point-in-time copy of a cache's counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStatsSnapshot {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub expirations: u64,
+}
+
+impl CacheStatsSnapshot {
+    /// Fraction of lookups that were served from the cache.
+    pub fn hit_ratio(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 { 0.0 } else { self.hits as f64 / lookups as f64 }
+    }
+}
+
+/// Lock-free hit/miss counters shared by the cache backends.
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+}
+
+impl CacheStats {
+    pub(crate) fn record_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_eviction(&self) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_expiration(&self) {
+        self.expirations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the current counter values.
+    pub fn snapshot(&self) -> CacheStatsSnapshot {
+        CacheStatsSnapshot {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A cache backend chosen at runtime from configuration.
+///
+/// `Cache` has generic methods and is therefore not object safe; this enum is the
+/// dispatch point services hold instead of a `Box<dyn Cache>`.
+pub enum CacheBackend {
+    Memory(MemoryCache),
+    Redis(RedisCache),
+    Tiered(TieredCache),
+}
+
+impl CacheBackend {
+    /// Returns the hit/miss counters of the backend, with both tiers folded together for
+    /// [`TieredCache`].
+    pub fn stats(&self) -> CacheStatsSnapshot {
+        match self {
+            CacheBackend::Memory(cache) => cache.stats(),
+            CacheBackend::Redis(cache) => cache.stats(),
+            CacheBackend::Tiered(cache) => cache.stats().combined(),
+        }
+    }
+}
+
+#[async_trait]
+impl Cache for CacheBackend {
+    async fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        match self {
+            CacheBackend::Memory(cache) => cache.get(key).await,
+            CacheBackend::Redis(cache) => cache.get(key).await,
+            CacheBackend::Tiered(cache) => cache.get(key).await,
+        }
+    }
+
+    async fn set<T: Serialize + Send + Sync>(&self, key: &str, value: T, ttl: Duration) -> bool {
+        match self {
+            CacheBackend::Memory(cache) => cache.set(key, value, ttl).await,
+            CacheBackend::Redis(cache) => cache.set(key, value, ttl).await,
+            CacheBackend::Tiered(cache) => cache.set(key, value, ttl).await,
+        }
+    }
+
+    async fn delete(&self, key: &str) -> bool {
+        match self {
+            CacheBackend::Memory(cache) => cache.delete(key).await,
+            CacheBackend::Redis(cache) => cache.delete(key).await,
+            CacheBackend::Tiered(cache) => cache.delete(key).await,
+        }
+    }
+}
+
+/// Builds the cache backend described by `config`.
+///
+/// When the tiered backend is requested but the Redis pool cannot be created, the
+/// in-process tier is returned on its own so the service keeps running.
+pub fn build_cache(config: &CacheConfig) -> anyhow::Result<CacheBackend> {
+    let memory_config = MemoryCacheConfig::from(config);
+
+    match config.backend {
+        CacheBackendKind::Memory => Ok(CacheBackend::Memory(MemoryCache::new(memory_config))),
+        CacheBackendKind::Redis => Ok(CacheBackend::Redis(RedisCache::with_pool_size(
+            &config.redis_url,
+            config.pool_size,
+        )?)),
+        CacheBackendKind::Tiered => {
+            match RedisCache::with_pool_size(&config.redis_url, config.pool_size) {
+                Ok(remote) => Ok(CacheBackend::Tiered(TieredCache::new(
+                    MemoryCache::new(memory_config),
+                    remote,
+                ))),
+                Err(e) => {
+                    warn!("Redis unavailable ({}), falling back to in-process cache", e);
+                    Ok(CacheBackend::Memory(MemoryCache::new(memory_config)))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(backend: CacheBackendKind, redis_url: &str) -> CacheConfig {
+        CacheConfig {
+            redis_url: redis_url.to_string(),
+            ttl_seconds: 60,
+            backend,
+            pool_size: 4,
+            max_entries: 16,
+            max_bytes: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_memory_backend() {
+        let cache = build_cache(&config(CacheBackendKind::Memory, "")).unwrap();
+        assert!(matches!(cache, CacheBackend::Memory(_)));
+
+        assert!(cache.set("answer", 42u32, Duration::from_secs(60)).await);
+        assert_eq!(cache.get::<u32>("answer").await, Some(42));
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_build_tiered_backend_with_invalid_url_falls_back_to_memory() {
+        let cache = build_cache(&config(CacheBackendKind::Tiered, "not a url")).unwrap();
+        assert!(matches!(cache, CacheBackend::Memory(_)));
+    }
+
+    #[test]
+    fn test_tiered_stats_count_redis_hits_once() {
+        let stats = TieredCacheStats {
+            local: CacheStatsSnapshot { hits: 5, misses: 4, evictions: 1, expirations: 0 },
+            remote: CacheStatsSnapshot { hits: 3, misses: 1, evictions: 0, expirations: 0 },
+        };
+        let combined = stats.combined();
+        assert_eq!(combined.hits, 8);
+        assert_eq!(combined.misses, 1);
+        assert_eq!(combined.evictions, 1);
+    }
+
+    #[test]
+    fn test_hit_ratio() {
+        let stats = CacheStatsSnapshot { hits: 3, misses: 1, evictions: 0, expirations: 0 };
+        assert_eq!(stats.hit_ratio(), 0.75);
+        assert_eq!(CacheStatsSnapshot::default().hit_ratio(), 0.0);
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use deadpool_redis::{Config, Pool, Runtime};
+use redis::AsyncCommands;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tracing::warn;
+
+use super::{Cache, CacheStats, CacheStatsSnapshot};
+
+const DEFAULT_POOL_SIZE: usize = 16;
+
+/// Redis-backed cache that reuses connections from a pool.
+pub struct RedisCache {
+    pool: Pool,
+    stats: CacheStats,
+}
+
+impl RedisCache {
+    /// Creates a cache with the default pool size.
+    pub fn new(redis_url: &str) -> anyhow::Result<Self> {
+        Self::with_pool_size(redis_url, DEFAULT_POOL_SIZE)
+    }
+
+    /// Creates a cache whose pool holds at most `pool_size` connections.
+    ///
+    /// Connections are opened lazily, so this succeeds even if Redis is not reachable yet.
+    pub fn with_pool_size(redis_url: &str, pool_size: usize) -> anyhow::Result<Self> {
+        redis::Client::open(redis_url)?;
+        let pool = Config::from_url(redis_url)
+            .builder()?
+            .max_size(pool_size.max(1))
+            .runtime(Runtime::Tokio1)
+            .build()?;
+        Ok(Self { pool, stats: CacheStats::default() })
+    }
+
+    /// Returns the hit/miss counters of this cache.
+    pub fn stats(&self) -> CacheStatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// Looks up the serialised value stored under `key`.
+    ///
+    /// Connection failures are reported as `Err` so callers can tell them apart from misses.
+    pub(crate) async fn get_raw(&self, key: &str) -> anyhow::Result<Option<String>> {
+        let mut conn = self.pool.get().await?;
+        let data: Option<String> = conn.get(key).await?;
+        match data {
+            Some(_) => self.stats.record_hit(),
+            None => self.stats.record_miss(),
+        }
+        Ok(data)
+    }
+
+    /// Looks up the serialised value stored under `key` together with the time it has left
+    /// to live, which is `None` for keys without an expiry.
+    pub(crate) async fn get_raw_with_ttl(
+        &self,
+        key: &str,
+    ) -> anyhow::Result<Option<(String, Option<Duration>)>> {
+        let mut conn = self.pool.get().await?;
+        // Read both in one transaction so the TTL belongs to the value returned
+        let (data, pttl): (Option<String>, i64) =
+            redis::pipe().atomic().get(key).pttl(key).query_async(&mut conn).await?;
+        match data {
+            Some(_) => self.stats.record_hit(),
+            None => self.stats.record_miss(),
+        }
+        // PTTL is -1 for keys without an expiry and -2 for missing keys
+        let remaining = u64::try_from(pttl).ok().map(Duration::from_millis);
+        Ok(data.map(|data| (data, remaining)))
+    }
+
+    pub(crate) async fn set_raw(
+        &self,
+        key: &str,
+        value: String,
+        ttl: Duration,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.pool.get().await?;
+        if ttl.is_zero() {
+            conn.set::<_, _, ()>(key, value).await?;
+        } else {
+            // Redis expiries have second granularity; round sub-second TTLs up.
+            let seconds = ttl.as_secs() + u64::from(ttl.subsec_nanos() > 0);
+            conn.set_ex::<_, _, ()>(key, value, seconds).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn delete_raw(&self, key: &str) -> anyhow::Result<bool> {
+        let mut conn = self.pool.get().await?;
+        let removed: usize = conn.del(key).await?;
+        Ok(removed > 0)
+    }
+}
+
+#[async_trait]
+impl Cache for RedisCache {
+    async fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        match self.get_raw(key).await {
+            Ok(data) => data.and_then(|d| serde_json::from_str(&d).ok()),
+            Err(e) => {
+                warn!("Redis get failed for {}: {}", key, e);
+                self.stats.record_miss();
+                None
+            }
+        }
+    }
+
+    async fn set<T: Serialize + Send + Sync>(&self, key: &str, value: T, ttl: Duration) -> bool {
+        let Ok(serialized) = serde_json::to_string(&value) else {
+            return false;
+        };
+        match self.set_raw(key, serialized, ttl).await {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Redis set failed for {}: {}", key, e);
+                false
+            }
+        }
+    }
+
+    async fn delete(&self, key: &str) -> bool {
+        match self.delete_raw(key).await {
+            Ok(removed) => removed,
+            Err(e) => {
+                warn!("Redis delete failed for {}: {}", key, e);
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tracing::{debug, warn};
+
+use super::{Cache, CacheStatsSnapshot, MemoryCache, RedisCache};
+
+/// Counters of both tiers of a [`TieredCache`], kept apart so local and Redis hits can be told
+/// apart.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TieredCacheStats {
+    /// The local tier, which sees every lookup.
+    pub local: CacheStatsSnapshot,
+    /// The Redis tier, which only sees local misses.
+    pub remote: CacheStatsSnapshot,
+}
+
+impl TieredCacheStats {
+    /// Folds both tiers into one snapshot: a lookup is a hit if either tier served it.
+    pub fn combined(&self) -> CacheStatsSnapshot {
+        CacheStatsSnapshot {
+            hits: self.local.hits + self.remote.hits,
+            misses: self.local.misses.saturating_sub(self.remote.hits),
+            evictions: self.local.evictions + self.remote.evictions,
+            expirations: self.local.expirations + self.remote.expirations,
+        }
+    }
+}
+
+/// Two-tier cache: an in-process LRU in front of a pooled Redis.
+///
+/// Reads are served from memory first and fall through to Redis, promoting hits into the
+/// local tier for at most the time they have left in Redis. Redis failures degrade to
+/// memory-only operation instead of failing requests.
+///
+/// Nothing invalidates promoted entries when another instance changes the key in Redis, so
+/// the promotion TTL bounds how stale a local read can be.
+pub struct TieredCache {
+    local: MemoryCache,
+    remote: RedisCache,
+    /// Longest TTL given to a Redis hit promoted into the local tier.
+    promotion_ttl: Duration,
+}
+
+impl TieredCache {
+    pub fn new(local: MemoryCache, remote: RedisCache) -> Self {
+        Self { local, remote, promotion_ttl: Duration::from_secs(60) }
+    }
+
+    /// Sets the longest TTL given to entries copied from Redis into memory.
+    pub fn with_promotion_ttl(mut self, ttl: Duration) -> Self {
+        self.promotion_ttl = ttl;
+        self
+    }
+
+    /// Counters of the local and Redis tiers.
+    pub fn stats(&self) -> TieredCacheStats {
+        TieredCacheStats { local: self.local.stats(), remote: self.remote.stats() }
+    }
+
+    async fn get_raw(&self, key: &str) -> Option<String> {
+        if let Some(value) = self.local.get_raw(key) {
+            return Some(value);
+        }
+
+        match self.remote.get_raw_with_ttl(key).await {
+            Ok(Some((value, remaining))) => {
+                if let Some(ttl) = self.local_ttl(remaining) {
+                    debug!("Promoting {} from Redis into the local tier for {:?}", key, ttl);
+                    self.local.set_raw(key, value.clone(), ttl);
+                }
+                Some(value)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Redis tier unavailable for get {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    /// The TTL of a promoted entry with `remaining` time left in Redis, or `None` if it is
+    /// about to expire and should not be promoted.
+    fn local_ttl(&self, remaining: Option<Duration>) -> Option<Duration> {
+        let ttl =
+            remaining.map_or(self.promotion_ttl, |remaining| remaining.min(self.promotion_ttl));
+        (!ttl.is_zero()).then_some(ttl)
+    }
+}
+
+#[async_trait]
+impl Cache for TieredCache {
+    async fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.get_raw(key).await.and_then(|d| serde_json::from_str(&d).ok())
+    }
+
+    /// Writes to both tiers; succeeds if at least the local tier accepted the value.
+    async fn set<T: Serialize + Send + Sync>(&self, key: &str, value: T, ttl: Duration) -> bool {
+        let Ok(serialized) = serde_json::to_string(&value) else {
+            return false;
+        };
+
+        let stored_locally = self.local.set_raw(key, serialized.clone(), ttl);
+        if let Err(e) = self.remote.set_raw(key, serialized, ttl).await {
+            warn!("Redis tier unavailable for set {}: {}", key, e);
+        }
+        stored_locally
+    }
+
+    async fn delete(&self, key: &str) -> bool {
+        let removed_locally = self.local.delete_raw(key);
+        match self.remote.delete_raw(key).await {
+            Ok(removed_remotely) => removed_locally || removed_remotely,
+            Err(e) => {
+                warn!("Redis tier unavailable for delete {}: {}", key, e);
+                removed_locally
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryCacheConfig;
+
+    #[tokio::test]
+    async fn test_serves_from_memory_when_redis_is_down() {
+        // Nothing listens on port 1, so every Redis operation fails fast.
+        let remote = RedisCache::with_pool_size("redis://127.0.0.1:1", 1).unwrap();
+        let cache = TieredCache::new(MemoryCache::new(MemoryCacheConfig::default()), remote);
+
+        assert!(cache.set("key", 7u32, Duration::from_secs(60)).await);
+        assert_eq!(cache.get::<u32>("key").await, Some(7));
+        assert!(cache.delete("key").await);
+        assert_eq!(cache.get::<u32>("key").await, None);
+
+        let stats = cache.stats();
+        assert_eq!(stats.local.hits, 1);
+        assert_eq!(stats.local.misses, 1);
+        assert_eq!(stats.remote.hits, 0);
+        assert_eq!(stats.combined().hits, 1);
+        assert_eq!(stats.combined().misses, 1);
+    }
+
+    #[test]
+    fn test_promotion_never_outlives_the_redis_entry() {
+        let remote = RedisCache::with_pool_size("redis://127.0.0.1:1", 1).unwrap();
+        let cache = TieredCache::new(MemoryCache::new(MemoryCacheConfig::default()), remote)
+            .with_promotion_ttl(Duration::from_secs(60));
+
+        assert_eq!(cache.local_ttl(None), Some(Duration::from_secs(60)));
+        assert_eq!(cache.local_ttl(Some(Duration::from_secs(600))), Some(Duration::from_secs(60)));
+        assert_eq!(
+            cache.local_ttl(Some(Duration::from_millis(1500))),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(cache.local_ttl(Some(Duration::ZERO)), None);
+    }
+}
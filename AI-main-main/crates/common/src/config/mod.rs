@@ -0,0 +1,412 @@
+use anyhow::Result;
+use config::{Config, ConfigError, Environment, File, Map};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::cache::CacheBackendKind;
+
+mod validation;
+mod watcher;
+
+pub use validation::{ConfigSource, Validate, ValidationErrors, Validator, Violation};
+pub use watcher::ConfigWatcher;
+
+/// Prefix of environment variables that override file settings, e.g. `APP_SERVER__PORT`.
+pub const ENV_PREFIX: &str = "APP";
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct AppConfig {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub cache: CacheConfig,
+    pub auth: AuthConfig,
+    pub metrics: MetricsConfig,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+    pub idle_timeout_seconds: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct CacheConfig {
+    pub redis_url: String,
+    pub ttl_seconds: u64,
+    /// Which cache implementation to build (`memory`, `redis` or `tiered`).
+    #[serde(default)]
+    pub backend: CacheBackendKind,
+    /// Maximum number of pooled Redis connections.
+    #[serde(default = "default_cache_pool_size")]
+    pub pool_size: usize,
+    /// Maximum number of entries held by the in-process tier.
+    #[serde(default = "default_cache_max_entries")]
+    pub max_entries: usize,
+    /// Optional bound on the bytes held by the in-process tier.
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+fn default_cache_pool_size() -> usize {
+    16
+}
+
+fn default_cache_max_entries() -> usize {
+    10_000
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub jwt_secret: String,
+    pub token_expiration_hours: u64,
+    /// Signing algorithm accepted by the JWT middleware.
+    #[serde(default)]
+    pub algorithm: JwtAlgorithm,
+    /// Expected `iss` claim; not checked when unset.
+    #[serde(default)]
+    pub issuer: Option<String>,
+    /// Expected `aud` claim; not checked when unset.
+    #[serde(default)]
+    pub audience: Option<String>,
+    /// Clock skew tolerated when checking `exp` and `nbf`.
+    #[serde(default = "default_leeway_seconds")]
+    pub leeway_seconds: u64,
+    /// Local JWKS file holding the public keys for RS256/ES256.
+    #[serde(default)]
+    pub jwks_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JwtAlgorithm {
+    #[default]
+    HS256,
+    RS256,
+    ES256,
+}
+
+fn default_leeway_seconds() -> u64 {
+    60
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub endpoint: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServiceConfig {
+    pub service_name: String,
+    pub host: String,
+    pub port: u16,
+    pub log_level: String,
+    pub metrics_enabled: bool,
+    pub database_url: Option<String>,
+}
+
+impl ServiceConfig {
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        Ok(self.check()?)
+    }
+}
+
+impl Validate for ServiceConfig {
+    fn validate(&self, rules: &mut Validator) {
+        rules.required("service_name", &self.service_name);
+        rules.required("host", &self.host);
+        rules.port("port", self.port);
+        rules.one_of("log_level", &self.log_level, &LOG_LEVELS);
+        if let Some(url) = &self.database_url {
+            rules.url("database_url", url, &["postgres", "postgresql"]);
+        }
+    }
+}
+
+const LOG_LEVELS: [&str; 5] = ["trace", "debug", "info", "warn", "error"];
+
+impl Validate for AppConfig {
+    fn validate(&self, rules: &mut Validator) {
+        rules.nested("server", &self.server);
+        rules.nested("database", &self.database);
+        rules.nested("cache", &self.cache);
+        rules.nested("auth", &self.auth);
+        rules.nested("metrics", &self.metrics);
+    }
+}
+
+impl Validate for ServerConfig {
+    fn validate(&self, rules: &mut Validator) {
+        rules.required("host", &self.host);
+        rules.port("port", self.port);
+    }
+}
+
+impl Validate for DatabaseConfig {
+    fn validate(&self, rules: &mut Validator) {
+        rules.url("url", &self.url, &["postgres", "postgresql"]);
+        rules.range("max_connections", self.max_connections, 1..=1000);
+        rules.range("idle_timeout_seconds", self.idle_timeout_seconds, 1..=86_400);
+    }
+}
+
+impl Validate for CacheConfig {
+    fn validate(&self, rules: &mut Validator) {
+        if self.backend != CacheBackendKind::Memory {
+            rules.url("redis_url", &self.redis_url, &["redis", "rediss"]);
+            rules.range("pool_size", self.pool_size, 1..=1024);
+        }
+        rules.range("ttl_seconds", self.ttl_seconds, 0..=30 * 86_400);
+        rules.check("max_entries", self.max_entries > 0, "must be greater than 0");
+        rules.check("max_bytes", self.max_bytes != Some(0), "must be greater than 0 when set");
+    }
+}
+
+impl Validate for AuthConfig {
+    fn validate(&self, rules: &mut Validator) {
+        rules.range("token_expiration_hours", self.token_expiration_hours, 1..=720);
+        rules.range("leeway_seconds", self.leeway_seconds, 0..=300);
+        match self.algorithm {
+            JwtAlgorithm::HS256 => rules.min_len("jwt_secret", &self.jwt_secret, 32),
+            JwtAlgorithm::RS256 | JwtAlgorithm::ES256 => rules.check(
+                "jwks_path",
+                self.jwks_path.as_deref().is_some_and(|path| !path.is_empty()),
+                "is required for RS256 and ES256",
+            ),
+        }
+    }
+}
+
+impl Validate for MetricsConfig {
+    fn validate(&self, rules: &mut Validator) {
+        // Either a path served by the service itself or a push gateway URL.
+        if self.enabled && !self.endpoint.starts_with('/') {
+            rules.url("endpoint", &self.endpoint, &["http", "https"]);
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigLoadError {
+    #[error("Failed to read configuration: {0}")]
+    Source(#[from] ConfigError),
+    #[error("Invalid configuration: {0}")]
+    Invalid(#[from] ValidationErrors),
+    #[error("Failed to watch configuration: {0}")]
+    Watch(#[from] notify::Error),
+}
+
+/// Loads [`AppConfig`] from a file plus `APP_` environment overrides and validates it.
+///
+/// Nested fields are addressed with a double underscore, so `APP_SERVER__PORT` overrides
+/// `server.port`.
+#[derive(Debug, Clone)]
+pub struct ConfigLoader {
+    path: PathBuf,
+    env_prefix: String,
+    env: Option<Map<String, String>>,
+}
+
+impl ConfigLoader {
+    /// `path` may omit the extension, as with `config::File::with_name`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), env_prefix: ENV_PREFIX.to_string(), env: None }
+    }
+
+    pub fn env_prefix(mut self, prefix: &str) -> Self {
+        self.env_prefix = prefix.to_string();
+        self
+    }
+
+    /// Reads overrides from `vars` instead of the process environment.
+    pub fn with_env(mut self, vars: Map<String, String>) -> Self {
+        self.env = Some(vars);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the configuration file on disk, resolving a missing extension.
+    pub fn resolved_path(&self) -> PathBuf {
+        if self.path.is_file() {
+            return self.path.clone();
+        }
+        ["toml", "json", "yaml", "yml", "ini", "ron", "json5"]
+            .iter()
+            .map(|ext| PathBuf::from(format!("{}.{}", self.path.display(), ext)))
+            .find(|candidate| candidate.is_file())
+            .unwrap_or_else(|| self.path.clone())
+    }
+
+    fn file_source(&self) -> File<config::FileSourceFile, config::FileFormat> {
+        File::with_name(&self.path.to_string_lossy())
+    }
+
+    /// Reads, merges and validates the configuration, reporting every violation at once.
+    pub fn load(&self) -> Result<AppConfig, ConfigLoadError> {
+        let config: AppConfig = Config::builder()
+            .add_source(self.file_source())
+            .add_source(
+                Environment::with_prefix(&self.env_prefix)
+                    .prefix_separator("_")
+                    .separator("__")
+                    .try_parsing(true)
+                    .source(self.env.clone()),
+            )
+            .build()?
+            .try_deserialize()?;
+
+        if let Err(errors) = config.check() {
+            let file = Config::builder().add_source(self.file_source()).build()?;
+            return Err(errors.attribute(|field| self.source_of(field, &file)).into());
+        }
+        Ok(config)
+    }
+
+    fn env_var(&self, field: &str) -> String {
+        format!("{}_{}", self.env_prefix, field.replace('.', "__")).to_uppercase()
+    }
+
+    fn source_of(&self, field: &str, file: &Config) -> ConfigSource {
+        let var = self.env_var(field);
+        let from_env = match &self.env {
+            Some(vars) => vars.contains_key(&var),
+            None => std::env::var_os(&var).is_some(),
+        };
+        if from_env {
+            ConfigSource::Env(var)
+        } else if file.get::<config::Value>(field).is_ok() {
+            ConfigSource::File(self.resolved_path())
+        } else {
+            ConfigSource::Default
+        }
+    }
+}
+
+/// Loads and validates `config/default` plus `APP_` environment overrides.
+pub fn load_config() -> Result<AppConfig, ConfigLoadError> {
+    ConfigLoader::new("config/default").load()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    pub(crate) const VALID_CONFIG: &str = r#"
+[server]
+host = "127.0.0.1"
+port = 8080
+
+[database]
+url = "postgres://localhost/app"
+max_connections = 5
+idle_timeout_seconds = 300
+
+[cache]
+redis_url = "redis://localhost:6379"
+ttl_seconds = 60
+
+[auth]
+jwt_secret = "0123456789abcdef0123456789abcdef"
+token_expiration_hours = 24
+
+[metrics]
+enabled = true
+endpoint = "/metrics"
+"#;
+
+    /// Writes `contents` to `default.toml` in a fresh temporary directory.
+    pub(crate) fn write_config(contents: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("config-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("default.toml");
+        fs::write(&path, contents).unwrap();
+        (dir, path)
+    }
+
+    fn env(vars: &[(&str, &str)]) -> Map<String, String> {
+        vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_env_overrides_nested_fields() {
+        let (dir, path) = write_config(VALID_CONFIG);
+        let config = ConfigLoader::new(dir.join("default"))
+            .with_env(env(&[("APP_SERVER__PORT", "9000")]))
+            .load()
+            .unwrap();
+        assert_eq!(config.server.port, 9000);
+        assert_eq!(config.cache.pool_size, 16);
+        assert_eq!(ConfigLoader::new(dir.join("default")).resolved_path(), path);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_every_violation_is_reported_with_its_source() {
+        let (dir, path) =
+            write_config(&VALID_CONFIG.replace("0123456789abcdef0123456789abcdef", "short"));
+        let err = ConfigLoader::new(&path)
+            .with_env(env(&[
+                ("APP_DATABASE__MAX_CONNECTIONS", "0"),
+                ("APP_CACHE__REDIS_URL", "http://localhost"),
+            ]))
+            .load()
+            .unwrap_err();
+
+        let ConfigLoadError::Invalid(errors) = err else {
+            panic!("expected validation errors, got {:?}", err);
+        };
+        let found: Vec<_> = errors
+            .violations()
+            .iter()
+            .map(|v| (v.field.as_str(), v.source.clone().unwrap()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "database.max_connections",
+                    ConfigSource::Env("APP_DATABASE__MAX_CONNECTIONS".to_string())
+                ),
+                ("cache.redis_url", ConfigSource::Env("APP_CACHE__REDIS_URL".to_string())),
+                ("auth.jwt_secret", ConfigSource::File(path.clone())),
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_service_config_validation() {
+        let mut config = ServiceConfig {
+            service_name: "chatbot".to_string(),
+            host: "0.0.0.0".to_string(),
+            port: 8080,
+            log_level: "info".to_string(),
+            metrics_enabled: true,
+            database_url: Some("postgres://localhost/chat".to_string()),
+        };
+        assert!(config.validate().is_ok());
+
+        config.log_level = "verbose".to_string();
+        config.database_url = Some("mysql://localhost/chat".to_string());
+        let err = config.check().unwrap_err();
+        assert_eq!(err.violations().len(), 2);
+    }
+}
@@ -0,0 +1,256 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use thiserror::Error;
+use url::Url;
+
+/// Where the value of a configuration field came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    File(PathBuf),
+    Env(String),
+    /// The field was absent and its serde default was used.
+    Default,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::File(path) => write!(f, "file {}", path.display()),
+            ConfigSource::Env(var) => write!(f, "env {}", var),
+            ConfigSource::Default => write!(f, "default value"),
+        }
+    }
+}
+
+/// A single failed validation rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Dotted path of the field, e.g. `server.port`.
+    pub field: String,
+    pub message: String,
+    /// Set by the loader once it knows which source supplied the field.
+    pub source: Option<ConfigSource>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)?;
+        if let Some(source) = &self.source {
+            write!(f, " (from {})", source)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every violation found while validating a configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub struct ValidationErrors {
+    violations: Vec<Violation>,
+}
+
+impl ValidationErrors {
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    /// Fills in the source of each violation using `source_of(field)`.
+    pub fn attribute(mut self, source_of: impl Fn(&str) -> ConfigSource) -> Self {
+        for violation in &mut self.violations {
+            violation.source = Some(source_of(&violation.field));
+        }
+        self
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} invalid field(s)", self.violations.len())?;
+        for violation in &self.violations {
+            write!(f, "; {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Declares the validation rules of a configuration section.
+///
+/// ```ignore
+/// impl Validate for ServerConfig {
+///     fn validate(&self, rules: &mut Validator) {
+///         rules.required("host", &self.host);
+///         rules.port("port", self.port);
+///     }
+/// }
+/// ```
+pub trait Validate {
+    fn validate(&self, rules: &mut Validator);
+
+    /// Runs the rules and returns every violation at once.
+    fn check(&self) -> Result<(), ValidationErrors> {
+        let mut rules = Validator::default();
+        self.validate(&mut rules);
+        rules.finish()
+    }
+}
+
+/// Collects rule violations instead of stopping at the first one.
+#[derive(Debug, Default)]
+pub struct Validator {
+    path: Vec<&'static str>,
+    violations: Vec<Violation>,
+}
+
+impl Validator {
+    /// Validates a nested section, prefixing its field names with `name.`.
+    pub fn nested<T: Validate>(&mut self, name: &'static str, section: &T) {
+        self.path.push(name);
+        section.validate(self);
+        self.path.pop();
+    }
+
+    /// Records a violation of `field` unless `ok` holds.
+    pub fn check(&mut self, field: &str, ok: bool, message: impl Into<String>) {
+        if !ok {
+            let field = self
+                .path
+                .iter()
+                .copied()
+                .chain(std::iter::once(field))
+                .collect::<Vec<_>>()
+                .join(".");
+            self.violations.push(Violation { field, message: message.into(), source: None });
+        }
+    }
+
+    pub fn required(&mut self, field: &str, value: &str) {
+        self.check(field, !value.trim().is_empty(), "is required");
+    }
+
+    pub fn min_len(&mut self, field: &str, value: &str, min: usize) {
+        self.check(field, value.len() >= min, format!("must be at least {} bytes long", min));
+    }
+
+    pub fn range<T: PartialOrd + fmt::Display>(
+        &mut self,
+        field: &str,
+        value: T,
+        range: RangeInclusive<T>,
+    ) {
+        let message =
+            format!("must be between {} and {}, got {}", range.start(), range.end(), value);
+        self.check(field, range.contains(&value), message);
+    }
+
+    /// Requires a usable TCP port; `0` would bind to a random one.
+    pub fn port(&mut self, field: &str, port: u16) {
+        self.check(field, port != 0, "must be a port between 1 and 65535");
+    }
+
+    /// Requires an absolute URL whose scheme is one of `schemes`.
+    pub fn url(&mut self, field: &str, value: &str, schemes: &[&str]) {
+        match Url::parse(value) {
+            Ok(url) if schemes.contains(&url.scheme()) => {}
+            Ok(url) => self.check(
+                field,
+                false,
+                format!("scheme '{}' is not one of {}", url.scheme(), schemes.join(", ")),
+            ),
+            Err(e) => self.check(field, false, format!("is not a valid URL: {}", e)),
+        }
+    }
+
+    pub fn one_of(&mut self, field: &str, value: &str, allowed: &[&str]) {
+        self.check(
+            field,
+            allowed.contains(&value),
+            format!("must be one of {}", allowed.join(", ")),
+        );
+    }
+
+    pub fn finish(self) -> Result<(), ValidationErrors> {
+        if self.violations.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors { violations: self.violations })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Inner {
+        port: u16,
+    }
+
+    impl Validate for Inner {
+        fn validate(&self, rules: &mut Validator) {
+            rules.port("port", self.port);
+        }
+    }
+
+    struct Outer {
+        name: String,
+        url: String,
+        workers: u32,
+        inner: Inner,
+    }
+
+    impl Validate for Outer {
+        fn validate(&self, rules: &mut Validator) {
+            rules.required("name", &self.name);
+            rules.url("url", &self.url, &["redis", "rediss"]);
+            rules.range("workers", self.workers, 1..=64);
+            rules.nested("inner", &self.inner);
+        }
+    }
+
+    #[test]
+    fn test_every_violation_is_reported_with_its_path() {
+        let config = Outer {
+            name: " ".to_string(),
+            url: "http://localhost".to_string(),
+            workers: 0,
+            inner: Inner { port: 0 },
+        };
+        let errors = config.check().unwrap_err();
+        let fields: Vec<_> = errors.violations().iter().map(|v| v.field.as_str()).collect();
+        assert_eq!(fields, vec!["name", "url", "workers", "inner.port"]);
+        assert!(errors.to_string().starts_with("4 invalid field(s)"));
+    }
+
+    #[test]
+    fn test_valid_config_passes() {
+        let config = Outer {
+            name: "svc".to_string(),
+            url: "redis://localhost:6379".to_string(),
+            workers: 4,
+            inner: Inner { port: 8080 },
+        };
+        assert!(config.check().is_ok());
+    }
+
+    #[test]
+    fn test_sources_are_attached() {
+        let config = Outer {
+            name: "svc".to_string(),
+            url: "not a url".to_string(),
+            workers: 4,
+            inner: Inner { port: 0 },
+        };
+        let errors = config.check().unwrap_err().attribute(|field| match field {
+            "url" => ConfigSource::Env("APP_URL".to_string()),
+            _ => ConfigSource::File(PathBuf::from("config/default.toml")),
+        });
+        let rendered: Vec<_> = errors.violations().iter().map(ToString::to_string).collect();
+        assert!(rendered[0].starts_with("url: is not a valid URL"));
+        assert!(rendered[0].ends_with("(from env APP_URL)"));
+        assert_eq!(
+            rendered[1],
+            "inner.port: must be a port between 1 and 65535 (from file config/default.toml)"
+        );
+    }
+}
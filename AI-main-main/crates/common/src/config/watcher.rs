@@ -0,0 +1,120 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use super::{AppConfig, ConfigLoadError, ConfigLoader};
+
+struct Shared {
+    loader: ConfigLoader,
+    sender: watch::Sender<Arc<AppConfig>>,
+}
+
+impl Shared {
+    /// Publishes a new snapshot if the reloaded config is valid and differs from the current one.
+    fn reload(&self) -> Result<bool, ConfigLoadError> {
+        let config = self.loader.load()?;
+        Ok(self.sender.send_if_modified(|current| {
+            if **current == config {
+                return false;
+            }
+            *current = Arc::new(config);
+            true
+        }))
+    }
+}
+
+/// Watches the configuration file and publishes validated snapshots to subscribers.
+///
+/// An update that fails to parse or validate is logged and discarded; subscribers keep the
+/// last good snapshot. Environment overrides are re-read on every reload.
+pub struct ConfigWatcher {
+    shared: Arc<Shared>,
+    path: PathBuf,
+    _watcher: RecommendedWatcher,
+}
+
+impl ConfigWatcher {
+    /// Loads the initial configuration, which must be valid, and starts watching its file.
+    pub fn start(loader: ConfigLoader) -> Result<Self, ConfigLoadError> {
+        let initial = loader.load()?;
+        let path = loader.resolved_path();
+        let (sender, _) = watch::channel(Arc::new(initial));
+        let shared = Arc::new(Shared { loader, sender });
+
+        let handler = {
+            let shared = shared.clone();
+            let path = path.clone();
+            move |result: notify::Result<Event>| match result {
+                Ok(event) if touches(&event, &path) => match shared.reload() {
+                    Ok(true) => info!("Reloaded configuration from {}", path.display()),
+                    Ok(false) => {}
+                    Err(e) => warn!("Rejected configuration update from {}: {}", path.display(), e),
+                },
+                Ok(_) => {}
+                Err(e) => warn!("Configuration watcher error: {}", e),
+            }
+        };
+
+        // Watch the directory rather than the file so editors that replace the file on save
+        // do not silently detach the watch.
+        let mut watcher = notify::recommended_watcher(handler)?;
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+        watcher.watch(dir.unwrap_or_else(|| ".".as_ref()), RecursiveMode::NonRecursive)?;
+
+        Ok(Self { shared, path, _watcher: watcher })
+    }
+
+    /// Returns the most recent valid configuration.
+    pub fn current(&self) -> Arc<AppConfig> {
+        self.shared.sender.borrow().clone()
+    }
+
+    /// Returns a receiver that is notified whenever a new snapshot is published.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<AppConfig>> {
+        self.shared.sender.subscribe()
+    }
+
+    /// Reloads immediately, e.g. on SIGHUP. Returns whether a new snapshot was published.
+    pub fn reload(&self) -> Result<bool, ConfigLoadError> {
+        self.shared.reload()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn touches(event: &Event, path: &Path) -> bool {
+    matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+        && event.paths.iter().any(|changed| changed.file_name() == path.file_name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::{VALID_CONFIG, write_config};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_valid_updates_are_published_and_invalid_ones_rejected() {
+        let (dir, path) = write_config(VALID_CONFIG);
+        let watcher = ConfigWatcher::start(ConfigLoader::new(&path)).unwrap();
+        let mut updates = watcher.subscribe();
+        assert_eq!(watcher.current().server.port, 8080);
+
+        std::fs::write(&path, VALID_CONFIG.replace("port = 8080", "port = 9090")).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), updates.changed()).await.unwrap().unwrap();
+        assert_eq!(updates.borrow_and_update().server.port, 9090);
+
+        std::fs::write(&path, VALID_CONFIG.replace("port = 8080", "port = 0")).unwrap();
+        let err = watcher.reload().unwrap_err();
+        assert!(matches!(err, ConfigLoadError::Invalid(_)));
+        assert_eq!(watcher.current().server.port, 9090);
+        assert!(!updates.has_changed().unwrap());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,14 +1,59 @@
+//! Module: Encryption
+//! Symmetric encryption helpers.
+//!
+//! New code should use [`Keyring`], which produces authenticated AES-256-GCM envelopes
+//! tagged with the ID of the key that sealed them. The AES-256-CBC `encrypt`/`decrypt`
+//! pair is kept for reading data written before envelopes existed; it has no integrity
+//! check.
 
 use aes::Aes256;
-use block_modes::{BlockMode, Cbc};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
 use block_modes::block_padding::Pkcs7;
+use block_modes::{BlockMode, Cbc};
 use rand::Rng;
+use std::collections::HashMap;
 use std::error::Error;
+use thiserror::Error;
 
 type Aes256Cbc = Cbc<Aes256, Pkcs7>;
 
+/// Current version byte written at the start of every envelope.
+pub const ENVELOPE_VERSION: u8 = 1;
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const MAX_KEY_ID_LEN: usize = u8::MAX as usize;
+
+/// Errors raised by the authenticated encryption API.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum EncryptionError {
+    #[error("Invalid key length: expected {KEY_LEN} bytes, got {0}")]
+    InvalidKeyLength(usize),
+
+    #[error("Invalid key ID: {0}")]
+    InvalidKeyId(String),
+
+    #[error("Unknown key ID: {0}")]
+    UnknownKey(String),
+
+    #[error("Unsupported envelope version: {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Malformed envelope: {0}")]
+    MalformedEnvelope(String),
+
+    /// The ciphertext, its header or the associated data was modified, or the wrong key
+    /// was used.
+    #[error("Authentication failed")]
+    AuthenticationFailed,
+}
+
 /// Encrypts data using AES-256-CBC.
 ///
+/// Legacy format without integrity protection; prefer [`Keyring::encrypt`].
+///
 /// # Arguments
 /// * `data` - The plaintext data to encrypt.
 /// * `key` - A 32-byte key for AES-256 encryption.
@@ -51,6 +96,232 @@ pub fn decrypt(data: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, Box<dyn Error>> {
     Ok(cipher.decrypt_vec(ciphertext)?)
 }
 
+/// Checks that `bytes`, such as a key read from configuration, is an AES-256 key.
+pub fn key_from_slice(bytes: &[u8]) -> Result<[u8; KEY_LEN], EncryptionError> {
+    bytes.try_into().map_err(|_| EncryptionError::InvalidKeyLength(bytes.len()))
+}
+
+/// The parsed header of a versioned envelope.
+///
+/// Layout: `version (1) | key_id_len (1) | key_id | nonce (12) | ciphertext + tag`.
+/// Everything before the nonce is authenticated together with the caller's associated
+/// data, so the key ID cannot be swapped without detection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvelopeHeader<'a> {
+    pub version: u8,
+    pub key_id: &'a str,
+}
+
+impl<'a> EnvelopeHeader<'a> {
+    /// Parses the header of `envelope`, returning it together with the remaining bytes.
+    pub fn parse(envelope: &'a [u8]) -> Result<(Self, &'a [u8]), EncryptionError> {
+        let (&version, rest) = envelope
+            .split_first()
+            .ok_or_else(|| EncryptionError::MalformedEnvelope("empty input".to_string()))?;
+        if version != ENVELOPE_VERSION {
+            return Err(EncryptionError::UnsupportedVersion(version));
+        }
+
+        let (&key_id_len, rest) = rest
+            .split_first()
+            .ok_or_else(|| EncryptionError::MalformedEnvelope("missing key ID".to_string()))?;
+        let key_id_len = key_id_len as usize;
+        if rest.len() < key_id_len + NONCE_LEN + TAG_LEN {
+            return Err(EncryptionError::MalformedEnvelope("envelope is truncated".to_string()));
+        }
+
+        let (key_id, rest) = rest.split_at(key_id_len);
+        let key_id = std::str::from_utf8(key_id)
+            .map_err(|_| EncryptionError::MalformedEnvelope("key ID is not UTF-8".to_string()))?;
+
+        Ok((Self { version, key_id }, rest))
+    }
+
+    fn header_len(&self) -> usize {
+        2 + self.key_id.len()
+    }
+}
+
+fn validate_key_id(key_id: &str) -> Result<(), EncryptionError> {
+    if key_id.is_empty() || key_id.len() > MAX_KEY_ID_LEN {
+        return Err(EncryptionError::InvalidKeyId(format!(
+            "key ID must be between 1 and {} bytes",
+            MAX_KEY_ID_LEN
+        )));
+    }
+    Ok(())
+}
+
+fn authenticated_data(header: &[u8], aad: &[u8]) -> Vec<u8> {
+    [header, aad].concat()
+}
+
+/// Seals `plaintext` with AES-256-GCM into a versioned envelope tagged with `key_id`.
+///
+/// `aad` is authenticated but not encrypted; the same bytes must be supplied to
+/// [`open_envelope`]. A fresh random nonce is generated for every call.
+pub fn seal_envelope(
+    plaintext: &[u8],
+    key: &[u8; 32],
+    key_id: &str,
+    aad: &[u8],
+) -> Result<Vec<u8>, EncryptionError> {
+    validate_key_id(key_id)?;
+
+    let mut envelope = Vec::with_capacity(2 + key_id.len() + NONCE_LEN + plaintext.len() + TAG_LEN);
+    envelope.push(ENVELOPE_VERSION);
+    envelope.push(key_id.len() as u8);
+    envelope.extend_from_slice(key_id.as_bytes());
+
+    let nonce = rand::thread_rng().gen::<[u8; NONCE_LEN]>();
+    let cipher = Aes256Gcm::new(key.into());
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload { msg: plaintext, aad: &authenticated_data(&envelope, aad) },
+        )
+        .map_err(|_| EncryptionError::AuthenticationFailed)?;
+
+    envelope.extend_from_slice(&nonce);
+    envelope.extend_from_slice(&ciphertext);
+    Ok(envelope)
+}
+
+/// Opens an envelope produced by [`seal_envelope`] with the given key.
+///
+/// Fails with [`EncryptionError::AuthenticationFailed`] if any byte of the envelope or
+/// `aad` differs from what was sealed.
+pub fn open_envelope(
+    envelope: &[u8],
+    key: &[u8; 32],
+    aad: &[u8],
+) -> Result<Vec<u8>, EncryptionError> {
+    let (header, body) = EnvelopeHeader::parse(envelope)?;
+    let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+
+    let cipher = Aes256Gcm::new(key.into());
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: &authenticated_data(&envelope[..header.header_len()], aad),
+            },
+        )
+        .map_err(|_| EncryptionError::AuthenticationFailed)
+}
+
+/// A set of named keys with one active key.
+///
+/// New data is always sealed under the active key; envelopes sealed under retired keys can
+/// still be opened as long as those keys remain in the ring. Rotation therefore only needs
+/// a new key, and existing data can be migrated lazily with [`Keyring::reencrypt`].
+#[derive(Clone)]
+pub struct Keyring {
+    active_key_id: String,
+    keys: HashMap<String, [u8; 32]>,
+}
+
+impl std::fmt::Debug for Keyring {
+    // Never print key material.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut key_ids: Vec<&String> = self.keys.keys().collect();
+        key_ids.sort();
+        f.debug_struct("Keyring")
+            .field("active_key_id", &self.active_key_id)
+            .field("key_ids", &key_ids)
+            .finish()
+    }
+}
+
+impl Keyring {
+    /// Creates a keyring whose only key is also the active one.
+    pub fn new(active_key_id: &str, key: [u8; 32]) -> Result<Self, EncryptionError> {
+        validate_key_id(active_key_id)?;
+        let mut keys = HashMap::new();
+        keys.insert(active_key_id.to_string(), key);
+        Ok(Self { active_key_id: active_key_id.to_string(), keys })
+    }
+
+    /// Returns the ID of the key used for new envelopes.
+    pub fn active_key_id(&self) -> &str {
+        &self.active_key_id
+    }
+
+    /// Returns `true` if the ring can open envelopes sealed under `key_id`.
+    pub fn contains(&self, key_id: &str) -> bool {
+        self.keys.contains_key(key_id)
+    }
+
+    /// Adds a decrypt-only key, e.g. one retired by another instance.
+    ///
+    /// Fails if the ring already has a key with this ID, since replacing it would make its
+    /// envelopes unreadable.
+    pub fn add_retired_key(&mut self, key_id: &str, key: [u8; 32]) -> Result<(), EncryptionError> {
+        validate_key_id(key_id)?;
+        if key_id == self.active_key_id {
+            return Err(EncryptionError::InvalidKeyId(format!("{} is the active key", key_id)));
+        }
+        if self.keys.contains_key(key_id) {
+            return Err(EncryptionError::InvalidKeyId(format!("{} already exists", key_id)));
+        }
+        self.keys.insert(key_id.to_string(), key);
+        Ok(())
+    }
+
+    /// Makes `key` the active key. The previous active key is kept for decryption.
+    pub fn rotate(&mut self, new_key_id: &str, key: [u8; 32]) -> Result<(), EncryptionError> {
+        validate_key_id(new_key_id)?;
+        if self.keys.contains_key(new_key_id) {
+            return Err(EncryptionError::InvalidKeyId(format!("{} already exists", new_key_id)));
+        }
+        self.keys.insert(new_key_id.to_string(), key);
+        self.active_key_id = new_key_id.to_string();
+        Ok(())
+    }
+
+    /// Removes a retired key once no envelope sealed under it remains.
+    pub fn remove_key(&mut self, key_id: &str) -> Result<(), EncryptionError> {
+        if key_id == self.active_key_id {
+            return Err(EncryptionError::InvalidKeyId(format!(
+                "cannot remove the active key {}",
+                key_id
+            )));
+        }
+        self.keys
+            .remove(key_id)
+            .map(|_| ())
+            .ok_or_else(|| EncryptionError::UnknownKey(key_id.to_string()))
+    }
+
+    /// Seals `plaintext` under the active key.
+    pub fn encrypt(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        seal_envelope(plaintext, &self.keys[&self.active_key_id], &self.active_key_id, aad)
+    }
+
+    /// Opens an envelope sealed under any key in the ring.
+    pub fn decrypt(&self, envelope: &[u8], aad: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let (header, _) = EnvelopeHeader::parse(envelope)?;
+        let key = self
+            .keys
+            .get(header.key_id)
+            .ok_or_else(|| EncryptionError::UnknownKey(header.key_id.to_string()))?;
+        open_envelope(envelope, key, aad)
+    }
+
+    /// Returns `true` if `envelope` was sealed under a key other than the active one.
+    pub fn needs_reencryption(&self, envelope: &[u8]) -> Result<bool, EncryptionError> {
+        let (header, _) = EnvelopeHeader::parse(envelope)?;
+        Ok(header.key_id != self.active_key_id)
+    }
+
+    /// Opens `envelope` and seals its plaintext again under the active key.
+    pub fn reencrypt(&self, envelope: &[u8], aad: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let plaintext = self.decrypt(envelope, aad)?;
+        self.encrypt(&plaintext, aad)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -68,11 +339,11 @@ mod tests {
 
     #[test]
     fn test_encrypt_invalid_key() {
-        let data = b"Sensitive data";
         let invalid_key = [0u8; 16]; // Incorrect key length
 
-        let result = encrypt(data, &invalid_key.try_into().unwrap_or([0u8; 32]));
-        assert!(result.is_err());
+        assert_eq!(key_from_slice(&invalid_key), Err(EncryptionError::InvalidKeyLength(16)));
+        let key = key_from_slice(&[0u8; 32]).unwrap();
+        assert!(Keyring::new("k1", key).unwrap().encrypt(b"Sensitive data", b"").is_ok());
     }
 
     #[test]
@@ -94,4 +365,98 @@ mod tests {
         let result = decrypt(&encrypted, &invalid_key);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_envelope_round_trip_with_aad() {
+        let key = [7u8; 32];
+        let envelope = seal_envelope(b"Sensitive data", &key, "k1", b"user:42").unwrap();
+
+        let (header, _) = EnvelopeHeader::parse(&envelope).unwrap();
+        assert_eq!(header.version, ENVELOPE_VERSION);
+        assert_eq!(header.key_id, "k1");
+        assert_eq!(open_envelope(&envelope, &key, b"user:42").unwrap(), b"Sensitive data");
+    }
+
+    #[test]
+    fn test_envelope_detects_tampering() {
+        let key = [7u8; 32];
+        let envelope = seal_envelope(b"Sensitive data", &key, "k1", b"").unwrap();
+
+        let mut tampered = envelope.clone();
+        *tampered.last_mut().unwrap() ^= 0x01;
+        assert_eq!(open_envelope(&tampered, &key, b""), Err(EncryptionError::AuthenticationFailed));
+
+        // The key ID is part of the authenticated header.
+        let mut relabelled = envelope.clone();
+        relabelled[2] = b'k' + 1;
+        assert_eq!(
+            open_envelope(&relabelled, &key, b""),
+            Err(EncryptionError::AuthenticationFailed)
+        );
+    }
+
+    #[test]
+    fn test_envelope_rejects_wrong_aad_and_key() {
+        let key = [7u8; 32];
+        let envelope = seal_envelope(b"Sensitive data", &key, "k1", b"user:42").unwrap();
+
+        assert!(open_envelope(&envelope, &key, b"user:43").is_err());
+        assert!(open_envelope(&envelope, &[8u8; 32], b"user:42").is_err());
+    }
+
+    #[test]
+    fn test_envelope_rejects_malformed_input() {
+        let key = [7u8; 32];
+        assert!(matches!(
+            open_envelope(&[], &key, b""),
+            Err(EncryptionError::MalformedEnvelope(_))
+        ));
+        assert_eq!(open_envelope(&[2, 0], &key, b""), Err(EncryptionError::UnsupportedVersion(2)));
+        assert!(matches!(
+            open_envelope(&[ENVELOPE_VERSION, 2, b'k', b'1'], &key, b""),
+            Err(EncryptionError::MalformedEnvelope(_))
+        ));
+    }
+
+    #[test]
+    fn test_keyring_rotation_keeps_old_envelopes_readable() {
+        let mut keyring = Keyring::new("2024-01", [1u8; 32]).unwrap();
+        let old = keyring.encrypt(b"old secret", b"").unwrap();
+
+        keyring.rotate("2024-06", [2u8; 32]).unwrap();
+        let new = keyring.encrypt(b"new secret", b"").unwrap();
+
+        assert_eq!(keyring.active_key_id(), "2024-06");
+        assert_eq!(keyring.decrypt(&old, b"").unwrap(), b"old secret");
+        assert_eq!(keyring.decrypt(&new, b"").unwrap(), b"new secret");
+        assert!(keyring.needs_reencryption(&old).unwrap());
+        assert!(!keyring.needs_reencryption(&new).unwrap());
+    }
+
+    #[test]
+    fn test_keyring_reencrypt_and_remove_retired_key() {
+        let mut keyring = Keyring::new("k1", [1u8; 32]).unwrap();
+        let old = keyring.encrypt(b"payload", b"ctx").unwrap();
+        keyring.rotate("k2", [2u8; 32]).unwrap();
+
+        let migrated = keyring.reencrypt(&old, b"ctx").unwrap();
+        keyring.remove_key("k1").unwrap();
+
+        assert_eq!(keyring.decrypt(&migrated, b"ctx").unwrap(), b"payload");
+        assert_eq!(keyring.decrypt(&old, b"ctx"), Err(EncryptionError::UnknownKey("k1".into())));
+        assert!(keyring.remove_key("k2").is_err());
+    }
+
+    #[test]
+    fn test_keyring_rejects_invalid_key_ids() {
+        assert!(Keyring::new("", [0u8; 32]).is_err());
+        assert!(Keyring::new(&"x".repeat(256), [0u8; 32]).is_err());
+
+        let mut keyring = Keyring::new("k1", [0u8; 32]).unwrap();
+        assert!(keyring.rotate("k1", [1u8; 32]).is_err());
+        assert!(keyring.add_retired_key("k1", [1u8; 32]).is_err());
+        keyring.add_retired_key("k0", [2u8; 32]).unwrap();
+        assert!(keyring.add_retired_key("k0", [3u8; 32]).is_err());
+        assert!(!format!("{:?}", keyring).contains("[0,"));
+    }
 }
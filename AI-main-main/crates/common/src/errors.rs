@@ -1,12 +1,303 @@
-use actix_web::{HttpResponse, ResponseError};
-use serde::Serialize;
+//! Module: Errors
+//! One error model shared by every service.
+//!
+//! Handlers and middleware return [`ApiError`] (or a crate error that converts into it) and
+//! clients always receive an RFC 7807 `application/problem+json` body with a stable
+//! machine-readable `code`, the request's correlation ID and optional field-level details.
+
+use actix_web::http::{StatusCode, header};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+use shared::SharedError;
+use std::fmt;
 use thiserror::Error;
+use tracing::error;
+
+use crate::auth::AuthError;
+use crate::run::RunError;
+
+/// Content type of problem details responses.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Header carrying the correlation ID of a request and its response.
+pub const CORRELATION_ID_HEADER: &str = "X-Correlation-ID";
+
+/// Correlation ID of the current request, stored in the request extensions by middleware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorrelationId(pub String);
+
+impl CorrelationId {
+    /// Reads the ID from the request extensions, falling back to the request header.
+    pub fn from_request(req: &HttpRequest) -> Option<String> {
+        if let Some(id) = req.extensions().get::<CorrelationId>() {
+            return Some(id.0.clone());
+        }
+        req.headers()
+            .get(CORRELATION_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| !id.is_empty())
+            .map(str::to_owned)
+    }
+}
+
+/// Stable, machine-readable error codes. Clients should branch on these, never on messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    BadRequest,
+    ValidationFailed,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    PayloadTooLarge,
+    UnprocessableEntity,
+    RateLimited,
+    Internal,
+    ServiceUnavailable,
+    Timeout,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "BAD_REQUEST",
+            ErrorCode::ValidationFailed => "VALIDATION_FAILED",
+            ErrorCode::Unauthorized => "UNAUTHORIZED",
+            ErrorCode::Forbidden => "FORBIDDEN",
+            ErrorCode::NotFound => "NOT_FOUND",
+            ErrorCode::Conflict => "CONFLICT",
+            ErrorCode::PayloadTooLarge => "PAYLOAD_TOO_LARGE",
+            ErrorCode::UnprocessableEntity => "UNPROCESSABLE_ENTITY",
+            ErrorCode::RateLimited => "RATE_LIMITED",
+            ErrorCode::Internal => "INTERNAL",
+            ErrorCode::ServiceUnavailable => "SERVICE_UNAVAILABLE",
+            ErrorCode::Timeout => "TIMEOUT",
+        }
+    }
+
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::ValidationFailed => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::UnprocessableEntity => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+
+    /// Picks the closest code for a bare status, e.g. from a framework error.
+    pub fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+            StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+            StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED => ErrorCode::NotFound,
+            StatusCode::CONFLICT => ErrorCode::Conflict,
+            StatusCode::PAYLOAD_TOO_LARGE => ErrorCode::PayloadTooLarge,
+            StatusCode::UNPROCESSABLE_ENTITY => ErrorCode::UnprocessableEntity,
+            StatusCode::TOO_MANY_REQUESTS => ErrorCode::RateLimited,
+            StatusCode::SERVICE_UNAVAILABLE => ErrorCode::ServiceUnavailable,
+            StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => ErrorCode::Timeout,
+            status if status.is_client_error() => ErrorCode::BadRequest,
+            _ => ErrorCode::Internal,
+        }
+    }
+
+    /// Short human-readable summary used as the problem `title`.
+    pub fn title(self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "Bad request",
+            ErrorCode::ValidationFailed => "Validation failed",
+            ErrorCode::Unauthorized => "Unauthorized",
+            ErrorCode::Forbidden => "Forbidden",
+            ErrorCode::NotFound => "Not found",
+            ErrorCode::Conflict => "Conflict",
+            ErrorCode::PayloadTooLarge => "Payload too large",
+            ErrorCode::UnprocessableEntity => "Unprocessable entity",
+            ErrorCode::RateLimited => "Too many requests",
+            ErrorCode::Internal => "Internal server error",
+            ErrorCode::ServiceUnavailable => "Service unavailable",
+            ErrorCode::Timeout => "Timeout",
+        }
+    }
+
+    /// Problem `type` URI; stable for a given code.
+    pub fn type_uri(self) -> String {
+        format!("urn:problem-type:{}", self.as_str().to_ascii_lowercase().replace('_', "-"))
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Problem with a single request field.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// RFC 7807 problem details body, extended with `code`, `correlation_id` and `errors`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub type_uri: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    pub code: ErrorCode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+/// The error every service returns to clients.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("{code}: {message}")]
+pub struct ApiError {
+    pub code: ErrorCode,
+    /// Client-safe explanation, rendered as the problem `detail`.
+    pub message: String,
+    pub fields: Vec<FieldError>,
+    pub correlation_id: Option<String>,
+    /// Server-side detail that is logged when the response is rendered, never sent.
+    pub cause: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            fields: Vec::new(),
+            correlation_id: None,
+            cause: None,
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::BadRequest, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Unauthorized, message)
+    }
+
+    /// Returns an error whose message does not leak `cause` to the client; the cause is
+    /// logged once, when the response is rendered.
+    pub fn internal(cause: impl fmt::Display) -> Self {
+        Self::new(ErrorCode::Internal, ErrorCode::Internal.title()).with_cause(cause)
+    }
+
+    pub fn validation(fields: Vec<FieldError>) -> Self {
+        let mut err = Self::new(ErrorCode::ValidationFailed, "One or more fields are invalid");
+        err.fields = fields;
+        err
+    }
+
+    pub fn with_field(mut self, field: impl Into<String>, message: impl Into<String>) -> Self {
+        self.fields.push(FieldError { field: field.into(), message: message.into() });
+        self
+    }
+
+    pub fn with_cause(mut self, cause: impl fmt::Display) -> Self {
+        self.cause = Some(cause.to_string());
+        self
+    }
+
+    pub fn with_correlation_id(mut self, correlation_id: impl Into<String>) -> Self {
+        self.correlation_id = Some(correlation_id.into());
+        self
+    }
+
+    pub fn to_problem(&self, instance: Option<&str>) -> ProblemDetails {
+        ProblemDetails {
+            type_uri: self.code.type_uri(),
+            title: self.code.title().to_string(),
+            status: self.code.status().as_u16(),
+            detail: self.message.clone(),
+            instance: instance.map(str::to_owned),
+            code: self.code,
+            correlation_id: self.correlation_id.clone(),
+            errors: self.fields.clone(),
+        }
+    }
+
+    /// Recovers the error model from a framework error, keeping codes of known error types.
+    pub fn from_actix(err: &actix_web::Error) -> Self {
+        if let Some(api) = err.as_error::<ApiError>() {
+            return api.clone();
+        }
+        if let Some(auth) = err.as_error::<AuthError>() {
+            return auth.into();
+        }
+        if let Some(app) = err.as_error::<AppError>() {
+            return app.into();
+        }
+
+        // Messages of unknown server errors may carry internals; only their status is kept.
+        let status = err.as_response_error().status_code();
+        let code = ErrorCode::from_status(status);
+        if status.is_server_error() {
+            return Self::new(code, code.title());
+        }
+        Self::new(code, err.to_string())
+    }
+
+    /// Builds the problem+json response for `req`, filling in its path and correlation ID.
+    pub fn response_for(&self, req: &HttpRequest) -> HttpResponse {
+        let mut problem = self.to_problem(Some(req.path()));
+        if problem.correlation_id.is_none() {
+            problem.correlation_id = CorrelationId::from_request(req);
+        }
+        self.log();
+        problem_response(self.code.status(), &problem)
+    }
+
+    /// Logs server errors; called once per rendered response rather than on conversion.
+    fn log(&self) {
+        if self.code.status().is_server_error() {
+            error!("{}: {}", self.code, self.cause.as_deref().unwrap_or(&self.message));
+        }
+    }
+}
 
-/// Define the error response structure
-#[derive(Debug, Serialize)]
-pub struct ErrorResponse {
-    pub error: String,
-    pub timestamp: chrono::DateTime<chrono::Utc>,
+pub(crate) fn problem_response(status: StatusCode, problem: &ProblemDetails) -> HttpResponse {
+    let body = serde_json::to_string(problem).unwrap_or_default();
+    let mut response = HttpResponse::build(status);
+    response.insert_header((header::CONTENT_TYPE, PROBLEM_JSON));
+    if let Some(id) = &problem.correlation_id {
+        response.insert_header((CORRELATION_ID_HEADER, id.as_str()));
+    }
+    response.body(body)
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.code.status()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.log();
+        problem_response(self.status_code(), &self.to_problem(None))
+    }
 }
 
 /// Define custom application errors
@@ -23,22 +314,23 @@ pub enum AppError {
     // TODO: Add other variants as needed
 }
 
+impl From<&AppError> for ApiError {
+    fn from(err: &AppError) -> Self {
+        match err {
+            AppError::BadRequest(msg) => ApiError::bad_request(msg.clone()),
+            AppError::Unauthorized(msg) => ApiError::unauthorized(msg.clone()),
+            AppError::InternalError(msg) => ApiError::internal(msg),
+        }
+    }
+}
+
 impl ResponseError for AppError {
     fn error_response(&self) -> HttpResponse {
-        let status_code = self.status_code();
-
-        HttpResponse::build(status_code).json(ErrorResponse {
-            error: self.to_string(),
-            timestamp: chrono::Utc::now(),
-        })
+        ApiError::from(self).error_response()
     }
 
-    fn status_code(&self) -> actix_web::http::StatusCode {
-        match *self {
-            AppError::BadRequest(_) => actix_web::http::StatusCode::BAD_REQUEST,
-            AppError::Unauthorized(_) => actix_web::http::StatusCode::UNAUTHORIZED,
-            AppError::InternalError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
-        }
+    fn status_code(&self) -> StatusCode {
+        ApiError::from(self).status_code()
     }
 }
 
@@ -46,18 +338,187 @@ impl ResponseError for AppError {
 pub enum CommonError {
     #[error("Invalid input: {0}")]
     InvalidInput(String),
-    
+
     #[error("Processing error: {0}")]
     ProcessingError(String),
-    
+
     #[error("Database error: {0}")]
     DatabaseError(String),
-    
+
     #[error("External service error: {0}")]
     ExternalServiceError(String),
-    
+
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
 
 pub type CommonResult<T> = Result<T, CommonError>;
+
+impl From<CommonError> for ApiError {
+    fn from(err: CommonError) -> Self {
+        match err {
+            CommonError::InvalidInput(msg) => ApiError::bad_request(msg),
+            CommonError::ProcessingError(msg) => ApiError::new(ErrorCode::UnprocessableEntity, msg),
+            CommonError::ExternalServiceError(msg) => {
+                ApiError::new(ErrorCode::ServiceUnavailable, "An upstream service failed")
+                    .with_cause(format!("External service error: {}", msg))
+            }
+            err @ (CommonError::DatabaseError(_) | CommonError::Other(_)) => {
+                ApiError::internal(err)
+            }
+        }
+    }
+}
+
+impl From<&AuthError> for ApiError {
+    fn from(err: &AuthError) -> Self {
+        match err {
+            AuthError::InsufficientScope(_) | AuthError::MissingRole(_) => {
+                ApiError::new(ErrorCode::Forbidden, err.to_string())
+            }
+            AuthError::Config(_) => ApiError::internal(err),
+            _ => ApiError::unauthorized(err.to_string()),
+        }
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(err: AuthError) -> Self {
+        ApiError::from(&err)
+    }
+}
+
+impl From<RunError> for ApiError {
+    fn from(err: RunError) -> Self {
+        match err {
+            RunError::NotFound(_) => ApiError::not_found(err.to_string()),
+            RunError::InvalidTransition { .. } => {
+                ApiError::new(ErrorCode::Conflict, err.to_string())
+            }
+            RunError::UnknownStatus(_) => ApiError::bad_request(err.to_string()),
+            RunError::Storage(_) => ApiError::internal(err),
+        }
+    }
+}
+
+impl From<&SharedError> for ApiError {
+    fn from(err: &SharedError) -> Self {
+        match err {
+            SharedError::InvalidInput(msg) => ApiError::bad_request(msg.clone()),
+            SharedError::OperationFailed(_) | SharedError::Unknown => ApiError::internal(err),
+        }
+    }
+}
+
+impl From<SharedError> for ApiError {
+    fn from(err: SharedError) -> Self {
+        ApiError::from(&err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_codes_map_to_statuses_and_back() {
+        for code in [
+            ErrorCode::BadRequest,
+            ErrorCode::Unauthorized,
+            ErrorCode::Forbidden,
+            ErrorCode::NotFound,
+            ErrorCode::Conflict,
+            ErrorCode::RateLimited,
+            ErrorCode::Internal,
+            ErrorCode::ServiceUnavailable,
+        ] {
+            assert_eq!(ErrorCode::from_status(code.status()), code);
+        }
+        assert_eq!(ErrorCode::ValidationFailed.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            serde_json::to_value(ErrorCode::RateLimited).unwrap(),
+            serde_json::json!("RATE_LIMITED")
+        );
+    }
+
+    #[test]
+    fn test_problem_json_body() {
+        let err = ApiError::validation(Vec::new())
+            .with_field("temperature", "must be between 0 and 2")
+            .with_correlation_id("req-1");
+        let response = err.error_response();
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.headers().get(header::CONTENT_TYPE).unwrap(), PROBLEM_JSON);
+        assert_eq!(response.headers().get(CORRELATION_ID_HEADER).unwrap(), "req-1");
+
+        let problem = err.to_problem(Some("/chat"));
+        assert_eq!(
+            serde_json::to_value(&problem).unwrap(),
+            serde_json::json!({
+                "type": "urn:problem-type:validation-failed",
+                "title": "Validation failed",
+                "status": 422,
+                "detail": "One or more fields are invalid",
+                "instance": "/chat",
+                "code": "VALIDATION_FAILED",
+                "correlation_id": "req-1",
+                "errors": [{ "field": "temperature", "message": "must be between 0 and 2" }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_response_for_request_uses_its_correlation_id() {
+        let req = TestRequest::get()
+            .uri("/runs/42")
+            .insert_header((CORRELATION_ID_HEADER, "abc"))
+            .to_http_request();
+        let response = ApiError::not_found("Run 42 not found").response_for(&req);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers().get(CORRELATION_ID_HEADER).unwrap(), "abc");
+    }
+
+    #[test]
+    fn test_internal_errors_do_not_leak_details() {
+        let err: ApiError = CommonError::DatabaseError("password=hunter2".to_string()).into();
+        assert_eq!(err.code, ErrorCode::Internal);
+        assert!(!err.message.contains("hunter2"));
+        assert!(!serde_json::to_string(&err.to_problem(None)).unwrap().contains("hunter2"));
+        assert_eq!(err.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(err.cause.as_deref().unwrap().contains("hunter2"));
+    }
+
+    #[test]
+    fn test_conversions_from_existing_errors() {
+        assert_eq!(
+            ApiError::from(SharedError::InvalidInput("x".into())).code,
+            ErrorCode::BadRequest
+        );
+        assert_eq!(
+            ApiError::from(&AppError::Unauthorized("x".into())).code,
+            ErrorCode::Unauthorized
+        );
+        assert_eq!(
+            ApiError::from(AuthError::InsufficientScope("chat:write".into())).code,
+            ErrorCode::Forbidden
+        );
+        assert_eq!(
+            ApiError::from(RunError::InvalidTransition {
+                from: crate::run::RunStatus::Completed,
+                to: crate::run::RunStatus::Running,
+            })
+            .code,
+            ErrorCode::Conflict
+        );
+        let unknown = "Paused".parse::<crate::run::RunStatus>().unwrap_err();
+        assert!(matches!(&unknown, RunError::UnknownStatus(status) if status == "Paused"));
+        assert_eq!(ApiError::from(unknown).code, ErrorCode::BadRequest);
+
+        let actix: actix_web::Error = AppError::BadRequest("bad".into()).into();
+        assert_eq!(ApiError::from_actix(&actix).code, ErrorCode::BadRequest);
+        let actix = actix_web::error::ErrorNotFound("nope");
+        assert_eq!(ApiError::from_actix(&actix), ApiError::new(ErrorCode::NotFound, "nope"));
+    }
+}
@@ -1,6 +1,13 @@
 //! Module: Common
 //! Provides shared utilities, metrics, and configurations for the workspace.
 
+pub mod auth;
+pub mod cache;
+pub mod config;
+pub mod encryption;
+pub mod errors;
 pub mod metrics;
+pub mod middleware;
+pub mod run;
 
 pub use metrics::*;
@@ -0,0 +1,272 @@
+use actix_web::{
+    Error, FromRequest, HttpMessage, HttpRequest,
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+};
+use futures::Future;
+use futures::future::{Ready, ok, ready};
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tracing::debug;
+
+use crate::auth::{AuthError, Claims, JwtAuthenticator};
+
+/// Middleware that authenticates bearer tokens and enforces route requirements.
+///
+/// Validated claims are stored in the request extensions and can be read by handlers with
+/// the [`AuthenticatedUser`] extractor.
+///
+/// ```ignore
+/// let auth = Arc::new(JwtAuthenticator::from_config(&config.auth)?);
+/// App::new().service(
+///     web::scope("/admin")
+///         .wrap(JwtAuth::new(auth.clone()).require_scopes(&["models:write"]))
+///         .route("/reload", web::post().to(reload)),
+/// )
+/// ```
+#[derive(Clone)]
+pub struct JwtAuth {
+    authenticator: Arc<JwtAuthenticator>,
+    requirements: Requirements,
+}
+
+#[derive(Clone, Default)]
+struct Requirements {
+    scopes: Vec<String>,
+    roles: Vec<String>,
+}
+
+impl JwtAuth {
+    pub fn new(authenticator: Arc<JwtAuthenticator>) -> Self {
+        Self { authenticator, requirements: Requirements::default() }
+    }
+
+    /// Requires every one of `scopes` to be granted by the token.
+    pub fn require_scopes(mut self, scopes: &[&str]) -> Self {
+        self.requirements.scopes.extend(scopes.iter().map(|s| s.to_string()));
+        self
+    }
+
+    /// Requires the token to carry every one of `roles`.
+    pub fn require_roles(mut self, roles: &[&str]) -> Self {
+        self.requirements.roles.extend(roles.iter().map(|r| r.to_string()));
+        self
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for JwtAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = JwtAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(JwtAuthMiddleware {
+            service,
+            authenticator: self.authenticator.clone(),
+            requirements: self.requirements.clone(),
+        })
+    }
+}
+
+pub struct JwtAuthMiddleware<S> {
+    service: S,
+    authenticator: Arc<JwtAuthenticator>,
+    requirements: Requirements,
+}
+
+impl<S> JwtAuthMiddleware<S> {
+    fn authorize(&self, req: &ServiceRequest) -> Result<Claims, AuthError> {
+        let token = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(bearer_token)
+            .filter(|token| !token.is_empty())
+            .ok_or(AuthError::MissingToken)?;
+
+        let claims = self.authenticator.validate(token)?;
+
+        if let Some(scope) = claims.missing_scope(&self.requirements.scopes) {
+            return Err(AuthError::InsufficientScope(scope.to_string()));
+        }
+        if let Some(role) = self.requirements.roles.iter().find(|role| !claims.has_role(role)) {
+            return Err(AuthError::MissingRole(role.clone()));
+        }
+        Ok(claims)
+    }
+}
+
+/// Extracts the credentials of a `Bearer` authorization header; the scheme name is
+/// case-insensitive (RFC 7235).
+fn bearer_token(value: &str) -> Option<&str> {
+    let (scheme, token) = value.trim_start().split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+impl<S, B> Service<ServiceRequest> for JwtAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        match self.authorize(&req) {
+            Ok(claims) => {
+                req.extensions_mut().insert(claims);
+                Box::pin(self.service.call(req))
+            }
+            Err(err) => {
+                debug!("Rejected {} {}: {}", req.method(), req.path(), err);
+                Box::pin(async move { Err(err.into()) })
+            }
+        }
+    }
+}
+
+/// Extractor for the claims of a request that passed [`JwtAuth`].
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser(pub Claims);
+
+impl FromRequest for AuthenticatedUser {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Claims>()
+                .cloned()
+                .map(AuthenticatedUser)
+                .ok_or_else(|| AuthError::MissingToken.into()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AuthConfig, JwtAlgorithm};
+    use actix_web::{App, HttpResponse, http::StatusCode, test, web};
+
+    fn authenticator() -> Arc<JwtAuthenticator> {
+        let config = AuthConfig {
+            jwt_secret: "middleware-secret".to_string(),
+            token_expiration_hours: 1,
+            algorithm: JwtAlgorithm::HS256,
+            issuer: Some("tests".to_string()),
+            audience: None,
+            leeway_seconds: 0,
+            jwks_path: None,
+        };
+        Arc::new(JwtAuthenticator::from_config(&config).unwrap())
+    }
+
+    async fn whoami(user: AuthenticatedUser) -> HttpResponse {
+        HttpResponse::Ok().body(user.0.sub)
+    }
+
+    #[actix_web::test]
+    async fn test_requests_without_valid_token_are_rejected() {
+        let auth = authenticator();
+        let app = test::init_service(App::new().service(
+            web::scope("/api").wrap(JwtAuth::new(auth)).route("/me", web::get().to(whoami)),
+        ))
+        .await;
+
+        let req = test::TestRequest::get().uri("/api/me").to_request();
+        let resp = test::try_call_service(&app, req).await;
+        let err = resp.expect_err("missing token must be rejected");
+        assert_eq!(err.as_response_error().status_code(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get()
+            .uri("/api/me")
+            .insert_header((header::AUTHORIZATION, "Bearer not-a-jwt"))
+            .to_request();
+        let err = test::try_call_service(&app, req).await.err().unwrap();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_valid_token_reaches_handler() {
+        let auth = authenticator();
+        let token = auth.issue("user-7", &[], &["chat:read"]).unwrap();
+        let app = test::init_service(App::new().service(
+            web::scope("/api").wrap(JwtAuth::new(auth)).route("/me", web::get().to(whoami)),
+        ))
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/api/me")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(test::read_body(resp).await, "user-7");
+
+        let req = test::TestRequest::get()
+            .uri("/api/me")
+            .insert_header((header::AUTHORIZATION, format!("bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(test::read_body(resp).await, "user-7");
+    }
+
+    #[actix_web::test]
+    async fn test_route_scope_and_role_requirements() {
+        let auth = authenticator();
+        let reader = auth.issue("reader", &[], &["chat:read"]).unwrap();
+        let admin = auth.issue("admin", &["admin"], &["chat:read", "chat:write"]).unwrap();
+        let app = test::init_service(
+            App::new().service(
+                web::scope("/admin")
+                    .wrap(
+                        JwtAuth::new(auth)
+                            .require_scopes(&["chat:write"])
+                            .require_roles(&["admin"]),
+                    )
+                    .route("/me", web::get().to(whoami)),
+            ),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/admin/me")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", reader)))
+            .to_request();
+        let err = test::try_call_service(&app, req).await.err().unwrap();
+        let response = err.error_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(
+            response
+                .headers()
+                .get(header::WWW_AUTHENTICATE)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .contains("insufficient_scope")
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/admin/me")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+}
@@ -1,17 +1,26 @@
-
 use actix_web::{
+    Error, HttpResponse, ResponseError,
+    body::{BoxBody, EitherBody},
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
-    Error, HttpResponse,
+    http::{StatusCode, header, header::HeaderMap},
 };
-use futures::future::{ok, Ready};
 use futures::Future;
+use futures::future::{Ready, ok};
 use std::{
+    fmt,
     pin::Pin,
     task::{Context, Poll},
 };
-use tracing::{error, info};
+use tracing::debug;
+
+use crate::errors::{ApiError, CorrelationId, ProblemDetails, problem_response};
 
-/// Middleware for handling errors in Actix Web applications.
+/// Middleware that renders every error as an RFC 7807 problem+json response.
+///
+/// Both errors returned by inner services (e.g. authentication middleware) and error
+/// responses produced from handler errors are converted through [`ApiError`], so clients
+/// see the same body whatever failed. The original status and extra headers such as
+/// `WWW-Authenticate` are preserved.
 pub struct ErrorHandler;
 
 impl<S, B> Transform<S, ServiceRequest> for ErrorHandler
@@ -19,7 +28,7 @@ where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B, BoxBody>>;
     type Error = Error;
     type Transform = ErrorHandlerMiddleware<S>;
     type InitError = ();
@@ -39,7 +48,7 @@ where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B, BoxBody>>;
     type Error = Error;
     type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
 
@@ -48,32 +57,167 @@ where
     }
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
+        // The request cannot be cloned before routing, so keep what rendering needs.
+        let method = req.method().to_string();
+        let path = req.path().to_string();
+        let correlation_id = CorrelationId::from_request(req.request());
         let fut = self.service.call(req);
-        let method = req.method().clone();
-        let path = req.uri().path().to_string();
 
         Box::pin(async move {
             match fut.await {
-                Ok(res) => Ok(res),
+                Ok(res) => {
+                    let Some(err) = res.response().error() else {
+                        return Ok(res.map_into_left_body());
+                    };
+                    // Inner middleware may have assigned an ID after we looked.
+                    let correlation_id =
+                        CorrelationId::from_request(res.request()).or(correlation_id);
+                    let problem =
+                        RenderedProblem::new(err, res.response(), &method, &path, correlation_id);
+                    let (req, _) = res.into_parts();
+                    Ok(ServiceResponse::new(req, problem.error_response()).map_into_right_body())
+                }
                 Err(err) => {
-                    // Log error with additional request details
-                    error!("Error processing request {} {}: {}", method, path, err);
-
-                    // Create a custom JSON error response
-                    let response = HttpResponse::InternalServerError().json({
-                        json!({
-                            "status": 500,
-                            "error": "Internal Server Error",
-                            "message": err.to_string(),
-                            "method": method.to_string(),
-                            "path": path
-                        })
-                    });
-
-                    info!("Error response sent for {} {}", method, path);
-                    Ok(req.into_response(response.into_body()))
+                    let original = err.error_response();
+                    Err(RenderedProblem::new(&err, &original, &method, &path, correlation_id)
+                        .into())
                 }
             }
         })
     }
 }
+
+/// An error already converted to problem details, keeping the original status and headers.
+#[derive(Debug)]
+struct RenderedProblem {
+    status: StatusCode,
+    problem: ProblemDetails,
+    headers: HeaderMap,
+}
+
+impl RenderedProblem {
+    fn new<B>(
+        err: &Error,
+        original: &HttpResponse<B>,
+        method: &str,
+        path: &str,
+        correlation_id: Option<String>,
+    ) -> Self {
+        // Server errors were already logged when `err` rendered its own response.
+        let status = original.status();
+        debug!("Request {} {} failed with {}: {}", method, path, status, err);
+
+        let mut problem = ApiError::from_actix(err).to_problem(Some(path));
+        problem.status = status.as_u16();
+        if problem.correlation_id.is_none() {
+            problem.correlation_id = correlation_id;
+        }
+
+        let mut headers = original.headers().clone();
+        headers.remove(header::CONTENT_TYPE);
+        headers.remove(header::CONTENT_LENGTH);
+        Self { status, problem, headers }
+    }
+}
+
+impl fmt::Display for RenderedProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.problem.code, self.problem.detail)
+    }
+}
+
+impl ResponseError for RenderedProblem {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = problem_response(self.status, &self.problem);
+        for (name, value) in &self.headers {
+            response.headers_mut().insert(name.clone(), value.clone());
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::JwtAuthenticator;
+    use crate::config::{AuthConfig, JwtAlgorithm};
+    use crate::errors::{CORRELATION_ID_HEADER, ErrorCode, PROBLEM_JSON};
+    use crate::middleware::auth::JwtAuth;
+    use actix_web::{App, test, web};
+    use std::sync::Arc;
+
+    async fn failing() -> Result<HttpResponse, ApiError> {
+        Err(ApiError::bad_request("prompt is empty").with_field("prompt", "must not be empty"))
+    }
+
+    async fn legacy() -> Result<HttpResponse, Error> {
+        Err(actix_web::error::ErrorServiceUnavailable("model warming up"))
+    }
+
+    #[actix_web::test]
+    async fn test_handler_errors_become_problem_json() {
+        let app = test::init_service(
+            App::new()
+                .wrap(ErrorHandler)
+                .route("/fail", web::get().to(failing))
+                .route("/legacy", web::get().to(legacy))
+                .route("/ok", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/fail")
+            .insert_header((CORRELATION_ID_HEADER, "corr-9"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), PROBLEM_JSON);
+        let problem: ProblemDetails = test::read_body_json(resp).await;
+        assert_eq!(problem.code, ErrorCode::BadRequest);
+        assert_eq!(problem.instance.as_deref(), Some("/fail"));
+        assert_eq!(problem.correlation_id.as_deref(), Some("corr-9"));
+        assert_eq!(problem.errors.len(), 1);
+
+        let req = test::TestRequest::get().uri("/legacy").to_request();
+        let problem: ProblemDetails = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(problem.code, ErrorCode::ServiceUnavailable);
+        assert_eq!(problem.status, 503);
+        assert_eq!(problem.detail, "Service unavailable");
+
+        let req = test::TestRequest::get().uri("/ok").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_middleware_errors_keep_status_and_headers() {
+        let config = AuthConfig {
+            jwt_secret: "error-handler-secret".to_string(),
+            token_expiration_hours: 1,
+            algorithm: JwtAlgorithm::HS256,
+            issuer: None,
+            audience: None,
+            leeway_seconds: 0,
+            jwks_path: None,
+        };
+        let auth = Arc::new(JwtAuthenticator::from_config(&config).unwrap());
+        let app = test::init_service(
+            App::new().wrap(ErrorHandler).service(
+                web::scope("/api")
+                    .wrap(JwtAuth::new(auth))
+                    .route("/me", web::get().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/api/me").to_request();
+        let err = test::try_call_service(&app, req).await.expect_err("token is missing");
+        let response = err.error_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers().get(header::CONTENT_TYPE).unwrap(), PROBLEM_JSON);
+        assert_eq!(response.headers().get(header::WWW_AUTHENTICATE).unwrap(), "Bearer");
+    }
+}
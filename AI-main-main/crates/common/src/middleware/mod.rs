@@ -1,2 +1,2 @@
+pub mod auth;
 pub mod error_handler;
-pub mod request_logger;
@@ -0,0 +1,163 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{InMemoryRunStore, RunError, RunEvent, RunMetadata, RunQuery, RunStore};
+
+/// One line of the journal: the run as it was after `event` was applied.
+#[derive(Serialize, Deserialize)]
+struct JournalRecord {
+    run: RunMetadata,
+    event: RunEvent,
+}
+
+/// Stores runs in an append-only JSON-lines journal.
+///
+/// The journal is replayed into memory on open, so reads never touch the disk. A partially
+/// written last line, as left by a crash mid-append, is dropped when the journal is opened.
+pub struct FileRunStore {
+    path: PathBuf,
+    journal: Mutex<File>,
+    index: InMemoryRunStore,
+}
+
+impl FileRunStore {
+    /// Opens the journal at `path`, creating it if it does not exist yet.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RunError> {
+        let path = path.as_ref().to_path_buf();
+        let mut journal = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(|e| storage_error(&path, e))?;
+
+        let mut contents = String::new();
+        journal.read_to_string(&mut contents).map_err(|e| storage_error(&path, e))?;
+
+        let complete_len = contents.rfind('\n').map_or(0, |i| i + 1);
+        if complete_len < contents.len() {
+            warn!("Dropping incomplete trailing record from run journal {}", path.display());
+            journal.set_len(complete_len as u64).map_err(|e| storage_error(&path, e))?;
+        }
+
+        let index = InMemoryRunStore::new();
+        for (line_no, line) in contents[..complete_len].lines().enumerate() {
+            let record: JournalRecord = serde_json::from_str(line).map_err(|e| {
+                RunError::Storage(format!("{}:{}: {}", path.display(), line_no + 1, e))
+            })?;
+            if record.event.from.is_none() {
+                index.insert(&record.run, &record.event)?;
+            } else {
+                index.update(&record.run, &record.event)?;
+            }
+        }
+
+        Ok(Self { path, journal: Mutex::new(journal), index })
+    }
+
+    fn append(&self, run: &RunMetadata, event: &RunEvent) -> Result<(), RunError> {
+        let mut line =
+            serde_json::to_string(&JournalRecord { run: run.clone(), event: event.clone() })
+                .map_err(|e| RunError::Storage(e.to_string()))?;
+        line.push('\n');
+
+        let mut journal = self.journal.lock().unwrap();
+        journal
+            .write_all(line.as_bytes())
+            .and_then(|_| journal.sync_data())
+            .map_err(|e| storage_error(&self.path, e))
+    }
+}
+
+fn storage_error(path: &Path, err: std::io::Error) -> RunError {
+    RunError::Storage(format!("{}: {}", path.display(), err))
+}
+
+impl RunStore for FileRunStore {
+    fn insert(&self, run: &RunMetadata, event: &RunEvent) -> Result<(), RunError> {
+        if self.index.get(run.id)?.is_some() {
+            return Err(RunError::Storage(format!("Run {} already exists", run.id)));
+        }
+        self.append(run, event)?;
+        self.index.insert(run, event)
+    }
+
+    fn update(&self, run: &RunMetadata, event: &RunEvent) -> Result<(), RunError> {
+        if self.index.get(run.id)?.is_none() {
+            return Err(RunError::NotFound(run.id));
+        }
+        self.append(run, event)?;
+        self.index.update(run, event)
+    }
+
+    fn get(&self, run_id: Uuid) -> Result<Option<RunMetadata>, RunError> {
+        self.index.get(run_id)
+    }
+
+    fn events(&self, run_id: Uuid) -> Result<Vec<RunEvent>, RunError> {
+        self.index.events(run_id)
+    }
+
+    fn list(&self, query: &RunQuery) -> Result<Vec<RunMetadata>, RunError> {
+        self.index.list(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::{RunManager, RunStatus};
+    use std::sync::Arc;
+
+    fn journal_path() -> PathBuf {
+        std::env::temp_dir().join(format!("runs-{}.jsonl", Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_history_survives_reopen() {
+        let path = journal_path();
+        let run_id = {
+            let manager = RunManager::with_store(Arc::new(FileRunStore::open(&path).unwrap()));
+            let run = manager.create_run(Some("alice"), None).unwrap();
+            manager.transition(run.id, RunStatus::Running, "worker-1", None).unwrap();
+            run.id
+        };
+
+        let manager = RunManager::with_store(Arc::new(FileRunStore::open(&path).unwrap()));
+        let run = manager.get_run(run_id).unwrap().unwrap();
+        assert_eq!(run.status, RunStatus::Running);
+        assert_eq!(run.user_id.as_deref(), Some("alice"));
+        assert_eq!(manager.events(run_id).unwrap().len(), 2);
+
+        manager.update_run_status(run_id, RunStatus::Completed).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_incomplete_trailing_record_is_dropped() {
+        let path = journal_path();
+        let run_id = {
+            let manager = RunManager::with_store(Arc::new(FileRunStore::open(&path).unwrap()));
+            manager.add_run().unwrap()
+        };
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"run\":{\"id\":")
+            .unwrap();
+
+        let manager = RunManager::with_store(Arc::new(FileRunStore::open(&path).unwrap()));
+        manager.update_run_status(run_id, RunStatus::Running).unwrap();
+
+        let manager = RunManager::with_store(Arc::new(FileRunStore::open(&path).unwrap()));
+        assert_eq!(manager.get_run(run_id).unwrap().unwrap().status, RunStatus::Running);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
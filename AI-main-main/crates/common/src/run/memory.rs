@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use uuid::Uuid;
+
+use super::{RunError, RunEvent, RunMetadata, RunQuery, RunStore};
+
+struct StoredRun {
+    run: RunMetadata,
+    events: Vec<RunEvent>,
+}
+
+/// Keeps runs in process memory; everything is lost on restart.
+#[derive(Default)]
+pub struct InMemoryRunStore {
+    runs: Mutex<HashMap<Uuid, StoredRun>>,
+}
+
+impl InMemoryRunStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RunStore for InMemoryRunStore {
+    fn insert(&self, run: &RunMetadata, event: &RunEvent) -> Result<(), RunError> {
+        let mut runs = self.runs.lock().unwrap();
+        if runs.contains_key(&run.id) {
+            return Err(RunError::Storage(format!("Run {} already exists", run.id)));
+        }
+        runs.insert(run.id, StoredRun { run: run.clone(), events: vec![event.clone()] });
+        Ok(())
+    }
+
+    fn update(&self, run: &RunMetadata, event: &RunEvent) -> Result<(), RunError> {
+        let mut runs = self.runs.lock().unwrap();
+        let stored = runs.get_mut(&run.id).ok_or(RunError::NotFound(run.id))?;
+        stored.run = run.clone();
+        stored.events.push(event.clone());
+        Ok(())
+    }
+
+    fn get(&self, run_id: Uuid) -> Result<Option<RunMetadata>, RunError> {
+        Ok(self.runs.lock().unwrap().get(&run_id).map(|stored| stored.run.clone()))
+    }
+
+    fn events(&self, run_id: Uuid) -> Result<Vec<RunEvent>, RunError> {
+        Ok(self
+            .runs
+            .lock()
+            .unwrap()
+            .get(&run_id)
+            .map(|stored| stored.events.clone())
+            .unwrap_or_default())
+    }
+
+    fn list(&self, query: &RunQuery) -> Result<Vec<RunMetadata>, RunError> {
+        let runs = self.runs.lock().unwrap();
+        let mut matching: Vec<RunMetadata> = runs
+            .values()
+            .filter(|stored| query.matches(&stored.run))
+            .map(|stored| stored.run.clone())
+            .collect();
+        matching.sort_by_key(|run| (run.created_at, run.id));
+        Ok(matching)
+    }
+}
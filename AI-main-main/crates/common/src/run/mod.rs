@@ -0,0 +1,502 @@
+//! Run lifecycle tracking.
+//!
+//! A run moves through the states of [`RunStatus`] along the edges allowed by
+//! [`RunStatus::can_transition_to`]. Every change is recorded as a [`RunEvent`] in an
+//! append-only log, and runs plus their history are kept in a pluggable [`RunStore`] so they
+//! survive restarts when a persistent backend is used.
+
+mod file;
+mod memory;
+mod sqlite;
+
+pub use file::FileRunStore;
+pub use memory::InMemoryRunStore;
+pub use sqlite::SqliteRunStore;
+
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Actor recorded for changes made by the manager itself, such as expiry.
+pub const SYSTEM_ACTOR: &str = "system";
+
+/// Represents the various statuses a run can have.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Eq, Copy, Default, Hash)]
+pub enum RunStatus {
+    #[default]
+    NotStarted,
+    Running,
+    Completed,
+    Failed,
+    Expired,
+    RequiredAction,
+}
+
+impl RunStatus {
+    /// Returns `true` if no further transitions are allowed out of this status.
+    pub fn is_terminal(self) -> bool {
+        matches!(self, RunStatus::Completed | RunStatus::Failed | RunStatus::Expired)
+    }
+
+    /// Returns `true` if the transition table allows moving from `self` to `next`.
+    pub fn can_transition_to(self, next: RunStatus) -> bool {
+        use RunStatus::*;
+
+        matches!(
+            (self, next),
+            (NotStarted, Running | Failed | Expired)
+                | (Running, Completed | Failed | Expired | RequiredAction)
+                | (RequiredAction, Running | Failed | Expired)
+        )
+    }
+}
+
+impl fmt::Display for RunStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for RunStatus {
+    type Err = RunError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "NotStarted" => Ok(RunStatus::NotStarted),
+            "Running" => Ok(RunStatus::Running),
+            "Completed" => Ok(RunStatus::Completed),
+            "Failed" => Ok(RunStatus::Failed),
+            "Expired" => Ok(RunStatus::Expired),
+            "RequiredAction" => Ok(RunStatus::RequiredAction),
+            other => Err(RunError::UnknownStatus(other.to_string())),
+        }
+    }
+}
+
+/// Errors raised by [`RunManager`] and the run stores.
+#[derive(Debug, Error)]
+pub enum RunError {
+    #[error("Run with ID {0} not found.")]
+    NotFound(Uuid),
+    #[error("Run cannot move from {from} to {to}.")]
+    InvalidTransition { from: RunStatus, to: RunStatus },
+    #[error("Unknown run status '{0}'.")]
+    UnknownStatus(String),
+    #[error("Run storage error: {0}")]
+    Storage(String),
+}
+
+/// Represents metadata for a specific run instance.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RunMetadata {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub status: RunStatus,
+    /// User that owns the run, if any.
+    #[serde(default)]
+    pub user_id: Option<String>,
+    /// Deadline after which a run that has not finished is moved to [`RunStatus::Expired`].
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl RunMetadata {
+    /// Creates a new RunMetadata instance with default values.
+    pub fn new() -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4(),
+            created_at: now,
+            updated_at: now,
+            status: RunStatus::NotStarted,
+            user_id: None,
+            expires_at: None,
+        }
+    }
+
+    /// Returns `true` if the run is unfinished and its deadline is at or before `now`.
+    pub fn is_overdue(&self, now: DateTime<Utc>) -> bool {
+        !self.status.is_terminal() && self.expires_at.is_some_and(|deadline| deadline <= now)
+    }
+
+    /// Updates the status of the run and modifies the timestamp.
+    ///
+    /// This does not consult the transition table; [`RunManager`] checks it before calling.
+    pub fn update_status(&mut self, new_status: RunStatus) {
+        self.status = new_status;
+        self.updated_at = Utc::now();
+    }
+}
+
+impl Default for RunMetadata {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One entry of a run's append-only history.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RunEvent {
+    pub run_id: Uuid,
+    /// Status before the change, or `None` for the event that created the run.
+    pub from: Option<RunStatus>,
+    pub to: RunStatus,
+    /// Who made the change: a user id, a service name or [`SYSTEM_ACTOR`].
+    pub actor: String,
+    pub reason: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
+/// Filter for [`RunStore::list`]. Unset fields match every run.
+#[derive(Debug, Clone, Default)]
+pub struct RunQuery {
+    pub status: Option<RunStatus>,
+    pub user_id: Option<String>,
+    /// Only runs whose deadline is at or before this instant.
+    pub expires_before: Option<DateTime<Utc>>,
+}
+
+impl RunQuery {
+    pub fn with_status(mut self, status: RunStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    pub fn for_user(mut self, user_id: impl Into<String>) -> Self {
+        self.user_id = Some(user_id.into());
+        self
+    }
+
+    /// Returns `true` if `run` satisfies every set field of the query.
+    pub fn matches(&self, run: &RunMetadata) -> bool {
+        self.status.is_none_or(|status| run.status == status)
+            && self.user_id.as_ref().is_none_or(|user| run.user_id.as_ref() == Some(user))
+            && self
+                .expires_before
+                .is_none_or(|before| run.expires_at.is_some_and(|deadline| deadline <= before))
+    }
+}
+
+/// Storage backend for runs and their event history.
+///
+/// `insert` and `update` must persist the run snapshot and its event together, so the log
+/// never disagrees with the stored status. Listing returns runs ordered by creation time.
+pub trait RunStore: Send + Sync {
+    fn insert(&self, run: &RunMetadata, event: &RunEvent) -> Result<(), RunError>;
+    fn update(&self, run: &RunMetadata, event: &RunEvent) -> Result<(), RunError>;
+    fn get(&self, run_id: Uuid) -> Result<Option<RunMetadata>, RunError>;
+    fn events(&self, run_id: Uuid) -> Result<Vec<RunEvent>, RunError>;
+    fn list(&self, query: &RunQuery) -> Result<Vec<RunMetadata>, RunError>;
+}
+
+/// Manages the state of multiple runs in a thread-safe way.
+pub struct RunManager {
+    store: Arc<dyn RunStore>,
+    // Held across read-check-write so concurrent transitions of a run cannot interleave.
+    write_lock: Mutex<()>,
+}
+
+impl RunManager {
+    /// Creates a new RunManager instance backed by memory only.
+    pub fn new() -> Self {
+        Self::with_store(Arc::new(InMemoryRunStore::new()))
+    }
+
+    /// Creates a manager that keeps runs in `store`.
+    pub fn with_store(store: Arc<dyn RunStore>) -> Self {
+        Self { store, write_lock: Mutex::new(()) }
+    }
+
+    /// Adds a new run without owner or deadline to the manager.
+    pub fn add_run(&self) -> Result<Uuid, RunError> {
+        self.create_run(None, None).map(|run| run.id)
+    }
+
+    /// Creates a run owned by `user_id` that expires `ttl` from now if it has not finished.
+    pub fn create_run(
+        &self,
+        user_id: Option<&str>,
+        ttl: Option<Duration>,
+    ) -> Result<RunMetadata, RunError> {
+        let mut run = RunMetadata::new();
+        run.user_id = user_id.map(str::to_owned);
+        run.expires_at = ttl
+            .and_then(|ttl| chrono::Duration::from_std(ttl).ok())
+            .and_then(|ttl| run.created_at.checked_add_signed(ttl));
+
+        let event = RunEvent {
+            run_id: run.id,
+            from: None,
+            to: run.status,
+            actor: user_id.unwrap_or(SYSTEM_ACTOR).to_string(),
+            reason: None,
+            at: run.created_at,
+        };
+
+        let _guard = self.write_lock.lock().unwrap();
+        self.store.insert(&run, &event)?;
+        Ok(run)
+    }
+
+    /// Moves a run to `status` on behalf of `actor`, recording the change in its history.
+    ///
+    /// Fails with [`RunError::InvalidTransition`] if the transition table forbids the move.
+    pub fn transition(
+        &self,
+        run_id: Uuid,
+        status: RunStatus,
+        actor: &str,
+        reason: Option<&str>,
+    ) -> Result<RunMetadata, RunError> {
+        let _guard = self.write_lock.lock().unwrap();
+        let mut run = self.store.get(run_id)?.ok_or(RunError::NotFound(run_id))?;
+        let from = run.status;
+        if !from.can_transition_to(status) {
+            return Err(RunError::InvalidTransition { from, to: status });
+        }
+
+        run.update_status(status);
+        let event = RunEvent {
+            run_id,
+            from: Some(from),
+            to: status,
+            actor: actor.to_string(),
+            reason: reason.map(str::to_owned),
+            at: run.updated_at,
+        };
+        self.store.update(&run, &event)?;
+        info!("Run {} moved from {} to {} by {}", run_id, from, status, actor);
+        Ok(run)
+    }
+
+    /// Updates the status of a run by ID on behalf of the system.
+    pub fn update_run_status(&self, run_id: Uuid, status: RunStatus) -> Result<(), RunError> {
+        self.transition(run_id, status, SYSTEM_ACTOR, None).map(|_| ())
+    }
+
+    /// Fetches metadata for a specific run by ID.
+    pub fn get_run(&self, run_id: Uuid) -> Result<Option<RunMetadata>, RunError> {
+        self.store.get(run_id)
+    }
+
+    /// Returns the history of a run, oldest event first.
+    pub fn events(&self, run_id: Uuid) -> Result<Vec<RunEvent>, RunError> {
+        if self.store.get(run_id)?.is_none() {
+            return Err(RunError::NotFound(run_id));
+        }
+        self.store.events(run_id)
+    }
+
+    /// Lists runs matching `query`, oldest first.
+    pub fn list_runs(&self, query: &RunQuery) -> Result<Vec<RunMetadata>, RunError> {
+        self.store.list(query)
+    }
+
+    /// Expires every unfinished run whose deadline has passed and returns their IDs.
+    pub fn sweep_expired(&self) -> Result<Vec<Uuid>, RunError> {
+        self.sweep_expired_at(Utc::now())
+    }
+
+    fn sweep_expired_at(&self, now: DateTime<Utc>) -> Result<Vec<Uuid>, RunError> {
+        let query = RunQuery { expires_before: Some(now), ..RunQuery::default() };
+        let mut expired = Vec::new();
+        for run in self.store.list(&query)?.into_iter().filter(|run| run.is_overdue(now)) {
+            match self.transition(run.id, RunStatus::Expired, SYSTEM_ACTOR, Some("deadline passed"))
+            {
+                Ok(_) => expired.push(run.id),
+                // The run finished between listing and expiring it.
+                Err(RunError::InvalidTransition { .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(expired)
+    }
+
+    /// Spawns a task that calls [`RunManager::sweep_expired`] every `interval`.
+    ///
+    /// The task only holds a weak reference and stops once the manager is dropped.
+    pub fn spawn_expiry_sweeper(
+        self: &Arc<Self>,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let manager: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                match manager.sweep_expired() {
+                    Ok(ids) if !ids.is_empty() => info!("Expired {} overdue runs", ids.len()),
+                    Ok(_) => {}
+                    Err(e) => error!("Run expiry sweep failed: {}", e),
+                }
+            }
+        })
+    }
+}
+
+impl Default for RunManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_metadata_creation() {
+        let metadata = RunMetadata::new();
+        assert_eq!(metadata.status, RunStatus::NotStarted);
+    }
+
+    #[test]
+    fn test_run_status_update() {
+        let mut metadata = RunMetadata::new();
+        metadata.update_status(RunStatus::Running);
+        assert_eq!(metadata.status, RunStatus::Running);
+    }
+
+    #[test]
+    fn test_run_manager_add_run() {
+        let manager = RunManager::new();
+        let run_id = manager.add_run().unwrap();
+        assert!(manager.get_run(run_id).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_run_manager_update_status() {
+        let manager = RunManager::new();
+        let run_id = manager.add_run().unwrap();
+        assert!(manager.update_run_status(run_id, RunStatus::Running).is_ok());
+        assert!(manager.update_run_status(run_id, RunStatus::Completed).is_ok());
+        assert_eq!(manager.get_run(run_id).unwrap().unwrap().status, RunStatus::Completed);
+    }
+
+    #[test]
+    fn test_run_manager_get_run() {
+        let manager = RunManager::new();
+        let run_id = manager.add_run().unwrap();
+        let run = manager.get_run(run_id).unwrap();
+        assert!(run.is_some());
+        assert!(manager.get_run(Uuid::new_v4()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_transition_table_is_enforced() {
+        let manager = RunManager::new();
+        let run_id = manager.add_run().unwrap();
+
+        let err = manager.update_run_status(run_id, RunStatus::Completed).unwrap_err();
+        assert!(matches!(
+            err,
+            RunError::InvalidTransition { from: RunStatus::NotStarted, to: RunStatus::Completed }
+        ));
+
+        manager.update_run_status(run_id, RunStatus::Running).unwrap();
+        manager.update_run_status(run_id, RunStatus::RequiredAction).unwrap();
+        manager.update_run_status(run_id, RunStatus::Running).unwrap();
+        manager.update_run_status(run_id, RunStatus::Failed).unwrap();
+
+        for next in [RunStatus::Running, RunStatus::Completed, RunStatus::NotStarted] {
+            assert!(manager.update_run_status(run_id, next).is_err());
+        }
+        assert!(matches!(
+            manager.update_run_status(Uuid::new_v4(), RunStatus::Running),
+            Err(RunError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_event_log_records_actor_and_reason() {
+        let manager = RunManager::new();
+        let run = manager.create_run(Some("alice"), None).unwrap();
+        manager.transition(run.id, RunStatus::Running, "scheduler", None).unwrap();
+        manager
+            .transition(run.id, RunStatus::RequiredAction, "reviewer", Some("needs approval"))
+            .unwrap();
+
+        let events = manager.events(run.id).unwrap();
+        let summary: Vec<_> =
+            events.iter().map(|e| (e.from, e.to, e.actor.as_str(), e.reason.as_deref())).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (None, RunStatus::NotStarted, "alice", None),
+                (Some(RunStatus::NotStarted), RunStatus::Running, "scheduler", None),
+                (
+                    Some(RunStatus::Running),
+                    RunStatus::RequiredAction,
+                    "reviewer",
+                    Some("needs approval")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sweep_expires_only_overdue_unfinished_runs() {
+        let manager = RunManager::new();
+        let overdue = manager.create_run(None, Some(Duration::from_secs(60))).unwrap();
+        let finished = manager.create_run(None, Some(Duration::from_secs(60))).unwrap();
+        let fresh = manager.create_run(None, Some(Duration::from_secs(3600))).unwrap();
+        let forever = manager.create_run(None, None).unwrap();
+        manager.update_run_status(finished.id, RunStatus::Running).unwrap();
+        manager.update_run_status(finished.id, RunStatus::Completed).unwrap();
+
+        let later = Utc::now() + chrono::Duration::minutes(5);
+        assert_eq!(manager.sweep_expired_at(later).unwrap(), vec![overdue.id]);
+
+        let status = |id| manager.get_run(id).unwrap().unwrap().status;
+        assert_eq!(status(overdue.id), RunStatus::Expired);
+        assert_eq!(status(finished.id), RunStatus::Completed);
+        assert_eq!(status(fresh.id), RunStatus::NotStarted);
+        assert_eq!(status(forever.id), RunStatus::NotStarted);
+
+        let last = manager.events(overdue.id).unwrap().pop().unwrap();
+        assert_eq!(last.actor, SYSTEM_ACTOR);
+        assert!(manager.sweep_expired_at(later).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_list_runs_by_status_and_user() {
+        let manager = RunManager::new();
+        let a = manager.create_run(Some("alice"), None).unwrap();
+        let b = manager.create_run(Some("alice"), None).unwrap();
+        manager.create_run(Some("bob"), None).unwrap();
+        manager.update_run_status(b.id, RunStatus::Running).unwrap();
+
+        let ids = |query: RunQuery| -> Vec<Uuid> {
+            manager.list_runs(&query).unwrap().into_iter().map(|run| run.id).collect()
+        };
+        assert_eq!(ids(RunQuery::default().for_user("alice")), vec![a.id, b.id]);
+        assert_eq!(
+            ids(RunQuery::default().for_user("alice").with_status(RunStatus::Running)),
+            vec![b.id]
+        );
+        assert_eq!(ids(RunQuery::default().with_status(RunStatus::NotStarted)).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_expiry_sweeper_stops_with_manager() {
+        let manager = Arc::new(RunManager::new());
+        let run = manager.create_run(None, Some(Duration::from_millis(1))).unwrap();
+        let handle = manager.spawn_expiry_sweeper(Duration::from_millis(5));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(manager.get_run(run.id).unwrap().unwrap().status, RunStatus::Expired);
+
+        drop(manager);
+        tokio::time::timeout(Duration::from_secs(1), handle).await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,276 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+use rusqlite::{Connection, OptionalExtension, Row, params, params_from_iter};
+use uuid::Uuid;
+
+use super::{RunError, RunEvent, RunMetadata, RunQuery, RunStatus, RunStore};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id TEXT PRIMARY KEY,
+        user_id TEXT,
+        status TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL,
+        expires_at INTEGER
+    );
+    CREATE INDEX IF NOT EXISTS runs_status ON runs (status);
+    CREATE INDEX IF NOT EXISTS runs_user_id ON runs (user_id);
+    CREATE TABLE IF NOT EXISTS run_events (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        run_id TEXT NOT NULL REFERENCES runs (id),
+        from_status TEXT,
+        to_status TEXT NOT NULL,
+        actor TEXT NOT NULL,
+        reason TEXT,
+        at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS run_events_run_id ON run_events (run_id);
+";
+
+const RUN_COLUMNS: &str = "id, user_id, status, created_at, updated_at, expires_at";
+
+/// Stores runs and their history in a SQLite database.
+///
+/// Timestamps are kept as microseconds since the Unix epoch so deadlines compare numerically.
+pub struct SqliteRunStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteRunStore {
+    /// Opens or creates the database at `path` and ensures the schema exists.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RunError> {
+        Self::from_connection(Connection::open(path).map_err(storage_error)?)
+    }
+
+    /// Opens a private in-memory database, mainly useful for tests.
+    pub fn open_in_memory() -> Result<Self, RunError> {
+        Self::from_connection(Connection::open_in_memory().map_err(storage_error)?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, RunError> {
+        conn.execute_batch(SCHEMA).map_err(storage_error)?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+}
+
+fn storage_error(err: rusqlite::Error) -> RunError {
+    RunError::Storage(err.to_string())
+}
+
+fn to_micros(at: DateTime<Utc>) -> i64 {
+    at.timestamp_micros()
+}
+
+fn from_micros(micros: i64) -> rusqlite::Result<DateTime<Utc>> {
+    DateTime::from_timestamp_micros(micros)
+        .ok_or(rusqlite::Error::IntegralValueOutOfRange(0, micros))
+}
+
+fn parse_column<T: std::str::FromStr>(row: &Row<'_>, idx: usize) -> rusqlite::Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let text: String = row.get(idx)?;
+    text.parse().map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+fn run_from_row(row: &Row<'_>) -> rusqlite::Result<RunMetadata> {
+    Ok(RunMetadata {
+        id: parse_column(row, 0)?,
+        user_id: row.get(1)?,
+        status: parse_column(row, 2)?,
+        created_at: from_micros(row.get(3)?)?,
+        updated_at: from_micros(row.get(4)?)?,
+        expires_at: row.get::<_, Option<i64>>(5)?.map(from_micros).transpose()?,
+    })
+}
+
+fn insert_event(conn: &Connection, event: &RunEvent) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO run_events (run_id, from_status, to_status, actor, reason, at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            event.run_id.to_string(),
+            event.from.map(|status| status.to_string()),
+            event.to.to_string(),
+            event.actor,
+            event.reason,
+            to_micros(event.at),
+        ],
+    )?;
+    Ok(())
+}
+
+impl RunStore for SqliteRunStore {
+    fn insert(&self, run: &RunMetadata, event: &RunEvent) -> Result<(), RunError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(storage_error)?;
+        tx.execute(
+            &format!("INSERT INTO runs ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6)", RUN_COLUMNS),
+            params![
+                run.id.to_string(),
+                run.user_id,
+                run.status.to_string(),
+                to_micros(run.created_at),
+                to_micros(run.updated_at),
+                run.expires_at.map(to_micros),
+            ],
+        )
+        .map_err(storage_error)?;
+        insert_event(&tx, event).map_err(storage_error)?;
+        tx.commit().map_err(storage_error)
+    }
+
+    fn update(&self, run: &RunMetadata, event: &RunEvent) -> Result<(), RunError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(storage_error)?;
+        let changed = tx
+            .execute(
+                "UPDATE runs SET user_id = ?2, status = ?3, updated_at = ?4, expires_at = ?5
+                 WHERE id = ?1",
+                params![
+                    run.id.to_string(),
+                    run.user_id,
+                    run.status.to_string(),
+                    to_micros(run.updated_at),
+                    run.expires_at.map(to_micros),
+                ],
+            )
+            .map_err(storage_error)?;
+        if changed == 0 {
+            return Err(RunError::NotFound(run.id));
+        }
+        insert_event(&tx, event).map_err(storage_error)?;
+        tx.commit().map_err(storage_error)
+    }
+
+    fn get(&self, run_id: Uuid) -> Result<Option<RunMetadata>, RunError> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!("SELECT {} FROM runs WHERE id = ?1", RUN_COLUMNS),
+            params![run_id.to_string()],
+            run_from_row,
+        )
+        .optional()
+        .map_err(storage_error)
+    }
+
+    fn events(&self, run_id: Uuid) -> Result<Vec<RunEvent>, RunError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT from_status, to_status, actor, reason, at FROM run_events
+                 WHERE run_id = ?1 ORDER BY seq",
+            )
+            .map_err(storage_error)?;
+        let events = stmt
+            .query_map(params![run_id.to_string()], |row| {
+                let from: Option<String> = row.get(0)?;
+                Ok(RunEvent {
+                    run_id,
+                    from: from.map(|status| status.parse::<RunStatus>()).transpose().map_err(
+                        |e| {
+                            rusqlite::Error::FromSqlConversionFailure(
+                                0,
+                                rusqlite::types::Type::Text,
+                                Box::new(e),
+                            )
+                        },
+                    )?,
+                    to: parse_column(row, 1)?,
+                    actor: row.get(2)?,
+                    reason: row.get(3)?,
+                    at: from_micros(row.get(4)?)?,
+                })
+            })
+            .map_err(storage_error)?;
+        events.collect::<rusqlite::Result<_>>().map_err(storage_error)
+    }
+
+    fn list(&self, query: &RunQuery) -> Result<Vec<RunMetadata>, RunError> {
+        let mut filters = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+        if let Some(status) = query.status {
+            values.push(Value::Text(status.to_string()));
+            filters.push(format!("status = ?{}", values.len()));
+        }
+        if let Some(user_id) = &query.user_id {
+            values.push(Value::Text(user_id.clone()));
+            filters.push(format!("user_id = ?{}", values.len()));
+        }
+        if let Some(before) = query.expires_before {
+            values.push(Value::Integer(to_micros(before)));
+            filters.push(format!("expires_at <= ?{}", values.len()));
+        }
+
+        let mut sql = format!("SELECT {} FROM runs", RUN_COLUMNS);
+        if !filters.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&filters.join(" AND "));
+        }
+        sql.push_str(" ORDER BY created_at, id");
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&sql).map_err(storage_error)?;
+        let runs = stmt.query_map(params_from_iter(values), run_from_row).map_err(storage_error)?;
+        runs.collect::<rusqlite::Result<_>>().map_err(storage_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::RunManager;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_runs_and_events_persist_across_connections() {
+        let path = std::env::temp_dir().join(format!("runs-{}.sqlite", Uuid::new_v4()));
+        let run_id = {
+            let manager = RunManager::with_store(Arc::new(SqliteRunStore::open(&path).unwrap()));
+            let run = manager.create_run(Some("alice"), Some(Duration::from_secs(60))).unwrap();
+            manager.transition(run.id, RunStatus::Running, "worker-1", Some("picked up")).unwrap();
+            run.id
+        };
+
+        let manager = RunManager::with_store(Arc::new(SqliteRunStore::open(&path).unwrap()));
+        let run = manager.get_run(run_id).unwrap().unwrap();
+        assert_eq!(run.status, RunStatus::Running);
+        assert!(run.expires_at.is_some());
+
+        let events = manager.events(run_id).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].actor, "worker-1");
+        assert_eq!(events[1].reason.as_deref(), Some("picked up"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_list_filters_in_sql() {
+        let manager = RunManager::with_store(Arc::new(SqliteRunStore::open_in_memory().unwrap()));
+        let a = manager.create_run(Some("alice"), Some(Duration::from_secs(1))).unwrap();
+        let b = manager.create_run(Some("bob"), Some(Duration::from_secs(3600))).unwrap();
+        manager.update_run_status(b.id, RunStatus::Running).unwrap();
+
+        let running = manager.list_runs(&RunQuery::default().with_status(RunStatus::Running));
+        assert_eq!(running.unwrap(), vec![manager.get_run(b.id).unwrap().unwrap()]);
+
+        let alice = manager.list_runs(&RunQuery::default().for_user("alice")).unwrap();
+        assert_eq!(alice.len(), 1);
+        assert_eq!(alice[0].id, a.id);
+
+        let soon = RunQuery {
+            expires_before: Some(Utc::now() + chrono::Duration::minutes(1)),
+            ..RunQuery::default()
+        };
+        let expiring: Vec<Uuid> = manager.list_runs(&soon).unwrap().iter().map(|r| r.id).collect();
+        assert_eq!(expiring, vec![a.id]);
+    }
+}
@@ -1,14 +1,7 @@
-use actix_web::{HttpResponse, ResponseError};
-use chrono::{DateTime, Utc};
-use serde::Serialize;
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use common::errors::ApiError;
 use thiserror::Error;
 
-#[derive(Debug, Serialize)]
-pub struct ErrorResponse {
-    pub error: String,
-    pub timestamp: DateTime<Utc>,
-}
-
 #[derive(Debug, Error)]
 pub enum AppError {
     #[error("Database error: {0}")]
@@ -19,15 +12,27 @@ pub enum AppError {
     InvalidInput(String),
 }
 
+impl From<&AppError> for ApiError {
+    fn from(err: &AppError) -> Self {
+        match err {
+            AppError::InvalidInput(msg) => ApiError::bad_request(msg.clone()),
+            AppError::DatabaseError(_) | AppError::InternalError(_) => ApiError::internal(err),
+        }
+    }
+}
+
+impl From<AppError> for ApiError {
+    fn from(err: AppError) -> Self {
+        ApiError::from(&err)
+    }
+}
+
 impl ResponseError for AppError {
-    fn error_response(&self) -> HttpResponse {
-        let status_code = match self {
-            AppError::DatabaseError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::InternalError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::InvalidInput(_) => actix_web::http::StatusCode::BAD_REQUEST,
-        };
+    fn status_code(&self) -> StatusCode {
+        ApiError::from(self).status_code()
+    }
 
-        HttpResponse::build(status_code)
-            .json(ErrorResponse { error: self.to_string(), timestamp: Utc::now() })
+    fn error_response(&self) -> HttpResponse {
+        ApiError::from(self).error_response()
     }
 }
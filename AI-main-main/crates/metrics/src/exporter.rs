@@ -1,18 +1,61 @@
+use std::sync::{Arc, OnceLock};
+
+use actix_web::{HttpResponse, web};
+
 use crate::MetricsError;
+use crate::registry::MetricsRegistry;
+
+/// Namespace of the process-wide registry created by [`setup_exporter`].
+pub const DEFAULT_NAMESPACE: &str = "app";
+
+/// Content type of the Prometheus text exposition format.
+pub const TEXT_FORMAT: &str = "text/plain; version=0.0.4; charset=utf-8";
 
-/// Sets up the metrics exporter.
+static GLOBAL: OnceLock<Arc<MetricsRegistry>> = OnceLock::new();
+
+/// Sets up the process-wide registry under [`DEFAULT_NAMESPACE`] unless one is installed.
 ///
 /// # Errors
 ///
-/// Returns `MetricsError` if the exporter setup fails.
+/// Returns `MetricsError` if the registry cannot be created.
 pub fn setup_exporter() -> Result<(), MetricsError> {
-    // #TODO: Implement exporter setup logic, e.g., Prometheus, Grafana
-    println!("Setting up metrics exporter...");
+    if GLOBAL.get().is_none() {
+        // Losing a race to another caller is fine; either registry serves.
+        let _ = GLOBAL.set(Arc::new(MetricsRegistry::new(DEFAULT_NAMESPACE)?));
+    }
+    Ok(())
+}
 
-    // Example placeholder for exporter setup
-    // Replace with actual exporter initialization code
-    // For instance, using Prometheus:
-    // prometheus_exporter::initialize()?;
+/// Installs `registry` as the process-wide registry, typically namespaced after the service.
+///
+/// # Errors
+///
+/// Returns `MetricsError::InitializationError` if a registry is already installed.
+pub fn install(registry: MetricsRegistry) -> Result<Arc<MetricsRegistry>, MetricsError> {
+    let registry = Arc::new(registry);
+    GLOBAL.set(registry.clone()).map_err(|_| {
+        MetricsError::InitializationError("a metrics registry is already installed".to_string())
+    })?;
+    Ok(registry)
+}
 
-    Ok(())
+/// Returns the process-wide registry, if one was set up.
+pub fn global() -> Option<Arc<MetricsRegistry>> {
+    GLOBAL.get().cloned()
+}
+
+/// Serves every metric of the registry in `app_data` in the Prometheus text format.
+///
+/// ```ignore
+/// let registry = Arc::new(MetricsRegistry::new("chatbot")?);
+/// App::new()
+///     .app_data(web::Data::from(registry.clone()))
+///     .wrap(RequestMetrics::new(&registry)?)
+///     .route("/metrics", web::get().to(metrics_handler))
+/// ```
+pub async fn metrics_handler(registry: web::Data<MetricsRegistry>) -> HttpResponse {
+    match registry.render() {
+        Ok(body) => HttpResponse::Ok().content_type(TEXT_FORMAT).body(body),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
 }
@@ -1,5 +1,15 @@
+//! Module: Metrics
+//! Provides a namespaced Prometheus registry, request instrumentation middleware and a
+//! `/metrics` exposition handler shared by every service.
+
 pub mod exporter;
+pub mod middleware;
 pub mod recorder;
+pub mod registry;
+
+pub use exporter::metrics_handler;
+pub use middleware::RequestMetrics;
+pub use registry::{Counter, Gauge, Histogram, MetricsRegistry};
 
 /// Initializes the metrics system.
 ///
@@ -7,9 +17,7 @@ pub mod recorder;
 ///
 /// Returns `MetricsError` if the initialization fails.
 pub fn initialize_metrics() -> Result<(), MetricsError> {
-    exporter::setup_exporter()?;
-    println!("Metrics library initialized!");
-    Ok(())
+    exporter::setup_exporter()
 }
 
 /// Records a metric with the given name and value.
@@ -32,7 +40,8 @@ pub fn record_metric(name: &str, value: f64) -> Result<(), MetricsError> {
 pub enum MetricsError {
     InitializationError(String),
     RecordingError(String),
-    // #TODO add more error variants as needed.
+    RegistrationError(String),
+    ExportError(String),
 }
 
 impl std::fmt::Display for MetricsError {
@@ -40,6 +49,8 @@ impl std::fmt::Display for MetricsError {
         match self {
             MetricsError::InitializationError(msg) => write!(f, "Initialization Error: {}", msg),
             MetricsError::RecordingError(msg) => write!(f, "Recording Error: {}", msg),
+            MetricsError::RegistrationError(msg) => write!(f, "Registration Error: {}", msg),
+            MetricsError::ExportError(msg) => write!(f, "Export Error: {}", msg),
         }
     }
 }
@@ -0,0 +1,202 @@
+use actix_web::{
+    Error,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::Method,
+};
+use futures::Future;
+use futures::future::{Ready, ready};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use crate::MetricsError;
+use crate::registry::{Counter, Gauge, Histogram, MetricsRegistry};
+
+/// Route label of requests that matched no route, so unknown paths cannot add series.
+pub const UNMATCHED_ROUTE: &str = "<unmatched>";
+
+/// Middleware that records request count, latency and in-flight requests per route.
+///
+/// Requests are labelled with the route pattern (`/runs/{id}`) rather than the raw path,
+/// and with the method and final status code:
+///
+/// * `<ns>_http_requests_total{method, route, status}`
+/// * `<ns>_http_request_duration_seconds{method, route}`
+/// * `<ns>_http_requests_in_flight`
+#[derive(Clone)]
+pub struct RequestMetrics {
+    requests: Counter,
+    duration: Histogram,
+    in_flight: Gauge,
+}
+
+impl RequestMetrics {
+    pub fn new(registry: &MetricsRegistry) -> Result<Self, MetricsError> {
+        Ok(Self {
+            requests: registry.counter(
+                "http_requests_total",
+                "Total number of HTTP requests",
+                &["method", "route", "status"],
+            )?,
+            duration: registry.histogram(
+                "http_request_duration_seconds",
+                "HTTP request latency in seconds",
+                &["method", "route"],
+                None,
+            )?,
+            in_flight: registry.gauge(
+                "http_requests_in_flight",
+                "Number of HTTP requests being served",
+                &[],
+            )?,
+        })
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestMetricsMiddleware { service, metrics: self.clone() }))
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: S,
+    metrics: RequestMetrics,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let started = Instant::now();
+        let method = method_label(req.method());
+        let metrics = self.metrics.clone();
+        let in_flight = InFlight::start(&metrics.in_flight);
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let result = fut.await;
+            drop(in_flight);
+            // The route is only known once routing has run, i.e. after the inner service.
+            let (route, status) = match &result {
+                Ok(res) => (
+                    res.request().match_pattern().unwrap_or_else(|| UNMATCHED_ROUTE.to_string()),
+                    res.status(),
+                ),
+                Err(err) => (UNMATCHED_ROUTE.to_string(), err.as_response_error().status_code()),
+            };
+            metrics.requests.inc(&[method, &route, status.as_str()]);
+            metrics.duration.observe(&[method, &route], started.elapsed().as_secs_f64());
+            result
+        })
+    }
+}
+
+/// Counts a request as in flight until dropped, so cancelled requests are released too.
+struct InFlight(Gauge);
+
+impl InFlight {
+    fn start(gauge: &Gauge) -> Self {
+        gauge.inc(&[]);
+        Self(gauge.clone())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec(&[]);
+    }
+}
+
+/// Folds non-standard methods into one label value.
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::PATCH => "PATCH",
+        Method::DELETE => "DELETE",
+        Method::HEAD => "HEAD",
+        Method::OPTIONS => "OPTIONS",
+        _ => "OTHER",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exporter::{TEXT_FORMAT, metrics_handler};
+    use actix_web::{App, HttpResponse, http::header, test, web};
+    use std::sync::Arc;
+
+    #[actix_web::test]
+    async fn test_requests_are_recorded_per_route_and_exposed() {
+        let registry = Arc::new(MetricsRegistry::new("svc").unwrap());
+        let metrics = RequestMetrics::new(&registry).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(registry.clone()))
+                .wrap(metrics.clone())
+                .route("/runs/{id}", web::get().to(HttpResponse::Ok))
+                .route("/metrics", web::get().to(metrics_handler)),
+        )
+        .await;
+
+        for uri in ["/runs/1", "/runs/2", "/missing"] {
+            test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        }
+
+        assert_eq!(metrics.requests.get(&["GET", "/runs/{id}", "200"]), 2.0);
+        assert_eq!(metrics.requests.get(&["GET", UNMATCHED_ROUTE, "404"]), 1.0);
+        assert_eq!(metrics.duration.sample_count(&["GET", "/runs/{id}"]), 2);
+        assert_eq!(metrics.in_flight.get(&[]), 0.0);
+
+        let resp =
+            test::call_service(&app, test::TestRequest::get().uri("/metrics").to_request()).await;
+        assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), TEXT_FORMAT);
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains(
+            "svc_http_requests_total{method=\"GET\",route=\"/runs/{id}\",status=\"200\"} 2"
+        ));
+        assert!(!body.contains("/runs/1"));
+    }
+
+    #[actix_web::test]
+    async fn test_dropped_requests_leave_in_flight() {
+        let registry = MetricsRegistry::new("svc").unwrap();
+        let metrics = RequestMetrics::new(&registry).unwrap();
+        let app = test::init_service(
+            App::new()
+                .wrap(metrics.clone())
+                .route("/hang", web::get().to(futures::future::pending::<HttpResponse>)),
+        )
+        .await;
+
+        let pending = app.call(test::TestRequest::get().uri("/hang").to_request());
+        assert_eq!(metrics.in_flight.get(&[]), 1.0);
+        drop(pending);
+        assert_eq!(metrics.in_flight.get(&[]), 0.0);
+    }
+}
@@ -1,24 +1,24 @@
 use crate::MetricsError;
+use crate::exporter;
 
-/// Records a specific metric.
+/// Records a specific metric as an unlabelled gauge in the process-wide registry.
 ///
 /// # Arguments
 ///
-/// * `name` - The name of the metric.
+/// * `name` - The name of the metric, without the registry namespace.
 /// * `value` - The value of the metric.
 ///
 /// # Errors
 ///
-/// Returns `MetricsError` if recording the metric fails.
+/// Returns `MetricsError` if no registry is set up or `name` is already used by a metric of
+/// another kind.
 pub fn record(name: &str, value: f64) -> Result<(), MetricsError> {
-    // #TODO: Implement metric recording logic.
-    println!("Recording metric: {} = {}", name, value);
-
-    // Example placeholder for recording logic
-    // Replace with actual metric recording code
-    // For instance, using Prometheus:
-    // let metric = prometheus::Counter::new(name, "metric description")?;
-    // metric.inc_by(value);
-
+    let registry = exporter::global().ok_or_else(|| {
+        MetricsError::RecordingError("metrics have not been initialized".to_string())
+    })?;
+    registry
+        .gauge(name, name, &[])
+        .map_err(|e| MetricsError::RecordingError(e.to_string()))?
+        .set(&[], value);
     Ok(())
 }
@@ -0,0 +1,377 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use prometheus::core::Collector;
+use prometheus::{
+    CounterVec, Encoder, GaugeVec, HistogramOpts, HistogramVec, Opts, Registry, TextEncoder, proto,
+};
+
+use crate::MetricsError;
+
+/// Default number of distinct label combinations a single metric may track.
+pub const DEFAULT_MAX_LABEL_SETS: usize = 1000;
+
+/// Label value that replaces every label once a metric reaches its cardinality limit.
+pub const OVERFLOW_LABEL_VALUE: &str = "__overflow__";
+
+/// A Prometheus registry whose metrics all share a namespace prefix.
+///
+/// Metrics are created lazily and looked up by name, so asking for the same counter twice
+/// returns the same handle instead of failing the way the global prometheus registry does.
+/// Each metric tracks at most `max_label_sets` label combinations; further combinations are
+/// folded into a single series labelled [`OVERFLOW_LABEL_VALUE`] so an unbounded label such
+/// as a user ID cannot exhaust memory.
+///
+/// ```ignore
+/// let registry = MetricsRegistry::new("chatbot")?;
+/// let replies = registry.counter("replies_total", "Replies sent", &["model"])?;
+/// replies.inc(&["gpt-4"]);
+/// ```
+pub struct MetricsRegistry {
+    namespace: String,
+    registry: Registry,
+    max_label_sets: usize,
+    families: Mutex<HashMap<String, Family>>,
+}
+
+#[derive(Clone)]
+enum Family {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+impl Family {
+    fn kind(&self) -> &'static str {
+        match self {
+            Family::Counter(_) => "counter",
+            Family::Gauge(_) => "gauge",
+            Family::Histogram(_) => "histogram",
+        }
+    }
+
+    fn limiter(&self) -> &LabelLimiter {
+        match self {
+            Family::Counter(counter) => &counter.limiter,
+            Family::Gauge(gauge) => &gauge.limiter,
+            Family::Histogram(histogram) => &histogram.limiter,
+        }
+    }
+}
+
+impl MetricsRegistry {
+    /// Creates an empty registry whose metric names are prefixed with `namespace_`.
+    pub fn new(namespace: &str) -> Result<Self, MetricsError> {
+        let valid = namespace
+            .chars()
+            .enumerate()
+            .all(|(i, c)| c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()));
+        if namespace.is_empty() || !valid {
+            return Err(MetricsError::InitializationError(format!(
+                "invalid metrics namespace '{}'",
+                namespace
+            )));
+        }
+        let registry = Registry::new_custom(Some(namespace.to_string()), None)
+            .map_err(|e| MetricsError::InitializationError(e.to_string()))?;
+        Ok(Self {
+            namespace: namespace.to_string(),
+            registry,
+            max_label_sets: DEFAULT_MAX_LABEL_SETS,
+            families: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Sets the label cardinality limit applied to metrics created afterwards.
+    pub fn with_max_label_sets(mut self, max_label_sets: usize) -> Self {
+        self.max_label_sets = max_label_sets.max(1);
+        self
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// The underlying prometheus registry, for collectors this crate does not wrap.
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Returns the counter `name`, registering it on first use.
+    pub fn counter(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+    ) -> Result<Counter, MetricsError> {
+        let family = self.get_or_register(name, labels, "counter", |limiter| {
+            let vec = CounterVec::new(Opts::new(name, help), labels)?;
+            Ok((Family::Counter(Counter { vec: vec.clone(), limiter }), Box::new(vec)))
+        })?;
+        match family {
+            Family::Counter(counter) => Ok(counter),
+            _ => unreachable!("kind is checked on lookup"),
+        }
+    }
+
+    /// Returns the gauge `name`, registering it on first use.
+    pub fn gauge(&self, name: &str, help: &str, labels: &[&str]) -> Result<Gauge, MetricsError> {
+        let family = self.get_or_register(name, labels, "gauge", |limiter| {
+            let vec = GaugeVec::new(Opts::new(name, help), labels)?;
+            Ok((Family::Gauge(Gauge { vec: vec.clone(), limiter }), Box::new(vec)))
+        })?;
+        match family {
+            Family::Gauge(gauge) => Ok(gauge),
+            _ => unreachable!("kind is checked on lookup"),
+        }
+    }
+
+    /// Returns the histogram `name`, registering it on first use.
+    ///
+    /// `buckets` defaults to prometheus' latency buckets (5ms to 10s). Buckets are fixed at
+    /// registration; a later lookup with different buckets returns the existing histogram.
+    pub fn histogram(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        buckets: Option<Vec<f64>>,
+    ) -> Result<Histogram, MetricsError> {
+        let family = self.get_or_register(name, labels, "histogram", |limiter| {
+            let mut opts = HistogramOpts::new(name, help);
+            if let Some(buckets) = buckets {
+                opts = opts.buckets(buckets);
+            }
+            let vec = HistogramVec::new(opts, labels)?;
+            Ok((Family::Histogram(Histogram { vec: vec.clone(), limiter }), Box::new(vec)))
+        })?;
+        match family {
+            Family::Histogram(histogram) => Ok(histogram),
+            _ => unreachable!("kind is checked on lookup"),
+        }
+    }
+
+    /// Encodes every registered metric in the Prometheus text exposition format.
+    pub fn render(&self) -> Result<String, MetricsError> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| MetricsError::ExportError(e.to_string()))?;
+        String::from_utf8(buffer).map_err(|e| MetricsError::ExportError(e.to_string()))
+    }
+
+    fn get_or_register(
+        &self,
+        name: &str,
+        labels: &[&str],
+        kind: &'static str,
+        create: impl FnOnce(LabelLimiter) -> prometheus::Result<(Family, Box<dyn Collector>)>,
+    ) -> Result<Family, MetricsError> {
+        let mut families = self.families.lock().unwrap();
+        match families.entry(name.to_string()) {
+            Entry::Occupied(entry) => {
+                let family = entry.get();
+                if family.kind() != kind {
+                    return Err(MetricsError::RegistrationError(format!(
+                        "{} is already registered as a {}",
+                        name,
+                        family.kind()
+                    )));
+                }
+                if *family.limiter().names != *labels {
+                    return Err(MetricsError::RegistrationError(format!(
+                        "{} is already registered with labels {:?}",
+                        name,
+                        family.limiter().names
+                    )));
+                }
+                Ok(family.clone())
+            }
+            Entry::Vacant(entry) => {
+                let limiter = LabelLimiter::new(labels, self.max_label_sets);
+                let (family, collector) = create(limiter).map_err(registration_error)?;
+                self.registry.register(collector).map_err(registration_error)?;
+                Ok(entry.insert(family).clone())
+            }
+        }
+    }
+}
+
+fn registration_error(err: prometheus::Error) -> MetricsError {
+    MetricsError::RegistrationError(err.to_string())
+}
+
+/// Tracks which label combinations a metric has seen and caps how many it may keep.
+#[derive(Clone)]
+struct LabelLimiter {
+    names: Arc<[String]>,
+    max: usize,
+    seen: Arc<Mutex<HashSet<Vec<String>>>>,
+}
+
+impl LabelLimiter {
+    fn new(names: &[&str], max: usize) -> Self {
+        Self {
+            names: names.iter().map(|name| name.to_string()).collect(),
+            max,
+            seen: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Returns the label values to record under: `values` itself, or the overflow series
+    /// once the limit is reached.
+    fn admit<'a>(&self, values: &[&'a str]) -> Vec<&'a str> {
+        if values.is_empty() {
+            return Vec::new();
+        }
+        let mut seen = self.seen.lock().unwrap();
+        let key: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+        if seen.contains(&key) || seen.len() < self.max {
+            seen.insert(key);
+            values.to_vec()
+        } else {
+            vec![OVERFLOW_LABEL_VALUE; values.len()]
+        }
+    }
+}
+
+/// A monotonically increasing counter.
+///
+/// Label values are positional and must match the label names the counter was registered
+/// with; like prometheus itself, a mismatched number of values panics.
+#[derive(Clone)]
+pub struct Counter {
+    vec: CounterVec,
+    limiter: LabelLimiter,
+}
+
+impl Counter {
+    pub fn inc(&self, labels: &[&str]) {
+        self.inc_by(labels, 1.0);
+    }
+
+    pub fn inc_by(&self, labels: &[&str], value: f64) {
+        self.vec.with_label_values(&self.limiter.admit(labels)).inc_by(value);
+    }
+
+    /// Current value under `labels`, or `0.0` if nothing was recorded there.
+    pub fn get(&self, labels: &[&str]) -> f64 {
+        find(&self.vec, &self.limiter, labels).map_or(0.0, |m| m.get_counter().get_value())
+    }
+}
+
+/// A value that can go up and down.
+#[derive(Clone)]
+pub struct Gauge {
+    vec: GaugeVec,
+    limiter: LabelLimiter,
+}
+
+impl Gauge {
+    pub fn set(&self, labels: &[&str], value: f64) {
+        self.vec.with_label_values(&self.limiter.admit(labels)).set(value);
+    }
+
+    pub fn add(&self, labels: &[&str], value: f64) {
+        self.vec.with_label_values(&self.limiter.admit(labels)).add(value);
+    }
+
+    pub fn inc(&self, labels: &[&str]) {
+        self.add(labels, 1.0);
+    }
+
+    pub fn dec(&self, labels: &[&str]) {
+        self.add(labels, -1.0);
+    }
+
+    /// Current value under `labels`, or `0.0` if nothing was recorded there.
+    pub fn get(&self, labels: &[&str]) -> f64 {
+        find(&self.vec, &self.limiter, labels).map_or(0.0, |m| m.get_gauge().get_value())
+    }
+}
+
+/// A distribution of observations, e.g. latencies.
+#[derive(Clone)]
+pub struct Histogram {
+    vec: HistogramVec,
+    limiter: LabelLimiter,
+}
+
+impl Histogram {
+    pub fn observe(&self, labels: &[&str], value: f64) {
+        self.vec.with_label_values(&self.limiter.admit(labels)).observe(value);
+    }
+
+    /// Number of observations recorded under `labels`.
+    pub fn sample_count(&self, labels: &[&str]) -> u64 {
+        find(&self.vec, &self.limiter, labels).map_or(0, |m| m.get_histogram().get_sample_count())
+    }
+}
+
+/// Looks up the series for `values` without creating it, so reads never add cardinality.
+fn find(
+    collector: &impl Collector,
+    limiter: &LabelLimiter,
+    values: &[&str],
+) -> Option<proto::Metric> {
+    let family = collector.collect().into_iter().next()?;
+    family.get_metric().iter().find_map(|metric| {
+        let matches = metric.get_label().len() == values.len()
+            && metric.get_label().iter().all(|pair| {
+                limiter
+                    .names
+                    .iter()
+                    .position(|name| name == pair.get_name())
+                    .is_some_and(|i| values[i] == pair.get_value())
+            });
+        matches.then(|| metric.clone())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_are_namespaced_and_reused() {
+        let registry = MetricsRegistry::new("chatbot").unwrap();
+        let replies = registry.counter("replies_total", "Replies sent", &["model"]).unwrap();
+        replies.inc(&["gpt-4"]);
+        registry.counter("replies_total", "Replies sent", &["model"]).unwrap().inc(&["gpt-4"]);
+        assert_eq!(replies.get(&["gpt-4"]), 2.0);
+
+        registry.gauge("sessions", "Open sessions", &[]).unwrap().set(&[], 3.0);
+        let text = registry.render().unwrap();
+        assert!(text.contains("chatbot_replies_total{model=\"gpt-4\"} 2"));
+        assert!(text.contains("chatbot_sessions 3"));
+    }
+
+    #[test]
+    fn test_conflicting_registrations_are_rejected() {
+        let registry = MetricsRegistry::new("svc").unwrap();
+        registry.counter("jobs", "Jobs", &["queue"]).unwrap();
+        assert!(matches!(
+            registry.gauge("jobs", "Jobs", &["queue"]),
+            Err(MetricsError::RegistrationError(_))
+        ));
+        assert!(matches!(
+            registry.counter("jobs", "Jobs", &["queue", "status"]),
+            Err(MetricsError::RegistrationError(_))
+        ));
+        assert!(MetricsRegistry::new("bad-namespace").is_err());
+    }
+
+    #[test]
+    fn test_label_sets_beyond_the_limit_are_folded() {
+        let registry = MetricsRegistry::new("svc").unwrap().with_max_label_sets(2);
+        let logins = registry.counter("logins_total", "Logins", &["user"]).unwrap();
+        for user in ["alice", "bob", "carol", "dave", "alice"] {
+            logins.inc(&[user]);
+        }
+        assert_eq!(logins.get(&["alice"]), 2.0);
+        assert_eq!(logins.get(&["bob"]), 1.0);
+        assert_eq!(logins.get(&[OVERFLOW_LABEL_VALUE]), 2.0);
+        assert!(!registry.render().unwrap().contains("carol"));
+    }
+}
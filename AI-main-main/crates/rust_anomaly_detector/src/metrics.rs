@@ -4,7 +4,7 @@ use anyhow::Result;
 /// Define and register a counter metric.
 lazy_static::lazy_static! {
     pub static ref REQUEST_COUNTER: CounterVec = register_counter_vec!(
-        "ai_fast_core_requests_total",
+        "rust_anomaly_detector_requests_total",
         "Total number of requests",
         &["service", "status"]
     ).unwrap();